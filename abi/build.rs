@@ -0,0 +1,279 @@
+//! Generates the Rust side of the system call ABI from `syscalls.toml`:
+//! the call numbers, the error codes, the kernel dispatch table and the
+//! user wrappers. The result is included by `src/lib.rs`.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+use toml::Value;
+
+// Arguments are passed in R1 - R3
+const MAX_ARGS: usize = 3;
+
+struct Arg {
+    name: String,
+    ty: String,
+}
+
+struct Syscall {
+    name: String,
+    number: u32,
+    args: Vec<Arg>,
+    returns: String,
+    doc: String,
+}
+
+struct Error {
+    name: String,
+    variant: String,
+    code: u32,
+    doc: String,
+}
+
+fn main() {
+    let source = fs::read_to_string("syscalls.toml").unwrap();
+    let table: Value = source.parse().expect("syscalls.toml is not valid TOML");
+
+    let abi_version = table["abi_version"].as_integer().expect("abi_version missing");
+    let syscalls = parse_syscalls(&table);
+    let errors = parse_errors(&table);
+
+    let mut out = String::new();
+    writeln!(out, "/// Version of the system call ABI, checked when a process is loaded.").unwrap();
+    writeln!(out, "pub const ABI_VERSION: u32 = {};", abi_version).unwrap();
+    writeln!(out).unwrap();
+
+    generate_numbers(&mut out, &syscalls);
+    generate_errors(&mut out, &errors);
+    generate_table(&mut out, &syscalls);
+    generate_dispatch(&mut out, &syscalls);
+    generate_user(&mut out, &syscalls);
+
+    let path = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("syscalls.rs");
+    fs::write(path, out).unwrap();
+
+    println!("cargo:rerun-if-changed=syscalls.toml");
+}
+
+fn parse_syscalls(table: &Value) -> Vec<Syscall> {
+    let mut syscalls: Vec<Syscall> = Vec::new();
+
+    for entry in table["syscall"].as_array().expect("no [[syscall]] entries") {
+        let name = entry["name"].as_str().unwrap().to_string();
+        let number = entry["number"].as_integer().unwrap() as u32;
+        let args: Vec<Arg> = entry["args"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|arg| Arg {
+                name: arg["name"].as_str().unwrap().to_string(),
+                ty: arg["type"].as_str().unwrap().to_string(),
+            })
+            .collect();
+        let returns = entry["returns"].as_str().unwrap().to_string();
+        let doc = entry["doc"].as_str().unwrap_or("").to_string();
+
+        if number == 0 {
+            panic!("syscall {}: number 0 is reserved", name);
+        }
+        if args.len() > MAX_ARGS {
+            panic!("syscall {}: at most {} arguments are supported", name, MAX_ARGS);
+        }
+        for arg in &args {
+            check_type(&name, &arg.ty, false);
+        }
+        check_type(&name, &returns, true);
+        if let Some(other) = syscalls.iter().find(|s| s.number == number || s.name == name) {
+            panic!("syscall {} clashes with {}", name, other.name);
+        }
+
+        syscalls.push(Syscall { name, number, args, returns, doc });
+    }
+
+    syscalls
+}
+
+fn parse_errors(table: &Value) -> Vec<Error> {
+    table["error"]
+        .as_array()
+        .expect("no [[error]] entries")
+        .iter()
+        .map(|entry| Error {
+            name: entry["name"].as_str().unwrap().to_string(),
+            variant: entry["variant"].as_str().unwrap().to_string(),
+            code: entry["code"].as_integer().unwrap() as u32,
+            doc: entry["doc"].as_str().unwrap_or("").to_string(),
+        })
+        .collect()
+}
+
+fn check_type(syscall: &str, ty: &str, is_return: bool) {
+    match ty {
+        "u32" | "i32" | "ptr" => {},
+        "mut_ptr" if !is_return => {},
+        "never" if is_return => {},
+        _ => panic!("syscall {}: unknown type {}", syscall, ty),
+    }
+}
+
+fn const_name(syscall: &Syscall) -> String {
+    syscall.name.to_uppercase()
+}
+
+fn type_variant(ty: &str) -> &'static str {
+    match ty {
+        "u32" => "Type::U32",
+        "i32" => "Type::I32",
+        "ptr" => "Type::Ptr",
+        "mut_ptr" => "Type::MutPtr",
+        _ => "Type::Never",
+    }
+}
+
+fn generate_numbers(out: &mut String, syscalls: &[Syscall]) {
+    writeln!(out, "/// System call numbers, passed in R0.").unwrap();
+    writeln!(out, "pub mod nr {{").unwrap();
+    for syscall in syscalls {
+        writeln!(out, "    #[doc = {:?}]", syscall.doc).unwrap();
+        writeln!(out, "    pub const {}: u32 = {};", const_name(syscall), syscall.number).unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+}
+
+fn generate_errors(out: &mut String, errors: &[Error]) {
+    writeln!(out, "/// Error codes. A failed call returns the negated code in R0.").unwrap();
+    writeln!(out, "#[derive(Copy, Clone, PartialEq, Eq, Debug)]").unwrap();
+    writeln!(out, "#[repr(u32)]").unwrap();
+    writeln!(out, "pub enum Errno {{").unwrap();
+    for error in errors {
+        writeln!(out, "    #[doc = {:?}]", error.doc).unwrap();
+        writeln!(out, "    {} = {},", error.variant, error.code).unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "pub const ERRNOS: &[(&str, Errno)] = &[").unwrap();
+    for error in errors {
+        writeln!(out, "    ({:?}, Errno::{}),", error.name, error.variant).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    writeln!(out).unwrap();
+}
+
+fn generate_table(out: &mut String, syscalls: &[Syscall]) {
+    writeln!(out, "pub const SYSCALLS: &[Syscall] = &[").unwrap();
+    for syscall in syscalls {
+        writeln!(out, "    Syscall {{").unwrap();
+        writeln!(out, "        name: {:?},", syscall.name).unwrap();
+        writeln!(out, "        number: {},", syscall.number).unwrap();
+        write!(out, "        args: &[").unwrap();
+        for arg in &syscall.args {
+            write!(out, "Arg {{ name: {:?}, ty: {} }}, ", arg.name, type_variant(&arg.ty)).unwrap();
+        }
+        writeln!(out, "],").unwrap();
+        writeln!(out, "        returns: {},", type_variant(&syscall.returns)).unwrap();
+        writeln!(out, "        doc: {:?},", syscall.doc).unwrap();
+        writeln!(out, "    }},").unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    writeln!(out).unwrap();
+}
+
+fn kernel_type(ty: &str) -> &'static str {
+    match ty {
+        "i32" => "i32",
+        // pointers are addresses in the caller's memory
+        _ => "u32",
+    }
+}
+
+fn generate_dispatch(out: &mut String, syscalls: &[Syscall]) {
+    writeln!(out, "/// Kernel side of the system calls, one method per call.").unwrap();
+    writeln!(out, "pub trait SyscallHandler {{").unwrap();
+    for syscall in syscalls {
+        let params: Vec<String> = syscall.args.iter()
+            .map(|arg| format!(", {}: {}", arg.name, kernel_type(&arg.ty)))
+            .collect();
+        writeln!(out, "    #[doc = {:?}]", syscall.doc).unwrap();
+        writeln!(out, "    fn sys_{}(&mut self{}) -> Result<u32, Errno>;", syscall.name, params.concat()).unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "/// Route a system call to its handler method.").unwrap();
+    writeln!(out, "pub fn dispatch<H: SyscallHandler>(handler: &mut H, number: u32, args: [u32; 3]) -> Result<u32, Errno> {{").unwrap();
+    writeln!(out, "    match number {{").unwrap();
+    for syscall in syscalls {
+        let params: Vec<String> = syscall.args.iter().enumerate()
+            .map(|(i, arg)| match kernel_type(&arg.ty) {
+                "u32" => format!("args[{}]", i),
+                ty => format!("args[{}] as {}", i, ty),
+            })
+            .collect();
+        writeln!(out, "        nr::{} => handler.sys_{}({}),", const_name(syscall), syscall.name, params.join(", ")).unwrap();
+    }
+    writeln!(out, "        _ => Err(Errno::NoSys),").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+}
+
+fn user_type(ty: &str) -> &'static str {
+    match ty {
+        "i32" => "i32",
+        "ptr" => "*const u8",
+        "mut_ptr" => "*mut u8",
+        _ => "u32",
+    }
+}
+
+fn generate_user(out: &mut String, syscalls: &[Syscall]) {
+    writeln!(out, "/// User side wrappers, one function per call.").unwrap();
+    writeln!(out, "#[cfg(all(feature = \"user\", target_arch = \"arm\"))]").unwrap();
+    writeln!(out, "#[allow(unused_unsafe)]").unwrap();
+    writeln!(out, "pub mod user {{").unwrap();
+    writeln!(out, "    use super::{{decode_result, nr, raw_syscall, Errno}};").unwrap();
+    for syscall in syscalls {
+        let params: Vec<String> = syscall.args.iter()
+            .map(|arg| format!("{}: {}", arg.name, user_type(&arg.ty)))
+            .collect();
+        let mut regs: Vec<String> = syscall.args.iter()
+            .map(|arg| match user_type(&arg.ty) {
+                "u32" => arg.name.clone(),
+                _ => format!("{} as u32", arg.name),
+            })
+            .collect();
+        while regs.len() < 3 {
+            regs.push("0".to_string());
+        }
+        let call = format!("raw_syscall(nr::{}, {})", const_name(syscall), regs.join(", "));
+        // the kernel dereferences pointer arguments
+        let qualifier = if syscall.args.iter().any(|arg| arg.ty.ends_with("ptr")) { "unsafe " } else { "" };
+
+        writeln!(out).unwrap();
+        writeln!(out, "    #[doc = {:?}]", syscall.doc).unwrap();
+        match syscall.returns.as_str() {
+            "never" => {
+                writeln!(out, "    pub {}fn sys_{}({}) -> ! {{", qualifier, syscall.name, params.join(", ")).unwrap();
+                writeln!(out, "        unsafe {{ {}; }}", call).unwrap();
+                writeln!(out, "        // the kernel switches away once the call returns").unwrap();
+                writeln!(out, "        loop {{").unwrap();
+                writeln!(out, "            unsafe {{ core::arch::asm!(\"wfi\"); }}").unwrap();
+                writeln!(out, "        }}").unwrap();
+            },
+            "ptr" => {
+                writeln!(out, "    pub {}fn sys_{}({}) -> Result<*mut u8, Errno> {{", qualifier, syscall.name, params.join(", ")).unwrap();
+                writeln!(out, "        decode_result(unsafe {{ {} }}).map(|value| value as *mut u8)", call).unwrap();
+            },
+            _ => {
+                writeln!(out, "    pub {}fn sys_{}({}) -> Result<u32, Errno> {{", qualifier, syscall.name, params.join(", ")).unwrap();
+                writeln!(out, "        decode_result(unsafe {{ {} }})", call).unwrap();
+            },
+        }
+        writeln!(out, "    }}").unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+}
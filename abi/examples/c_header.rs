@@ -0,0 +1,107 @@
+//! Writes the C system call header for the C SDK to stdout.
+//!
+//! ```text
+//! cargo run --example c_header > ../csdk/include/chocos/syscall.h
+//! ```
+
+use chocos_abi::{Type, ABI_VERSION, APP_MAGIC, CAP_REBOOT, ERRNOS, SYSCALLS};
+
+fn c_type(ty: Type) -> &'static str {
+    match ty {
+        Type::U32 => "unsigned int",
+        Type::I32 => "int",
+        Type::Ptr => "const void *",
+        Type::MutPtr => "void *",
+        Type::Never => "void",
+    }
+}
+
+fn main() {
+    println!("/* Generated from abi/syscalls.toml by `cargo run --example c_header`. Do not edit. */");
+    println!();
+    println!("#ifndef CHOCOS_SYSCALL_H");
+    println!("#define CHOCOS_SYSCALL_H");
+    println!();
+
+    println!("/* Checked by the kernel when the app is loaded */");
+    println!("#define CHOC_ABI_VERSION  {}", ABI_VERSION);
+    println!("#define CHOC_APP_MAGIC    {:#010x}", APP_MAGIC);
+    println!("#define CHOC_CAP_REBOOT   {:#x}", CAP_REBOOT);
+    println!();
+
+    println!("/* System call numbers, passed in r0 */");
+    for syscall in SYSCALLS {
+        println!("#define SYS_{:<12} {:>3} /* {} */", syscall.name.to_uppercase(), syscall.number, syscall.doc);
+    }
+    println!();
+
+    println!("/* Error codes, returned negated in r0 */");
+    for (name, errno) in ERRNOS {
+        println!("#define CHOC_{:<11} {:>3}", name, *errno as u32);
+    }
+    println!("#define CHOC_MAX_ERRNO    4095");
+    println!();
+
+    println!("/* A result in [-CHOC_MAX_ERRNO, -1] is a negated error code */");
+    println!("#define CHOC_IS_ERROR(ret) ((unsigned int)(ret) >= (unsigned int)-CHOC_MAX_ERRNO)");
+    println!();
+
+    println!("/* Header at the start of every app image, see crt0.c */");
+    println!("struct choc_app_header {{");
+    println!("    unsigned int magic;");
+    println!("    unsigned int abi_version;");
+    println!("    void (*entry)(void);");
+    println!("    const void * end;");
+    println!("    unsigned int caps;");
+    println!("}};");
+    println!();
+
+    println!("static inline int choc_syscall(int id, int arg1, int arg2, int arg3) {{");
+    println!("    register int r0 __asm__(\"r0\") = id;");
+    println!("    register int r1 __asm__(\"r1\") = arg1;");
+    println!("    register int r2 __asm__(\"r2\") = arg2;");
+    println!("    register int r3 __asm__(\"r3\") = arg3;");
+    println!("    __asm__ volatile(");
+    println!("        \"svc 0\"");
+    println!("        : \"+r\"(r0), \"+r\"(r1), \"+r\"(r2), \"+r\"(r3)");
+    println!("        :");
+    println!("        : \"memory\");");
+    println!("    return r0;");
+    println!("}}");
+
+    for syscall in SYSCALLS {
+        let params: Vec<String> = syscall.args.iter()
+            .map(|arg| format!("{} {}", c_type(arg.ty), arg.name))
+            .collect();
+        let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+        let mut regs: Vec<String> = syscall.args.iter().map(|arg| format!("(int){}", arg.name)).collect();
+        while regs.len() < 3 {
+            regs.push("0".to_string());
+        }
+        let call = format!("choc_syscall(SYS_{}, {})", syscall.name.to_uppercase(), regs.join(", "));
+
+        println!();
+        println!("/* {} */", syscall.doc);
+        match syscall.returns {
+            Type::Never => {
+                println!("static inline void sys_{}({}) {{", syscall.name, params);
+                println!("    {};", call);
+                println!("    /* the kernel switches away once the call returns */");
+                println!("    while (1)");
+                println!("        __asm__ volatile(\"wfi\");");
+            },
+            Type::Ptr => {
+                println!("static inline void * sys_{}({}) {{", syscall.name, params);
+                println!("    return (void *){};", call);
+            },
+            _ => {
+                println!("static inline int sys_{}({}) {{", syscall.name, params);
+                println!("    return {};", call);
+            },
+        }
+        println!("}}");
+    }
+    println!();
+
+    println!("#endif");
+}
@@ -0,0 +1,118 @@
+//! System call ABI shared by the kernel and the userland SDKs.
+//!
+//! Everything about the calls themselves is generated from
+//! `syscalls.toml`. The C header in `csdk/include/chocos/syscall.h` comes
+//! from the same table, run `cargo run --example c_header` after changing it.
+//! `memory` is what memory a process may pass to them.
+
+#![no_std]
+
+pub mod memory;
+
+/// Register level type of a system call argument or result.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Type {
+    U32,
+    I32,
+    Ptr,
+    MutPtr,
+    Never,
+}
+
+pub struct Arg {
+    pub name: &'static str,
+    pub ty: Type,
+}
+
+/// Description of one system call, used to generate bindings.
+pub struct Syscall {
+    pub name: &'static str,
+    pub number: u32,
+    pub args: &'static [Arg],
+    pub returns: Type,
+    pub doc: &'static str,
+}
+
+include!(concat!(env!("OUT_DIR"), "/syscalls.rs"));
+
+const MAX_ERRNO: u32 = 4095;
+
+impl Errno {
+    pub fn from_code(code: u32) -> Option<Errno> {
+        ERRNOS.iter().map(|&(_, errno)| errno).find(|&errno| errno as u32 == code)
+    }
+}
+
+/// Encode the result of a system call for R0.
+pub fn encode_result(result: Result<u32, Errno>) -> u32 {
+    match result {
+        Ok(value) => value,
+        Err(errno) => (errno as u32).wrapping_neg(),
+    }
+}
+
+/// Decode R0 after a system call.
+pub fn decode_result(value: u32) -> Result<u32, Errno> {
+    if value.wrapping_neg() <= MAX_ERRNO && value != 0 {
+        // unknown codes still mean failure
+        Err(Errno::from_code(value.wrapping_neg()).unwrap_or(Errno::Inval))
+    } else {
+        Ok(value)
+    }
+}
+
+/// "CHOC", little endian.
+pub const APP_MAGIC: u32 = 0x434f_4843;
+
+/// Header at the start of every app image. `create` is given the address
+/// of this header and refuses images built for another ABI version.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct AppHeader {
+    pub magic: u32,
+    pub abi_version: u32,
+    /// Address of `_start`
+    pub entry: u32,
+    /// Address right behind the image, where its signature trailer starts
+    pub end: u32,
+    /// What the app may do besides the ordinary calls, `CAP_*` bits. Only
+    /// a signature keeps an app from giving itself more.
+    pub caps: u32,
+}
+
+/// May restart the board, `reboot` and `reboot_recovery`
+pub const CAP_REBOOT: u32 = 1 << 0;
+
+impl AppHeader {
+    pub fn check(&self) -> Result<u32, Errno> {
+        if self.magic != APP_MAGIC || self.abi_version != ABI_VERSION {
+            return Err(Errno::NoExec);
+        }
+        Ok(self.entry)
+    }
+}
+
+/// Whether capabilities `caps` include `cap`, Errno::Perm if not
+pub fn require_cap(caps: u32, cap: u32) -> Result<(), Errno> {
+    if caps & cap == cap {
+        Ok(())
+    } else {
+        Err(Errno::Perm)
+    }
+}
+
+/// Issue a system call.
+#[cfg(all(feature = "user", target_arch = "arm"))]
+#[inline(always)]
+pub unsafe fn raw_syscall(number: u32, arg1: u32, arg2: u32, arg3: u32) -> u32 {
+    let return_value: u32;
+    core::arch::asm!("
+        svc 0
+    ",
+        inout("r0") number => return_value,
+        inout("r1") arg1 => _,
+        inout("r2") arg2 => _,
+        inout("r3") arg3 => _
+    );
+    return_value
+}
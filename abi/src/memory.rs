@@ -0,0 +1,52 @@
+//! Memory a process may hand the kernel in a system call.
+//!
+//! Its own RAM slot and the static data the apps are linked at can be
+//! read and written, the user program flash only read. Everything else,
+//! the kernel and the other processes' slots, is refused with EFAULT;
+//! see docs/memory_layout.md.
+
+use core::ops::Range;
+
+use crate::Errno;
+
+/// .data and .bss of the apps, see libchoc/memory.x and csdk/memory.ld
+pub const APP_STATIC_RAM: Range<u32> = 0x2000_0000..0x2000_1000;
+/// Where the app images are flashed
+pub const USER_FLASH: Range<u32> = 0x0802_0000..0x0806_0000;
+
+/// The memory of one process
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserMemory {
+    /// Its RAM slot, heap and stack
+    pub slot: Range<u32>,
+}
+
+impl UserMemory {
+    /// `len` bytes at `addr` the kernel may read for the process
+    pub fn check(&self, addr: u32, len: u32) -> Result<(), Errno> {
+        let end = addr.checked_add(len).ok_or(Errno::Fault)?;
+        match self.readable_end(addr) {
+            Some(limit) if end <= limit => Ok(()),
+            _ => Err(Errno::Fault),
+        }
+    }
+
+    /// `len` bytes at `addr` the kernel may write for the process
+    pub fn check_mut(&self, addr: u32, len: u32) -> Result<(), Errno> {
+        let end = addr.checked_add(len).ok_or(Errno::Fault)?;
+        match self.writable().find(|range| range.contains(&addr)) {
+            Some(range) if end <= range.end => Ok(()),
+            _ => Err(Errno::Fault),
+        }
+    }
+
+    /// The end of the readable region `addr` lies in, e.g. as far as a NUL
+    /// terminated string may run
+    pub fn readable_end(&self, addr: u32) -> Option<u32> {
+        self.writable().chain([USER_FLASH]).find(|range| range.contains(&addr)).map(|range| range.end)
+    }
+
+    fn writable(&self) -> impl Iterator<Item = Range<u32>> {
+        [self.slot.clone(), APP_STATIC_RAM].into_iter()
+    }
+}
@@ -0,0 +1,19 @@
+use chocos_abi::{require_cap, AppHeader, Errno, ABI_VERSION, APP_MAGIC, CAP_REBOOT};
+
+fn header(caps: u32) -> AppHeader {
+    AppHeader { magic: APP_MAGIC, abi_version: ABI_VERSION, entry: 0x0803_0011, end: 0x0803_0400, caps }
+}
+
+#[test]
+fn header_layout() {
+    // libchoc's link.x and the C SDK's crt0 lay it out the same way
+    assert_eq!(core::mem::size_of::<AppHeader>(), 20);
+    assert_eq!(header(0).check(), Ok(0x0803_0011));
+    assert_eq!(AppHeader { abi_version: ABI_VERSION - 1, ..header(0) }.check(), Err(Errno::NoExec));
+}
+
+#[test]
+fn ordinary_app_may_not_reboot() {
+    assert_eq!(require_cap(header(0).caps, CAP_REBOOT), Err(Errno::Perm));
+    assert_eq!(require_cap(header(CAP_REBOOT).caps, CAP_REBOOT), Ok(()));
+}
@@ -0,0 +1,54 @@
+use chocos_abi::memory::{UserMemory, APP_STATIC_RAM, USER_FLASH};
+use chocos_abi::Errno;
+
+// the slot of pid 1
+fn memory() -> UserMemory {
+    UserMemory { slot: 0x2000_B000..0x2000_C000 }
+}
+
+#[test]
+fn own_slot() {
+    let mem = memory();
+    assert_eq!(mem.check_mut(0x2000_B000, 0x1000), Ok(()));
+    assert_eq!(mem.check(0x2000_BF00, 0x100), Ok(()));
+    assert_eq!(mem.check_mut(0x2000_BF00, 0x101), Err(Errno::Fault));
+    // the slot of pid 2 right below
+    assert_eq!(mem.check_mut(0x2000_AFFF, 1), Err(Errno::Fault));
+    assert_eq!(mem.check(0x2000_AFFF, 2), Err(Errno::Fault));
+}
+
+#[test]
+fn static_buffer() {
+    // e.g. a `static mut BUF: [u8; 64]` of a libchoc app, or of a C app
+    let mem = memory();
+    for buf in [APP_STATIC_RAM.start + 0x40, 0x2000_0800] {
+        assert_eq!(mem.check_mut(buf, 64), Ok(()));
+        assert_eq!(mem.check(buf, 64), Ok(()));
+    }
+    assert_eq!(mem.readable_end(0x2000_0800), Some(APP_STATIC_RAM.end));
+    // running into kernel data
+    assert_eq!(mem.check_mut(APP_STATIC_RAM.end - 8, 16), Err(Errno::Fault));
+}
+
+#[test]
+fn kernel_memory() {
+    let mem = memory();
+    // kernel .data/.bss, heap, MSP stack and the handoff block
+    for addr in [0x2000_1000, 0x2000_3000, 0x2000_D000, 0x2000_F500] {
+        assert_eq!(mem.check(addr, 4), Err(Errno::Fault));
+        assert_eq!(mem.check_mut(addr, 4), Err(Errno::Fault));
+        assert_eq!(mem.readable_end(addr), None);
+    }
+    // the OS itself
+    assert_eq!(mem.check(0x0801_0000, 4), Err(Errno::Fault));
+    assert_eq!(mem.check(u32::MAX, 2), Err(Errno::Fault));
+}
+
+#[test]
+fn user_flash_is_read_only() {
+    let mem = memory();
+    assert_eq!(mem.check(USER_FLASH.start, 0x100), Ok(()));
+    assert_eq!(mem.check_mut(USER_FLASH.start, 0x100), Err(Errno::Fault));
+    assert_eq!(mem.check(USER_FLASH.end - 4, 8), Err(Errno::Fault));
+    assert_eq!(mem.readable_end(0x0803_0000), Some(USER_FLASH.end));
+}
@@ -7,6 +7,9 @@
 //! Cargo re-run the build script whenever `memory.x` is changed,
 //! updating `memory.x` ensures a rebuild of the application with the
 //! new memory settings.
+//!
+//! It also writes the public key for checking image signatures to
+//! `public_key.rs`, see `chocos_isp::build`.
 
 use std::env;
 use std::fs::File;
@@ -28,4 +31,6 @@ fn main() {
     // here, we ensure the build script is only re-run when
     // `memory.x` is changed.
     println!("cargo:rerun-if-changed=memory.x");
+
+    chocos_isp::build::write_public_key(out);
 }
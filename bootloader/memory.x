@@ -6,7 +6,11 @@ MEMORY
   /* FLASH : ORIGIN = 0x00000000, LENGTH = 256K */
 
   
-  FLASH : ORIGIN = 0x08000000, LENGTH = 512K
+  /* The first 64K only: the OS slots and everything the flash protocol
+     may erase start at 0x08010000 (chocos_isp::Layout::writable_start),
+     so a bootloader that outgrows this fails to link instead of
+     overlapping them. */
+  FLASH : ORIGIN = 0x08000000, LENGTH = 64K
   RAM : ORIGIN = 0x20000000, LENGTH = 64K
   
 
@@ -0,0 +1,113 @@
+use chocos_isp::dfu::{self, request, Dfu, Region};
+use chocos_isp::image::Policy;
+use chocos_isp::Action;
+use usb_device::class_prelude::*;
+use usb_device::control::{Recipient, Request, RequestType};
+use usb_device::Result;
+
+const REGIONS: [Region; 4] = Region::STM32F103;
+
+// The DFU interface of flash mode, see `chocos_isp::dfu`. Flash work
+// requested here is done by `poll` from the main loop, with the flash the
+// HID protocol uses.
+pub struct DfuClass<'a> {
+    iface: InterfaceNumber,
+    names: [StringIndex; REGIONS.len()],
+    dfu: Dfu<'a>,
+    action: Action,
+}
+
+impl<'a> DfuClass<'a> {
+    pub fn new<B: UsbBus>(alloc: &UsbBusAllocator<B>, policy: Policy<'a>) -> Self {
+        DfuClass {
+            iface: alloc.interface(),
+            names: REGIONS.map(|_| alloc.string()),
+            dfu: Dfu::new(&REGIONS, policy),
+            action: Action::None,
+        }
+    }
+
+    // Writes the block or checks the image the host has sent. Returns
+    // `Action::Reset` once the host wants the board restarted.
+    pub fn poll<F: chocos_isp::Flash>(&mut self, flash: &mut F) -> Action {
+        self.dfu.poll(flash);
+        core::mem::replace(&mut self.action, Action::None)
+    }
+
+    fn is_mine(&self, req: &Request) -> bool {
+        req.recipient == Recipient::Interface && req.index == u8::from(self.iface) as u16
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for DfuClass<'_> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+        for (alt, name) in self.names.iter().enumerate() {
+            writer.interface_alt(self.iface, alt as u8, dfu::CLASS, dfu::SUBCLASS, dfu::PROTOCOL, Some(*name))?;
+        }
+        writer.write(dfu::FUNCTIONAL_DESCRIPTOR_TYPE, &dfu::functional_descriptor())
+    }
+
+    fn get_string(&self, index: StringIndex, _lang_id: u16) -> Option<&str> {
+        let alt = self.names.iter().position(|name| *name == index)?;
+        Some(REGIONS[alt].name)
+    }
+
+    fn reset(&mut self) {
+        if self.dfu.usb_reset() == Action::Reset {
+            self.action = Action::Reset;
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        if !self.is_mine(&req) {
+            return;
+        }
+        let ok = match (req.request_type, req.request) {
+            (RequestType::Standard, Request::SET_INTERFACE) => self.dfu.set_alt(req.value as usize),
+            (RequestType::Class, request::DETACH) => {
+                self.action = self.dfu.detach();
+                true
+            }
+            (RequestType::Class, request::DNLOAD) => self.dfu.download(xfer.data()),
+            (RequestType::Class, request::CLRSTATUS) => self.dfu.clear_status(),
+            (RequestType::Class, request::ABORT) => self.dfu.abort(),
+            _ => return,
+        };
+        if ok {
+            xfer.accept().ok();
+        } else {
+            xfer.reject().ok();
+        }
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        if !self.is_mine(&req) {
+            return;
+        }
+        match (req.request_type, req.request) {
+            (RequestType::Standard, Request::GET_INTERFACE) => {
+                xfer.accept_with(&[self.dfu.alt() as u8]).ok();
+            }
+            (RequestType::Class, request::UPLOAD) => {
+                // the flash is mapped into the address space, reading needs
+                // no help from the flash controller
+                let region = self.dfu.region();
+                let memory = unsafe { core::slice::from_raw_parts(region.start as *const u8, region.size as usize) };
+                let dfu = &mut self.dfu;
+                xfer.accept(|buf| {
+                    let len = buf.len().min(req.length as usize);
+                    dfu.upload(memory, &mut buf[..len]).ok_or(UsbError::InvalidState)
+                }).ok();
+            }
+            (RequestType::Class, request::GETSTATUS) => {
+                xfer.accept_with(&self.dfu.get_status()).ok();
+            }
+            (RequestType::Class, request::GETSTATE) => {
+                xfer.accept_with(&[self.dfu.get_state()]).ok();
+            }
+            _ => {}
+        }
+    }
+}
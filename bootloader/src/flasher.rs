@@ -0,0 +1,48 @@
+use chocos_isp::{Flash, FlashError, Layout};
+use stm32f1xx_hal::flash::{self, FlashSize, FlashWriter, Parts, SectorSize};
+
+// Flash backend of the flash mode protocol, see the `chocos-isp` crate.
+// The layout puts the bootloader in the first 64K; the protocol never
+// erases or writes those pages, so a bad image can always be flashed again.
+pub struct IspFlash {
+    parts: Parts,
+}
+
+impl IspFlash {
+    pub fn new(parts: Parts) -> Self {
+        IspFlash { parts }
+    }
+
+    fn writer(&mut self) -> FlashWriter {
+        self.parts.writer(SectorSize::Sz2K, FlashSize::Sz512K)
+    }
+}
+
+impl Flash for IspFlash {
+    fn layout(&self) -> Layout {
+        Layout::STM32F103
+    }
+
+    fn erase_page(&mut self, addr: u32) -> Result<(), FlashError> {
+        self.writer().page_erase(addr - Layout::STM32F103.base).map_err(convert)
+    }
+
+    // The controller programs half words, every write is read back.
+    fn write(&mut self, addr: u32, data: &[u8]) -> Result<(), FlashError> {
+        self.writer().write(addr - Layout::STM32F103.base, data).map_err(convert)
+    }
+
+    fn read(&self, addr: u32, len: usize) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(addr as *const u8, len) }
+    }
+}
+
+fn convert(e: flash::Error) -> FlashError {
+    match e {
+        flash::Error::AddressLargerThanFlash | flash::Error::LengthTooLong => FlashError::OutOfRange,
+        flash::Error::AddressMisaligned | flash::Error::LengthNotMultiple2 => FlashError::Misaligned,
+        flash::Error::EraseError => FlashError::Erase,
+        flash::Error::VerifyError => FlashError::Verify,
+        _ => FlashError::Program,
+    }
+}
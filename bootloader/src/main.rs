@@ -1,15 +1,23 @@
 #![no_std]
 #![no_main]
 
-// mod flasher;
+#[cfg(feature = "dfu")]
+mod dfu;
+mod flasher;
+mod serial;
 
-use cortex_m::{interrupt::{free as critical_section}, asm::delay};
 use stm32f1xx_hal::{pac::interrupt, gpio::{Input, Floating, PushPull, Output}, usb::{Peripheral, UsbBus}};
 
 use stm32f1xx_hal::gpio::GpioExt;
-use usb_device::{class_prelude::{UsbBusAllocator}, device::{UsbDeviceBuilder, UsbVidPid, UsbDevice}, UsbError};
-use usbd_hid::descriptor::SerializedDescriptor;
-use usbd_hid::{hid_class::HIDClass, descriptor::KeyboardReport};
+use usb_device::{class_prelude::{UsbBusAllocator}, device::{UsbDeviceBuilder, UsbDeviceState, UsbVidPid, UsbDevice}, UsbError};
+use usbd_hid::hid_class::HIDClass;
+
+use chocos_isp::{dfu::Region, image::Policy, protocol::{REPORT_DESCRIPTOR, REPORT_SIZE}, slots::{self, SlotLayout}, usb::{self as identity, SERIAL_SIZE}, ymodem::Ymodem, Action, Isp, ReportIo};
+use chocos_handoff::{Handoff, PanicLocation, Request, ResetReason};
+use flasher::IspFlash;
+use serial::{SerialPort, Ticks};
+#[cfg(feature = "dfu")]
+use dfu::DfuClass;
 
 // #[cfg(not(debug_assertions))]
 use core::panic::PanicInfo;
@@ -21,20 +29,40 @@ use core::panic::PanicInfo;
 
 use cortex_m::{asm, delay::Delay};
 use cortex_m_rt::entry;
-use stm32f1xx_hal::{device, prelude::*};
+use stm32f1xx_hal::{device, prelude::*, serial::{Config, Serial}};
+
+pub const BOOTLOADER_VERSION: (u8, u8) = (0, 1);
+
+const SYSCLK: u32 = 72_000_000;
+// Flash mode falls back to YMODEM on USART1 when no USB host has
+// configured the device by then, in tenths of a second
+const USB_TIMEOUT: u32 = 30;
+const SERIAL_BAUD: u32 = 115_200;
+// quiet line before the YMODEM receiver asks again
+const SERIAL_TIMEOUT: u32 = 10;
 
-pub const OS_ADDR: u32 = 0x0801_0000;
+// PUBLIC_KEY, from build.rs
+include!(concat!(env!("OUT_DIR"), "/public_key.rs"));
 
 #[entry]
 fn main() -> ! {
     let p = device::Peripherals::take().unwrap();
     let cp = device::CorePeripherals::take().unwrap();
 
+    // record why we got here and take what the OS asked for, once
+    let mut handoff = unsafe { Handoff::load() }.unwrap_or_default();
+    handoff.reset_reason = ResetReason::from_csr(p.RCC.csr.read().bits());
+    handoff.boot_count = handoff.boot_count.wrapping_add(1);
+    handoff.bootloader_version = BOOTLOADER_VERSION;
+    let request = core::mem::replace(&mut handoff.request, Request::None);
+    unsafe { handoff.store(); }
+    p.RCC.csr.modify(|_, w| w.rmvf().set_bit());
+
     let rcc = p.RCC.constrain();
     let mut flash = p.FLASH.constrain();
 
     // Initialize clocks
-    let _clocks = rcc.cfgr
+    let clocks = rcc.cfgr
         .use_hse(8.mhz())
         .sysclk(72.mhz())
         .hclk(72.mhz())
@@ -45,6 +73,16 @@ fn main() -> ! {
     let mut scb = cp.SCB;
     let mut gpioa = p.GPIOA.split();
     let mut gpiod = p.GPIOD.split();
+    let mut afio = p.AFIO.constrain();
+
+    let (tx, rx) = Serial::usart1(
+        p.USART1,
+        (gpioa.pa9.into_alternate_push_pull(&mut gpioa.crh), gpioa.pa10.into_floating_input(&mut gpioa.crh)),
+        &mut afio.mapr,
+        Config::default().baudrate(SERIAL_BAUD.bps()),
+        clocks,
+    ).split();
+    let serial = SerialPort::new(tx, rx);
 
     // debug in memory: set scb to 0x20000000 so interrupt handler would work properly
     // unsafe { scb.vtor.write(0x2000_0000); }
@@ -52,25 +90,18 @@ fn main() -> ! {
     // #[cfg(debug_assertions)]
     // let _ = hprintln!("Bootloader init");
 
-    // check SRAM flash flag
-    unsafe {
-        let flag = core::ptr::read_volatile(0x2000_FFF0 as *const [u8; 8]);
-        // let _ = hprintln!("flag: {:?}", flag);
-        // Flag: 0xB00710AD 0xE2000000 
-        if flag == [0xAD, 0x10, 0x07, 0xB0, 0x00, 0x00, 0x00, 0xE2] {
-            // clear the flag
-            core::ptr::write_volatile(0x2000_FFF0 as *mut [u8; 8], [0x00; 8]);
-            // #[cfg(debug_assertions)]
-            // let _ = hprintln!("SRAM flag set, jumping to isp");
-            go_bootloader(flash, p.USB, &mut gpioa.crh, &mut gpiod.crl, gpioa.pa11, gpioa.pa12, gpiod.pd6);
-        }
+    // asked for by the OS, or we panicked last time
+    if request == Request::Recovery {
+        // #[cfg(debug_assertions)]
+        // let _ = hprintln!("Recovery requested, jumping to isp");
+        go_bootloader(IspFlash::new(flash), p.USB, &mut gpioa.crh, &mut gpiod.crl, gpioa.pa11, gpioa.pa12, gpiod.pd6, serial, cp.SYST);
     }
 
     
     // wait
-    let mut sleeper = Delay::new(cp.SYST, 72_000_000);
+    let mut sleeper = Delay::new(cp.SYST, SYSCLK);
     sleeper.delay_ms(1000);
-    sleeper.free();
+    let syst = sleeper.free();
 
     // assume GPIOA1 is the bootloader button
 
@@ -78,15 +109,39 @@ fn main() -> ! {
     if btn.is_high() {
         // #[cfg(debug_assertions)]
         // let _ = hprintln!("Button pressed, jumping to isp");
-        go_bootloader(flash, p.USB, &mut gpioa.crh, &mut gpiod.crl, gpioa.pa11, gpioa.pa12, gpiod.pd6);
+        go_bootloader(IspFlash::new(flash), p.USB, &mut gpioa.crh, &mut gpiod.crl, gpioa.pa11, gpioa.pa12, gpiod.pd6, serial, syst);
     }
 
 
+    // pick one of the two OS slots and count the boot if its image is
+    // still on trial. An interrupted flash, an erased chip or images that
+    // never confirmed themselves leave nothing worth booting, stay in
+    // flash mode instead of jumping into garbage. A slot the OS asked for
+    // goes first
+    let first = match request {
+        Request::BootSlot(slot) => Some(slot),
+        _ => None,
+    };
+    let mut isp_flash = IspFlash::new(flash);
+    let os_addr = match slots::prepare_boot_from(&mut isp_flash, &SlotLayout::STM32F103, &policy(), first) {
+        Some(addr) => addr,
+        None => go_bootloader(isp_flash, p.USB, &mut gpioa.crh, &mut gpiod.crl, gpioa.pa11, gpioa.pa12, gpiod.pd6, serial, syst),
+    };
+
     // boot usercode
     // #[cfg(debug_assertions)]
     // let _ = hprintln!("Jumping to user code");
 
-    boot(&mut scb, OS_ADDR as *const u32);
+    boot(&mut scb, os_addr as *const u32);
+}
+
+// Which OS images are good enough to boot
+fn policy() -> Policy<'static> {
+    Policy {
+        key: PUBLIC_KEY.as_ref(),
+        allow_unsealed: cfg!(feature = "allow-unsealed"),
+        allow_unsigned: cfg!(feature = "dev-mode"),
+    }
 }
 
 // Jump to the user application code
@@ -103,16 +158,22 @@ fn boot(scb: &mut cortex_m::peripheral::SCB, vtable: *const u32) -> ! {
 static mut USB_BUS: Option<UsbBusAllocator<UsbBus<Peripheral>>> = None;
 static mut USB_HID: Option<HIDClass<'static, UsbBus<Peripheral>>> = None;
 static mut USB_DEVICE: Option<UsbDevice<'static, UsbBus<Peripheral>>> = None;
+// the serial number string, from the unique ID, the same as the OS's
+static mut SERIAL: [u8; SERIAL_SIZE] = [0; SERIAL_SIZE];
+#[cfg(feature = "dfu")]
+static mut USB_DFU: Option<DfuClass<'static>> = None;
 
 // static usbBus: RefCell<Option<UsbBusAllocator<UsbBus<Peripheral>>>> = RefCell::new(None);
 fn go_bootloader(
-    _flash: stm32f1xx_hal::flash::Parts, 
+    flash: IspFlash, 
     usb: stm32f1xx_hal::pac::USB, 
     crh: &mut stm32f1xx_hal::gpio::Cr<stm32f1xx_hal::gpio::CRH, 'A'>, 
     crl: &mut stm32f1xx_hal::gpio::Cr<stm32f1xx_hal::gpio::CRL, 'D'>, 
     pa11: stm32f1xx_hal::gpio::gpioa::PA11<Input<Floating>>, 
     pa12: stm32f1xx_hal::gpio::gpioa::PA12<Input<Floating>>, 
-    pd6: stm32f1xx_hal::gpio::gpiod::PD6<Input<Floating>>
+    pd6: stm32f1xx_hal::gpio::gpiod::PD6<Input<Floating>>,
+    mut serial: SerialPort,
+    syst: cortex_m::peripheral::SYST,
 ) -> ! {
     // #[cfg(debug_assertions)]
     // let _ = hprintln!("Init flasher");
@@ -121,12 +182,12 @@ fn go_bootloader(
     let mut usb_dp = pa12.into_push_pull_output(crh);
 
     usb_en.set_high();
-    asm::delay(72_000_000 / 10);
+    asm::delay(SYSCLK / 10);
 
     usb_en.set_low();
 
     usb_dp.set_low();
-    asm::delay(72_000_000 / 100);
+    asm::delay(SYSCLK / 100);
 
     let usb: Peripheral = Peripheral {
         usb,
@@ -138,11 +199,14 @@ fn go_bootloader(
     unsafe { USB_BUS = Some(usb_bus); }
     let bus_ref = unsafe { USB_BUS.as_ref().unwrap() };
 
-    let usb_hid = HIDClass::new(&bus_ref, KeyboardReport::desc(), 10);
-    let usb_device = UsbDeviceBuilder::new(&bus_ref, UsbVidPid(0x16c0, 0x27dd))
-        .manufacturer("Test Company")
-        .product("ChocOS Keyboard (Recovery)")
-        .serial_number("0001")
+    let usb_hid = HIDClass::new(&bus_ref, REPORT_DESCRIPTOR, 10);
+    #[cfg(feature = "dfu")]
+    unsafe { USB_DFU = Some(DfuClass::new(&bus_ref, policy())); }
+    let serial = identity::serial_number(&unsafe { identity::read_uid() }, unsafe { &mut SERIAL });
+    let usb_device = UsbDeviceBuilder::new(&bus_ref, UsbVidPid(identity::VID, identity::PID))
+        .manufacturer(identity::MANUFACTURER)
+        .product(identity::RECOVERY_PRODUCT)
+        .serial_number(serial)
         .device_class(0xEF)
         .max_packet_size_0(64)
         .build();
@@ -152,63 +216,102 @@ fn go_bootloader(
         USB_DEVICE = Some(usb_device);
     }
 
-    // unsafe {
-    //     // stm32f1xx_hal::pac::NVIC::unmask(stm32f1xx_hal::pac::Interrupt::USB_LP_CAN_RX0);
-    // }
+    let mut isp = Isp::new(flash, BOOTLOADER_VERSION);
+    let mut ticks = Ticks::new(syst, SYSCLK);
 
-    // usbBus.replace(Some(UsbBus::new(usb)));
+    // USB first; without a host it never gets configured, then the images
+    // come over the serial port until the next reset
+    let mut usb_seen = false;
 
-    // critical_section(|cs| {
-    //     USB_BUS.borrow(cs).replace(Some(UsbBus::new(usb)));
-    //     let bus = USB_BUS.borrow(cs).borrow().as_ref().unwrap();
+    loop {
+        poll_usb();
 
-    //     let flasher = IspFlash::new(flash, &bus);
-    //     FLASHER.borrow(cs).replace(Some(flasher));
-    // });
+        let usb_hid = unsafe { USB_HID.as_mut().unwrap() };
+        let action = isp.poll(&mut HidReports(usb_hid));
+        // DFU downloads are written here, outside of the control transfers
+        #[cfg(feature = "dfu")]
+        let action = match action {
+            Action::None => unsafe { USB_DFU.as_mut().unwrap() }.poll(isp.flash_mut()),
+            action => action,
+        };
+
+        if action == Action::Reset {
+            // give the IN transfer time to complete before dropping off the bus
+            for _ in 0..1000 {
+                poll_usb();
+            }
+            cortex_m::peripheral::SCB::sys_reset();
+        }
 
-    // unsafe {
-    //     stm32f1xx_hal::pac::NVIC::unmask(stm32f1xx_hal::pac::Interrupt::USB_HP_CAN_TX);
-    //     stm32f1xx_hal::pac::NVIC::unmask(stm32f1xx_hal::pac::Interrupt::USB_LP_CAN_RX0);
-    // }
+        if !usb_seen {
+            usb_seen = unsafe { USB_DEVICE.as_ref().unwrap() }.state() == UsbDeviceState::Configured;
+            if !usb_seen && ticks.now() >= USB_TIMEOUT {
+                serial_flash_mode(isp.flash_mut(), &mut serial, &mut ticks);
+            }
+        }
+    }
+}
+
+// YMODEM on USART1, see `chocos_isp::ymodem`. Restarts once a batch of
+// good images is in.
+fn serial_flash_mode(flash: &mut IspFlash, serial: &mut SerialPort, ticks: &mut Ticks) -> ! {
+    let mut ymodem = Ymodem::new(&Region::STM32F103, policy());
+    let mut quiet_since = ticks.now();
+    ymodem.timeout(serial);
 
     loop {
-        // asm::wfi();
-        let usb_dev = unsafe { USB_DEVICE.as_mut().unwrap() };
-        let usb_hid = unsafe { USB_HID.as_mut().unwrap() };
-        
-        let poll_result = usb_dev.poll(&mut [usb_hid]);
-        if !poll_result {
-            continue;
+        if ymodem.poll(flash, serial) == Action::Reset {
+            // let the last ACK go out
+            asm::delay(SYSCLK / 100);
+            cortex_m::peripheral::SCB::sys_reset();
         }
-        let mut buf = [0u8; 64];
-        match usb_hid.pull_raw_output(&mut buf) {
-            Ok(_size) => {
-                // TODO
-            },
-            Err(UsbError::InvalidEndpoint) => {
-    
-            },
-            Err(UsbError::WouldBlock) => {
-                continue;
-            },
-            n => unreachable!()
+
+        let now = ticks.now();
+        if serial.take_activity() {
+            quiet_since = now;
+        } else if now - quiet_since >= SERIAL_TIMEOUT {
+            ymodem.timeout(serial);
+            quiet_since = now;
         }
-    
+    }
+}
+
+fn poll_usb() {
+    let usb_dev = unsafe { USB_DEVICE.as_mut().unwrap() };
+    let usb_hid = unsafe { USB_HID.as_mut().unwrap() };
+
+    #[cfg(not(feature = "dfu"))]
+    usb_dev.poll(&mut [usb_hid]);
+    #[cfg(feature = "dfu")]
+    usb_dev.poll(&mut [usb_hid, unsafe { USB_DFU.as_mut().unwrap() }]);
+}
+
+// The flash mode protocol over the HID endpoints
+struct HidReports<'a>(&'a HIDClass<'static, UsbBus<Peripheral>>);
+
+impl ReportIo for HidReports<'_> {
+    fn read_report(&mut self, buf: &mut [u8; REPORT_SIZE]) -> Option<usize> {
+        self.0.pull_raw_output(buf).ok()
+    }
 
+    fn write_report(&mut self, report: &[u8; REPORT_SIZE]) -> bool {
+        // anything but a busy endpoint won't get better by retrying
+        !matches!(self.0.push_raw_input(report), Err(UsbError::WouldBlock))
     }
-    // let flash = flasher::IspFlash::new(p, clocks);
-    // flash.take_control();
 }
 
 // #[cfg(not(debug_assertions))]
 #[inline(never)]
 #[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
+fn panic(info: &PanicInfo) -> ! {
     // House is on fire!
 
     unsafe {
-        // set flasher flag
-        core::ptr::write_volatile(0x2000_FFF0 as *mut [u8; 8], [0xAD, 0x10, 0x07, 0xB0, 0x00, 0x00, 0x00, 0xE2]);
+        // leave the location behind and come back up in flash mode
+        chocos_handoff::update(|handoff| {
+            handoff.request = Request::Recovery;
+            handoff.panic = info.location().map(|l| PanicLocation::new(l.file(), l.line(), l.column()));
+        });
 
         // reset the board
         // See also: https://developer.arm.com/documentation/dui0552/a/Cihehdge
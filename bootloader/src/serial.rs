@@ -0,0 +1,63 @@
+use chocos_isp::ymodem::SerialIo;
+use cortex_m::peripheral::{syst::SystClkSource, SYST};
+use embedded_hal::serial::{Read, Write};
+use stm32f1xx_hal::{pac::USART1, serial::{Rx, Tx}};
+
+// The serial fallback of flash mode on USART1 (PA9 TX, PA10 RX), the pins
+// the OS logs to. See `chocos_isp::ymodem`.
+pub struct SerialPort {
+    tx: Tx<USART1>,
+    rx: Rx<USART1>,
+    // something arrived since the last `take_activity`
+    active: bool,
+}
+
+impl SerialPort {
+    pub fn new(tx: Tx<USART1>, rx: Rx<USART1>) -> Self {
+        SerialPort { tx, rx, active: false }
+    }
+
+    pub fn take_activity(&mut self) -> bool {
+        core::mem::replace(&mut self.active, false)
+    }
+}
+
+impl SerialIo for SerialPort {
+    // an overrun loses a byte, the block CRC catches that
+    fn read_byte(&mut self) -> Option<u8> {
+        let b = self.rx.read().ok()?;
+        self.active = true;
+        Some(b)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            while self.tx.write(*b).is_err() {}
+        }
+    }
+}
+
+// Time for the timeouts of flash mode, from SysTick without its interrupt:
+// the counter wraps every 100 ms and is checked from the main loop.
+pub struct Ticks {
+    syst: SYST,
+    tenths: u32,
+}
+
+impl Ticks {
+    pub fn new(mut syst: SYST, sysclk: u32) -> Self {
+        syst.set_clock_source(SystClkSource::Core);
+        syst.set_reload(sysclk / 10 - 1);
+        syst.clear_current();
+        syst.enable_counter();
+        Ticks { syst, tenths: 0 }
+    }
+
+    // Tenths of a second since `new`, has to be called more often than that
+    pub fn now(&mut self) -> u32 {
+        if self.syst.has_wrapped() {
+            self.tenths += 1;
+        }
+        self.tenths
+    }
+}
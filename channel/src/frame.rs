@@ -0,0 +1,42 @@
+// Channel reports
+//
+// Every report is 64 bytes, the same vendor defined report as the flash
+// mode protocol, on both endpoints:
+//
+//   flags u8 | len u8 | data[62]
+//
+// A message is split over as many reports as it needs: the first has
+// START, the last END, a short one both. Status reports carry no data and
+// go from the device to the host only. ACK says the app took the host's
+// message, so the next one may be sent; NAK that it was dropped, because
+// no app has the channel open or it broke the rules. The host sends one
+// message at a time and waits for either.
+
+pub const REPORT_SIZE: usize = 64;
+pub const HEADER_SIZE: usize = 2;
+pub const MAX_DATA: usize = REPORT_SIZE - HEADER_SIZE;
+/// Longest message either way
+pub const MAX_MESSAGE: usize = 512;
+
+pub const START: u8 = 0x01;
+pub const END: u8 = 0x02;
+pub const ACK: u8 = 0x10;
+pub const NAK: u8 = 0x20;
+
+pub fn report(flags: u8, data: &[u8]) -> [u8; REPORT_SIZE] {
+    assert!(data.len() <= MAX_DATA);
+    let mut report = [0; REPORT_SIZE];
+    report[0] = flags;
+    report[1] = data.len() as u8;
+    report[HEADER_SIZE..HEADER_SIZE + data.len()].copy_from_slice(data);
+    report
+}
+
+/// Flags and data of a report, `None` if it is too short for its length
+pub fn parse(report: &[u8]) -> Option<(u8, &[u8])> {
+    let len = *report.get(1)? as usize;
+    if len > MAX_DATA {
+        return None;
+    }
+    Some((report[0], report.get(HEADER_SIZE..HEADER_SIZE + len)?))
+}
@@ -0,0 +1,14 @@
+//! Vendor HID channel of ChocOS.
+//!
+//! Messages between a host tool and the app that opened the channel, on
+//! the second, vendor defined HID interface of the OS. This crate is the
+//! part both ends share: the report layout in `frame`, and `Sender` and
+//! `Receiver` that split messages into reports and put them back
+//! together. The OS adds the USB endpoint and the system calls.
+
+#![no_std]
+
+pub mod frame;
+pub mod message;
+
+pub use message::{ChannelError, Receiver, Sender};
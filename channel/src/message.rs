@@ -0,0 +1,154 @@
+// Messages over channel reports
+//
+// Both keep one message. The receiving end holds on to a finished message
+// until it is taken and drops whatever comes meanwhile, which is where the
+// ACK of the flow control comes in. The sending end hands out one report
+// at a time, for whenever the endpoint is free, with status reports first.
+
+use crate::frame::{self, END, MAX_DATA, MAX_MESSAGE, REPORT_SIZE, START};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChannelError {
+    /// Longer than `MAX_MESSAGE`
+    TooLong,
+    /// The last message hasn't been taken or sent yet
+    Busy,
+    /// Data that doesn't belong to a started message, or a bad report
+    Malformed,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    Idle,
+    Receiving,
+    Complete,
+}
+
+pub struct Receiver {
+    buf: [u8; MAX_MESSAGE],
+    len: usize,
+    state: State,
+}
+
+impl Receiver {
+    pub fn new() -> Self {
+        Receiver { buf: [0; MAX_MESSAGE], len: 0, state: State::Idle }
+    }
+
+    /// Adds the next report. `Ok(true)` once it completes a message. A
+    /// bad report drops the message it belongs to; a report that comes
+    /// while a message waits to be taken is dropped itself.
+    pub fn push(&mut self, report: &[u8]) -> Result<bool, ChannelError> {
+        let (flags, data) = frame::parse(report).ok_or(ChannelError::Malformed)?;
+        if self.state == State::Complete {
+            return Err(ChannelError::Busy);
+        }
+        if flags & START != 0 {
+            self.len = 0;
+            self.state = State::Receiving;
+        } else if self.state != State::Receiving {
+            return Err(ChannelError::Malformed);
+        }
+        if self.len + data.len() > MAX_MESSAGE {
+            self.state = State::Idle;
+            return Err(ChannelError::TooLong);
+        }
+        self.buf[self.len..self.len + data.len()].copy_from_slice(data);
+        self.len += data.len();
+        if flags & END != 0 {
+            self.state = State::Complete;
+        }
+        Ok(self.state == State::Complete)
+    }
+
+    /// The finished message, until `clear`
+    pub fn message(&self) -> Option<&[u8]> {
+        (self.state == State::Complete).then(|| &self.buf[..self.len])
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.state = State::Idle;
+    }
+}
+
+impl Default for Receiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Sender {
+    buf: [u8; MAX_MESSAGE],
+    len: usize,
+    // bytes handed out already, None while there is no message
+    sent: Option<usize>,
+    // status flags to report before the next data
+    status: u8,
+}
+
+impl Sender {
+    pub fn new() -> Self {
+        Sender { buf: [0; MAX_MESSAGE], len: 0, sent: None, status: 0 }
+    }
+
+    pub fn send(&mut self, message: &[u8]) -> Result<(), ChannelError> {
+        if message.len() > MAX_MESSAGE {
+            return Err(ChannelError::TooLong);
+        }
+        if self.is_busy() {
+            return Err(ChannelError::Busy);
+        }
+        self.buf[..message.len()].copy_from_slice(message);
+        self.len = message.len();
+        self.sent = Some(0);
+        Ok(())
+    }
+
+    /// A message is still on its way out
+    pub fn is_busy(&self) -> bool {
+        self.sent.is_some()
+    }
+
+    /// Reports `ACK` or `NAK` to the other end, ahead of any data
+    pub fn status(&mut self, flags: u8) {
+        self.status |= flags;
+    }
+
+    /// The report to send next, `sent` once the endpoint took it
+    pub fn next_report(&self) -> Option<[u8; REPORT_SIZE]> {
+        if self.status != 0 {
+            return Some(frame::report(self.status, &[]));
+        }
+        let sent = self.sent?;
+        let end = self.len.min(sent + MAX_DATA);
+        let mut flags = if sent == 0 { START } else { 0 };
+        if end == self.len {
+            flags |= END;
+        }
+        Some(frame::report(flags, &self.buf[sent..end]))
+    }
+
+    pub fn sent(&mut self) {
+        if self.status != 0 {
+            self.status = 0;
+            return;
+        }
+        if let Some(sent) = self.sent {
+            let end = self.len.min(sent + MAX_DATA);
+            self.sent = if end == self.len { None } else { Some(end) };
+        }
+    }
+
+    /// Drops the message going out and any status
+    pub fn clear(&mut self) {
+        self.sent = None;
+        self.status = 0;
+    }
+}
+
+impl Default for Sender {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,90 @@
+use chocos_channel::frame::{self, ACK, END, MAX_DATA, MAX_MESSAGE, NAK, REPORT_SIZE, START};
+use chocos_channel::{ChannelError, Receiver, Sender};
+
+fn reports(sender: &mut Sender) -> Vec<[u8; REPORT_SIZE]> {
+    std::iter::from_fn(|| {
+        let report = sender.next_report()?;
+        sender.sent();
+        Some(report)
+    })
+    .collect()
+}
+
+fn message(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + 1) as u8).collect()
+}
+
+#[test]
+fn round_trip() {
+    for len in [0, 1, MAX_DATA, MAX_DATA + 1, 3 * MAX_DATA, MAX_MESSAGE] {
+        let sent = message(len);
+        let mut sender = Sender::new();
+        sender.send(&sent).unwrap();
+        let reports = reports(&mut sender);
+        assert_eq!(reports.len(), len.max(1).div_ceil(MAX_DATA));
+        assert!(!sender.is_busy());
+
+        let mut receiver = Receiver::new();
+        for (i, report) in reports.iter().enumerate() {
+            assert_eq!(receiver.push(report), Ok(i == reports.len() - 1));
+        }
+        assert_eq!(receiver.message(), Some(&sent[..]));
+    }
+}
+
+#[test]
+fn one_message_at_a_time() {
+    let mut sender = Sender::new();
+    sender.send(b"first").unwrap();
+    assert_eq!(sender.send(b"second"), Err(ChannelError::Busy));
+    assert_eq!(sender.send(&message(MAX_MESSAGE + 1)), Err(ChannelError::TooLong));
+    reports(&mut sender);
+    sender.send(b"second").unwrap();
+
+    // a waiting message keeps the next one out until it is taken
+    let mut receiver = Receiver::new();
+    receiver.push(&frame::report(START | END, b"first")).unwrap();
+    assert_eq!(receiver.push(&frame::report(START | END, b"second")), Err(ChannelError::Busy));
+    assert_eq!(receiver.message(), Some(&b"first"[..]));
+    receiver.clear();
+    assert_eq!(receiver.message(), None);
+    assert_eq!(receiver.push(&frame::report(START | END, b"second")), Ok(true));
+}
+
+#[test]
+fn status_goes_first() {
+    let mut sender = Sender::new();
+    sender.send(&message(MAX_DATA + 1)).unwrap();
+    let first = sender.next_report().unwrap();
+    sender.sent();
+    sender.status(ACK);
+    sender.status(NAK);
+    let reports = reports(&mut sender);
+    assert_eq!(frame::parse(&first), Some((START, &message(MAX_DATA)[..])));
+    assert_eq!(frame::parse(&reports[0]), Some((ACK | NAK, &[][..])));
+    assert_eq!(frame::parse(&reports[1]).unwrap().0, END);
+    assert_eq!(reports.len(), 2);
+}
+
+#[test]
+fn bad_reports() {
+    let mut receiver = Receiver::new();
+    // data without a start
+    assert_eq!(receiver.push(&frame::report(END, b"x")), Err(ChannelError::Malformed));
+    let mut report = frame::report(START, b"");
+    report[1] = MAX_DATA as u8 + 1;
+    assert_eq!(receiver.push(&report), Err(ChannelError::Malformed));
+    assert_eq!(receiver.push(&[START]), Err(ChannelError::Malformed));
+
+    // too long drops the message, a new start begins the next one
+    let chunk = message(MAX_DATA);
+    let mut result = receiver.push(&frame::report(START, &chunk));
+    while result == Ok(false) {
+        result = receiver.push(&frame::report(0, &chunk));
+    }
+    assert_eq!(result, Err(ChannelError::TooLong));
+    assert_eq!(receiver.push(&frame::report(0, b"x")), Err(ChannelError::Malformed));
+    receiver.push(&frame::report(START, b"a")).unwrap();
+    assert_eq!(receiver.push(&frame::report(START | END, b"b")), Ok(true));
+    assert_eq!(receiver.message(), Some(&b"b"[..]));
+}
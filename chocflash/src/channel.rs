@@ -0,0 +1,82 @@
+// Host end of the vendor HID channel of the running OS, see
+// docs/os/channel.md. One message goes out at a time and is answered
+// with ACK once the app took it; messages from the app may come in
+// between and are kept until `recv`.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use chocos_channel::frame::{self, ACK, NAK};
+use chocos_channel::{ChannelError, Receiver, Sender};
+
+use crate::error::{Error, Result};
+use crate::transport::Transport;
+
+pub struct Channel<T: Transport> {
+    transport: T,
+    receiver: Receiver,
+    received: VecDeque<Vec<u8>>,
+}
+
+impl<T: Transport> Channel<T> {
+    pub fn new(transport: T) -> Self {
+        Channel { transport, receiver: Receiver::new(), received: VecDeque::new() }
+    }
+
+    // Sends `message` and waits until the app has taken it
+    pub fn send(&mut self, message: &[u8], timeout: Duration) -> Result<()> {
+        let mut sender = Sender::new();
+        sender.send(message).map_err(|e| match e {
+            ChannelError::TooLong => {
+                Error::Protocol(format!("message of {} bytes, at most {}", message.len(), frame::MAX_MESSAGE))
+            }
+            e => Error::Protocol(format!("{:?}", e)),
+        })?;
+        while let Some(report) = sender.next_report() {
+            self.transport.write_report(&report)?;
+            sender.sent();
+        }
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let flags = self.read(deadline)?;
+            if flags & NAK != 0 {
+                return Err(Error::Protocol("message dropped, no app has the channel open".into()));
+            }
+            if flags & ACK != 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    // The next message from the app
+    pub fn recv(&mut self, timeout: Duration) -> Result<Vec<u8>> {
+        let deadline = Instant::now() + timeout;
+        while self.received.is_empty() {
+            self.read(deadline)?;
+        }
+        Ok(self.received.pop_front().unwrap())
+    }
+
+    // Reads one report, keeps the messages it completes and returns its flags
+    fn read(&mut self, deadline: Instant) -> Result<u8> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        if timeout.is_zero() {
+            return Err(Error::Timeout);
+        }
+        let report = self.transport.read_report(timeout)?;
+        let (flags, _) = frame::parse(&report).ok_or_else(|| Error::Protocol("bad channel report".into()))?;
+        if flags & (ACK | NAK) != 0 {
+            return Ok(flags);
+        }
+        match self.receiver.push(&report) {
+            Ok(true) => {
+                self.received.push_back(self.receiver.message().unwrap().to_vec());
+                self.receiver.clear();
+            }
+            Ok(false) => {}
+            Err(e) => return Err(Error::Protocol(format!("channel message: {:?}", e))),
+        }
+        Ok(flags)
+    }
+}
@@ -0,0 +1,48 @@
+use std::{fmt, io};
+
+use crate::protocol::Status;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Timeout,
+    DeviceNotFound(String),
+    // the device answered something that is not a response to our request
+    Protocol(String),
+    // the device rejected a request
+    Status { cmd: u8, addr: u32, status: Status },
+    Image(String),
+    Key(String),
+    VerifyFailed { addr: u32, expected: u32, actual: u32 },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Timeout => write!(f, "device did not answer in time"),
+            Error::DeviceNotFound(what) => write!(f, "no {} found", what),
+            Error::Protocol(msg) => write!(f, "protocol error: {}", msg),
+            Error::Status { cmd, addr, status } => {
+                write!(f, "command {:#04x} at {:#010x} failed: {}", cmd, addr, status)
+            }
+            Error::Image(msg) => write!(f, "bad image: {}", msg),
+            Error::Key(msg) => write!(f, "bad key: {}", msg),
+            Error::VerifyFailed { addr, expected, actual } => write!(
+                f,
+                "verify failed at {:#010x}: expected crc {:#010x}, device has {:#010x}",
+                addr, expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
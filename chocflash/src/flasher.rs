@@ -0,0 +1,273 @@
+use std::time::Duration;
+
+use chocos_isp::image::{OsHeader, OS_HEADER_OFFSET, OS_HEADER_SIZE, OS_MAGIC};
+use chocos_isp::slots::{tally, SlotLayout};
+
+use crate::error::{Error, Result};
+use crate::image::{Image, Segment};
+use crate::protocol::{cmd, crc32, DeviceInfo, Request, Response, Status, MAX_DATA, PROTOCOL_VERSION};
+use crate::transport::Transport;
+
+// Flash layout, see docs/memory_layout.md
+pub const OS_ADDR: u32 = 0x0801_0000;
+pub const OS_END: u32 = 0x0802_0000;
+pub const USER_FLASH_START: u32 = 0x0802_0000;
+pub const USER_FLASH_END: u32 = 0x0806_0000;
+pub const OS_B_ADDR: u32 = 0x0806_0000;
+pub const OS_B_END: u32 = 0x0807_0000;
+pub const KEYMAP_ADDR: u32 = 0x0807_0000;
+pub const KEYMAP_END: u32 = 0x0807_0800;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+const CRC_RETRIES: u32 = 3;
+
+// The two OS slots, see chocos_isp::slots
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Slot {
+    A,
+    B,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    Os(Slot),
+    App,
+    Keymap,
+}
+
+impl Target {
+    pub fn region(self) -> (u32, u32) {
+        match self {
+            Target::Os(Slot::A) => (OS_ADDR, OS_END),
+            Target::Os(Slot::B) => (OS_B_ADDR, OS_B_END),
+            Target::App => (USER_FLASH_START, USER_FLASH_END),
+            Target::Keymap => (KEYMAP_ADDR, KEYMAP_END),
+        }
+    }
+
+    pub fn default_addr(self) -> u32 {
+        self.region().0
+    }
+}
+
+// Refuses images that would spill out of the region they are meant for,
+// so an app can't overwrite the OS and the other way round.
+pub fn check_region(image: &Image, target: Target) -> Result<()> {
+    let (start, end) = target.region();
+    match (image.start(), image.end()) {
+        (Some(s), Some(e)) if s >= start && e <= end => Ok(()),
+        (Some(s), Some(e)) => Err(Error::Image(format!(
+            "image spans {:#010x}..{:#010x}, outside of the {:?} region {:#010x}..{:#010x}",
+            s, e, target, start, end
+        ))),
+        _ => Err(Error::Image("image is empty".into())),
+    }
+}
+
+// What the bootloader knows about an OS slot. Only the header is read,
+// whether the whole image is intact is up to the bootloader.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BootSlot {
+    pub addr: u32,
+    pub header: Option<OsHeader>,
+    pub confirmed: bool,
+    pub attempts: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Erase,
+    Write,
+    Verify,
+}
+
+pub struct Flasher<T: Transport> {
+    transport: T,
+    seq: u8,
+    pub timeout: Duration,
+}
+
+impl<T: Transport> Flasher<T> {
+    pub fn new(transport: T) -> Self {
+        Flasher { transport, seq: 0, timeout: TIMEOUT }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    // Sends one request and waits for the response with the same sequence
+    // number. Late answers to earlier, timed out requests are skipped.
+    fn transact(&mut self, mut req: Request) -> Result<Response> {
+        self.seq = self.seq.wrapping_add(1);
+        req.seq = self.seq;
+        self.transport.write_report(&req.to_bytes())?;
+
+        loop {
+            let report = self.transport.read_report(self.timeout)?;
+            let res = Response::parse(&report).ok_or_else(|| Error::Protocol("short response".into()))?;
+            if res.seq != req.seq {
+                continue;
+            }
+            if res.cmd != req.cmd {
+                return Err(Error::Protocol(format!("response to {:#04x} for request {:#04x}", res.cmd, req.cmd)));
+            }
+            return Ok(res);
+        }
+    }
+
+    fn request(&mut self, req: Request) -> Result<Response> {
+        let (cmd, addr) = (req.cmd, req.addr);
+        let res = self.transact(req)?;
+        match res.status {
+            Status::Ok => Ok(res),
+            status => Err(Error::Status { cmd, addr, status }),
+        }
+    }
+
+    pub fn info(&mut self) -> Result<DeviceInfo> {
+        let res = self.request(Request::new(cmd::INFO, 0))?;
+        let info = DeviceInfo::parse(res.payload()).ok_or_else(|| Error::Protocol("short device info".into()))?;
+        if info.protocol_version != PROTOCOL_VERSION {
+            return Err(Error::Protocol(format!(
+                "bootloader speaks protocol {}, chocflash speaks {}",
+                info.protocol_version, PROTOCOL_VERSION
+            )));
+        }
+        Ok(info)
+    }
+
+    pub fn erase_page(&mut self, addr: u32) -> Result<()> {
+        self.request(Request::new(cmd::ERASE_PAGE, addr)).map(|_| ())
+    }
+
+    // One chunk of at most MAX_DATA bytes. A chunk damaged on the way is
+    // rejected before anything is programmed, so it is simply sent again.
+    pub fn write_chunk(&mut self, addr: u32, data: &[u8]) -> Result<()> {
+        let mut req = Request::new(cmd::WRITE, addr).with_data(data);
+        req.crc = crc32(data);
+
+        let mut attempt = 0;
+        loop {
+            match self.request(req.clone()) {
+                Err(Error::Status { status: Status::CrcMismatch, .. }) if attempt < CRC_RETRIES => attempt += 1,
+                result => return result.map(|_| ()),
+            }
+        }
+    }
+
+    pub fn read(&mut self, addr: u32, len: usize) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(len);
+        while out.len() < len {
+            let n = (len - out.len()).min(MAX_DATA);
+            let mut req = Request::new(cmd::READ, addr + out.len() as u32);
+            req.len = n as u8;
+            let res = self.request(req)?;
+            if res.payload().len() != n || crc32(res.payload()) != res.value {
+                return Err(Error::Protocol(format!("corrupt read at {:#010x}", res.addr)));
+            }
+            out.extend_from_slice(res.payload());
+        }
+        Ok(out)
+    }
+
+    pub fn verify(&mut self, addr: u32, data: &[u8]) -> Result<()> {
+        let expected = crc32(data);
+        let mut req = Request::new(cmd::VERIFY, addr).with_data(&(data.len() as u32).to_le_bytes());
+        req.crc = expected;
+
+        let res = self.transact(req)?;
+        match res.status {
+            Status::Ok => Ok(()),
+            Status::VerifyFailed => Err(Error::VerifyFailed { addr, expected, actual: res.value }),
+            status => Err(Error::Status { cmd: cmd::VERIFY, addr, status }),
+        }
+    }
+
+    pub fn boot_slots(&mut self) -> Result<Vec<BootSlot>> {
+        let layout = SlotLayout::STM32F103;
+        let page_size = self.info()?.page_size as usize;
+        let log = self.read(layout.log_page, page_size)?;
+
+        let mut slots = Vec::new();
+        for (slot, &addr) in layout.slots.iter().enumerate() {
+            let raw = self.read(addr, OS_HEADER_OFFSET + OS_HEADER_SIZE)?;
+            let header = OsHeader::parse(&raw).filter(|header| header.magic == OS_MAGIC);
+            let (confirmed, attempts) = header.map_or((false, 0), |header| tally(&log, slot, header.crc));
+            slots.push(BootSlot { addr, header, confirmed, attempts });
+        }
+        Ok(slots)
+    }
+
+    pub fn reset(&mut self) -> Result<()> {
+        self.request(Request::new(cmd::RESET, 0)).map(|_| ())
+    }
+
+    // Erases every page the image touches, writes it and checks it.
+    pub fn flash(&mut self, image: &Image, verify: bool, progress: &mut dyn FnMut(Stage, usize, usize)) -> Result<()> {
+        let info = self.info()?;
+        let segments: Vec<Segment> = image.segments.iter().map(align_segment).collect();
+
+        for seg in &segments {
+            if seg.addr < info.writable_start || seg.end() > info.flash_end() {
+                return Err(Error::Image(format!(
+                    "{:#010x}..{:#010x} is not writable on this device",
+                    seg.addr,
+                    seg.end()
+                )));
+            }
+        }
+
+        let mut pages: Vec<u32> = segments
+            .iter()
+            .flat_map(|seg| {
+                let first = seg.addr / info.page_size;
+                let last = (seg.end() - 1) / info.page_size;
+                (first..=last).map(move |page| page * info.page_size)
+            })
+            .collect();
+        pages.dedup();
+
+        for (i, &page) in pages.iter().enumerate() {
+            self.erase_page(page)?;
+            progress(Stage::Erase, i + 1, pages.len());
+        }
+
+        let total: usize = segments.iter().map(|s| s.data.len()).sum();
+        let mut done = 0;
+        for seg in &segments {
+            for (i, chunk) in seg.data.chunks(MAX_DATA).enumerate() {
+                self.write_chunk(seg.addr + (i * MAX_DATA) as u32, chunk)?;
+                done += chunk.len();
+                progress(Stage::Write, done, total);
+            }
+        }
+
+        if verify {
+            self.verify_image(&Image { segments }, progress)?;
+        }
+        Ok(())
+    }
+
+    pub fn verify_image(&mut self, image: &Image, progress: &mut dyn FnMut(Stage, usize, usize)) -> Result<()> {
+        for (i, seg) in image.segments.iter().enumerate() {
+            self.verify(seg.addr, &seg.data)?;
+            progress(Stage::Verify, i + 1, image.segments.len());
+        }
+        Ok(())
+    }
+}
+
+// Flash is programmed in half words. Odd edges are padded with 0xFF,
+// which leaves the erased byte next to them untouched.
+fn align_segment(seg: &Segment) -> Segment {
+    let mut seg = seg.clone();
+    if !seg.addr.is_multiple_of(2) {
+        seg.addr -= 1;
+        seg.data.insert(0, 0xFF);
+    }
+    if !seg.data.len().is_multiple_of(2) {
+        seg.data.push(0xFF);
+    }
+    seg
+}
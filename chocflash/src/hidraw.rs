@@ -0,0 +1,103 @@
+// Linux hidraw transport. Devices are found through sysfs, so there is no
+// dependency on libudev or libusb; only read/write access to the
+// /dev/hidrawN node is needed (see docs/bootloader/protocol.md for a udev rule).
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+use crate::protocol::REPORT_SIZE;
+use crate::transport::Transport;
+
+pub struct HidrawDevice {
+    file: File,
+    pub path: PathBuf,
+}
+
+impl HidrawDevice {
+    pub fn open(vid: u16, pid: u16, product: &str, interface: Option<u8>) -> Result<HidrawDevice> {
+        let path = find(vid, pid, product, interface)?
+            .ok_or_else(|| Error::DeviceNotFound(format!("\"{}\" ({:04x}:{:04x})", product, vid, pid)))?;
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+        Ok(HidrawDevice { file, path })
+    }
+}
+
+// Looks for a hidraw node whose HID_ID and HID_NAME match. HID_NAME is
+// "<manufacturer> <product>", so the product is matched as a suffix. The
+// OS has more than one HID interface, `interface` picks one of them.
+pub fn find(vid: u16, pid: u16, product: &str, interface: Option<u8>) -> Result<Option<PathBuf>> {
+    let id = format!("0003:{:08X}:{:08X}", vid, pid);
+    let entries = match fs::read_dir("/sys/class/hidraw") {
+        Ok(entries) => entries,
+        Err(_) => return Ok(None),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let uevent = match fs::read_to_string(entry.path().join("device/uevent")) {
+            Ok(uevent) => uevent,
+            Err(_) => continue,
+        };
+
+        let mut id_matches = false;
+        let mut name_matches = false;
+        for line in uevent.lines() {
+            if let Some(value) = line.strip_prefix("HID_ID=") {
+                id_matches = value.eq_ignore_ascii_case(&id);
+            } else if let Some(value) = line.strip_prefix("HID_NAME=") {
+                name_matches = value == product || value.ends_with(&format!(" {}", product));
+            }
+        }
+
+        let interface_matches = match interface {
+            Some(number) => interface_number(&entry.path().join("device")) == Some(number),
+            None => true,
+        };
+
+        if id_matches && name_matches && interface_matches {
+            return Ok(Some(PathBuf::from("/dev").join(entry.file_name())));
+        }
+    }
+    Ok(None)
+}
+
+// The HID device sits below its USB interface, whose sysfs name ends in
+// "<config>.<interface>", e.g. 1-1:1.0
+fn interface_number(device: &Path) -> Option<u8> {
+    let device = fs::canonicalize(device).ok()?;
+    let name = device.parent()?.file_name()?.to_str()?;
+    name.rsplit('.').next()?.parse().ok()
+}
+
+impl Transport for HidrawDevice {
+    fn write_report(&mut self, report: &[u8]) -> Result<()> {
+        // hidraw wants the report ID first, 0 for devices without IDs
+        let mut buf = Vec::with_capacity(report.len() + 1);
+        buf.push(0);
+        buf.extend_from_slice(report);
+        self.file.write_all(&buf)?;
+        Ok(())
+    }
+
+    fn read_report(&mut self, timeout: Duration) -> Result<[u8; REPORT_SIZE]> {
+        let mut pfd = libc::pollfd { fd: self.file.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        let ret = unsafe { libc::poll(&mut pfd, 1, timeout.as_millis() as libc::c_int) };
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        if ret == 0 {
+            return Err(Error::Timeout);
+        }
+
+        let mut buf = [0u8; REPORT_SIZE];
+        let n = self.file.read(&mut buf)?;
+        if n != REPORT_SIZE {
+            return Err(Error::Protocol(format!("short report of {} bytes", n)));
+        }
+        Ok(buf)
+    }
+}
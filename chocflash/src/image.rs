@@ -0,0 +1,253 @@
+// Firmware images: ELF, Intel HEX or raw binary, reduced to a list of
+// contiguous segments at absolute flash addresses.
+
+use std::path::Path;
+
+use chocos_abi::APP_MAGIC;
+use chocos_isp::image::{OsHeader, OS_MAGIC};
+use chocos_isp::sign::{self, SECRET_KEY_SIZE};
+use chocos_keyboard::Keymap;
+
+use crate::error::{Error, Result};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub addr: u32,
+    pub data: Vec<u8>,
+}
+
+impl Segment {
+    pub fn end(&self) -> u32 {
+        self.addr + self.data.len() as u32
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Image {
+    pub segments: Vec<Segment>,
+}
+
+impl Image {
+    pub fn start(&self) -> Option<u32> {
+        self.segments.first().map(|s| s.addr)
+    }
+
+    pub fn end(&self) -> Option<u32> {
+        self.segments.last().map(|s| s.end())
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|s| s.data.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Sorts the segments and joins the ones that touch. Overlapping
+    // segments are an error, they can't both end up in flash.
+    fn add(&mut self, addr: u32, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        if addr.checked_add(data.len() as u32).is_none() {
+            return Err(Error::Image(format!("segment at {:#010x} wraps around", addr)));
+        }
+        self.segments.push(Segment { addr, data: data.to_vec() });
+        self.segments.sort_by_key(|s| s.addr);
+
+        let mut merged: Vec<Segment> = Vec::with_capacity(self.segments.len());
+        for seg in self.segments.drain(..) {
+            match merged.last_mut() {
+                Some(last) if last.end() > seg.addr => {
+                    return Err(Error::Image(format!("segments overlap at {:#010x}", seg.addr)));
+                }
+                Some(last) if last.end() == seg.addr => last.data.extend_from_slice(&seg.data),
+                _ => merged.push(seg),
+            }
+        }
+        self.segments = merged;
+        Ok(())
+    }
+}
+
+pub fn load(path: &Path, base: u32) -> Result<Image> {
+    let bytes = std::fs::read(path)?;
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    parse(&bytes, ext, base)
+}
+
+// `base` is only used for raw binaries, ELF and HEX files carry their addresses.
+pub fn parse(bytes: &[u8], ext: &str, base: u32) -> Result<Image> {
+    if bytes.starts_with(b"\x7fELF") {
+        parse_elf(bytes)
+    } else if ext.eq_ignore_ascii_case("hex") || ext.eq_ignore_ascii_case("ihex") {
+        parse_hex(bytes)
+    } else {
+        let mut image = Image::default();
+        image.add(base, bytes)?;
+        Ok(image)
+    }
+}
+
+// Loads the PT_LOAD program headers at their physical (load) address, which
+// is where initialised .data lives in flash as well.
+pub fn parse_elf(bytes: &[u8]) -> Result<Image> {
+    const PT_LOAD: u32 = 1;
+
+    let err = |msg: &str| Error::Image(format!("elf: {}", msg));
+    if bytes.len() < 52 || &bytes[0..4] != b"\x7fELF" {
+        return Err(err("not an ELF file"));
+    }
+    if bytes[4] != 1 || bytes[5] != 1 {
+        return Err(err("only 32 bit little endian files are supported"));
+    }
+
+    let u16_at = |off: usize| u16::from_le_bytes([bytes[off], bytes[off + 1]]) as usize;
+    let u32_at = |off: usize| u32::from_le_bytes([bytes[off], bytes[off + 1], bytes[off + 2], bytes[off + 3]]);
+
+    let phoff = u32_at(0x1C) as usize;
+    let phentsize = u16_at(0x2A);
+    let phnum = u16_at(0x2C);
+    if phentsize < 32 || phoff + phentsize * phnum > bytes.len() {
+        return Err(err("truncated program header table"));
+    }
+
+    let mut image = Image::default();
+    for i in 0..phnum {
+        let ph = phoff + i * phentsize;
+        if u32_at(ph) != PT_LOAD {
+            continue;
+        }
+        let offset = u32_at(ph + 4) as usize;
+        let paddr = u32_at(ph + 12);
+        let filesz = u32_at(ph + 16) as usize;
+        if filesz == 0 {
+            continue;
+        }
+        let data = bytes.get(offset..offset + filesz).ok_or_else(|| err("segment outside the file"))?;
+        image.add(paddr, data)?;
+    }
+
+    if image.is_empty() {
+        return Err(err("no loadable segments"));
+    }
+    Ok(image)
+}
+
+pub fn parse_hex(bytes: &[u8]) -> Result<Image> {
+    let text = std::str::from_utf8(bytes).map_err(|_| Error::Image("hex: not a text file".into()))?;
+    let mut image = Image::default();
+    let mut upper: u32 = 0;
+
+    // collect runs of consecutive data records before adding them
+    let mut run_addr = 0u32;
+    let mut run: Vec<u8> = Vec::new();
+
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let err = |msg: &str| Error::Image(format!("hex line {}: {}", n + 1, msg));
+
+        let hex = line.strip_prefix(':').ok_or_else(|| err("missing ':'"))?;
+        if hex.len() % 2 != 0 || hex.len() < 10 {
+            return Err(err("bad record length"));
+        }
+        let record = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<std::result::Result<Vec<u8>, _>>()
+            .map_err(|_| err("invalid hex digit"))?;
+
+        let len = record[0] as usize;
+        if record.len() != len + 5 {
+            return Err(err("byte count does not match"));
+        }
+        if record.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return Err(err("checksum mismatch"));
+        }
+
+        let offset = u16::from_be_bytes([record[1], record[2]]) as u32;
+        let data = &record[4..4 + len];
+        match record[3] {
+            0x00 => {
+                let addr = upper + offset;
+                if run.is_empty() || run_addr + run.len() as u32 != addr {
+                    image.add(run_addr, &run)?;
+                    run.clear();
+                    run_addr = addr;
+                }
+                run.extend_from_slice(data);
+            }
+            0x01 => break,
+            0x02 if len == 2 => upper = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4,
+            0x04 if len == 2 => upper = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16,
+            // start address records don't matter for flashing
+            0x03 | 0x05 => {}
+            _ => return Err(err("unsupported record")),
+        }
+    }
+    image.add(run_addr, &run)?;
+
+    if image.is_empty() {
+        return Err(Error::Image("hex: no data records".into()));
+    }
+    Ok(image)
+}
+
+// The image as one piece, gaps between segments read as erased flash
+fn flatten(image: &Image) -> Result<(u32, Vec<u8>)> {
+    let (start, end) = match (image.start(), image.end()) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return Err(Error::Image("image is empty".into())),
+    };
+    let mut data = vec![0xFF; (end - start) as usize];
+    for seg in &image.segments {
+        let off = (seg.addr - start) as usize;
+        data[off..off + seg.data.len()].copy_from_slice(&seg.data);
+    }
+    Ok((start, data))
+}
+
+// Fills in the CRC of the OS image header, see chocos_isp::image.
+pub fn seal_os(image: &Image, base: u32) -> Result<Image> {
+    let (start, mut data) = flatten(image)?;
+    if start != base {
+        return Err(Error::Image(format!("OS image starts at {:#010x}, not at {:#010x}", start, base)));
+    }
+
+    let header = chocos_isp::image::seal_os(&mut data).map_err(|e| Error::Image(e.to_string()))?;
+    data.truncate(header.length as usize);
+    Ok(Image { segments: vec![Segment { addr: start, data }] })
+}
+
+// Refuses a keymap the OS would not take, see chocos_keyboard::keymap
+pub fn check_keymap(image: &Image) -> Result<()> {
+    let (_, data) = flatten(image)?;
+    Keymap::parse(&data).map_err(|e| Error::Image(format!("not a valid keymap: {:?}", e)))?;
+    Ok(())
+}
+
+// Appends the signature trailer, see chocos_isp::sign. An OS image is
+// signed up to the length in its header, so it has to be sealed first;
+// an app up to the end address in its app header.
+pub fn sign(image: &Image, secret: &[u8; SECRET_KEY_SIZE]) -> Result<Image> {
+    let (start, mut data) = flatten(image)?;
+    let word = |off: usize| data.get(off..off + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+
+    let len = match OsHeader::parse(&data) {
+        Some(header) if header.magic == OS_MAGIC => header.length as usize,
+        _ if word(0) == Some(APP_MAGIC) => word(12).unwrap_or(0).wrapping_sub(start) as usize,
+        _ => return Err(Error::Image("neither an OS nor an app image, nothing to sign".into())),
+    };
+    if len == 0 || len > data.len() {
+        return Err(Error::Image(format!("header says the image is {} bytes, the file has {}", len, data.len())));
+    }
+
+    data.truncate(len);
+    let trailer = sign::sign(&data, secret).ok_or_else(|| Error::Key("the secret key is all zeros".into()))?;
+    data.extend_from_slice(&trailer);
+    Ok(Image { segments: vec![Segment { addr: start, data }] })
+}
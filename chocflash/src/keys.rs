@@ -0,0 +1,49 @@
+// Signing keys, stored as one line of hex. The secret key is the 32 byte
+// Ed25519 seed, the public key goes into the bootloader and OS builds.
+
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use chocos_isp::sign::{public_key, PUBLIC_KEY_SIZE, SECRET_KEY_SIZE};
+
+use crate::error::{Error, Result};
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex<const N: usize>(text: &str) -> Option<[u8; N]> {
+    let text = text.trim();
+    if text.len() != 2 * N || !text.is_ascii() {
+        return None;
+    }
+    let mut out = [0; N];
+    for (i, b) in out.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&text[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(out)
+}
+
+pub fn read_secret(path: &Path) -> Result<[u8; SECRET_KEY_SIZE]> {
+    let text = std::fs::read_to_string(path)?;
+    from_hex(&text).ok_or_else(|| Error::Key(format!("{} is not a secret key", path.display())))
+}
+
+// Writes a new key pair. An existing secret key is never overwritten,
+// everything signed with it would have to be signed again.
+pub fn generate(secret_path: &Path, public_path: &Path) -> Result<[u8; PUBLIC_KEY_SIZE]> {
+    let mut secret = [0; SECRET_KEY_SIZE];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut secret)?;
+    let public = public_key(&secret).ok_or_else(|| Error::Key("got an all zero key".into()))?;
+
+    let mut file = OpenOptions::new().write(true).create_new(true).open(secret_path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    writeln!(file, "{}", to_hex(&secret))?;
+    std::fs::write(public_path, format!("{}\n", to_hex(&public)))?;
+    Ok(public)
+}
@@ -0,0 +1,21 @@
+//! Host side of the ChocOS bootloader flash mode.
+//!
+//! `Flasher` drives the protocol over any `Transport`: the hidraw device on
+//! Linux, or `sim::SimulatedBootloader` for testing without a board.
+//! `Channel` talks to an app over the vendor channel of the running OS.
+
+pub mod channel;
+pub mod error;
+pub mod flasher;
+#[cfg(target_os = "linux")]
+pub mod hidraw;
+pub mod image;
+pub mod keys;
+pub mod protocol;
+pub mod sim;
+pub mod transport;
+
+pub use channel::Channel;
+pub use error::{Error, Result};
+pub use flasher::{BootSlot, Flasher, Slot, Stage, Target};
+pub use transport::Transport;
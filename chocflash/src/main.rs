@@ -0,0 +1,309 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use chocflash::flasher::check_region;
+use chocflash::hidraw::{self, HidrawDevice};
+use chocflash::transport::{
+    CHANNEL_INTERFACE, KEYBOARD_INTERFACE, OS_PRODUCT, PID, REBOOT_REQUEST, RECOVERY_PRODUCT, RECOVERY_REQUEST, VID,
+};
+use chocflash::{image, keys, Channel, Error, Flasher, Result, Slot, Stage, Target, Transport};
+use chocos_isp::image::OsHeader;
+use chocos_isp::slots::MAX_ATTEMPTS;
+
+const USAGE: &str = "\
+usage: chocflash <command> [options]
+
+commands:
+    info                      show bootloader and flash layout
+    flash <file>              erase, write and verify an image, then reset
+    verify <file>             compare an image with the device
+    read <addr> <len> <out>   dump flash to a file
+    seal <file> <out>         fill in the CRC of an OS image, write it as .bin
+    sign <file> <out>         seal (OS) and sign an image with --key, write it as .bin
+    keygen <secret> <public>  create a signing key pair
+    slots                     show the OS images in both slots and their boot state
+    reset                     leave flash mode and boot the OS
+    recover                   ask the running OS to reboot into flash mode
+    reboot                    ask the running OS to restart
+    channel <message>         send a message to the app on the OS channel, print its replies
+
+options:
+    --os                      the image is the OS for slot a (at 0x08010000)
+    --slot <a|b>              the image is the OS for slot a or b (b at 0x08060000)
+    --app                     the image is a user app (default, from 0x08020000)
+    --keymap                  the image is a keymap (at 0x08070000), see docs/os/keyboard.md
+    --addr <addr>             load address of a .bin file
+    --no-verify               skip verification after flashing
+    --no-reset                stay in flash mode after flashing
+    --recover                 reboot a running OS into flash mode first
+    --key <secret>            sign the image before flashing or verifying it
+
+<file> is an ELF, Intel HEX (.hex) or raw binary file. OS images are
+sealed before they are flashed or verified.";
+
+#[derive(Clone)]
+struct Options {
+    command: String,
+    positional: Vec<String>,
+    target: Target,
+    addr: Option<u32>,
+    verify: bool,
+    reset: bool,
+    recover: bool,
+    key: Option<PathBuf>,
+}
+
+fn parse_args() -> std::result::Result<Options, String> {
+    let mut args = std::env::args().skip(1);
+    let command = args.next().ok_or_else(|| "missing command".to_string())?;
+    let mut opts = Options {
+        command,
+        positional: Vec::new(),
+        target: Target::App,
+        addr: None,
+        verify: true,
+        reset: true,
+        recover: false,
+        key: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--os" => opts.target = Target::Os(Slot::A),
+            "--slot" => {
+                let value = args.next().ok_or("--slot needs a value")?;
+                opts.target = match value.as_str() {
+                    "a" | "A" => Target::Os(Slot::A),
+                    "b" | "B" => Target::Os(Slot::B),
+                    _ => return Err(format!("unknown slot {}", value)),
+                };
+            }
+            "--app" => opts.target = Target::App,
+            "--keymap" => opts.target = Target::Keymap,
+            "--addr" => {
+                let value = args.next().ok_or("--addr needs a value")?;
+                opts.addr = Some(parse_u32(&value)?);
+            }
+            "--no-verify" => opts.verify = false,
+            "--no-reset" => opts.reset = false,
+            "--recover" => opts.recover = true,
+            "--key" => opts.key = Some(args.next().ok_or("--key needs a file")?.into()),
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => opts.positional.push(arg),
+        }
+    }
+    Ok(opts)
+}
+
+fn parse_u32(s: &str) -> std::result::Result<u32, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number {}", s))
+}
+
+fn progress(stage: Stage, done: usize, total: usize) {
+    let name = match stage {
+        Stage::Erase => "erase ",
+        Stage::Write => "write ",
+        Stage::Verify => "verify",
+    };
+    eprint!("\r{} {:>7}/{:<7}", name, done, total);
+    if done == total {
+        eprintln!();
+    }
+    let _ = std::io::stderr().flush();
+}
+
+// Sends the recovery request to the running OS and waits for the
+// bootloader to come up in flash mode.
+fn recover() -> Result<()> {
+    let mut os = HidrawDevice::open(VID, PID, OS_PRODUCT, Some(KEYBOARD_INTERFACE))?;
+    os.write_report(&RECOVERY_REQUEST)?;
+    eprintln!("requested flash mode from {}", os.path.display());
+    drop(os);
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if hidraw::find(VID, PID, RECOVERY_PRODUCT, None)?.is_some() {
+            return Ok(());
+        }
+        sleep(Duration::from_millis(200));
+    }
+    Err(Error::DeviceNotFound(format!("\"{}\" after the recovery request", RECOVERY_PRODUCT)))
+}
+
+fn open(opts: &Options) -> Result<Flasher<HidrawDevice>> {
+    if opts.recover {
+        recover()?;
+    }
+    Ok(Flasher::new(HidrawDevice::open(VID, PID, RECOVERY_PRODUCT, None)?))
+}
+
+fn load(opts: &Options) -> Result<image::Image> {
+    let path = opts.positional.first().ok_or_else(|| Error::Image("missing <file>".into()))?;
+    let image = image::load(Path::new(path), opts.addr.unwrap_or_else(|| opts.target.default_addr()))?;
+    let image = match opts.target {
+        Target::Os(_) => image::seal_os(&image, opts.target.default_addr())?,
+        Target::App => image,
+        Target::Keymap => {
+            image::check_keymap(&image)?;
+            image
+        }
+    };
+    let image = match &opts.key {
+        Some(key) => image::sign(&image, &keys::read_secret(key)?)?,
+        None => image,
+    };
+    check_region(&image, opts.target)?;
+    Ok(image)
+}
+
+fn run(opts: &Options) -> Result<()> {
+    match opts.command.as_str() {
+        "info" => {
+            let info = open(opts)?.info()?;
+            println!("protocol        {}", info.protocol_version);
+            println!("bootloader      {}.{}", info.bootloader_version.0, info.bootloader_version.1);
+            println!("flash           {:#010x}..{:#010x} ({}K)", info.flash_base, info.flash_end(), info.flash_size / 1024);
+            println!("page size       {}", info.page_size);
+            println!("writable from   {:#010x}", info.writable_start);
+            println!("os address      {:#010x}", info.os_addr);
+        }
+        "flash" => {
+            let image = load(opts)?;
+            let mut flasher = open(opts)?;
+            println!(
+                "flashing {} bytes to {:#010x}..{:#010x}",
+                image.len(),
+                image.start().unwrap_or(0),
+                image.end().unwrap_or(0)
+            );
+            flasher.flash(&image, opts.verify, &mut progress)?;
+            if opts.reset {
+                flasher.reset()?;
+            }
+        }
+        "verify" => {
+            let image = load(opts)?;
+            open(opts)?.verify_image(&image, &mut progress)?;
+            println!("ok");
+        }
+        "read" => {
+            let [addr, len, out] = match opts.positional.as_slice() {
+                [addr, len, out] => [addr, len, out],
+                _ => return Err(Error::Image("read needs <addr> <len> <out>".into())),
+            };
+            let addr = parse_u32(addr).map_err(Error::Image)?;
+            let len = parse_u32(len).map_err(Error::Image)?;
+            let data = open(opts)?.read(addr, len as usize)?;
+            std::fs::write(PathBuf::from(out), data)?;
+        }
+        "seal" => {
+            let out = opts.positional.get(1).ok_or_else(|| Error::Image("seal needs <file> <out>".into()))?;
+            let target = match opts.target {
+                Target::App | Target::Keymap => Target::Os(Slot::A),
+                os => os,
+            };
+            let image = load(&Options { target, ..opts.clone() })?;
+            std::fs::write(out, &image.segments[0].data)?;
+            let header = OsHeader::parse(&image.segments[0].data).unwrap();
+            println!("sealed {} bytes, crc {:#010x}", header.length, header.crc);
+        }
+        "slots" => {
+            for (slot, state) in open(opts)?.boot_slots()?.iter().enumerate() {
+                let name = if slot == 0 { 'a' } else { 'b' };
+                let header = match state.header {
+                    Some(header) => header,
+                    None => {
+                        println!("slot {}  {:#010x}  empty", name, state.addr);
+                        continue;
+                    }
+                };
+                let boot = if state.confirmed {
+                    "confirmed".to_string()
+                } else if state.attempts >= MAX_ATTEMPTS {
+                    "failed, rolled back".to_string()
+                } else {
+                    format!("on trial, booted {} of {} times", state.attempts, MAX_ATTEMPTS)
+                };
+                println!(
+                    "slot {}  {:#010x}  version {}.{}.{}  crc {:#010x}  {}",
+                    name,
+                    state.addr,
+                    header.version >> 16,
+                    (header.version >> 8) & 0xFF,
+                    header.version & 0xFF,
+                    header.crc,
+                    boot
+                );
+            }
+        }
+        "sign" => {
+            let out = opts.positional.get(1).ok_or_else(|| Error::Image("sign needs <file> <out>".into()))?;
+            if opts.key.is_none() {
+                return Err(Error::Key("sign needs --key <secret>".into()));
+            }
+            let image = load(opts)?;
+            std::fs::write(out, &image.segments[0].data)?;
+            println!("signed {} bytes at {:#010x}", image.len(), image.start().unwrap_or(0));
+        }
+        "keygen" => {
+            let [secret, public] = match opts.positional.as_slice() {
+                [secret, public] => [secret, public],
+                _ => return Err(Error::Key("keygen needs <secret> <public>".into())),
+            };
+            let key = keys::generate(Path::new(secret), Path::new(public))?;
+            println!("public key {}", keys::to_hex(&key));
+            println!("keep {} private, build the bootloader and OS with {}", secret, public);
+        }
+        "reset" => open(opts)?.reset()?,
+        "recover" => recover()?,
+        "reboot" => {
+            let mut os = HidrawDevice::open(VID, PID, OS_PRODUCT, Some(KEYBOARD_INTERFACE))?;
+            os.write_report(&REBOOT_REQUEST)?;
+            eprintln!("requested a restart from {}", os.path.display());
+        }
+        "channel" => {
+            let message = opts.positional.first().ok_or_else(|| Error::Protocol("channel needs <message>".into()))?;
+            let mut channel = Channel::new(HidrawDevice::open(VID, PID, OS_PRODUCT, Some(CHANNEL_INTERFACE))?);
+            channel.send(message.as_bytes(), Duration::from_secs(5))?;
+            // whatever the app answers within a second
+            loop {
+                match channel.recv(Duration::from_secs(1)) {
+                    Ok(reply) => println!("{}", String::from_utf8_lossy(&reply)),
+                    Err(Error::Timeout) => break,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        other => {
+            eprintln!("unknown command {}\n\n{}", other, USAGE);
+            exit(2);
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let opts = match parse_args() {
+        Ok(opts) => opts,
+        Err(msg) => {
+            if !msg.is_empty() {
+                eprintln!("{}\n", msg);
+            }
+            eprintln!("{}", USAGE);
+            exit(2);
+        }
+    };
+
+    if let Err(e) = run(&opts) {
+        eprintln!("error: {}", e);
+        exit(1);
+    }
+}
@@ -0,0 +1,5 @@
+// The protocol types are shared with the bootloader, see the chocos-isp crate
+// and docs/bootloader/protocol.md.
+
+pub use chocos_isp::crc32;
+pub use chocos_isp::protocol::*;
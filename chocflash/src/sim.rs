@@ -0,0 +1,98 @@
+// In-process bootloader for running chocflash without hardware. It is the
+// bootloader's own protocol core from chocos-isp over an in-memory flash
+// model, which follows the rules of the real controller: 2K erase pages,
+// half word programming that only works on erased cells, and protected
+// bootloader pages.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use chocos_isp::image::Policy;
+use chocos_isp::slots::{self, SlotLayout};
+use chocos_isp::{Action, Flash, Isp, Layout, MemFlash, ReportIo};
+
+use crate::error::{Error, Result};
+use crate::protocol::{cmd, HEADER_SIZE, REPORT_SIZE};
+use crate::transport::Transport;
+
+pub struct SimulatedBootloader {
+    isp: Isp<MemFlash<Vec<u8>>>,
+    pub resets: u32,
+    // corrupt the payload of the next n WRITE requests on the way in
+    pub corrupt_writes: u32,
+    io: Queues,
+}
+
+#[derive(Default)]
+struct Queues {
+    requests: VecDeque<Vec<u8>>,
+    responses: VecDeque<[u8; REPORT_SIZE]>,
+}
+
+impl ReportIo for Queues {
+    fn read_report(&mut self, buf: &mut [u8; REPORT_SIZE]) -> Option<usize> {
+        let report = self.requests.pop_front()?;
+        let n = report.len().min(REPORT_SIZE);
+        buf[..n].copy_from_slice(&report[..n]);
+        Some(n)
+    }
+
+    fn write_report(&mut self, report: &[u8; REPORT_SIZE]) -> bool {
+        self.responses.push_back(*report);
+        true
+    }
+}
+
+impl Default for SimulatedBootloader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimulatedBootloader {
+    pub fn new() -> Self {
+        let layout = Layout::STM32F103;
+        SimulatedBootloader {
+            isp: Isp::new(MemFlash::new(layout, vec![0; layout.size as usize]), (0, 1)),
+            resets: 0,
+            corrupt_writes: 0,
+            io: Queues::default(),
+        }
+    }
+
+    pub fn slice(&self, addr: u32, len: usize) -> &[u8] {
+        self.isp.flash().read(addr, len)
+    }
+
+    pub fn flash_mut(&mut self) -> &mut MemFlash<Vec<u8>> {
+        self.isp.flash_mut()
+    }
+
+    // What the bootloader does after a reset: picks the OS slot to start
+    // and counts the attempt if the image is on trial.
+    pub fn boot(&mut self, policy: &Policy) -> Option<u32> {
+        slots::prepare_boot(self.isp.flash_mut(), &SlotLayout::STM32F103, policy)
+    }
+}
+
+impl Transport for SimulatedBootloader {
+    fn write_report(&mut self, report: &[u8]) -> Result<()> {
+        let mut report = report.to_vec();
+        if report.first() == Some(&cmd::WRITE) && report.len() > HEADER_SIZE && self.corrupt_writes > 0 {
+            self.corrupt_writes -= 1;
+            report[HEADER_SIZE] ^= 0xFF;
+        }
+        self.io.requests.push_back(report);
+
+        while !self.io.requests.is_empty() {
+            if self.isp.poll(&mut self.io) == Action::Reset {
+                self.resets += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_report(&mut self, _timeout: Duration) -> Result<[u8; REPORT_SIZE]> {
+        self.io.responses.pop_front().ok_or(Error::Timeout)
+    }
+}
@@ -0,0 +1,39 @@
+use std::time::Duration;
+
+use crate::error::Result;
+use crate::protocol::REPORT_SIZE;
+
+// Built with the same CHOCOS_USB_* variables as the firmware, see
+// chocos_isp::usb
+pub use chocos_isp::usb::{PID, RECOVERY_PRODUCT, VID};
+pub use chocos_isp::usb::PRODUCT as OS_PRODUCT;
+
+// HID interfaces of the running OS: the keyboard, which also takes the
+// requests below, and the vendor channel (docs/os/channel.md)
+pub const KEYBOARD_INTERFACE: u8 = 0;
+pub const CHANNEL_INTERFACE: u8 = 1;
+
+// Output report the running OS takes as a request to reboot into flash
+// mode. The keyboard's own output report is its report ID and the LED
+// byte, so a full 8 byte report can't be mistaken for one. Matches os/src/usb_hid.rs.
+pub const RECOVERY_REQUEST: [u8; 8] = *b"CHOCBOOT";
+// The same for a plain restart of the OS
+pub const REBOOT_REQUEST: [u8; 8] = *b"CHOCRSET";
+
+// One HID report in each direction. Everything above this, the protocol
+// and the flashing logic, runs the same against real hardware and the
+// simulated bootloader in `sim`.
+pub trait Transport {
+    fn write_report(&mut self, report: &[u8]) -> Result<()>;
+    fn read_report(&mut self, timeout: Duration) -> Result<[u8; REPORT_SIZE]>;
+}
+
+impl<T: Transport + ?Sized> Transport for &mut T {
+    fn write_report(&mut self, report: &[u8]) -> Result<()> {
+        (**self).write_report(report)
+    }
+
+    fn read_report(&mut self, timeout: Duration) -> Result<[u8; REPORT_SIZE]> {
+        (**self).read_report(timeout)
+    }
+}
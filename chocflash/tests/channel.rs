@@ -0,0 +1,77 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use chocflash::protocol::REPORT_SIZE;
+use chocflash::{Channel, Error, Result, Transport};
+use chocos_channel::frame::{ACK, END, MAX_DATA, MAX_MESSAGE, NAK};
+use chocos_channel::{Receiver, Sender};
+
+// The OS end with an app that answers every message with it in upper case
+// twice, or with no app at all
+struct EchoApp {
+    open: bool,
+    receiver: Receiver,
+    reports: VecDeque<[u8; REPORT_SIZE]>,
+}
+
+impl EchoApp {
+    fn new(open: bool) -> Self {
+        EchoApp { open, receiver: Receiver::new(), reports: VecDeque::new() }
+    }
+
+    fn queue(&mut self, sender: &mut Sender) {
+        while let Some(report) = sender.next_report() {
+            self.reports.push_back(report);
+            sender.sent();
+        }
+    }
+}
+
+impl Transport for EchoApp {
+    fn write_report(&mut self, report: &[u8]) -> Result<()> {
+        let mut sender = Sender::new();
+        if !self.open {
+            if report[0] & END != 0 {
+                sender.status(NAK);
+                self.queue(&mut sender);
+            }
+            return Ok(());
+        }
+        if self.receiver.push(report) == Ok(true) {
+            let reply = self.receiver.message().unwrap().to_ascii_uppercase();
+            self.receiver.clear();
+            sender.status(ACK);
+            self.queue(&mut sender);
+            for _ in 0..2 {
+                sender.send(&reply).unwrap();
+                self.queue(&mut sender);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_report(&mut self, _timeout: Duration) -> Result<[u8; REPORT_SIZE]> {
+        self.reports.pop_front().ok_or(Error::Timeout)
+    }
+}
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+#[test]
+fn send_and_receive() {
+    let mut channel = Channel::new(EchoApp::new(true));
+    for message in [&b"ping"[..], &[b'x'; MAX_DATA * 3 + 1], &[]] {
+        channel.send(message, TIMEOUT).unwrap();
+        for _ in 0..2 {
+            assert_eq!(channel.recv(TIMEOUT).unwrap(), message.to_ascii_uppercase());
+        }
+        assert!(matches!(channel.recv(TIMEOUT), Err(Error::Timeout)));
+    }
+}
+
+#[test]
+fn dropped_messages_fail() {
+    let mut channel = Channel::new(EchoApp::new(false));
+    assert!(matches!(channel.send(b"ping", TIMEOUT), Err(Error::Protocol(_))));
+    assert!(matches!(channel.send(&[0; MAX_MESSAGE + 1], TIMEOUT), Err(Error::Protocol(_))));
+}
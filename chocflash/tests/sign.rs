@@ -0,0 +1,78 @@
+use chocflash::flasher::OS_ADDR;
+use chocflash::image::{self, Image};
+use chocflash::keys;
+use chocflash::sim::SimulatedBootloader;
+use chocflash::Flasher;
+use chocos_abi::{ABI_VERSION, APP_MAGIC};
+use chocos_isp::image::{Policy, OS_HEADER_OFFSET, OS_MAGIC};
+use chocos_isp::sign::{check_signature, public_key, TRAILER_SIZE};
+
+const SECRET: [u8; 32] = [0x42; 32];
+
+fn no_progress(_: chocflash::Stage, _: usize, _: usize) {}
+
+fn built_os() -> Image {
+    let mut bin = vec![0u8; 0x800];
+    bin[0..4].copy_from_slice(&0x2000_E500u32.to_le_bytes());
+    bin[4..8].copy_from_slice(&(OS_ADDR + 0x201).to_le_bytes());
+    bin[OS_HEADER_OFFSET..OS_HEADER_OFFSET + 4].copy_from_slice(&OS_MAGIC.to_le_bytes());
+    bin[OS_HEADER_OFFSET + 4..OS_HEADER_OFFSET + 8].copy_from_slice(&0x800u32.to_le_bytes());
+    bin[OS_HEADER_OFFSET + 8..OS_HEADER_OFFSET + 12].copy_from_slice(&[0xFF; 4]);
+    image::parse(&bin, "bin", OS_ADDR).unwrap()
+}
+
+#[test]
+fn signed_os_boots() {
+    let key = public_key(&SECRET).unwrap();
+    let release = Policy { key: Some(&key), allow_unsealed: false, allow_unsigned: false };
+
+    let sealed = image::seal_os(&built_os(), OS_ADDR).unwrap();
+    let mut sim = SimulatedBootloader::new();
+    Flasher::new(&mut sim).flash(&sealed, true, &mut no_progress).unwrap();
+    assert_eq!(sim.boot(&release), None);
+
+    let signed = image::sign(&sealed, &SECRET).unwrap();
+    assert_eq!(signed.len(), 0x800 + TRAILER_SIZE);
+    Flasher::new(&mut sim).flash(&signed, true, &mut no_progress).unwrap();
+    assert_eq!(sim.boot(&release), Some(OS_ADDR));
+}
+
+#[test]
+fn signed_app() {
+    let start: u32 = 0x0803_0000;
+    let mut bin = vec![0x5A; 0x400];
+    bin[0..4].copy_from_slice(&APP_MAGIC.to_le_bytes());
+    bin[4..8].copy_from_slice(&ABI_VERSION.to_le_bytes());
+    bin[8..12].copy_from_slice(&(start + 0x11).to_le_bytes());
+    bin[12..16].copy_from_slice(&(start + 0x300).to_le_bytes());
+
+    // only up to the end in the app header is signed
+    let signed = image::sign(&image::parse(&bin, "bin", start).unwrap(), &SECRET).unwrap();
+    let data = &signed.segments[0].data;
+    assert_eq!(data.len(), 0x300 + TRAILER_SIZE);
+
+    let key = public_key(&SECRET).unwrap();
+    let policy = Policy { key: Some(&key), ..Policy::default() };
+    assert_eq!(check_signature(&data[..0x300], Some(&data[0x300..]), &policy), Ok(()));
+}
+
+#[test]
+fn only_images_with_a_header_are_signed() {
+    let raw = image::parse(&[0u8; 64], "bin", 0x0803_0000).unwrap();
+    assert!(image::sign(&raw, &SECRET).is_err());
+}
+
+#[test]
+fn keygen_round_trip() {
+    let dir = std::env::temp_dir().join(format!("chocflash-keygen-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (secret, public) = (dir.join("chocos.key"), dir.join("chocos.pub"));
+
+    let key = keys::generate(&secret, &public).unwrap();
+    assert_eq!(public_key(&keys::read_secret(&secret).unwrap()), Some(key));
+    assert_eq!(keys::from_hex(&std::fs::read_to_string(&public).unwrap()), Some(key));
+    // never replaces a key
+    assert!(keys::generate(&secret, &public).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
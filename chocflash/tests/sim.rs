@@ -0,0 +1,217 @@
+use chocflash::flasher::{check_region, KEYMAP_ADDR, OS_ADDR, OS_B_ADDR, USER_FLASH_START};
+use chocflash::image::{self, Image, Segment};
+use chocflash::protocol::Status;
+use chocflash::sim::SimulatedBootloader;
+use chocflash::{Error, Flasher, Slot, Target};
+use chocos_isp::image::Policy;
+use chocos_keyboard::keycode::A;
+use chocos_keyboard::{keymap, Action};
+
+fn image_at(addr: u32, len: usize) -> Image {
+    let data = (0..len).map(|i| (i * 7 + 3) as u8).collect::<Vec<u8>>();
+    image::parse(&data, "bin", addr).unwrap()
+}
+
+fn no_progress(_: chocflash::Stage, _: usize, _: usize) {}
+
+const DEV_MODE: Policy = Policy { key: None, allow_unsealed: false, allow_unsigned: true };
+
+#[test]
+fn flash_and_verify_app() {
+    let mut sim = SimulatedBootloader::new();
+    let image = image_at(USER_FLASH_START, 5000);
+
+    let mut flasher = Flasher::new(&mut sim);
+    flasher.flash(&image, true, &mut no_progress).unwrap();
+    flasher.verify_image(&image, &mut no_progress).unwrap();
+    flasher.reset().unwrap();
+
+    assert_eq!(sim.slice(USER_FLASH_START, 5000), &image.segments[0].data[..]);
+    assert_eq!(sim.resets, 1);
+}
+
+#[test]
+fn reflashing_erases_first() {
+    let mut sim = SimulatedBootloader::new();
+    let mut flasher = Flasher::new(&mut sim);
+    flasher.flash(&image_at(OS_ADDR, 3000), true, &mut no_progress).unwrap();
+
+    let second = image::parse(&[0x55; 100], "bin", OS_ADDR).unwrap();
+    flasher.flash(&second, true, &mut no_progress).unwrap();
+    assert_eq!(sim.slice(OS_ADDR, 100), &[0x55; 100][..]);
+}
+
+#[test]
+fn odd_length_is_padded() {
+    let mut sim = SimulatedBootloader::new();
+    let image = image_at(USER_FLASH_START, 53);
+    Flasher::new(&mut sim).flash(&image, true, &mut no_progress).unwrap();
+    assert_eq!(sim.slice(USER_FLASH_START, 53), &image.segments[0].data[..]);
+    assert_eq!(sim.slice(USER_FLASH_START + 53, 1), &[0xFF]);
+}
+
+#[test]
+fn corrupted_chunk_is_resent() {
+    let mut sim = SimulatedBootloader::new();
+    sim.corrupt_writes = 2;
+    let image = image_at(USER_FLASH_START, 200);
+    Flasher::new(&mut sim).flash(&image, true, &mut no_progress).unwrap();
+    assert_eq!(sim.slice(USER_FLASH_START, 200), &image.segments[0].data[..]);
+}
+
+#[test]
+fn bootloader_pages_are_refused() {
+    let mut sim = SimulatedBootloader::new();
+    let mut flasher = Flasher::new(&mut sim);
+    match flasher.erase_page(0x0800_0000) {
+        Err(Error::Status { status: Status::BadAddress, .. }) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(flasher.flash(&image_at(0x0800_F000, 16), true, &mut no_progress).is_err());
+}
+
+#[test]
+fn verify_detects_mismatch() {
+    let mut sim = SimulatedBootloader::new();
+    let mut flasher = Flasher::new(&mut sim);
+    flasher.flash(&image_at(USER_FLASH_START, 64), false, &mut no_progress).unwrap();
+    let other = image::parse(&[0u8; 64], "bin", USER_FLASH_START).unwrap();
+    assert!(matches!(
+        flasher.verify_image(&other, &mut no_progress),
+        Err(Error::VerifyFailed { .. })
+    ));
+}
+
+#[test]
+fn read_back() {
+    let mut sim = SimulatedBootloader::new();
+    let image = image_at(USER_FLASH_START, 150);
+    let mut flasher = Flasher::new(&mut sim);
+    flasher.flash(&image, true, &mut no_progress).unwrap();
+    assert_eq!(flasher.read(USER_FLASH_START, 150).unwrap(), image.segments[0].data);
+}
+
+#[test]
+fn regions() {
+    assert!(check_region(&image_at(USER_FLASH_START, 16), Target::App).is_ok());
+    assert!(check_region(&image_at(USER_FLASH_START, 16), Target::Os(Slot::A)).is_err());
+    assert!(check_region(&image_at(OS_ADDR, 0x1_0001), Target::Os(Slot::A)).is_err());
+    assert!(check_region(&image_at(KEYMAP_ADDR, 0x801), Target::Keymap).is_err());
+}
+
+#[test]
+fn keymaps_are_checked() {
+    let layer: &[Action] = &[Action::Key(A)];
+    let mut data = [0; keymap::MAX_SIZE];
+    let len = keymap::write(&mut data, 1, 1, 0, &[layer], &[]).unwrap();
+    let keymap = image::parse(&data[..len], "bin", KEYMAP_ADDR).unwrap();
+    assert!(image::check_keymap(&keymap).is_ok());
+    assert!(check_region(&keymap, Target::Keymap).is_ok());
+    assert!(image::check_keymap(&image_at(KEYMAP_ADDR, len)).is_err());
+}
+
+#[test]
+fn intel_hex() {
+    let hex = ":020000040802F0\n\
+               :0400000001020304F2\n\
+               :0400040005060708DE\n\
+               :04001000AABBCCDDDE\n\
+               :00000001FF\n";
+    let image = image::parse(hex.as_bytes(), "hex", 0).unwrap();
+    assert_eq!(
+        image.segments,
+        vec![
+            Segment { addr: 0x0802_0000, data: vec![1, 2, 3, 4, 5, 6, 7, 8] },
+            Segment { addr: 0x0802_0010, data: vec![0xAA, 0xBB, 0xCC, 0xDD] },
+        ]
+    );
+
+    let bad = ":0400000001020304F3\n";
+    assert!(image::parse(bad.as_bytes(), "hex", 0).is_err());
+}
+
+#[test]
+fn elf_uses_load_addresses() {
+    // ELF32 header, two PT_LOAD headers: .text and .data stored behind it
+    let mut elf = vec![0u8; 52 + 2 * 32];
+    elf[0..4].copy_from_slice(b"\x7fELF");
+    elf[4] = 1;
+    elf[5] = 1;
+    elf[0x1C..0x20].copy_from_slice(&52u32.to_le_bytes());
+    elf[0x2A..0x2C].copy_from_slice(&32u16.to_le_bytes());
+    elf[0x2C..0x2E].copy_from_slice(&2u16.to_le_bytes());
+
+    let text_off = elf.len() as u32;
+    elf.extend_from_slice(&[1, 2, 3, 4]);
+    let data_off = elf.len() as u32;
+    elf.extend_from_slice(&[9, 9]);
+
+    let ph = |elf: &mut Vec<u8>, i: usize, offset: u32, vaddr: u32, paddr: u32, size: u32| {
+        let base = 52 + i * 32;
+        elf[base..base + 4].copy_from_slice(&1u32.to_le_bytes());
+        elf[base + 4..base + 8].copy_from_slice(&offset.to_le_bytes());
+        elf[base + 8..base + 12].copy_from_slice(&vaddr.to_le_bytes());
+        elf[base + 12..base + 16].copy_from_slice(&paddr.to_le_bytes());
+        elf[base + 16..base + 20].copy_from_slice(&size.to_le_bytes());
+    };
+    ph(&mut elf, 0, text_off, 0x0802_0000, 0x0802_0000, 4);
+    ph(&mut elf, 1, data_off, 0x2000_0000, 0x0802_0004, 2);
+
+    let image = image::parse(&elf, "elf", 0).unwrap();
+    assert_eq!(image.segments, vec![Segment { addr: 0x0802_0000, data: vec![1, 2, 3, 4, 9, 9] }]);
+}
+
+// An OS build as the linker leaves it: vectors and an unsealed header
+fn built_os(base: u32, version: u32) -> Image {
+    use chocos_isp::image::{OS_HEADER_OFFSET, OS_MAGIC};
+
+    let mut bin = vec![0u8; 0x800];
+    bin[0..4].copy_from_slice(&0x2000_E500u32.to_le_bytes());
+    bin[4..8].copy_from_slice(&(base + 0x201).to_le_bytes());
+    bin[OS_HEADER_OFFSET..OS_HEADER_OFFSET + 4].copy_from_slice(&OS_MAGIC.to_le_bytes());
+    bin[OS_HEADER_OFFSET + 4..OS_HEADER_OFFSET + 8].copy_from_slice(&0x800u32.to_le_bytes());
+    bin[OS_HEADER_OFFSET + 8..OS_HEADER_OFFSET + 12].copy_from_slice(&[0xFF; 4]);
+    bin[OS_HEADER_OFFSET + 12..OS_HEADER_OFFSET + 16].copy_from_slice(&version.to_le_bytes());
+    image::parse(&bin, "bin", base).unwrap()
+}
+
+#[test]
+fn os_image_is_sealed_before_flashing() {
+    use chocos_isp::image::check_os;
+
+    let image = image::seal_os(&built_os(OS_ADDR, 0x100), OS_ADDR).unwrap();
+    let mut sim = SimulatedBootloader::new();
+    Flasher::new(&mut sim).flash(&image, true, &mut no_progress).unwrap();
+
+    assert!(check_os(sim.slice(OS_ADDR, 0x1_0000), OS_ADDR, &DEV_MODE).is_ok());
+    assert!(image::seal_os(&image_at(OS_ADDR, 0x800), OS_ADDR).is_err());
+    assert!(image::seal_os(&built_os(OS_B_ADDR, 0x100), OS_ADDR).is_err());
+}
+
+#[test]
+fn update_through_the_second_slot() {
+    use chocos_isp::slots::{self, SlotLayout, MAX_ATTEMPTS};
+
+    let mut sim = SimulatedBootloader::new();
+    let old = image::seal_os(&built_os(OS_ADDR, 0x100), OS_ADDR).unwrap();
+    Flasher::new(&mut sim).flash(&old, true, &mut no_progress).unwrap();
+    assert_eq!(sim.boot(&DEV_MODE), Some(OS_ADDR));
+    let crc = Flasher::new(&mut sim).boot_slots().unwrap()[0].header.unwrap().crc;
+    slots::confirm(sim.flash_mut(), &SlotLayout::STM32F103, 0, crc).unwrap();
+
+    let new = image::seal_os(&built_os(OS_B_ADDR, 0x200), OS_B_ADDR).unwrap();
+    assert!(check_region(&new, Target::Os(Slot::B)).is_ok());
+    assert!(check_region(&new, Target::Os(Slot::A)).is_err());
+    Flasher::new(&mut sim).flash(&new, true, &mut no_progress).unwrap();
+
+    // the new OS never confirms itself
+    for _ in 0..MAX_ATTEMPTS {
+        assert_eq!(sim.boot(&DEV_MODE), Some(OS_B_ADDR));
+    }
+    assert_eq!(sim.boot(&DEV_MODE), Some(OS_ADDR));
+
+    let states = Flasher::new(&mut sim).boot_slots().unwrap();
+    assert!(states[0].confirmed);
+    assert_eq!(states[1].header.unwrap().version, 0x200);
+    assert_eq!((states[1].confirmed, states[1].attempts), (false, MAX_ATTEMPTS));
+}
@@ -0,0 +1,98 @@
+// Console commands
+//
+// The kernel runs them; parsing and the help text live here.
+
+/// Where `hprint!` output goes
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LogTarget {
+    Usart,
+    Usb,
+    Both,
+}
+
+impl LogTarget {
+    pub fn from_name(name: &str) -> Option<LogTarget> {
+        match name {
+            "usart" => Some(LogTarget::Usart),
+            "usb" => Some(LogTarget::Usb),
+            "both" => Some(LogTarget::Both),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogTarget::Usart => "usart",
+            LogTarget::Usb => "usb",
+            LogTarget::Both => "both",
+        }
+    }
+
+    pub fn usart(self) -> bool {
+        self != LogTarget::Usb
+    }
+
+    pub fn usb(self) -> bool {
+        self != LogTarget::Usart
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// An empty line
+    Nothing,
+    Help,
+    /// List the processes
+    Ps,
+    /// Kernel heap usage and fragmentation
+    Heap,
+    /// Show the log target, or set it
+    Log(Option<LogTarget>),
+    Reboot,
+    /// Reboot into flash mode
+    Recover,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CommandError<'a> {
+    Unknown(&'a str),
+    /// How the command is used
+    Usage(&'a str),
+}
+
+pub const HELP: &str = "\
+help                  this text
+ps                    list the processes
+heap                  show kernel heap usage
+log [usart|usb|both]  show or set where the kernel log goes
+reboot                restart the board
+recover               restart into flash mode
+";
+
+const LOG_USAGE: &str = "log [usart|usb|both]";
+
+pub fn parse(line: &str) -> Result<Command, CommandError<'_>> {
+    let mut words = line.split_whitespace();
+    let name = match words.next() {
+        Some(name) => name,
+        None => return Ok(Command::Nothing),
+    };
+    let command = match (name, words.next()) {
+        ("help", None) => Command::Help,
+        ("ps", None) => Command::Ps,
+        ("heap", None) => Command::Heap,
+        ("log", None) => Command::Log(None),
+        ("log", Some(target)) => {
+            Command::Log(Some(LogTarget::from_name(target).ok_or(CommandError::Usage(LOG_USAGE))?))
+        }
+        ("reboot", None) => Command::Reboot,
+        ("recover", None) => Command::Recover,
+        // none of the others takes an argument
+        ("help" | "ps" | "heap" | "reboot" | "recover", Some(_)) => return Err(CommandError::Usage(name)),
+        (name, _) => return Err(CommandError::Unknown(name)),
+    };
+    if words.next().is_some() {
+        return Err(CommandError::Usage(LOG_USAGE));
+    }
+    Ok(command)
+}
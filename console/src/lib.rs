@@ -0,0 +1,17 @@
+//! Serial console of ChocOS.
+//!
+//! The OS adds a CDC-ACM serial function next to its HID interfaces that
+//! carries the kernel log and a small interactive console. This crate is
+//! the part without the hardware: `Ring`, the output buffer the log and
+//! the console share, `LineEditor` for what the terminal types, and the
+//! commands in `command`. The OS adds the USB class and runs the commands.
+
+#![no_std]
+
+pub mod command;
+pub mod line;
+pub mod ring;
+
+pub use command::{Command, CommandError, LogTarget};
+pub use line::LineEditor;
+pub use ring::Ring;
@@ -0,0 +1,99 @@
+// Line editing for a dumb terminal
+//
+// Printable characters are echoed and collected, backspace takes the last
+// one back, Enter hands the line over and Ctrl-C drops it. Anything else,
+// escape sequences included, is ignored.
+
+use crate::ring::Ring;
+
+pub const MAX_LINE: usize = 80;
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+const CTRL_C: u8 = 0x03;
+const ESCAPE: u8 = 0x1B;
+
+pub struct LineEditor {
+    buf: [u8; MAX_LINE],
+    len: usize,
+    // a "\n" right after "\r" ends no second line
+    after_cr: bool,
+    // where in an escape sequence such as an arrow key, ESC [ A or ESC O A:
+    // 0 outside, 1 after ESC, 2 before the final byte
+    escape: u8,
+}
+
+impl LineEditor {
+    pub const fn new() -> Self {
+        LineEditor { buf: [0; MAX_LINE], len: 0, after_cr: false, escape: 0 }
+    }
+
+    /// Takes one byte from the terminal and echoes it to `out`. `true`
+    /// once a line is complete, see `line`.
+    pub fn push<const N: usize>(&mut self, byte: u8, out: &mut Ring<N>) -> bool {
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+        match self.escape {
+            1 if byte == b'[' || byte == b'O' => {
+                self.escape = 2;
+                return false;
+            }
+            1 => {
+                self.escape = 0;
+                return false;
+            }
+            2 => {
+                if (0x40..=0x7E).contains(&byte) {
+                    self.escape = 0;
+                }
+                return false;
+            }
+            _ => {}
+        }
+        match byte {
+            ESCAPE => {
+                self.escape = 1;
+                false
+            }
+            b'\n' if after_cr => false,
+            b'\r' | b'\n' => {
+                out.push(b"\r\n");
+                true
+            }
+            CTRL_C => {
+                self.len = 0;
+                out.push(b"^C\r\n");
+                true
+            }
+            BACKSPACE | DELETE => {
+                if self.len > 0 {
+                    self.len -= 1;
+                    out.push(b"\x08 \x08");
+                }
+                false
+            }
+            0x20..=0x7E if self.len < MAX_LINE => {
+                self.buf[self.len] = byte;
+                self.len += 1;
+                out.push(&[byte]);
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// The line so far, `clear` it once it is handled
+    pub fn line(&self) -> &str {
+        // only ASCII gets in
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl Default for LineEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
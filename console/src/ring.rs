@@ -0,0 +1,80 @@
+// Output buffer of the console
+//
+// The kernel log and the console's replies go into one ring, from any
+// context, and the USB interrupt takes them out as the host reads. What
+// doesn't fit is dropped and counted, older output is never overwritten:
+// the log from boot stays until a terminal opens the port.
+
+pub struct Ring<const N: usize> {
+    buf: [u8; N],
+    // next byte to take out
+    head: usize,
+    len: usize,
+    dropped: usize,
+}
+
+impl<const N: usize> Ring<N> {
+    pub const fn new() -> Self {
+        Ring { buf: [0; N], head: 0, len: 0, dropped: 0 }
+    }
+
+    /// Adds as much of `data` as fits, returns how much that was
+    pub fn push(&mut self, data: &[u8]) -> usize {
+        let n = data.len().min(N - self.len);
+        for (i, &byte) in data[..n].iter().enumerate() {
+            self.buf[(self.head + self.len + i) % N] = byte;
+        }
+        self.len += n;
+        self.dropped += data.len() - n;
+        n
+    }
+
+    /// Adds `text` with every "\n" turned into "\r\n" for terminals
+    pub fn push_text(&mut self, text: &str) {
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.push(b"\r\n");
+            }
+            self.push(line.as_bytes());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The oldest bytes, up to the end of the buffer
+    pub fn peek(&self) -> &[u8] {
+        let end = (self.head + self.len).min(N);
+        &self.buf[self.head..end]
+    }
+
+    /// Takes out `n` bytes that `peek` returned
+    pub fn consume(&mut self, n: usize) {
+        let n = n.min(self.len);
+        self.head = (self.head + n) % N;
+        self.len -= n;
+    }
+
+    /// Bytes dropped since the last call
+    pub fn take_dropped(&mut self) -> usize {
+        core::mem::replace(&mut self.dropped, 0)
+    }
+}
+
+impl<const N: usize> Default for Ring<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> core::fmt::Write for Ring<N> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.push_text(s);
+        Ok(())
+    }
+}
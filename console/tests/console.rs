@@ -0,0 +1,65 @@
+use chocos_console::command::{self, Command, CommandError, LogTarget};
+use chocos_console::{LineEditor, Ring};
+
+fn drain<const N: usize>(ring: &mut Ring<N>) -> Vec<u8> {
+    let mut out = Vec::new();
+    while !ring.is_empty() {
+        let chunk = ring.peek().to_vec();
+        ring.consume(chunk.len());
+        out.extend(chunk);
+    }
+    out
+}
+
+#[test]
+fn ring_keeps_the_oldest() {
+    let mut ring = Ring::<8>::new();
+    assert_eq!(ring.push(b"abcde"), 5);
+    ring.consume(3);
+    // wraps around the end
+    assert_eq!(ring.push(b"fghijkl"), 6);
+    assert_eq!(ring.len(), 8);
+    assert_eq!(ring.take_dropped(), 1);
+    assert_eq!(ring.take_dropped(), 0);
+    assert_eq!(ring.peek(), b"defgh");
+    assert_eq!(drain(&mut ring), b"defghijk");
+
+    let mut ring = Ring::<16>::new();
+    ring.push_text("one\ntwo\n");
+    assert_eq!(drain(&mut ring), b"one\r\ntwo\r\n");
+}
+
+#[test]
+fn line_editing() {
+    let mut out = Ring::<64>::new();
+    let mut editor = LineEditor::new();
+    let mut lines = Vec::new();
+    for &byte in b"pz\x7fs\x1b[A\r\nlog x\x03help\x1bOB\n" {
+        if editor.push(byte, &mut out) {
+            lines.push(editor.line().to_string());
+            editor.clear();
+        }
+    }
+    assert_eq!(lines, ["ps", "", "help"]);
+    assert_eq!(drain(&mut out), b"pz\x08 \x08s\r\nlog x^C\r\nhelp\r\n");
+}
+
+#[test]
+fn commands() {
+    assert_eq!(command::parse("  "), Ok(Command::Nothing));
+    assert_eq!(command::parse("ps"), Ok(Command::Ps));
+    assert_eq!(command::parse("heap"), Ok(Command::Heap));
+    assert_eq!(command::parse("heap 1"), Err(CommandError::Usage("heap")));
+    assert_eq!(command::parse(" log  usb "), Ok(Command::Log(Some(LogTarget::Usb))));
+    assert_eq!(command::parse("log"), Ok(Command::Log(None)));
+    assert!(matches!(command::parse("log serial"), Err(CommandError::Usage(_))));
+    assert!(matches!(command::parse("log usb usart"), Err(CommandError::Usage(_))));
+    assert_eq!(command::parse("reboot now"), Err(CommandError::Usage("reboot")));
+    assert_eq!(command::parse("format c:"), Err(CommandError::Unknown("format")));
+
+    for target in [LogTarget::Usart, LogTarget::Usb, LogTarget::Both] {
+        assert_eq!(LogTarget::from_name(target.name()), Some(target));
+    }
+    assert!(LogTarget::Both.usart() && LogTarget::Both.usb());
+    assert!(!LogTarget::Usart.usb() && !LogTarget::Usb.usart());
+}
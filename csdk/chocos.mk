@@ -0,0 +1,40 @@
+# Build rules for ChocOS C apps
+#
+# In the app's Makefile set APP and SRCS, then include this file:
+#
+#     APP = hello
+#     SRCS = main.c
+#     include ../csdk/chocos.mk
+#
+# An app can override the load address with its own memory.ld. One that
+# reboots the board asks for it in its header:
+#
+#     CFLAGS += -DCHOC_APP_CAPS=CHOC_CAP_REBOOT
+
+CHOCOS_SDK := $(dir $(lastword $(MAKEFILE_LIST)))
+
+CC = arm-none-eabi-gcc
+OBJCOPY = arm-none-eabi-objcopy
+
+CFLAGS += -mcpu=cortex-m3 -mthumb -Os -g -Wall -ffunction-sections -fdata-sections
+CFLAGS += -I$(CHOCOS_SDK)include
+LDFLAGS += -mcpu=cortex-m3 -mthumb --specs=nano.specs -nostartfiles -Wl,--gc-sections
+LDFLAGS += -L. -L$(CHOCOS_SDK) -T chocos.ld
+
+SDK_SRCS = $(CHOCOS_SDK)src/crt0.c $(CHOCOS_SDK)src/syscalls.c
+
+all: $(APP).elf $(APP).bin $(APP).hex
+
+$(APP).elf: $(SRCS) $(SDK_SRCS)
+	$(CC) $(CFLAGS) $(LDFLAGS) -o $@ $^
+
+$(APP).bin: $(APP).elf
+	$(OBJCOPY) -O binary $< $@
+
+$(APP).hex: $(APP).elf
+	$(OBJCOPY) -O ihex $< $@
+
+clean:
+	rm -f $(APP).elf $(APP).bin $(APP).hex
+
+.PHONY: all clean
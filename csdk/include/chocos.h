@@ -0,0 +1,223 @@
+#ifndef CHOCOS_H
+#define CHOCOS_H
+
+#include <stddef.h>
+
+#include "chocos/syscall.h"
+
+/* Mirrors a Rust `&str`, which is what SYS_PRINT expects a pointer to. */
+struct choc_str {
+    const char * ptr;
+    size_t len;
+};
+
+static inline void choc_yield(void) {
+    sys_yield();
+}
+
+static inline int choc_print(const char * text, size_t len) {
+    struct choc_str str = { text, len };
+    return sys_print(&str);
+}
+
+static inline int choc_print_cstr(const char * text) {
+    return sys_print_cstr(text);
+}
+
+static inline int choc_print_u32(unsigned int num) {
+    return sys_print_u32(num);
+}
+
+/* Returns the pid of the new process or a negated error code */
+static inline int choc_create(unsigned int image) {
+    return sys_create(image);
+}
+
+/* Returns the old break or a negated error code */
+static inline void * choc_sbrk(int increment) {
+    return sys_sbrk(increment);
+}
+
+/* Returns 0 or a negated error code */
+static inline int choc_confirm_boot(void) {
+    return sys_confirm_boot();
+}
+
+/* Only return, with a negated error code, if the app may not reboot (see CHOC_APP_CAPS in crt0.c) */
+static inline int choc_reboot(void) {
+    return sys_reboot();
+}
+
+static inline int choc_reboot_recovery(void) {
+    return sys_reboot_recovery();
+}
+
+/* USB keyboard. `code` is a HID keyboard usage (0x04 'a' ...), the
+   modifiers are CHOC_KEY_LEFT_CTRL ... CHOC_KEY_RIGHT_GUI. Returns 0 or a
+   negated error code, -CHOC_EAGAIN while earlier reports are still queued */
+#define CHOC_KEY_LEFT_CTRL   0xE0
+#define CHOC_KEY_LEFT_SHIFT  0xE1
+#define CHOC_KEY_LEFT_ALT    0xE2
+#define CHOC_KEY_LEFT_GUI    0xE3
+#define CHOC_KEY_RIGHT_CTRL  0xE4
+#define CHOC_KEY_RIGHT_SHIFT 0xE5
+#define CHOC_KEY_RIGHT_ALT   0xE6
+#define CHOC_KEY_RIGHT_GUI   0xE7
+
+static inline int choc_key_press(unsigned char code) {
+    return sys_key_press(code);
+}
+
+static inline int choc_key_release(unsigned char code) {
+    return sys_key_release(code);
+}
+
+static inline int choc_key_tap(unsigned char code) {
+    return sys_key_tap(code);
+}
+
+/* Mouse buttons, several at once */
+#define CHOC_MOUSE_LEFT    0x01
+#define CHOC_MOUSE_RIGHT   0x02
+#define CHOC_MOUSE_MIDDLE  0x04
+#define CHOC_MOUSE_BACK    0x08
+#define CHOC_MOUSE_FORWARD 0x10
+
+static inline int choc_mouse_press(unsigned char buttons) {
+    return sys_mouse_press(buttons);
+}
+
+static inline int choc_mouse_release(unsigned char buttons) {
+    return sys_mouse_release(buttons);
+}
+
+static inline int choc_mouse_move(int x, int y, int wheel) {
+    return sys_mouse_move(x, y, wheel);
+}
+
+/* Media keys: usages of the consumer page */
+#define CHOC_MEDIA_PLAY_PAUSE     0xCD
+#define CHOC_MEDIA_NEXT_TRACK     0xB5
+#define CHOC_MEDIA_PREVIOUS_TRACK 0xB6
+#define CHOC_MEDIA_STOP           0xB7
+#define CHOC_MEDIA_MUTE           0xE2
+#define CHOC_MEDIA_VOLUME_UP      0xE9
+#define CHOC_MEDIA_VOLUME_DOWN    0xEA
+
+static inline int choc_consumer_press(unsigned short usage) {
+    return sys_consumer_press(usage);
+}
+
+static inline int choc_consumer_release(void) {
+    return sys_consumer_release();
+}
+
+static inline int choc_consumer_tap(unsigned short usage) {
+    return sys_consumer_tap(usage);
+}
+
+/* Keyboard report mode. A host that asked for the boot protocol gets boot
+   reports either way */
+#define CHOC_KEYBOARD_BOOT 0
+#define CHOC_KEYBOARD_NKRO 1
+
+static inline int choc_keyboard_mode(unsigned int mode) {
+    return sys_keyboard_mode(mode);
+}
+
+/* Keyboard LEDs the host lights. After choc_led_subscribe, choc_led_read
+   blocks until they change */
+#define CHOC_LED_NUM_LOCK    0x01
+#define CHOC_LED_CAPS_LOCK   0x02
+#define CHOC_LED_SCROLL_LOCK 0x04
+#define CHOC_LED_COMPOSE     0x08
+#define CHOC_LED_KANA        0x10
+
+static inline int choc_led_subscribe(void) {
+    return sys_led_subscribe();
+}
+
+static inline int choc_led_unsubscribe(void) {
+    return sys_led_unsubscribe();
+}
+
+static inline int choc_led_read(void) {
+    return sys_led_read();
+}
+
+/* USB suspend. choc_suspend_read returns 1 while the host has the bus
+   suspended; after choc_suspend_subscribe it blocks until that changes */
+static inline int choc_suspend_subscribe(void) {
+    return sys_suspend_subscribe();
+}
+
+static inline int choc_suspend_unsubscribe(void) {
+    return sys_suspend_unsubscribe();
+}
+
+static inline int choc_suspend_read(void) {
+    return sys_suspend_read();
+}
+
+/* Key matrix. While subscribed, choc_matrix_read returns the next event,
+   waiting for one if there is none yet */
+#define CHOC_EVENT_COL(e)     ((e) & 0xFF)
+#define CHOC_EVENT_ROW(e)     (((e) >> 8) & 0xFF)
+#define CHOC_EVENT_PRESSED(e) (((e) >> 16) & 1)
+
+static inline int choc_matrix_subscribe(void) {
+    return sys_matrix_subscribe();
+}
+
+static inline int choc_matrix_unsubscribe(void) {
+    return sys_matrix_unsubscribe();
+}
+
+static inline int choc_matrix_read(void) {
+    return sys_matrix_read();
+}
+
+/* Keymap actions, one unsigned short per key, see docs/os/keyboard.md */
+#define CHOC_ACTION_NONE        0x0000
+#define CHOC_ACTION_TRANSPARENT 0x0001
+#define CHOC_ACTION_KEY(k)      (k)
+#define CHOC_ACTION_MO(l)       (0x0100 | (l))
+#define CHOC_ACTION_TG(l)       (0x0200 | (l))
+#define CHOC_ACTION_MACRO(n)    (0x0300 | (n))
+#define CHOC_ACTION_LT(l, k)    (0x4000 | ((l) << 8) | (k))
+#define CHOC_ACTION_MT(m, k)    (0x5000 | (((m) - CHOC_KEY_LEFT_CTRL) << 8) | (k))
+
+static inline int choc_keymap_load(const void * keymap, unsigned int len) {
+    return sys_keymap_load(keymap, len);
+}
+
+static inline int choc_keymap_restore(void) {
+    return sys_keymap_restore();
+}
+
+/* Vendor HID channel to a host tool, see docs/os/channel.md. Messages
+   are up to CHOC_CHANNEL_MAX bytes; choc_channel_recv waits for one and
+   returns its length */
+#define CHOC_CHANNEL_MAX 512
+
+static inline int choc_channel_open(void) {
+    return sys_channel_open();
+}
+
+static inline int choc_channel_close(void) {
+    return sys_channel_close();
+}
+
+static inline int choc_channel_recv(void * buf, unsigned int len) {
+    return sys_channel_recv(buf, len);
+}
+
+static inline int choc_channel_send(const void * data, unsigned int len) {
+    return sys_channel_send(data, len);
+}
+
+static inline void choc_exit(int code) {
+    sys_exit(code);
+}
+
+#endif
@@ -0,0 +1,263 @@
+/* Generated from abi/syscalls.toml by `cargo run --example c_header`. Do not edit. */
+
+#ifndef CHOCOS_SYSCALL_H
+#define CHOCOS_SYSCALL_H
+
+/* Checked by the kernel when the app is loaded */
+#define CHOC_ABI_VERSION  14
+#define CHOC_APP_MAGIC    0x434f4843
+#define CHOC_CAP_REBOOT   0x1
+
+/* System call numbers, passed in r0 */
+#define SYS_YIELD          1 /* Give up the rest of the time slice. */
+#define SYS_PRINT          3 /* Write a string to the kernel console. `text` points to a `&str`. */
+#define SYS_PRINT_CSTR     4 /* Write a NUL terminated string to the kernel console. */
+#define SYS_EXIT           5 /* Terminate the calling process. */
+#define SYS_CREATE         6 /* Start a new process from the app image at `image`. Returns its pid. */
+#define SYS_PRINT_U32      7 /* Write an unsigned integer in decimal to the kernel console. */
+#define SYS_SBRK           8 /* Move the program break by `increment` bytes. Returns the old break. */
+#define SYS_CONFIRM_BOOT   9 /* Mark the running OS image as good, so the bootloader keeps booting it. */
+#define SYS_REBOOT        10 /* Restart the board and boot the OS again. Only returns on failure. */
+#define SYS_REBOOT_RECOVERY  11 /* Restart the board into the bootloader's flash mode. Only returns on failure. */
+#define SYS_KEY_PRESS     12 /* Hold a key of the USB keyboard down. `code` is a HID keyboard usage, 0xE0 - 0xE7 are the modifiers. */
+#define SYS_KEY_RELEASE   13 /* Let go of a key of the USB keyboard. */
+#define SYS_KEY_TAP       14 /* Press and release a key of the USB keyboard. */
+#define SYS_MATRIX_SUBSCRIBE  15 /* Take the key events of the key matrix instead of the USB keyboard, until `matrix_unsubscribe` or exit. */
+#define SYS_MATRIX_UNSUBSCRIBE  16 /* Hand the key matrix back to the USB keyboard. */
+#define SYS_MATRIX_READ   17 /* The next key event of the subscribed matrix: column in bits 0-7, row in bits 8-15, bit 16 set for a press. Blocks until there is one. */
+#define SYS_KEYMAP_LOAD   18 /* Type with the keymap of `len` bytes at `data` from now on, see docs/os/keyboard.md. EINVAL if it is no valid keymap. */
+#define SYS_KEYMAP_RESTORE  19 /* Go back to the keymap stored in flash, or the default one. */
+#define SYS_CHANNEL_OPEN  20 /* Take the vendor HID channel to the host, see docs/os/channel.md. EBUSY if another process has it. */
+#define SYS_CHANNEL_CLOSE  21 /* Give the channel back, dropping whatever is still on its way. */
+#define SYS_CHANNEL_RECV  22 /* Wait for the next message from the host and copy up to `len` bytes of it to `buf`. Returns the length of the whole message. */
+#define SYS_CHANNEL_SEND  23 /* Send the `len` bytes at `data` to the host, waiting for the last message to go out first. EINVAL if longer than 512 bytes. */
+#define SYS_MOUSE_PRESS   24 /* Hold down the mouse buttons in `buttons`: bit 0 left, 1 right, 2 middle, 3 back, 4 forward. EAGAIN while the mouse report queue is full. */
+#define SYS_MOUSE_RELEASE  25 /* Let go of the mouse buttons in `buttons`. */
+#define SYS_MOUSE_MOVE    26 /* Move the mouse by `x` and `y` and turn the wheel by `wheel`, in steps of up to 127. EINVAL if that takes more than 32 reports. */
+#define SYS_CONSUMER_PRESS  27 /* Hold down a media key, a usage of the consumer page (0xE9 volume up). It replaces the one held before. */
+#define SYS_CONSUMER_RELEASE  28 /* Let go of the media key. */
+#define SYS_CONSUMER_TAP  29 /* Press and release a media key. */
+#define SYS_KEYBOARD_MODE  30 /* Send the keys in boot reports (0, six keys at most, the default) or NKRO reports (1, any number of keys). */
+#define SYS_LED_SUBSCRIBE  31 /* Hear of changes of the keyboard LEDs. The next led_read returns at once. */
+#define SYS_LED_UNSUBSCRIBE  32 /* Stop hearing of LED changes. EPERM if not subscribed. */
+#define SYS_LED_READ      33 /* The keyboard LEDs, bit 0 Num Lock, 1 Caps Lock, 2 Scroll Lock. A subscriber blocks until they changed since its last read. */
+#define SYS_SUSPEND_SUBSCRIBE  34 /* Hear of the host suspending and resuming the USB bus. The next suspend_read returns at once. */
+#define SYS_SUSPEND_UNSUBSCRIBE  35 /* Stop hearing of suspend and resume. EPERM if not subscribed. */
+#define SYS_SUSPEND_READ  36 /* 1 while the USB bus is suspended, 0 otherwise. A subscriber blocks until that changed since its last read. */
+
+/* Error codes, returned negated in r0 */
+#define CHOC_EPERM         1
+#define CHOC_EIO           5
+#define CHOC_ENOEXEC       8
+#define CHOC_EAGAIN       11
+#define CHOC_ENOMEM       12
+#define CHOC_EFAULT       14
+#define CHOC_EBUSY        16
+#define CHOC_EINVAL       22
+#define CHOC_ENOSYS       88
+#define CHOC_MAX_ERRNO    4095
+
+/* A result in [-CHOC_MAX_ERRNO, -1] is a negated error code */
+#define CHOC_IS_ERROR(ret) ((unsigned int)(ret) >= (unsigned int)-CHOC_MAX_ERRNO)
+
+/* Header at the start of every app image, see crt0.c */
+struct choc_app_header {
+    unsigned int magic;
+    unsigned int abi_version;
+    void (*entry)(void);
+    const void * end;
+    unsigned int caps;
+};
+
+static inline int choc_syscall(int id, int arg1, int arg2, int arg3) {
+    register int r0 __asm__("r0") = id;
+    register int r1 __asm__("r1") = arg1;
+    register int r2 __asm__("r2") = arg2;
+    register int r3 __asm__("r3") = arg3;
+    __asm__ volatile(
+        "svc 0"
+        : "+r"(r0), "+r"(r1), "+r"(r2), "+r"(r3)
+        :
+        : "memory");
+    return r0;
+}
+
+/* Give up the rest of the time slice. */
+static inline int sys_yield(void) {
+    return choc_syscall(SYS_YIELD, 0, 0, 0);
+}
+
+/* Write a string to the kernel console. `text` points to a `&str`. */
+static inline int sys_print(const void * text) {
+    return choc_syscall(SYS_PRINT, (int)text, 0, 0);
+}
+
+/* Write a NUL terminated string to the kernel console. */
+static inline int sys_print_cstr(const void * text) {
+    return choc_syscall(SYS_PRINT_CSTR, (int)text, 0, 0);
+}
+
+/* Terminate the calling process. */
+static inline void sys_exit(int code) {
+    choc_syscall(SYS_EXIT, (int)code, 0, 0);
+    /* the kernel switches away once the call returns */
+    while (1)
+        __asm__ volatile("wfi");
+}
+
+/* Start a new process from the app image at `image`. Returns its pid. */
+static inline int sys_create(unsigned int image) {
+    return choc_syscall(SYS_CREATE, (int)image, 0, 0);
+}
+
+/* Write an unsigned integer in decimal to the kernel console. */
+static inline int sys_print_u32(unsigned int num) {
+    return choc_syscall(SYS_PRINT_U32, (int)num, 0, 0);
+}
+
+/* Move the program break by `increment` bytes. Returns the old break. */
+static inline void * sys_sbrk(int increment) {
+    return (void *)choc_syscall(SYS_SBRK, (int)increment, 0, 0);
+}
+
+/* Mark the running OS image as good, so the bootloader keeps booting it. */
+static inline int sys_confirm_boot(void) {
+    return choc_syscall(SYS_CONFIRM_BOOT, 0, 0, 0);
+}
+
+/* Restart the board and boot the OS again. Only returns on failure. */
+static inline int sys_reboot(void) {
+    return choc_syscall(SYS_REBOOT, 0, 0, 0);
+}
+
+/* Restart the board into the bootloader's flash mode. Only returns on failure. */
+static inline int sys_reboot_recovery(void) {
+    return choc_syscall(SYS_REBOOT_RECOVERY, 0, 0, 0);
+}
+
+/* Hold a key of the USB keyboard down. `code` is a HID keyboard usage, 0xE0 - 0xE7 are the modifiers. */
+static inline int sys_key_press(unsigned int code) {
+    return choc_syscall(SYS_KEY_PRESS, (int)code, 0, 0);
+}
+
+/* Let go of a key of the USB keyboard. */
+static inline int sys_key_release(unsigned int code) {
+    return choc_syscall(SYS_KEY_RELEASE, (int)code, 0, 0);
+}
+
+/* Press and release a key of the USB keyboard. */
+static inline int sys_key_tap(unsigned int code) {
+    return choc_syscall(SYS_KEY_TAP, (int)code, 0, 0);
+}
+
+/* Take the key events of the key matrix instead of the USB keyboard, until `matrix_unsubscribe` or exit. */
+static inline int sys_matrix_subscribe(void) {
+    return choc_syscall(SYS_MATRIX_SUBSCRIBE, 0, 0, 0);
+}
+
+/* Hand the key matrix back to the USB keyboard. */
+static inline int sys_matrix_unsubscribe(void) {
+    return choc_syscall(SYS_MATRIX_UNSUBSCRIBE, 0, 0, 0);
+}
+
+/* The next key event of the subscribed matrix: column in bits 0-7, row in bits 8-15, bit 16 set for a press. Blocks until there is one. */
+static inline int sys_matrix_read(void) {
+    return choc_syscall(SYS_MATRIX_READ, 0, 0, 0);
+}
+
+/* Type with the keymap of `len` bytes at `data` from now on, see docs/os/keyboard.md. EINVAL if it is no valid keymap. */
+static inline int sys_keymap_load(const void * data, unsigned int len) {
+    return choc_syscall(SYS_KEYMAP_LOAD, (int)data, (int)len, 0);
+}
+
+/* Go back to the keymap stored in flash, or the default one. */
+static inline int sys_keymap_restore(void) {
+    return choc_syscall(SYS_KEYMAP_RESTORE, 0, 0, 0);
+}
+
+/* Take the vendor HID channel to the host, see docs/os/channel.md. EBUSY if another process has it. */
+static inline int sys_channel_open(void) {
+    return choc_syscall(SYS_CHANNEL_OPEN, 0, 0, 0);
+}
+
+/* Give the channel back, dropping whatever is still on its way. */
+static inline int sys_channel_close(void) {
+    return choc_syscall(SYS_CHANNEL_CLOSE, 0, 0, 0);
+}
+
+/* Wait for the next message from the host and copy up to `len` bytes of it to `buf`. Returns the length of the whole message. */
+static inline int sys_channel_recv(void * buf, unsigned int len) {
+    return choc_syscall(SYS_CHANNEL_RECV, (int)buf, (int)len, 0);
+}
+
+/* Send the `len` bytes at `data` to the host, waiting for the last message to go out first. EINVAL if longer than 512 bytes. */
+static inline int sys_channel_send(const void * data, unsigned int len) {
+    return choc_syscall(SYS_CHANNEL_SEND, (int)data, (int)len, 0);
+}
+
+/* Hold down the mouse buttons in `buttons`: bit 0 left, 1 right, 2 middle, 3 back, 4 forward. EAGAIN while the mouse report queue is full. */
+static inline int sys_mouse_press(unsigned int buttons) {
+    return choc_syscall(SYS_MOUSE_PRESS, (int)buttons, 0, 0);
+}
+
+/* Let go of the mouse buttons in `buttons`. */
+static inline int sys_mouse_release(unsigned int buttons) {
+    return choc_syscall(SYS_MOUSE_RELEASE, (int)buttons, 0, 0);
+}
+
+/* Move the mouse by `x` and `y` and turn the wheel by `wheel`, in steps of up to 127. EINVAL if that takes more than 32 reports. */
+static inline int sys_mouse_move(int x, int y, int wheel) {
+    return choc_syscall(SYS_MOUSE_MOVE, (int)x, (int)y, (int)wheel);
+}
+
+/* Hold down a media key, a usage of the consumer page (0xE9 volume up). It replaces the one held before. */
+static inline int sys_consumer_press(unsigned int usage) {
+    return choc_syscall(SYS_CONSUMER_PRESS, (int)usage, 0, 0);
+}
+
+/* Let go of the media key. */
+static inline int sys_consumer_release(void) {
+    return choc_syscall(SYS_CONSUMER_RELEASE, 0, 0, 0);
+}
+
+/* Press and release a media key. */
+static inline int sys_consumer_tap(unsigned int usage) {
+    return choc_syscall(SYS_CONSUMER_TAP, (int)usage, 0, 0);
+}
+
+/* Send the keys in boot reports (0, six keys at most, the default) or NKRO reports (1, any number of keys). */
+static inline int sys_keyboard_mode(unsigned int mode) {
+    return choc_syscall(SYS_KEYBOARD_MODE, (int)mode, 0, 0);
+}
+
+/* Hear of changes of the keyboard LEDs. The next led_read returns at once. */
+static inline int sys_led_subscribe(void) {
+    return choc_syscall(SYS_LED_SUBSCRIBE, 0, 0, 0);
+}
+
+/* Stop hearing of LED changes. EPERM if not subscribed. */
+static inline int sys_led_unsubscribe(void) {
+    return choc_syscall(SYS_LED_UNSUBSCRIBE, 0, 0, 0);
+}
+
+/* The keyboard LEDs, bit 0 Num Lock, 1 Caps Lock, 2 Scroll Lock. A subscriber blocks until they changed since its last read. */
+static inline int sys_led_read(void) {
+    return choc_syscall(SYS_LED_READ, 0, 0, 0);
+}
+
+/* Hear of the host suspending and resuming the USB bus. The next suspend_read returns at once. */
+static inline int sys_suspend_subscribe(void) {
+    return choc_syscall(SYS_SUSPEND_SUBSCRIBE, 0, 0, 0);
+}
+
+/* Stop hearing of suspend and resume. EPERM if not subscribed. */
+static inline int sys_suspend_unsubscribe(void) {
+    return choc_syscall(SYS_SUSPEND_UNSUBSCRIBE, 0, 0, 0);
+}
+
+/* 1 while the USB bus is suspended, 0 otherwise. A subscriber blocks until that changed since its last read. */
+static inline int sys_suspend_read(void) {
+    return choc_syscall(SYS_SUSPEND_READ, 0, 0, 0);
+}
+
+#endif
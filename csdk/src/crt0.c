@@ -0,0 +1,51 @@
+/*
+ * crt0 for ChocOS C apps
+ *
+ * The kernel checks the header at the start of the image and starts the
+ * process at _start with the stack already set up. Initialize RAM, run
+ * the constructors and hand the return value of main to exit().
+ */
+
+#include <stdlib.h>
+#include <string.h>
+
+#include "chocos/syscall.h"
+
+/* Capabilities the app asks for, e.g. -DCHOC_APP_CAPS=CHOC_CAP_REBOOT */
+#ifndef CHOC_APP_CAPS
+#define CHOC_APP_CAPS 0
+#endif
+
+extern unsigned int __sidata, __sdata, __edata, __sbss, __ebss;
+extern const char __choc_image_end[];
+
+extern void __libc_init_array(void);
+extern int main(void);
+
+void _start(void);
+
+__attribute__((section(".choc_header"), used))
+const struct choc_app_header __choc_header = {
+    .magic = CHOC_APP_MAGIC,
+    .abi_version = CHOC_ABI_VERSION,
+    .entry = _start,
+    .end = __choc_image_end,
+    .caps = CHOC_APP_CAPS,
+};
+
+__attribute__((noreturn))
+void _start(void) {
+    memcpy(&__sdata, &__sidata, (char *)&__edata - (char *)&__sdata);
+    memset(&__sbss, 0, (char *)&__ebss - (char *)&__sbss);
+
+    __libc_init_array();
+
+    exit(main());
+}
+
+/* Called by __libc_init_array, normally provided by crti.o */
+void _init(void) {
+}
+
+void _fini(void) {
+}
@@ -0,0 +1,84 @@
+/*
+ * newlib system call stubs for ChocOS
+ */
+
+#include <errno.h>
+#include <sys/stat.h>
+
+#include "chocos.h"
+
+#undef errno
+extern int errno;
+
+int _write(int fd, const char * buf, int len) {
+    if (fd != 1 && fd != 2) {
+        errno = EBADF;
+        return -1;
+    }
+
+    int ret = choc_print(buf, len);
+    if (CHOC_IS_ERROR(ret)) {
+        errno = -ret;
+        return -1;
+    }
+    return len;
+}
+
+/* There is no console input yet, so stdin is always at end of file. */
+int _read(int fd, char * buf, int len) {
+    (void)buf;
+    (void)len;
+    if (fd != 0) {
+        errno = EBADF;
+        return -1;
+    }
+    return 0;
+}
+
+void * _sbrk(int increment) {
+    void * old_brk = choc_sbrk(increment);
+    if (CHOC_IS_ERROR(old_brk)) {
+        errno = -(int)old_brk;
+        return (void *)-1;
+    }
+    return old_brk;
+}
+
+void _exit(int code) {
+    choc_exit(code);
+    while (1)
+        ;
+}
+
+int _close(int fd) {
+    (void)fd;
+    return -1;
+}
+
+int _fstat(int fd, struct stat * st) {
+    (void)fd;
+    st->st_mode = S_IFCHR;
+    return 0;
+}
+
+int _isatty(int fd) {
+    return fd <= 2;
+}
+
+int _lseek(int fd, int offset, int whence) {
+    (void)fd;
+    (void)offset;
+    (void)whence;
+    return 0;
+}
+
+int _getpid(void) {
+    return 1;
+}
+
+int _kill(int pid, int sig) {
+    (void)pid;
+    (void)sig;
+    errno = EINVAL;
+    return -1;
+}
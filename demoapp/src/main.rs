@@ -1,29 +1,19 @@
 #![no_std]
 #![no_main]
 
-#[macro_use]
-mod stdlib;
+use libchoc::alloc::format;
+use libchoc::{print, println};
 
-use core::{arch::asm, panic::PanicInfo};
-
-entry!(main);
+libchoc::entry!(main);
 
 fn main() -> ! {
-    
+    let greeting = format!("Hello from the heap, {}!", "demoapp");
+    println!("{}", greeting);
 
+    let mut counter = 0u32;
     loop {
-        unsafe { asm!("wfi"); }
-    }
-}
-
-#[inline(never)]
-#[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    // House is on fire!
-
-    unsafe {
-        loop {
-            asm!("wfi");
-        }
+        counter += 1;
+        print!("Counter: {}\n", counter);
+        libchoc::yield_now();
     }
 }
@@ -1,26 +1,20 @@
-#include "stdlib.h"
+#include <stdio.h>
+#include <stdlib.h>
 
-int main();
+#include <chocos.h>
 
-void _start() {
-    main();
-
-    _exit(0);
-}
-
-int main() {
-
-    create(0x080300C2);
+int main(void) {
+    int * counter = malloc(sizeof(int));
+    if (counter == NULL) {
+        printf("malloc failed\n");
+        return 1;
+    }
 
-    int counter = 0;
+    *counter = 0;
     while (1)
     {
-        counter++;
-        print("Hello World!\n");
-        print("Counter: ");
-        printu32(counter);
-        print("\n");
-        yield();
+        (*counter)++;
+        printf("Hello World!\nCounter: %d\n", *counter);
+        choc_yield();
     }
 }
-
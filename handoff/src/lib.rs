@@ -0,0 +1,253 @@
+//! Handoff block between the ChocOS bootloader and the OS.
+//!
+//! A small record in the reserved top of SRAM (0x2000F500 - 0x2000FFFF).
+//! Neither the bootloader nor the OS link anything there and no runtime
+//! clears it, so it survives a reset:
+//!
+//! - the bootloader notes why the chip reset, counts the boots since power
+//!   on and leaves its version for the OS
+//! - the OS asks for the next boot to go into flash mode or to start a
+//!   given OS slot, the bootloader takes the request once
+//! - the panic handlers of both leave the panic location behind
+//!
+//! Magic, version and a CRC-32 guard the block. After power on, or if
+//! anything overwrote it, it doesn't load and a fresh one is started.
+//!
+//!   magic u32 | version u32 | reset reason u32 | boot count u32 |
+//!   request u32 | bootloader version u32 | panic line u32 |
+//!   panic column u32 | panic file [48] | crc u32
+
+#![no_std]
+
+use chocos_isp::crc32;
+
+pub const ADDR: usize = 0x2000_F500;
+pub const AREA_END: usize = 0x2001_0000;
+pub const MAGIC: u32 = u32::from_le_bytes(*b"HOFF");
+/// Bumped whenever the layout changes; an older block is dropped
+pub const VERSION: u32 = 1;
+pub const SIZE: usize = 8 * 4 + PANIC_FILE_LEN + 4;
+pub const PANIC_FILE_LEN: usize = 48;
+
+const _: () = assert!(ADDR + SIZE <= AREA_END);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResetReason {
+    PowerOn,
+    /// The reset pin, e.g. the reset button
+    Pin,
+    /// `SCB::sys_reset`, after a panic or a requested reboot
+    Software,
+    IndependentWatchdog,
+    WindowWatchdog,
+    LowPower,
+    Unknown,
+}
+
+impl ResetReason {
+    /// From the reset flags in RCC_CSR. A software or watchdog reset also
+    /// sets the pin flag, so that one counts last.
+    pub fn from_csr(csr: u32) -> ResetReason {
+        const FLAGS: [(u32, ResetReason); 6] = [
+            (27, ResetReason::PowerOn),
+            (31, ResetReason::LowPower),
+            (30, ResetReason::WindowWatchdog),
+            (29, ResetReason::IndependentWatchdog),
+            (28, ResetReason::Software),
+            (26, ResetReason::Pin),
+        ];
+        FLAGS.iter().find(|(bit, _)| csr & (1 << bit) != 0).map_or(ResetReason::Unknown, |(_, reason)| *reason)
+    }
+
+    fn code(self) -> u32 {
+        match self {
+            ResetReason::PowerOn => 1,
+            ResetReason::Pin => 2,
+            ResetReason::Software => 3,
+            ResetReason::IndependentWatchdog => 4,
+            ResetReason::WindowWatchdog => 5,
+            ResetReason::LowPower => 6,
+            ResetReason::Unknown => 0,
+        }
+    }
+
+    fn from_code(code: u32) -> ResetReason {
+        match code {
+            1 => ResetReason::PowerOn,
+            2 => ResetReason::Pin,
+            3 => ResetReason::Software,
+            4 => ResetReason::IndependentWatchdog,
+            5 => ResetReason::WindowWatchdog,
+            6 => ResetReason::LowPower,
+            _ => ResetReason::Unknown,
+        }
+    }
+}
+
+/// What the bootloader should do on the next boot
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Request {
+    None,
+    /// Stay in flash mode
+    Recovery,
+    /// Start the OS in this slot (0 = A, 1 = B) if it is bootable, no
+    /// matter which version is newer
+    BootSlot(usize),
+}
+
+impl Request {
+    fn code(self) -> u32 {
+        match self {
+            Request::None => 0,
+            Request::Recovery => 1,
+            Request::BootSlot(slot) => 0x100 | slot as u32,
+        }
+    }
+
+    fn from_code(code: u32) -> Request {
+        match code {
+            1 => Request::Recovery,
+            0x100 | 0x101 => Request::BootSlot((code & 0xFF) as usize),
+            _ => Request::None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PanicLocation {
+    file: [u8; PANIC_FILE_LEN],
+    pub line: u32,
+    pub column: u32,
+}
+
+impl PanicLocation {
+    /// Long paths keep their end, the file name is what matters.
+    pub fn new(file: &str, line: u32, column: u32) -> Self {
+        let mut tail = file.len().saturating_sub(PANIC_FILE_LEN);
+        while !file.is_char_boundary(tail) {
+            tail += 1;
+        }
+        let mut buf = [0; PANIC_FILE_LEN];
+        buf[..file.len() - tail].copy_from_slice(&file.as_bytes()[tail..]);
+        PanicLocation { file: buf, line, column }
+    }
+
+    pub fn file(&self) -> &str {
+        let len = self.file.iter().position(|b| *b == 0).unwrap_or(PANIC_FILE_LEN);
+        core::str::from_utf8(&self.file[..len]).unwrap_or("?")
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Handoff {
+    pub reset_reason: ResetReason,
+    /// Boots since power on
+    pub boot_count: u32,
+    pub request: Request,
+    /// Major and minor of the bootloader that started the OS
+    pub bootloader_version: (u8, u8),
+    pub panic: Option<PanicLocation>,
+}
+
+impl Default for Handoff {
+    fn default() -> Self {
+        Handoff {
+            reset_reason: ResetReason::Unknown,
+            boot_count: 0,
+            request: Request::None,
+            bootloader_version: (0, 0),
+            panic: None,
+        }
+    }
+}
+
+fn word(raw: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([raw[4 * i], raw[4 * i + 1], raw[4 * i + 2], raw[4 * i + 3]])
+}
+
+impl Handoff {
+    pub fn encode(&self) -> [u8; SIZE] {
+        let (line, column, file) = match &self.panic {
+            Some(panic) => (panic.line, panic.column, panic.file),
+            None => (0, 0, [0; PANIC_FILE_LEN]),
+        };
+        let (major, minor) = self.bootloader_version;
+        let words = [
+            MAGIC,
+            VERSION,
+            self.reset_reason.code(),
+            self.boot_count,
+            self.request.code(),
+            (major as u32) << 8 | minor as u32,
+            line,
+            column,
+        ];
+
+        let mut raw = [0; SIZE];
+        for (i, w) in words.iter().enumerate() {
+            raw[4 * i..4 * i + 4].copy_from_slice(&w.to_le_bytes());
+        }
+        raw[32..32 + PANIC_FILE_LEN].copy_from_slice(&file);
+        let crc = crc32(&raw[..SIZE - 4]);
+        raw[SIZE - 4..].copy_from_slice(&crc.to_le_bytes());
+        raw
+    }
+
+    /// `None` unless magic, version and CRC match
+    pub fn decode(raw: &[u8; SIZE]) -> Option<Handoff> {
+        if word(raw, 0) != MAGIC || word(raw, 1) != VERSION || crc32(&raw[..SIZE - 4]) != word(raw, SIZE / 4 - 1) {
+            return None;
+        }
+        let version = word(raw, 5);
+        let (line, column) = (word(raw, 6), word(raw, 7));
+        let panic = if line == 0 {
+            None
+        } else {
+            let mut file = [0; PANIC_FILE_LEN];
+            file.copy_from_slice(&raw[32..32 + PANIC_FILE_LEN]);
+            Some(PanicLocation { file, line, column })
+        };
+        Some(Handoff {
+            reset_reason: ResetReason::from_code(word(raw, 2)),
+            boot_count: word(raw, 3),
+            request: Request::from_code(word(raw, 4)),
+            bootloader_version: ((version >> 8) as u8, version as u8),
+            panic,
+        })
+    }
+
+    /// The block in SRAM, if there is a valid one.
+    ///
+    /// # Safety
+    ///
+    /// Only on the device, where `ADDR` is the reserved SRAM.
+    pub unsafe fn load() -> Option<Handoff> {
+        let mut raw = [0; SIZE];
+        for (i, b) in raw.iter_mut().enumerate() {
+            *b = core::ptr::read_volatile((ADDR + i) as *const u8);
+        }
+        Handoff::decode(&raw)
+    }
+
+    /// Writes the block to SRAM.
+    ///
+    /// # Safety
+    ///
+    /// As for `load`.
+    pub unsafe fn store(&self) {
+        for (i, b) in self.encode().iter().enumerate() {
+            core::ptr::write_volatile((ADDR + i) as *mut u8, *b);
+        }
+    }
+}
+
+/// Changes the block in SRAM, starting a fresh one if there is none.
+///
+/// # Safety
+///
+/// As for `Handoff::load`.
+pub unsafe fn update(f: impl FnOnce(&mut Handoff)) {
+    let mut handoff = Handoff::load().unwrap_or_default();
+    f(&mut handoff);
+    handoff.store();
+}
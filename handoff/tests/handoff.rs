@@ -0,0 +1,66 @@
+use chocos_handoff::{Handoff, PanicLocation, Request, ResetReason, PANIC_FILE_LEN, SIZE};
+
+fn handoff() -> Handoff {
+    Handoff {
+        reset_reason: ResetReason::Software,
+        boot_count: 7,
+        request: Request::BootSlot(1),
+        bootloader_version: (0, 1),
+        panic: Some(PanicLocation::new("src/task_scheduler.rs", 120, 9)),
+    }
+}
+
+#[test]
+fn round_trip() {
+    let raw = handoff().encode();
+    assert_eq!(Handoff::decode(&raw), Some(handoff()));
+
+    let fresh = Handoff::default();
+    assert_eq!(Handoff::decode(&fresh.encode()), Some(fresh));
+}
+
+#[test]
+fn whatever_power_on_leaves_is_dropped() {
+    assert_eq!(Handoff::decode(&[0; SIZE]), None);
+    assert_eq!(Handoff::decode(&[0xFF; SIZE]), None);
+
+    // any change in the block is caught
+    for i in 0..SIZE {
+        let mut raw = handoff().encode();
+        raw[i] ^= 0x10;
+        assert_eq!(Handoff::decode(&raw), None, "byte {}", i);
+    }
+}
+
+#[test]
+fn panic_location() {
+    let panic = PanicLocation::new("src/main.rs", 42, 5);
+    assert_eq!(panic.file(), "src/main.rs");
+
+    let long = "/home/someone/.cargo/registry/src/github.com-1ecc6299db9ec823/stm32f1xx-hal-0.8.0/src/usb.rs";
+    let panic = PanicLocation::new(long, 1, 1);
+    assert_eq!(panic.file().len(), PANIC_FILE_LEN);
+    assert!(panic.file().ends_with("stm32f1xx-hal-0.8.0/src/usb.rs"));
+
+    // never cut into a character
+    let panic = PanicLocation::new(&"ä".repeat(PANIC_FILE_LEN), 1, 1);
+    assert_eq!(panic.file(), "ä".repeat(PANIC_FILE_LEN / 2));
+}
+
+#[test]
+fn reset_reason_from_flags() {
+    assert_eq!(ResetReason::from_csr(0x0C00_0000), ResetReason::PowerOn);
+    assert_eq!(ResetReason::from_csr(0x1400_0000), ResetReason::Software);
+    assert_eq!(ResetReason::from_csr(0x2400_0000), ResetReason::IndependentWatchdog);
+    assert_eq!(ResetReason::from_csr(0x0400_0000), ResetReason::Pin);
+    assert_eq!(ResetReason::from_csr(0), ResetReason::Unknown);
+}
+
+#[test]
+fn unknown_requests_do_nothing() {
+    let mut raw = Handoff { request: Request::BootSlot(2), ..handoff() }.encode();
+    assert_eq!(Handoff::decode(&raw).unwrap().request, Request::None);
+
+    raw = Handoff { request: Request::Recovery, ..handoff() }.encode();
+    assert_eq!(Handoff::decode(&raw).unwrap().request, Request::Recovery);
+}
@@ -0,0 +1,49 @@
+//! For the build scripts of the bootloader and the OS (feature `build`):
+//! both embed the public key signed images are checked with.
+
+extern crate std;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::string::ToString;
+use std::{format, println};
+
+use crate::sign::PUBLIC_KEY_SIZE;
+
+/// Writes `public_key.rs` to `out` with `PUBLIC_KEY`, read as hex from the
+/// file named by CHOCOS_PUBLIC_KEY or keys/chocos.pub (see `chocflash
+/// keygen`). Only a dev-mode build may go without one.
+pub fn write_public_key(out: &Path) {
+    println!("cargo:rerun-if-env-changed=CHOCOS_PUBLIC_KEY");
+    let path = env::var("CHOCOS_PUBLIC_KEY").unwrap_or_else(|_| "../keys/chocos.pub".to_string());
+    println!("cargo:rerun-if-changed={}", path);
+
+    let key = match fs::read_to_string(&path) {
+        Ok(hex) => match parse_public_key(&hex) {
+            Some(bytes) => format!("Some({:?})", bytes),
+            None => panic!("{}: expected {} hex digits", path, 2 * PUBLIC_KEY_SIZE),
+        },
+        Err(_) if env::var_os("CARGO_FEATURE_DEV_MODE").is_some() => "None".to_string(),
+        Err(e) => panic!(
+            "no public key at {} ({}): create one with `chocflash keygen`, \
+             point CHOCOS_PUBLIC_KEY at it, or build with --features dev-mode",
+            path, e
+        ),
+    };
+    let source = format!("pub static PUBLIC_KEY: Option<[u8; {}]> = {};\n", PUBLIC_KEY_SIZE, key);
+    fs::write(out.join("public_key.rs"), source).unwrap();
+}
+
+/// A public key file: one line of hex
+pub fn parse_public_key(text: &str) -> Option<[u8; PUBLIC_KEY_SIZE]> {
+    let hex = text.trim();
+    if hex.len() != 2 * PUBLIC_KEY_SIZE || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut key = [0; PUBLIC_KEY_SIZE];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(key)
+}
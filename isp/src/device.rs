@@ -0,0 +1,182 @@
+// Device side of the protocol: request handling and the report state machine
+
+use crate::crc32::crc32;
+use crate::flash::{Flash, FlashError};
+use crate::protocol::{cmd, read_u32, DeviceInfo, Request, Response, Status, MAX_DATA, PROTOCOL_VERSION, REPORT_SIZE};
+
+/// The two HID endpoints, as seen by the protocol.
+pub trait ReportIo {
+    /// Takes the next report from the host, if one has arrived, and
+    /// returns its length.
+    fn read_report(&mut self, buf: &mut [u8; REPORT_SIZE]) -> Option<usize>;
+
+    /// Hands a report to the host. `false` while the endpoint is still
+    /// busy with the previous one; the report is offered again later.
+    fn write_report(&mut self, report: &[u8; REPORT_SIZE]) -> bool;
+}
+
+/// What the caller has to do after a poll
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Action {
+    None,
+    /// The response to RESET is on its way, restart once it has gone out
+    Reset,
+}
+
+pub struct Isp<F> {
+    flash: F,
+    version: (u8, u8),
+    // response that didn't fit into the endpoint yet
+    pending: Option<([u8; REPORT_SIZE], Action)>,
+}
+
+impl<F: Flash> Isp<F> {
+    pub fn new(flash: F, version: (u8, u8)) -> Self {
+        Isp { flash, version, pending: None }
+    }
+
+    pub fn flash(&self) -> &F {
+        &self.flash
+    }
+
+    pub fn flash_mut(&mut self) -> &mut F {
+        &mut self.flash
+    }
+
+    pub fn info(&self) -> DeviceInfo {
+        let layout = self.flash.layout();
+        DeviceInfo {
+            protocol_version: PROTOCOL_VERSION,
+            bootloader_version: self.version,
+            page_size: layout.page_size,
+            flash_base: layout.base,
+            flash_size: layout.size,
+            writable_start: layout.writable_start,
+            os_addr: layout.os_addr,
+        }
+    }
+
+    /// Runs one step: sends the pending response, or takes the next request,
+    /// handles it and starts sending its response. Only one request is in
+    /// flight at a time, a new one is not read before the last answer is out.
+    pub fn poll<R: ReportIo>(&mut self, io: &mut R) -> Action {
+        if self.pending.is_none() {
+            let mut buf = [0u8; REPORT_SIZE];
+            let req = match io.read_report(&mut buf) {
+                Some(n) => Request::parse(&buf[..n.min(REPORT_SIZE)]),
+                None => None,
+            };
+            if let Some(req) = req {
+                let (res, action) = self.handle(&req);
+                self.pending = Some((res.to_bytes(), action));
+            }
+        }
+
+        match self.pending {
+            Some((report, action)) if io.write_report(&report) => {
+                self.pending = None;
+                action
+            }
+            _ => Action::None,
+        }
+    }
+
+    pub fn handle(&mut self, req: &Request) -> (Response, Action) {
+        match req.cmd {
+            cmd::INFO => (self.handle_info(req), Action::None),
+            cmd::ERASE_PAGE => (self.erase_page(req), Action::None),
+            cmd::WRITE => (self.write(req), Action::None),
+            cmd::READ => (self.read(req), Action::None),
+            cmd::VERIFY => (self.verify(req), Action::None),
+            cmd::RESET => (Response::new(req, Status::Ok), Action::Reset),
+            _ => (Response::new(req, Status::UnknownCommand), Action::None),
+        }
+    }
+
+    fn handle_info(&self, req: &Request) -> Response {
+        let mut res = Response::new(req, Status::Ok);
+        let info = self.info().to_bytes();
+        res.data[..info.len()].copy_from_slice(&info);
+        res.len = info.len() as u8;
+        res
+    }
+
+    fn erase_page(&mut self, req: &Request) -> Response {
+        let layout = self.flash.layout();
+        if !layout.is_readable(req.addr, layout.page_size) {
+            return Response::new(req, Status::BadAddress);
+        }
+        if !layout.is_page_aligned(req.addr) {
+            return Response::new(req, Status::BadAlignment);
+        }
+        if !layout.is_writable(req.addr, layout.page_size) {
+            return Response::new(req, Status::BadAddress);
+        }
+        result(req, self.flash.erase_page(req.addr))
+    }
+
+    // The chunk CRC covers the payload only. A mismatch means the report was
+    // damaged on the way, nothing has been written and the host may resend.
+    fn write(&mut self, req: &Request) -> Response {
+        let layout = self.flash.layout();
+        let len = req.len as u32;
+        if len == 0 || len as usize > MAX_DATA || !layout.is_write_aligned(len) {
+            return Response::new(req, Status::BadLength);
+        }
+        if !layout.is_write_aligned(req.addr) {
+            return Response::new(req, Status::BadAlignment);
+        }
+        if !layout.is_writable(req.addr, len) {
+            return Response::new(req, Status::BadAddress);
+        }
+        if crc32(req.payload()) != req.crc {
+            return Response::new(req, Status::CrcMismatch);
+        }
+        result(req, self.flash.write(req.addr, req.payload()))
+    }
+
+    fn read(&self, req: &Request) -> Response {
+        let len = req.len as usize;
+        if len == 0 || len > MAX_DATA {
+            return Response::new(req, Status::BadLength);
+        }
+        if !self.flash.layout().is_readable(req.addr, len as u32) {
+            return Response::new(req, Status::BadAddress);
+        }
+        let mut res = Response::new(req, Status::Ok);
+        let data = self.flash.read(req.addr, len);
+        res.data[..len].copy_from_slice(data);
+        res.len = len as u8;
+        res.value = crc32(data);
+        res
+    }
+
+    // Checks a whole image in one go. The image length is the first word of
+    // data, `crc` the expected CRC-32; `value` of the response carries the
+    // CRC the device computed.
+    fn verify(&self, req: &Request) -> Response {
+        let len = read_u32(&req.data[0..4]);
+        if len == 0 {
+            return Response::new(req, Status::BadLength);
+        }
+        if !self.flash.layout().is_readable(req.addr, len) {
+            return Response::new(req, Status::BadAddress);
+        }
+        let actual = crc32(self.flash.read(req.addr, len as usize));
+
+        let mut res = Response::new(req, if actual == req.crc { Status::Ok } else { Status::VerifyFailed });
+        res.value = actual;
+        res
+    }
+}
+
+fn result(req: &Request, result: Result<(), FlashError>) -> Response {
+    let status = match result {
+        Ok(()) => Status::Ok,
+        Err(FlashError::OutOfRange) => Status::BadAddress,
+        Err(FlashError::Misaligned) => Status::BadAlignment,
+        Err(FlashError::Verify) => Status::VerifyFailed,
+        Err(FlashError::Erase) | Err(FlashError::Program) => Status::FlashError,
+    };
+    Response::new(req, status)
+}
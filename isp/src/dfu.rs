@@ -0,0 +1,346 @@
+// USB DFU 1.1 device side
+//
+// Flash mode can expose a standard DFU interface next to the HID protocol,
+// so `dfu-util` works without chocflash. Every alternate setting of the
+// interface is one flash region (see `Region::STM32F103`). A download is
+// written from the start of the region, block after block, erasing pages
+// as it reaches them; an upload reads the whole region.
+//
+// The OS slots take sealed (and signed) images from `chocflash seal` or
+// `chocflash sign`. After the last block ("manifestation") an OS image is
+// checked like the bootloader checks it before booting, a bad one ends in
+// dfuERROR with errFIRMWARE. The device is manifestation tolerant and
+// stays in dfuIDLE; DFU_DETACH or a USB reset afterwards restarts it.
+//
+// Control transfers never wait for the flash: a block stays in dfuDNBUSY
+// until the caller's `poll` writes it, GETSTATUS tells the host how long
+// to wait.
+
+use crate::device::Action;
+use crate::flash::{Flash, FlashError};
+use crate::image::{check_os, Policy};
+
+/// wTransferSize, the largest block of one DNLOAD or UPLOAD
+pub const TRANSFER_SIZE: usize = 256;
+pub const DFU_VERSION: u16 = 0x0110;
+pub const DETACH_TIMEOUT_MS: u16 = 255;
+
+// Interface class, subclass and protocol of a device in DFU mode
+pub const CLASS: u8 = 0xFE;
+pub const SUBCLASS: u8 = 0x01;
+pub const PROTOCOL: u8 = 0x02;
+pub const FUNCTIONAL_DESCRIPTOR_TYPE: u8 = 0x21;
+
+// bitCanDnload | bitCanUpload | bitManifestationTolerant | bitWillDetach
+const ATTRIBUTES: u8 = 0x0F;
+
+// What GETSTATUS asks the host to wait, generous for the STM32F1
+const ERASE_MS: u32 = 40;
+const WRITE_MS: u32 = 10;
+const MANIFEST_MS: u32 = 500;
+
+pub mod request {
+    pub const DETACH: u8 = 0;
+    pub const DNLOAD: u8 = 1;
+    pub const UPLOAD: u8 = 2;
+    pub const GETSTATUS: u8 = 3;
+    pub const CLRSTATUS: u8 = 4;
+    pub const GETSTATE: u8 = 5;
+    pub const ABORT: u8 = 6;
+}
+
+/// bState, the DFU mode states this device goes through
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    Idle = 2,
+    DnloadSync = 3,
+    DnBusy = 4,
+    DnloadIdle = 5,
+    ManifestSync = 6,
+    Manifest = 7,
+    UploadIdle = 9,
+    Error = 10,
+}
+
+/// bStatus
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    Ok = 0x00,
+    ErrErase = 0x04,
+    ErrProg = 0x06,
+    ErrVerify = 0x07,
+    ErrAddress = 0x08,
+    ErrFirmware = 0x0A,
+    ErrStalledPkt = 0x0F,
+}
+
+/// One alternate setting
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    /// Shown by `dfu-util -l`
+    pub name: &'static str,
+    /// File name (without extension) that picks the region over YMODEM
+    pub file: &'static str,
+    pub start: u32,
+    pub size: u32,
+    /// Holds an OS image that is checked after the download
+    pub os: bool,
+}
+
+impl Region {
+    pub const STM32F103: [Region; 4] = [
+        Region { name: "OS slot A", file: "os-a", start: 0x0801_0000, size: 0x1_0000, os: true },
+        Region { name: "OS slot B", file: "os-b", start: 0x0806_0000, size: 0x1_0000, os: true },
+        Region { name: "Programs", file: "programs", start: 0x0802_0000, size: 0x4_0000, os: false },
+        Region { name: "Keymap", file: "keymap", start: 0x0807_0000, size: 0x800, os: false },
+    ];
+}
+
+/// The DFU functional descriptor, without length and type
+pub fn functional_descriptor() -> [u8; 7] {
+    let [detach_lo, detach_hi] = DETACH_TIMEOUT_MS.to_le_bytes();
+    let [size_lo, size_hi] = (TRANSFER_SIZE as u16).to_le_bytes();
+    let [version_lo, version_hi] = DFU_VERSION.to_le_bytes();
+    [ATTRIBUTES, detach_lo, detach_hi, size_lo, size_hi, version_lo, version_hi]
+}
+
+pub struct Dfu<'a> {
+    regions: &'a [Region],
+    policy: Policy<'a>,
+    alt: usize,
+    state: State,
+    status: Status,
+    // bytes of the current download or upload so far
+    offset: u32,
+    // everything below is erased in this download
+    erased_to: u32,
+    block: [u8; TRANSFER_SIZE],
+    block_len: usize,
+    // a block or the manifestation waits for `poll`
+    pending: bool,
+    // a download went through, the next USB reset boots it
+    manifested: bool,
+}
+
+impl<'a> Dfu<'a> {
+    pub fn new(regions: &'a [Region], policy: Policy<'a>) -> Self {
+        Dfu {
+            regions,
+            policy,
+            alt: 0,
+            state: State::Idle,
+            status: Status::Ok,
+            offset: 0,
+            erased_to: 0,
+            block: [0; TRANSFER_SIZE],
+            block_len: 0,
+            pending: false,
+            manifested: false,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    pub fn alt(&self) -> usize {
+        self.alt
+    }
+
+    pub fn regions(&self) -> &'a [Region] {
+        self.regions
+    }
+
+    /// The region of the current alternate setting
+    pub fn region(&self) -> Region {
+        self.regions[self.alt]
+    }
+
+    /// SET_INTERFACE, only between transfers
+    pub fn set_alt(&mut self, alt: usize) -> bool {
+        if alt >= self.regions.len() || !matches!(self.state, State::Idle | State::Error) {
+            return false;
+        }
+        self.alt = alt;
+        true
+    }
+
+    /// DFU_DNLOAD with the next block, an empty one ends the download.
+    /// `false` stalls the request.
+    pub fn download(&mut self, data: &[u8]) -> bool {
+        match self.state {
+            State::Idle if !data.is_empty() => {
+                self.offset = 0;
+                self.erased_to = self.region().start;
+            }
+            State::DnloadIdle => {}
+            _ => return self.stall(),
+        }
+        if data.len() > TRANSFER_SIZE {
+            return self.stall();
+        }
+        self.block[..data.len()].copy_from_slice(data);
+        self.block_len = data.len();
+        self.pending = true;
+        self.state = if data.is_empty() { State::ManifestSync } else { State::DnloadSync };
+        true
+    }
+
+    /// DFU_UPLOAD: copies the next part of the region into `buf`. `memory`
+    /// is the content of the current region. A short block ends the upload.
+    pub fn upload(&mut self, memory: &[u8], buf: &mut [u8]) -> Option<usize> {
+        match self.state {
+            State::Idle => self.offset = 0,
+            State::UploadIdle => {}
+            _ => {
+                self.stall();
+                return None;
+            }
+        }
+        let rest = &memory[(self.offset as usize).min(memory.len())..];
+        let len = buf.len().min(rest.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        self.offset += len as u32;
+        self.state = if len < buf.len() { State::Idle } else { State::UploadIdle };
+        Some(len)
+    }
+
+    /// DFU_GETSTATUS: status, poll timeout, state and no status string.
+    /// Moves on from the sync states once `poll` has done the work.
+    pub fn get_status(&mut self) -> [u8; 6] {
+        let mut timeout = 0;
+        match self.state {
+            State::DnloadSync | State::DnBusy if self.pending => {
+                self.state = State::DnBusy;
+                timeout = WRITE_MS;
+                if self.region().start + self.offset + self.block_len as u32 > self.erased_to {
+                    timeout += ERASE_MS;
+                }
+            }
+            State::DnloadSync | State::DnBusy => self.state = State::DnloadIdle,
+            State::ManifestSync | State::Manifest if self.pending => {
+                self.state = State::Manifest;
+                timeout = MANIFEST_MS;
+            }
+            State::ManifestSync | State::Manifest => {
+                self.state = State::Idle;
+                self.manifested = true;
+            }
+            _ => {}
+        }
+        let [t0, t1, t2, _] = timeout.to_le_bytes();
+        [self.status as u8, t0, t1, t2, self.state as u8, 0]
+    }
+
+    /// DFU_GETSTATE
+    pub fn get_state(&self) -> u8 {
+        self.state as u8
+    }
+
+    /// DFU_CLRSTATUS, leaves dfuERROR
+    pub fn clear_status(&mut self) -> bool {
+        if self.state != State::Error {
+            return self.stall();
+        }
+        self.state = State::Idle;
+        self.status = Status::Ok;
+        true
+    }
+
+    /// DFU_ABORT, drops a download or upload that is not busy
+    pub fn abort(&mut self) -> bool {
+        match self.state {
+            State::Idle | State::DnloadSync | State::DnloadIdle | State::ManifestSync | State::UploadIdle => {
+                self.state = State::Idle;
+                self.pending = false;
+                true
+            }
+            _ => self.stall(),
+        }
+    }
+
+    /// DFU_DETACH. The device detaches itself (bitWillDetach): restart once
+    /// the request is acknowledged.
+    pub fn detach(&mut self) -> Action {
+        Action::Reset
+    }
+
+    /// A USB reset after a finished download restarts into the new image.
+    pub fn usb_reset(&mut self) -> Action {
+        if self.manifested {
+            Action::Reset
+        } else {
+            Action::None
+        }
+    }
+
+    /// Does the flash work requested by the last DNLOAD, outside of any
+    /// control transfer.
+    pub fn poll<F: Flash>(&mut self, flash: &mut F) {
+        if !self.pending {
+            return;
+        }
+        self.pending = false;
+        let result = match self.state {
+            State::DnloadSync | State::DnBusy => self.write_block(flash),
+            State::ManifestSync | State::Manifest => self.manifest(flash),
+            _ => Ok(()),
+        };
+        if let Err(status) = result {
+            self.state = State::Error;
+            self.status = status;
+        }
+    }
+
+    fn write_block<F: Flash>(&mut self, flash: &mut F) -> Result<(), Status> {
+        let region = self.region();
+        let layout = flash.layout();
+        let addr = region.start + self.offset;
+        // an odd last block is padded with erased bytes
+        let len = self.block_len;
+        let align = layout.write_align as usize;
+        let padded = (len + align - 1) / align * align;
+        for b in &mut self.block[len..padded] {
+            *b = 0xFF;
+        }
+
+        // only the last block may be short, the next one would be misaligned
+        if self.offset as usize + padded > region.size as usize
+            || !layout.is_write_aligned(addr)
+            || !layout.is_writable(addr, padded as u32)
+        {
+            return Err(Status::ErrAddress);
+        }
+        while self.erased_to < addr + padded as u32 {
+            flash.erase_page(self.erased_to).map_err(|_| Status::ErrErase)?;
+            self.erased_to += layout.page_size;
+        }
+        flash.write(addr, &self.block[..padded]).map_err(|e| match e {
+            FlashError::Verify => Status::ErrVerify,
+            FlashError::OutOfRange | FlashError::Misaligned => Status::ErrAddress,
+            FlashError::Erase | FlashError::Program => Status::ErrProg,
+        })?;
+        self.offset += len as u32;
+        Ok(())
+    }
+
+    fn manifest<F: Flash>(&mut self, flash: &F) -> Result<(), Status> {
+        let region = self.region();
+        if region.os {
+            check_os(flash.read(region.start, region.size as usize), region.start, &self.policy)
+                .map_err(|_| Status::ErrFirmware)?;
+        }
+        Ok(())
+    }
+
+    fn stall(&mut self) -> bool {
+        self.state = State::Error;
+        self.status = Status::ErrStalledPkt;
+        self.pending = false;
+        false
+    }
+}
@@ -0,0 +1,142 @@
+// Flash backend of the protocol and an in-memory model of it
+
+/// Geometry of the device flash and which part of it flash mode may touch.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    pub base: u32,
+    pub size: u32,
+    /// Smallest erasable unit
+    pub page_size: u32,
+    /// Writes have to start and end on a multiple of this
+    pub write_align: u32,
+    /// Everything below belongs to the bootloader and is never erased or written
+    pub writable_start: u32,
+    pub os_addr: u32,
+}
+
+impl Layout {
+    /// STM32F103 high density: 512K in 2K pages, half word programming,
+    /// the bootloader in the first 64K.
+    pub const STM32F103: Layout = Layout {
+        base: 0x0800_0000,
+        size: 512 * 1024,
+        page_size: 2048,
+        write_align: 2,
+        writable_start: 0x0801_0000,
+        os_addr: 0x0801_0000,
+    };
+
+    pub fn end(&self) -> u32 {
+        self.base + self.size
+    }
+
+    pub fn is_readable(&self, addr: u32, len: u32) -> bool {
+        addr >= self.base && addr.checked_add(len).map_or(false, |end| end <= self.end())
+    }
+
+    pub fn is_writable(&self, addr: u32, len: u32) -> bool {
+        addr >= self.writable_start && self.is_readable(addr, len)
+    }
+
+    pub fn is_page_aligned(&self, addr: u32) -> bool {
+        (addr - self.base) % self.page_size == 0
+    }
+
+    pub fn is_write_aligned(&self, value: u32) -> bool {
+        value % self.write_align == 0
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FlashError {
+    OutOfRange,
+    Misaligned,
+    Erase,
+    Program,
+    /// Programmed, but reading back gives different data
+    Verify,
+}
+
+pub trait Flash {
+    fn layout(&self) -> Layout;
+
+    /// Erases the page starting at `addr`.
+    fn erase_page(&mut self, addr: u32) -> Result<(), FlashError>;
+
+    /// Programs `data` at `addr`. Both are aligned to `Layout::write_align`
+    /// and the target has been erased.
+    fn write(&mut self, addr: u32, data: &[u8]) -> Result<(), FlashError>;
+
+    /// `addr..addr + len` is inside the flash.
+    fn read(&self, addr: u32, len: usize) -> &[u8];
+}
+
+/// Flash model over a plain buffer, with the rules of the real controller:
+/// erase works on whole pages, programming on aligned units, and a unit
+/// can only be programmed once after an erase.
+pub struct MemFlash<B> {
+    layout: Layout,
+    mem: B,
+    pub erase_count: u32,
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> MemFlash<B> {
+    /// `mem` has to be `layout.size` bytes. It is erased here.
+    pub fn new(layout: Layout, mut mem: B) -> Self {
+        assert_eq!(mem.as_ref().len(), layout.size as usize);
+        for b in mem.as_mut().iter_mut() {
+            *b = 0xFF;
+        }
+        MemFlash { layout, mem, erase_count: 0 }
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        self.mem.as_ref()
+    }
+
+    fn offset(&self, addr: u32) -> usize {
+        (addr - self.layout.base) as usize
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> Flash for MemFlash<B> {
+    fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    fn erase_page(&mut self, addr: u32) -> Result<(), FlashError> {
+        if !self.layout.is_readable(addr, self.layout.page_size) {
+            return Err(FlashError::OutOfRange);
+        }
+        if !self.layout.is_page_aligned(addr) {
+            return Err(FlashError::Misaligned);
+        }
+        let off = self.offset(addr);
+        for b in &mut self.mem.as_mut()[off..off + self.layout.page_size as usize] {
+            *b = 0xFF;
+        }
+        self.erase_count += 1;
+        Ok(())
+    }
+
+    fn write(&mut self, addr: u32, data: &[u8]) -> Result<(), FlashError> {
+        if !self.layout.is_readable(addr, data.len() as u32) {
+            return Err(FlashError::OutOfRange);
+        }
+        if !self.layout.is_write_aligned(addr) || !self.layout.is_write_aligned(data.len() as u32) {
+            return Err(FlashError::Misaligned);
+        }
+        let off = self.offset(addr);
+        let target = &mut self.mem.as_mut()[off..off + data.len()];
+        if target.iter().any(|b| *b != 0xFF) {
+            return Err(FlashError::Program);
+        }
+        target.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn read(&self, addr: u32, len: usize) -> &[u8] {
+        let off = self.offset(addr);
+        &self.mem.as_ref()[off..off + len]
+    }
+}
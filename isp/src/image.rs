@@ -0,0 +1,147 @@
+// OS image header
+//
+// The OS carries a 16 byte header at a fixed offset behind its vector
+// table (see os/memory.x). The linker fills in magic, length and version;
+// the CRC is patched in after the build by `chocflash seal` or
+// `chocflash flash --os`. The CRC-32 covers the first `length` bytes of
+// the image with the CRC field itself read as zero.
+//
+//   magic u32 | length u32 | crc u32 | version u32
+//
+// The version is major << 16 | minor << 8 | patch of the OS crate. A
+// signature trailer may follow the image, see `sign`.
+
+use core::fmt;
+
+use crate::crc32::Crc32;
+use crate::sign::{check_signature, PUBLIC_KEY_SIZE, TRAILER_SIZE};
+
+pub const OS_MAGIC: u32 = u32::from_le_bytes(*b"CHOS");
+pub const OS_HEADER_OFFSET: usize = 0x1F0;
+pub const OS_HEADER_SIZE: usize = 16;
+// What the linker leaves in the CRC field of a fresh build
+pub const UNSEALED_CRC: u32 = 0xFFFF_FFFF;
+
+const CRC_FIELD: usize = OS_HEADER_OFFSET + 8;
+
+// Where the initial stack pointer of a bootable image may point
+const RAM_START: u32 = 0x2000_0000;
+const RAM_END: u32 = 0x2001_0000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OsHeader {
+    pub magic: u32,
+    pub length: u32,
+    pub crc: u32,
+    pub version: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageError {
+    /// No header magic, nothing (or something else) is flashed there
+    NoHeader,
+    /// The length in the header doesn't fit the image or the region
+    BadLength,
+    /// Built, but the CRC was never filled in
+    Unsealed,
+    CrcMismatch { expected: u32, actual: u32 },
+    /// Stack pointer or reset vector point nowhere useful
+    BadVectors,
+    /// No signature trailer behind the image
+    Unsigned,
+    BadSignature,
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::NoHeader => write!(f, "no OS image header"),
+            ImageError::BadLength => write!(f, "image length in the header is out of range"),
+            ImageError::Unsealed => write!(f, "image is not sealed"),
+            ImageError::CrcMismatch { expected, actual } => {
+                write!(f, "image crc is {:#010x}, header says {:#010x}", actual, expected)
+            }
+            ImageError::BadVectors => write!(f, "bad stack pointer or reset vector"),
+            ImageError::Unsigned => write!(f, "image is not signed"),
+            ImageError::BadSignature => write!(f, "image signature does not match the key"),
+        }
+    }
+}
+
+/// What the bootloader accepts besides a complete, sealed and signed image
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Policy<'a> {
+    /// The key signatures are checked with
+    pub key: Option<&'a [u8; PUBLIC_KEY_SIZE]>,
+    /// Images whose CRC was never filled in, for loading with a debugger
+    pub allow_unsealed: bool,
+    /// Images without a signature, or with one there is no key to check for
+    pub allow_unsigned: bool,
+}
+
+impl OsHeader {
+    pub fn parse(image: &[u8]) -> Option<OsHeader> {
+        let raw = image.get(OS_HEADER_OFFSET..OS_HEADER_OFFSET + OS_HEADER_SIZE)?;
+        let word = |i: usize| u32::from_le_bytes([raw[i], raw[i + 1], raw[i + 2], raw[i + 3]]);
+        Some(OsHeader { magic: word(0), length: word(4), crc: word(8), version: word(12) })
+    }
+}
+
+/// CRC of the first `len` bytes of `image`, with the CRC field as zero.
+pub fn image_crc(image: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(&image[..CRC_FIELD]);
+    crc.update(&[0; 4]);
+    crc.update(&image[CRC_FIELD + 4..]);
+    crc.finish()
+}
+
+fn header(image: &[u8]) -> Result<OsHeader, ImageError> {
+    let header = OsHeader::parse(image).ok_or(ImageError::NoHeader)?;
+    if header.magic != OS_MAGIC {
+        return Err(ImageError::NoHeader);
+    }
+    let length = header.length as usize;
+    if length < OS_HEADER_OFFSET + OS_HEADER_SIZE || length > image.len() {
+        return Err(ImageError::BadLength);
+    }
+    Ok(header)
+}
+
+/// Checks the OS image in `region`, which is flashed at `base`, and the
+/// signature behind it.
+pub fn check_os(region: &[u8], base: u32, policy: &Policy) -> Result<OsHeader, ImageError> {
+    let header = header(region)?;
+    let length = header.length as usize;
+    let image = &region[..length];
+
+    if header.crc == UNSEALED_CRC {
+        if !policy.allow_unsealed {
+            return Err(ImageError::Unsealed);
+        }
+    } else {
+        let actual = image_crc(image);
+        if actual != header.crc {
+            return Err(ImageError::CrcMismatch { expected: header.crc, actual });
+        }
+    }
+
+    let word = |i: usize| u32::from_le_bytes([image[i], image[i + 1], image[i + 2], image[i + 3]]);
+    let sp = word(0);
+    let reset = word(4) & !1;
+    if !(RAM_START..=RAM_END).contains(&sp) || reset < base || reset >= base + header.length {
+        return Err(ImageError::BadVectors);
+    }
+
+    check_signature(image, region.get(length..length + TRAILER_SIZE), policy)?;
+    Ok(header)
+}
+
+/// Fills in the CRC of a freshly built image. `image` may be longer than
+/// the length in the header, the rest is not covered.
+pub fn seal_os(image: &mut [u8]) -> Result<OsHeader, ImageError> {
+    let mut header = header(image)?;
+    header.crc = image_crc(&image[..header.length as usize]);
+    image[CRC_FIELD..CRC_FIELD + 4].copy_from_slice(&header.crc.to_le_bytes());
+    Ok(header)
+}
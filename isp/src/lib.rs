@@ -0,0 +1,40 @@
+//! Flash mode ("ISP") protocol core of the ChocOS bootloader.
+//!
+//! The bootloader only provides a `Flash` backend for the STM32 flash
+//! controller and a `ReportIo` over its HID endpoints; command parsing,
+//! checks and the request/response state machine live here, so they run
+//! unchanged on a host against `MemFlash`. chocflash uses the same types
+//! for the host side of the protocol.
+//!
+//! `image` describes the header the bootloader checks on the OS image
+//! before booting it, and how chocflash seals a freshly built image.
+//! `slots` picks which of the two OS slots to boot and keeps track of
+//! boot attempts and confirmations. `sign` signs images and checks their
+//! Ed25519 signatures. `dfu` is the standard USB DFU 1.1 interface of
+//! flash mode, for `dfu-util`, and `ymodem` its serial fallback. `usb` is
+//! the USB identity the bootloader, the OS and chocflash agree on.
+//! `build`, with the feature of the same name, is shared by the build
+//! scripts of the bootloader and the OS.
+
+#![no_std]
+// Has to build with the bootloader's pinned nightly, which predates
+// `is_multiple_of`, `div_ceil` and `Option::is_some_and`.
+#![allow(clippy::manual_is_multiple_of, clippy::manual_div_ceil, clippy::unnecessary_map_or)]
+
+#[cfg(feature = "build")]
+pub mod build;
+pub mod crc32;
+pub mod device;
+pub mod dfu;
+pub mod flash;
+pub mod image;
+pub mod protocol;
+pub mod sign;
+pub mod slots;
+pub mod usb;
+pub mod ymodem;
+
+pub use crc32::{crc32, Crc32};
+pub use device::{Action, Isp, ReportIo};
+pub use flash::{Flash, FlashError, Layout, MemFlash};
+pub use protocol::{DeviceInfo, Request, Response, Status};
@@ -0,0 +1,269 @@
+use core::fmt;
+
+// Flash mode protocol
+//
+// Every transfer is one 64 byte HID report, host to device on the OUT
+// endpoint and device to host on the IN endpoint. The device answers every
+// request with exactly one response carrying the same command and sequence
+// number. All integers are little endian, all addresses absolute.
+//
+// Request:  cmd u8 | seq u8 | len u8 | reserved u8 | addr u32 | crc u32 | data[52]
+// Response: cmd u8 | seq u8 | status u8 | len u8  | addr u32 | value u32 | data[52]
+
+pub const REPORT_SIZE: usize = 64;
+pub const HEADER_SIZE: usize = 12;
+pub const MAX_DATA: usize = REPORT_SIZE - HEADER_SIZE;
+
+pub const PROTOCOL_VERSION: u8 = 1;
+
+// Vendor defined page 0xFF00 with one 64 byte input and one 64 byte
+// output report, no report IDs.
+pub const REPORT_DESCRIPTOR: &[u8] = &[
+    0x06, 0x00, 0xFF, // Usage Page (Vendor Defined 0xFF00)
+    0x09, 0x01,       // Usage (0x01)
+    0xA1, 0x01,       // Collection (Application)
+    0x09, 0x02,       //   Usage (0x02)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x75, 0x08,       //   Report Size (8)
+    0x95, 0x40,       //   Report Count (64)
+    0x81, 0x02,       //   Input (Data, Var, Abs)
+    0x09, 0x03,       //   Usage (0x03)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x75, 0x08,       //   Report Size (8)
+    0x95, 0x40,       //   Report Count (64)
+    0x91, 0x02,       //   Output (Data, Var, Abs)
+    0xC0,             // End Collection
+];
+
+pub mod cmd {
+    pub const INFO: u8 = 0x01;
+    pub const ERASE_PAGE: u8 = 0x02;
+    pub const WRITE: u8 = 0x03;
+    pub const READ: u8 = 0x04;
+    pub const VERIFY: u8 = 0x05;
+    pub const RESET: u8 = 0x06;
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Status {
+    Ok,
+    UnknownCommand,
+    BadAddress,
+    BadAlignment,
+    BadLength,
+    CrcMismatch,
+    FlashError,
+    VerifyFailed,
+    Unknown(u8),
+}
+
+impl Status {
+    pub fn from_u8(code: u8) -> Status {
+        match code {
+            0 => Status::Ok,
+            1 => Status::UnknownCommand,
+            2 => Status::BadAddress,
+            3 => Status::BadAlignment,
+            4 => Status::BadLength,
+            5 => Status::CrcMismatch,
+            6 => Status::FlashError,
+            7 => Status::VerifyFailed,
+            n => Status::Unknown(n),
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            Status::Ok => 0,
+            Status::UnknownCommand => 1,
+            Status::BadAddress => 2,
+            Status::BadAlignment => 3,
+            Status::BadLength => 4,
+            Status::CrcMismatch => 5,
+            Status::FlashError => 6,
+            Status::VerifyFailed => 7,
+            Status::Unknown(n) => n,
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Ok => write!(f, "ok"),
+            Status::UnknownCommand => write!(f, "unknown command"),
+            Status::BadAddress => write!(f, "bad address"),
+            Status::BadAlignment => write!(f, "bad alignment"),
+            Status::BadLength => write!(f, "bad length"),
+            Status::CrcMismatch => write!(f, "crc mismatch"),
+            Status::FlashError => write!(f, "flash error"),
+            Status::VerifyFailed => write!(f, "verify failed"),
+            Status::Unknown(n) => write!(f, "unknown status {}", n),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub cmd: u8,
+    pub seq: u8,
+    pub len: u8,
+    pub addr: u32,
+    pub crc: u32,
+    pub data: [u8; MAX_DATA],
+}
+
+impl Request {
+    pub fn new(cmd: u8, addr: u32) -> Request {
+        Request { cmd, seq: 0, len: 0, addr, crc: 0, data: [0; MAX_DATA] }
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.data[..(self.len as usize).min(MAX_DATA)]
+    }
+
+    pub fn with_data(mut self, data: &[u8]) -> Request {
+        self.data[..data.len()].copy_from_slice(data);
+        self.len = data.len() as u8;
+        self
+    }
+
+    pub fn to_bytes(&self) -> [u8; REPORT_SIZE] {
+        let mut buf = [0u8; REPORT_SIZE];
+        buf[0] = self.cmd;
+        buf[1] = self.seq;
+        buf[2] = self.len;
+        buf[4..8].copy_from_slice(&self.addr.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.crc.to_le_bytes());
+        buf[HEADER_SIZE..].copy_from_slice(&self.data);
+        buf
+    }
+
+    // A report cut short by the host is padded with zeros, `len` decides
+    // how much of the data counts.
+    pub fn parse(buf: &[u8]) -> Option<Request> {
+        if buf.len() < HEADER_SIZE {
+            return None;
+        }
+        let mut data = [0u8; MAX_DATA];
+        let n = (buf.len() - HEADER_SIZE).min(MAX_DATA);
+        data[..n].copy_from_slice(&buf[HEADER_SIZE..HEADER_SIZE + n]);
+        Some(Request {
+            cmd: buf[0],
+            seq: buf[1],
+            len: buf[2],
+            addr: read_u32(&buf[4..8]),
+            crc: read_u32(&buf[8..12]),
+            data,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Response {
+    pub cmd: u8,
+    pub seq: u8,
+    pub status: Status,
+    pub len: u8,
+    pub addr: u32,
+    pub value: u32,
+    pub data: [u8; MAX_DATA],
+}
+
+impl Response {
+    pub fn new(req: &Request, status: Status) -> Response {
+        Response {
+            cmd: req.cmd,
+            seq: req.seq,
+            status,
+            len: 0,
+            addr: req.addr,
+            value: 0,
+            data: [0; MAX_DATA],
+        }
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.data[..(self.len as usize).min(MAX_DATA)]
+    }
+
+    pub fn to_bytes(&self) -> [u8; REPORT_SIZE] {
+        let mut buf = [0u8; REPORT_SIZE];
+        buf[0] = self.cmd;
+        buf[1] = self.seq;
+        buf[2] = self.status.code();
+        buf[3] = self.len;
+        buf[4..8].copy_from_slice(&self.addr.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.value.to_le_bytes());
+        buf[HEADER_SIZE..].copy_from_slice(&self.data);
+        buf
+    }
+
+    pub fn parse(buf: &[u8]) -> Option<Response> {
+        if buf.len() < REPORT_SIZE {
+            return None;
+        }
+        let mut data = [0u8; MAX_DATA];
+        data.copy_from_slice(&buf[HEADER_SIZE..REPORT_SIZE]);
+        Some(Response {
+            cmd: buf[0],
+            seq: buf[1],
+            status: Status::from_u8(buf[2]),
+            len: buf[3],
+            addr: read_u32(&buf[4..8]),
+            value: read_u32(&buf[8..12]),
+            data,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub protocol_version: u8,
+    pub bootloader_version: (u8, u8),
+    pub page_size: u32,
+    pub flash_base: u32,
+    pub flash_size: u32,
+    pub writable_start: u32,
+    pub os_addr: u32,
+}
+
+impl DeviceInfo {
+    pub fn parse(data: &[u8]) -> Option<DeviceInfo> {
+        if data.len() < 24 {
+            return None;
+        }
+        Some(DeviceInfo {
+            protocol_version: data[0],
+            bootloader_version: (data[1], data[2]),
+            page_size: read_u32(&data[4..8]),
+            flash_base: read_u32(&data[8..12]),
+            flash_size: read_u32(&data[12..16]),
+            writable_start: read_u32(&data[16..20]),
+            os_addr: read_u32(&data[20..24]),
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; 24] {
+        let mut buf = [0u8; 24];
+        buf[0] = self.protocol_version;
+        buf[1] = self.bootloader_version.0;
+        buf[2] = self.bootloader_version.1;
+        buf[4..8].copy_from_slice(&self.page_size.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.flash_base.to_le_bytes());
+        buf[12..16].copy_from_slice(&self.flash_size.to_le_bytes());
+        buf[16..20].copy_from_slice(&self.writable_start.to_le_bytes());
+        buf[20..24].copy_from_slice(&self.os_addr.to_le_bytes());
+        buf
+    }
+
+    pub fn flash_end(&self) -> u32 {
+        self.flash_base + self.flash_size
+    }
+}
+
+pub fn read_u32(buf: &[u8]) -> u32 {
+    u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]])
+}
@@ -0,0 +1,62 @@
+// Image signatures
+//
+// Signed OS and app images carry a trailer right behind the signed bytes:
+//
+//   magic u32 | Ed25519 signature [64]
+//
+// For the OS the signature covers the sealed image (CRC included) up to
+// the length in its header, for apps everything from the app header up
+// to the end address in it. The bootloader and the kernel check it with
+// the public key they were built with.
+
+use ed25519_compact::{KeyPair, PublicKey, Seed, Signature};
+
+use crate::image::{ImageError, Policy};
+
+pub const SIGNATURE_MAGIC: u32 = u32::from_le_bytes(*b"CSIG");
+pub const SIGNATURE_SIZE: usize = 64;
+pub const TRAILER_SIZE: usize = 4 + SIGNATURE_SIZE;
+pub const PUBLIC_KEY_SIZE: usize = 32;
+pub const SECRET_KEY_SIZE: usize = 32;
+
+fn parse_trailer(trailer: &[u8]) -> Option<Signature> {
+    let magic = trailer.get(0..4)?;
+    if magic != SIGNATURE_MAGIC.to_le_bytes() {
+        return None;
+    }
+    Signature::from_slice(trailer.get(4..TRAILER_SIZE)?).ok()
+}
+
+/// Checks the signature of `signed` in `trailer`, the bytes behind it
+/// (`None` if the image ends at the end of its region).
+pub fn check_signature(signed: &[u8], trailer: Option<&[u8]>, policy: &Policy) -> Result<(), ImageError> {
+    let signature = match trailer.and_then(parse_trailer) {
+        Some(signature) => signature,
+        None if policy.allow_unsigned => return Ok(()),
+        None => return Err(ImageError::Unsigned),
+    };
+    match policy.key {
+        Some(key) => PublicKey::new(*key).verify(signed, &signature).map_err(|_| ImageError::BadSignature),
+        // nothing to check it with
+        None if policy.allow_unsigned => Ok(()),
+        None => Err(ImageError::Unsigned),
+    }
+}
+
+/// Public key of the secret key (seed) `secret`, `None` for an all zero
+/// secret.
+pub fn public_key(secret: &[u8; SECRET_KEY_SIZE]) -> Option<[u8; PUBLIC_KEY_SIZE]> {
+    let pair = KeyPair::try_from_seed(Seed::new(*secret)).ok()?;
+    Some(*pair.pk)
+}
+
+/// The trailer to append to `signed`.
+pub fn sign(signed: &[u8], secret: &[u8; SECRET_KEY_SIZE]) -> Option<[u8; TRAILER_SIZE]> {
+    let pair = KeyPair::try_from_seed(Seed::new(*secret)).ok()?;
+    let signature = pair.sk.sign(signed, None);
+
+    let mut trailer = [0; TRAILER_SIZE];
+    trailer[0..4].copy_from_slice(&SIGNATURE_MAGIC.to_le_bytes());
+    trailer[4..].copy_from_slice(&signature[..]);
+    Some(trailer)
+}
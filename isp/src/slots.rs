@@ -0,0 +1,247 @@
+// A/B OS slots
+//
+// The OS can live in either of two slots; every build is linked for one
+// of them (feature `slot-b` of the OS). The bootloader starts the image
+// with the highest version; of two with the same version the one flashed
+// last, which is the one on trial or else the one confirmed last. A new
+// image is on trial until the OS confirms it with the `confirm_boot`
+// system call: every start of an unconfirmed image is counted, and once
+// it has been started MAX_ATTEMPTS times without confirming, the
+// bootloader falls back to the other slot.
+//
+// Attempts and confirmations are appended to a log in one flash page, so
+// counting a boot only ever programs erased flash:
+//
+//   tag u32 | image crc u32
+//
+// Records name the image by its CRC, so an image flashed into a slot
+// starts out unconfirmed even if the slot held a confirmed one before.
+
+use crate::flash::{Flash, FlashError};
+use crate::image::{check_os, ImageError, OsHeader, Policy, OS_HEADER_OFFSET, OS_HEADER_SIZE, OS_MAGIC};
+
+/// Starts of an unconfirmed image before it counts as broken
+pub const MAX_ATTEMPTS: u32 = 3;
+pub const RECORD_SIZE: usize = 8;
+
+const TAG_ATTEMPT: u32 = 0xB007_A000;
+const TAG_CONFIRM: u32 = 0xB007_C000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SlotLayout {
+    pub slots: [u32; 2],
+    pub slot_size: u32,
+    /// The flash page holding the boot log
+    pub log_page: u32,
+}
+
+impl SlotLayout {
+    /// Slot A where the OS has always been, slot B in the reserved area
+    /// behind the user programs and the log in the last page of flash.
+    pub const STM32F103: SlotLayout = SlotLayout {
+        slots: [0x0801_0000, 0x0806_0000],
+        slot_size: 0x1_0000,
+        log_page: 0x0807_F800,
+    };
+
+    /// The slot `addr` lies in
+    pub fn slot_of(&self, addr: u32) -> Option<usize> {
+        self.slots.iter().position(|&start| addr >= start && addr - start < self.slot_size)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Record {
+    Attempt { slot: usize, crc: u32 },
+    Confirm { slot: usize, crc: u32 },
+}
+
+impl Record {
+    fn parse(raw: &[u8]) -> Option<Record> {
+        let tag = u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]);
+        let crc = u32::from_le_bytes([raw[4], raw[5], raw[6], raw[7]]);
+        let slot = (tag & 0xFF) as usize;
+        match tag & !0xFF {
+            _ if slot > 1 => None,
+            TAG_ATTEMPT => Some(Record::Attempt { slot, crc }),
+            TAG_CONFIRM => Some(Record::Confirm { slot, crc }),
+            _ => None,
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; RECORD_SIZE] {
+        let (tag, slot, crc) = match self {
+            Record::Attempt { slot, crc } => (TAG_ATTEMPT, slot, crc),
+            Record::Confirm { slot, crc } => (TAG_CONFIRM, slot, crc),
+        };
+        let mut raw = [0; RECORD_SIZE];
+        raw[0..4].copy_from_slice(&(tag | slot as u32).to_le_bytes());
+        raw[4..8].copy_from_slice(&crc.to_le_bytes());
+        raw
+    }
+}
+
+fn is_erased(raw: &[u8]) -> bool {
+    raw.iter().all(|b| *b == 0xFF)
+}
+
+/// The records in the log page, oldest first. Records torn by a reset
+/// in the middle of programming are skipped.
+pub fn records(page: &[u8]) -> impl Iterator<Item = Record> + '_ {
+    page.chunks_exact(RECORD_SIZE).take_while(|raw| !is_erased(raw)).filter_map(Record::parse)
+}
+
+/// Whether the image with `crc` in `slot` is confirmed, and how often it
+/// has been started.
+pub fn tally(page: &[u8], slot: usize, crc: u32) -> (bool, u32) {
+    records(page).fold((false, 0), |(confirmed, attempts), record| match record {
+        Record::Attempt { slot: s, crc: c } if s == slot && c == crc => (confirmed, attempts + 1),
+        Record::Confirm { slot: s, crc: c } if s == slot && c == crc => (true, attempts),
+        _ => (confirmed, attempts),
+    })
+}
+
+// Where in the log the image with `crc` in `slot` was confirmed; an image
+// confirmed later was flashed later.
+fn confirmed_at(page: &[u8], slot: usize, crc: u32) -> Option<usize> {
+    records(page).position(|record| record == Record::Confirm { slot, crc })
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SlotState {
+    pub addr: u32,
+    pub image: Result<OsHeader, ImageError>,
+    pub confirmed: bool,
+    pub attempts: u32,
+    /// Index of the confirmation in the log, to tell which of two confirmed
+    /// images is the newer one
+    pub confirmed_at: Option<usize>,
+}
+
+impl SlotState {
+    /// A valid image that is confirmed or has attempts left
+    pub fn is_bootable(&self) -> bool {
+        self.image.is_ok() && (self.confirmed || self.attempts < MAX_ATTEMPTS)
+    }
+
+    pub fn version(&self) -> Option<u32> {
+        self.image.ok().map(|header| header.version)
+    }
+}
+
+pub fn slot_states<F: Flash>(flash: &F, layout: &SlotLayout, policy: &Policy) -> [SlotState; 2] {
+    let page = flash.read(layout.log_page, flash.layout().page_size as usize);
+    let state = |slot: usize| {
+        let addr = layout.slots[slot];
+        let image = check_os(flash.read(addr, layout.slot_size as usize), addr, policy);
+        let (confirmed, attempts) = image.map_or((false, 0), |header| tally(page, slot, header.crc));
+        let confirmed_at = image.ok().and_then(|header| confirmed_at(page, slot, header.crc));
+        SlotState { addr, image, confirmed, attempts, confirmed_at }
+    };
+    [state(0), state(1)]
+}
+
+/// The order the bootloader tries the slots in: highest version first. On
+/// a tie the image on trial, so a build flashed without a version bump
+/// still gets tried, then the one confirmed last, then slot A.
+pub fn boot_order(states: &[SlotState; 2]) -> [usize; 2] {
+    let rank = |s: &SlotState| (s.version(), !s.confirmed, s.confirmed_at);
+    if rank(&states[1]) > rank(&states[0]) {
+        [1, 0]
+    } else {
+        [0, 1]
+    }
+}
+
+/// Picks the slot to boot and counts the attempt if its image is still on
+/// trial. Returns the address of the slot, or `None` if nothing can be
+/// booted.
+pub fn prepare_boot<F: Flash>(flash: &mut F, layout: &SlotLayout, policy: &Policy) -> Option<u32> {
+    prepare_boot_from(flash, layout, policy, None)
+}
+
+/// `prepare_boot`, but tries `first` before the usual order, e.g. when the
+/// OS asked for a slot through the handoff block.
+pub fn prepare_boot_from<F: Flash>(flash: &mut F, layout: &SlotLayout, policy: &Policy, first: Option<usize>) -> Option<u32> {
+    let states = slot_states(flash, layout, policy);
+    let order = match first {
+        Some(slot) if slot < 2 => [slot, 1 - slot],
+        _ => boot_order(&states),
+    };
+    for slot in order {
+        let state = states[slot];
+        let header = match state.image {
+            Ok(header) if state.is_bootable() => header,
+            _ => continue,
+        };
+        if state.confirmed {
+            return Some(state.addr);
+        }
+        // an attempt that can't be counted could end in a boot loop
+        if append(flash, layout, Record::Attempt { slot, crc: header.crc }).is_ok() {
+            return Some(state.addr);
+        }
+    }
+    None
+}
+
+/// Marks the image with `crc` in `slot` as good, called by the OS once it
+/// is up. Confirming twice writes nothing.
+pub fn confirm<F: Flash>(flash: &mut F, layout: &SlotLayout, slot: usize, crc: u32) -> Result<(), FlashError> {
+    let page = flash.read(layout.log_page, flash.layout().page_size as usize);
+    if tally(page, slot, crc).0 {
+        return Ok(());
+    }
+    append(flash, layout, Record::Confirm { slot, crc })
+}
+
+fn append<F: Flash>(flash: &mut F, layout: &SlotLayout, record: Record) -> Result<(), FlashError> {
+    let page = flash.read(layout.log_page, flash.layout().page_size as usize);
+    let offset = match page.chunks_exact(RECORD_SIZE).position(is_erased) {
+        Some(index) => index * RECORD_SIZE,
+        None => compact(flash, layout)?,
+    };
+    flash.write(layout.log_page + offset as u32, &record.to_bytes())
+}
+
+// CRC in the header of the image in `slot`, without checking the image
+fn header_crc<F: Flash>(flash: &F, layout: &SlotLayout, slot: usize) -> Option<u32> {
+    let raw = flash.read(layout.slots[slot], OS_HEADER_OFFSET + OS_HEADER_SIZE);
+    OsHeader::parse(raw).filter(|header| header.magic == OS_MAGIC).map(|header| header.crc)
+}
+
+// Rewrites a full log with only the records about the images in the slots
+// now. Returns where the next record goes. A reset in between loses the
+// log: the images start over on trial and the OS confirms them again.
+fn compact<F: Flash>(flash: &mut F, layout: &SlotLayout) -> Result<usize, FlashError> {
+    let mut live = [Record::Attempt { slot: 0, crc: 0 }; 2 * (MAX_ATTEMPTS as usize + 1)];
+    let mut count = 0;
+
+    let page = flash.read(layout.log_page, flash.layout().page_size as usize);
+    let crcs = [header_crc(flash, layout, 0), header_crc(flash, layout, 1)];
+    let at = |slot: usize| crcs[slot].and_then(|crc| confirmed_at(page, slot, crc));
+    // confirmations keep their order, it picks between equal versions
+    let order = if at(1) < at(0) { [1, 0] } else { [0, 1] };
+    for slot in order {
+        let crc = match crcs[slot] {
+            Some(crc) => crc,
+            None => continue,
+        };
+        let (confirmed, attempts) = tally(page, slot, crc);
+        if confirmed {
+            live[count] = Record::Confirm { slot, crc };
+            count += 1;
+        } else {
+            for _ in 0..attempts.min(MAX_ATTEMPTS) {
+                live[count] = Record::Attempt { slot, crc };
+                count += 1;
+            }
+        }
+    }
+
+    flash.erase_page(layout.log_page)?;
+    for (i, record) in live[..count].iter().enumerate() {
+        flash.write(layout.log_page + (i * RECORD_SIZE) as u32, &record.to_bytes())?;
+    }
+    Ok(count * RECORD_SIZE)
+}
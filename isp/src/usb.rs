@@ -0,0 +1,78 @@
+// USB identity of the board, the same in flash mode and in the OS
+//
+// VID, PID and the strings are set when the bootloader and the OS are
+// built, from these environment variables, so a batch of boards can carry
+// its own without a patch:
+//
+//   CHOCOS_USB_VID, CHOCOS_USB_PID       hex, e.g. 0x16c0
+//   CHOCOS_USB_MANUFACTURER
+//   CHOCOS_USB_PRODUCT                   the OS
+//   CHOCOS_USB_RECOVERY_PRODUCT          flash mode
+//
+// Both have to be built with the same ones, chocflash too, since it finds
+// the board by them. The serial number is the chip's 96 bit unique ID in
+// hex, so boards on one host can be told apart.
+
+pub const VID: u16 = hex_or(option_env!("CHOCOS_USB_VID"), 0x16c0);
+pub const PID: u16 = hex_or(option_env!("CHOCOS_USB_PID"), 0x27dd);
+pub const MANUFACTURER: &str = str_or(option_env!("CHOCOS_USB_MANUFACTURER"), "ChocOS");
+pub const PRODUCT: &str = str_or(option_env!("CHOCOS_USB_PRODUCT"), "ChocOS Keyboard");
+pub const RECOVERY_PRODUCT: &str = str_or(option_env!("CHOCOS_USB_RECOVERY_PRODUCT"), "ChocOS Keyboard (Recovery)");
+
+/// Where the STM32F1 keeps its unique ID
+pub const UID_ADDRESS: u32 = 0x1FFF_F7E8;
+pub const UID_SIZE: usize = 12;
+/// Length of the serial number string, two hex digits per byte of the ID
+pub const SERIAL_SIZE: usize = 2 * UID_SIZE;
+
+/// Reads the unique ID of the chip.
+///
+/// # Safety
+///
+/// Only on the STM32F1, anywhere else the address is not the ID.
+pub unsafe fn read_uid() -> [u8; UID_SIZE] {
+    core::ptr::read_volatile(UID_ADDRESS as *const [u8; UID_SIZE])
+}
+
+/// The serial number string of the board with unique ID `uid`: upper case
+/// hex, in the order the bytes are in memory.
+pub fn serial_number<'a>(uid: &[u8; UID_SIZE], buf: &'a mut [u8; SERIAL_SIZE]) -> &'a str {
+    const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+    for (i, byte) in uid.iter().enumerate() {
+        buf[2 * i] = DIGITS[(byte >> 4) as usize];
+        buf[2 * i + 1] = DIGITS[(byte & 0x0F) as usize];
+    }
+    // only hex digits went in
+    core::str::from_utf8(buf).unwrap_or("")
+}
+
+const fn str_or(value: Option<&'static str>, default: &'static str) -> &'static str {
+    match value {
+        Some(value) => value,
+        None => default,
+    }
+}
+
+// A bad value stops the build
+const fn hex_or(value: Option<&'static str>, default: u16) -> u16 {
+    let digits = match value {
+        Some(value) => value.as_bytes(),
+        None => return default,
+    };
+    let mut i = if digits.len() > 2 && digits[0] == b'0' && (digits[1] == b'x' || digits[1] == b'X') { 2 } else { 0 };
+    if i == digits.len() || digits.len() - i > 4 {
+        panic!("a USB ID is 1 to 4 hex digits");
+    }
+    let mut id = 0;
+    while i < digits.len() {
+        let digit = match digits[i] {
+            b'0'..=b'9' => digits[i] - b'0',
+            b'a'..=b'f' => digits[i] - b'a' + 10,
+            b'A'..=b'F' => digits[i] - b'A' + 10,
+            _ => panic!("a USB ID is 1 to 4 hex digits"),
+        };
+        id = id << 4 | digit as u16;
+        i += 1;
+    }
+    id
+}
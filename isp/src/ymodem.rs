@@ -0,0 +1,316 @@
+// YMODEM receiver for the serial fallback of flash mode
+//
+// When no USB host shows up, the bootloader listens on USART1 instead and
+// takes images from any YMODEM sender (`sb`, minicom, Tera Term). The file
+// name picks the flash region (`Region::file`, e.g. `os-a.bin`), the data
+// is written from the start of the region, erasing pages as it reaches
+// them, with the same `Flash` backend as the HID protocol. Once a file is
+// complete an OS image is checked like the bootloader checks it before
+// booting; a bad one, an unknown name or a write error cancels the
+// transfer. Several files can go in one batch, the empty header closing
+// the batch restarts the board.
+//
+// Only CRC-16 mode: 128 byte (SOH) and 1024 byte (STX) blocks, each
+// answered with ACK or NAK. The receiver doesn't keep time itself, the
+// caller reports a quiet line with `timeout`.
+
+use crate::device::Action;
+use crate::dfu::Region;
+use crate::flash::Flash;
+use crate::image::{check_os, Policy};
+
+pub const SOH: u8 = 0x01;
+pub const STX: u8 = 0x02;
+pub const EOT: u8 = 0x04;
+pub const ACK: u8 = 0x06;
+pub const NAK: u8 = 0x15;
+pub const CAN: u8 = 0x18;
+/// Asks the sender for CRC-16 blocks
+pub const CRC_MODE: u8 = b'C';
+
+// start byte, block number and its complement, data, CRC
+const HEADER_LEN: usize = 3;
+const PACKET_MAX: usize = HEADER_LEN + 1024 + 2;
+// quiet periods in the middle of a file before giving up on it
+const MAX_RETRIES: u32 = 10;
+
+/// The serial line, as seen by the receiver.
+pub trait SerialIo {
+    /// The next byte from the host, if one has arrived.
+    fn read_byte(&mut self) -> Option<u8>;
+
+    fn write(&mut self, bytes: &[u8]);
+}
+
+/// CRC-16/XMODEM of a block
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for b in data {
+        crc ^= (*b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { crc << 1 ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    /// Waiting for the header block of the next file
+    Header,
+    /// Between the data blocks of a file
+    Data,
+    /// The first EOT was refused, waiting for the sender to repeat it
+    Eot,
+}
+
+pub struct Ymodem<'a> {
+    regions: &'a [Region],
+    policy: Policy<'a>,
+    state: State,
+    packet: [u8; PACKET_MAX],
+    len: usize,
+    // a CAN from the sender, a second one aborts
+    cancel: bool,
+    retries: u32,
+    // the current file
+    region: usize,
+    size: Option<u32>,
+    offset: u32,
+    erased_to: u32,
+    block: u8,
+    // files written in this batch
+    files: u32,
+}
+
+impl<'a> Ymodem<'a> {
+    pub fn new(regions: &'a [Region], policy: Policy<'a>) -> Self {
+        Ymodem {
+            regions,
+            policy,
+            state: State::Header,
+            packet: [0; PACKET_MAX],
+            len: 0,
+            cancel: false,
+            retries: 0,
+            region: 0,
+            size: None,
+            offset: 0,
+            erased_to: 0,
+            block: 0,
+            files: 0,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Files written and checked so far
+    pub fn files(&self) -> u32 {
+        self.files
+    }
+
+    /// Takes whatever the host has sent and answers it. `Action::Reset`
+    /// once a batch with at least one file is over.
+    pub fn poll<F: Flash, S: SerialIo>(&mut self, flash: &mut F, io: &mut S) -> Action {
+        while let Some(b) = io.read_byte() {
+            if self.receive(b, flash, io) == Action::Reset {
+                return Action::Reset;
+            }
+        }
+        Action::None
+    }
+
+    /// Nothing arrived for about a second: asks for the next file again, or
+    /// drops a partial block and asks for it again.
+    pub fn timeout<S: SerialIo>(&mut self, io: &mut S) {
+        self.len = 0;
+        self.cancel = false;
+        match self.state {
+            State::Header => io.write(&[CRC_MODE]),
+            State::Data | State::Eot => {
+                self.retries += 1;
+                if self.retries > MAX_RETRIES {
+                    self.abort(io);
+                } else {
+                    io.write(&[NAK]);
+                }
+            }
+        }
+    }
+
+    fn receive<F: Flash, S: SerialIo>(&mut self, b: u8, flash: &mut F, io: &mut S) -> Action {
+        if self.len == 0 {
+            match b {
+                SOH | STX => {}
+                EOT => {
+                    self.end_of_file(flash, io);
+                    return Action::None;
+                }
+                CAN if self.cancel => {
+                    self.state = State::Header;
+                    self.cancel = false;
+                    return Action::None;
+                }
+                CAN => {
+                    self.cancel = true;
+                    return Action::None;
+                }
+                // line noise between blocks
+                _ => return Action::None,
+            }
+        }
+        self.cancel = false;
+        self.packet[self.len] = b;
+        self.len += 1;
+        let expected = if self.packet[0] == STX { 1024 } else { 128 } + HEADER_LEN + 2;
+        if self.len < expected {
+            return Action::None;
+        }
+        self.len = 0;
+        self.retries = 0;
+
+        let p = &self.packet[..expected];
+        let crc = u16::from_be_bytes([p[expected - 2], p[expected - 1]]);
+        if p[1] != !p[2] || crc16(&p[HEADER_LEN..expected - 2]) != crc {
+            io.write(&[NAK]);
+            return Action::None;
+        }
+        let (block, len) = (p[1], expected - HEADER_LEN - 2);
+        match self.state {
+            State::Header if block == 0 => self.header(len, io),
+            // the sender missed our ACK and repeats the last block
+            State::Data if block == self.block.wrapping_sub(1) => {
+                io.write(&[ACK]);
+                if block == 0 {
+                    io.write(&[CRC_MODE]);
+                }
+                Action::None
+            }
+            State::Data if block == self.block => {
+                match self.write_block(len, flash) {
+                    Ok(()) => {
+                        self.block = self.block.wrapping_add(1);
+                        io.write(&[ACK]);
+                    }
+                    Err(()) => self.abort(io),
+                }
+                Action::None
+            }
+            _ => {
+                self.abort(io);
+                Action::None
+            }
+        }
+    }
+
+    // Block 0: file name, NUL, size in decimal and optional fields. An
+    // empty name closes the batch.
+    fn header<S: SerialIo>(&mut self, len: usize, io: &mut S) -> Action {
+        let info = &self.packet[HEADER_LEN..HEADER_LEN + len];
+        let name_len = info.iter().position(|b| *b == 0).unwrap_or(len);
+        if name_len == 0 {
+            io.write(&[ACK]);
+            return if self.files > 0 { Action::Reset } else { Action::None };
+        }
+
+        let name = &info[..name_len];
+        let stem = &name[..name.iter().position(|b| *b == b'.').unwrap_or(name_len)];
+        let region = self.regions.iter().position(|r| r.file.as_bytes().eq_ignore_ascii_case(stem));
+        let size = parse_size(&info[(name_len + 1).min(len)..]);
+        let region = match region {
+            Some(region) if size.map_or(true, |size| size <= self.regions[region].size) => region,
+            _ => {
+                self.abort(io);
+                return Action::None;
+            }
+        };
+
+        self.region = region;
+        self.size = size;
+        self.offset = 0;
+        self.erased_to = self.regions[region].start;
+        self.block = 1;
+        self.state = State::Data;
+        io.write(&[ACK, CRC_MODE]);
+        Action::None
+    }
+
+    fn write_block<F: Flash>(&mut self, len: usize, flash: &mut F) -> Result<(), ()> {
+        let region = self.regions[self.region];
+        // the last block is padded, the header told how much of it counts
+        let len = match self.size {
+            Some(size) => (len as u32).min(size.saturating_sub(self.offset)) as usize,
+            None => len,
+        };
+        if len == 0 {
+            return Ok(());
+        }
+        if self.offset + len as u32 > region.size {
+            return Err(());
+        }
+
+        let layout = flash.layout();
+        let align = layout.write_align as usize;
+        let padded = (len + align - 1) / align * align;
+        let data = &mut self.packet[HEADER_LEN..HEADER_LEN + padded];
+        for b in &mut data[len..] {
+            *b = 0xFF;
+        }
+        let addr = region.start + self.offset;
+        if !layout.is_write_aligned(addr) || !layout.is_writable(addr, padded as u32) {
+            return Err(());
+        }
+        while self.erased_to < addr + padded as u32 {
+            flash.erase_page(self.erased_to).map_err(|_| ())?;
+            self.erased_to += layout.page_size;
+        }
+        flash.write(addr, data).map_err(|_| ())?;
+        self.offset += len as u32;
+        Ok(())
+    }
+
+    // The sender is done with the file. The first EOT is refused, some
+    // senders get it out of line noise; the second one ends the file.
+    fn end_of_file<F: Flash, S: SerialIo>(&mut self, flash: &F, io: &mut S) {
+        match self.state {
+            State::Data => {
+                self.state = State::Eot;
+                io.write(&[NAK]);
+            }
+            State::Eot => {
+                let region = self.regions[self.region];
+                let short = self.size.map_or(false, |size| self.offset < size);
+                let bad_os = region.os
+                    && check_os(flash.read(region.start, region.size as usize), region.start, &self.policy).is_err();
+                if short || bad_os {
+                    self.abort(io);
+                    return;
+                }
+                self.files += 1;
+                self.state = State::Header;
+                io.write(&[ACK, CRC_MODE]);
+            }
+            // the file was already taken, our ACK got lost
+            State::Header => io.write(&[ACK]),
+        }
+    }
+
+    // Cancels the transfer on both sides and waits for the next file.
+    fn abort<S: SerialIo>(&mut self, io: &mut S) {
+        self.state = State::Header;
+        self.len = 0;
+        self.retries = 0;
+        io.write(&[CAN, CAN]);
+    }
+}
+
+// Decimal, up to the first space or NUL
+fn parse_size(field: &[u8]) -> Option<u32> {
+    let digits = &field[..field.iter().position(|b| !b.is_ascii_digit()).unwrap_or(field.len())];
+    if digits.is_empty() {
+        return None;
+    }
+    digits.iter().try_fold(0u32, |n, d| n.checked_mul(10)?.checked_add((d - b'0') as u32))
+}
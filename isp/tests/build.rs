@@ -0,0 +1,14 @@
+use chocos_isp::build::parse_public_key;
+
+#[test]
+fn public_key_file() {
+    let hex = "00112233445566778899aabbccddeeffFFEEDDCCBBAA99887766554433221100";
+    let key = parse_public_key(&format!("{}\n", hex)).unwrap();
+    assert_eq!(key[..4], [0x00, 0x11, 0x22, 0x33]);
+    assert_eq!(key[16..20], [0xFF, 0xEE, 0xDD, 0xCC]);
+
+    assert_eq!(parse_public_key(&hex[2..]), None);
+    assert_eq!(parse_public_key(&format!("{}00", hex)), None);
+    assert_eq!(parse_public_key(&hex.replace("00112233", "+0112233")), None);
+    assert_eq!(parse_public_key(&hex.replace("0011", "00zz")), None);
+}
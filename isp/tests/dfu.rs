@@ -0,0 +1,146 @@
+use chocos_isp::dfu::{functional_descriptor, Dfu, Region, State, Status, TRANSFER_SIZE};
+use chocos_isp::image::{seal_os, Policy, OS_HEADER_OFFSET, OS_MAGIC, UNSEALED_CRC};
+use chocos_isp::{Action, Flash, Layout, MemFlash};
+
+const L: Layout = Layout::STM32F103;
+const POLICY: Policy = Policy { key: None, allow_unsealed: false, allow_unsigned: true };
+const SLOT_B: usize = 1;
+const PROGRAMS: usize = 2;
+
+fn flash() -> MemFlash<Vec<u8>> {
+    MemFlash::new(L, vec![0; L.size as usize])
+}
+
+fn dfu() -> Dfu<'static> {
+    Dfu::new(&Region::STM32F103, POLICY)
+}
+
+fn os_image(base: u32) -> Vec<u8> {
+    let len = 0x1234;
+    let mut image: Vec<u8> = (0..len).map(|i| (i * 7) as u8).collect();
+    image[0..4].copy_from_slice(&0x2000_E500u32.to_le_bytes());
+    image[4..8].copy_from_slice(&(base + 0x201).to_le_bytes());
+    let h = OS_HEADER_OFFSET;
+    image[h..h + 4].copy_from_slice(&OS_MAGIC.to_le_bytes());
+    image[h + 4..h + 8].copy_from_slice(&(len as u32).to_le_bytes());
+    image[h + 8..h + 12].copy_from_slice(&UNSEALED_CRC.to_le_bytes());
+    seal_os(&mut image).unwrap();
+    image
+}
+
+// What dfu-util does for `-D`: blocks, each followed by GETSTATUS until
+// the device is no longer busy, then an empty block.
+fn download(dfu: &mut Dfu, flash: &mut MemFlash<Vec<u8>>, image: &[u8]) -> [u8; 6] {
+    for block in image.chunks(TRANSFER_SIZE).chain(Some(&[][..])) {
+        assert!(dfu.download(block));
+        let mut status = dfu.get_status();
+        while status[4] == State::DnBusy as u8 || status[4] == State::Manifest as u8 {
+            assert!(u32::from_le_bytes([status[1], status[2], status[3], 0]) > 0);
+            dfu.poll(flash);
+            status = dfu.get_status();
+        }
+        if status[0] != Status::Ok as u8 {
+            return status;
+        }
+    }
+    dfu.get_status()
+}
+
+#[test]
+fn download_into_an_os_slot() {
+    let (mut dfu, mut flash) = (dfu(), flash());
+    let start = Region::STM32F103[SLOT_B].start;
+    flash.write(start + 0x2000, &[0; 4]).unwrap();
+
+    assert!(dfu.set_alt(SLOT_B));
+    let image = os_image(start);
+    let status = download(&mut dfu, &mut flash, &image);
+    assert_eq!(status, [Status::Ok as u8, 0, 0, 0, State::Idle as u8, 0]);
+    assert_eq!(flash.read(start, image.len()), &image[..]);
+    // pages are erased as the download reaches them, the rest is left alone
+    assert_eq!(flash.erase_count, 3);
+    assert_eq!(flash.read(start + 0x2000, 4), &[0; 4]);
+    assert_eq!(dfu.usb_reset(), Action::Reset);
+}
+
+#[test]
+fn broken_os_image_fails_manifestation() {
+    let (mut dfu, mut flash) = (dfu(), flash());
+    // linked for slot A
+    let image = os_image(Region::STM32F103[0].start);
+    assert!(dfu.set_alt(SLOT_B));
+    let status = download(&mut dfu, &mut flash, &image);
+    assert_eq!(status[0], Status::ErrFirmware as u8);
+    assert_eq!(dfu.state(), State::Error);
+    assert_eq!(dfu.usb_reset(), Action::None);
+
+    assert!(dfu.clear_status());
+    assert_eq!(dfu.state(), State::Idle);
+}
+
+#[test]
+fn programs_are_not_checked() {
+    let (mut dfu, mut flash) = (dfu(), flash());
+    assert!(dfu.set_alt(PROGRAMS));
+    // an odd length, the last byte is padded
+    let data = vec![0x5A; 1001];
+    assert_eq!(download(&mut dfu, &mut flash, &data)[0], Status::Ok as u8);
+    let start = Region::STM32F103[PROGRAMS].start;
+    assert_eq!(flash.read(start, 1002), &[&data[..], &[0xFF]].concat()[..]);
+}
+
+#[test]
+fn download_past_the_region() {
+    let (mut dfu, mut flash) = (dfu(), flash());
+    let region = Region::STM32F103[0];
+    let status = download(&mut dfu, &mut flash, &vec![0; region.size as usize + 2]);
+    assert_eq!(status[0], Status::ErrAddress as u8);
+    // the slot behind is untouched
+    assert!(flash.read(region.start + region.size, 2048).iter().all(|b| *b == 0xFF));
+}
+
+#[test]
+fn upload_reads_the_region() {
+    let (mut dfu, mut flash) = (dfu(), flash());
+    let region = Region::STM32F103[0];
+    flash.write(region.start, &[1, 2, 3, 4]).unwrap();
+    let memory = flash.read(region.start, region.size as usize);
+
+    let mut read = Vec::new();
+    let mut buf = [0; TRANSFER_SIZE];
+    loop {
+        let n = dfu.upload(memory, &mut buf).unwrap();
+        read.extend_from_slice(&buf[..n]);
+        if n < TRANSFER_SIZE {
+            break;
+        }
+    }
+    assert_eq!(read, memory);
+    assert_eq!(dfu.state(), State::Idle);
+}
+
+#[test]
+fn requests_out_of_place_stall() {
+    let mut dfu = dfu();
+    // an empty download before any data
+    assert!(!dfu.download(&[]));
+    assert_eq!(dfu.get_status()[..1], [Status::ErrStalledPkt as u8]);
+    assert!(dfu.clear_status());
+    assert!(!dfu.clear_status());
+    assert!(dfu.clear_status());
+
+    assert!(!dfu.set_alt(Region::STM32F103.len()));
+    assert!(dfu.download(&[0; 16]));
+    // no switching regions in the middle of a download
+    assert!(!dfu.set_alt(1));
+    assert!(dfu.abort());
+    assert_eq!(dfu.state(), State::Idle);
+    assert!(!dfu.download(&[0; TRANSFER_SIZE + 1]));
+}
+
+#[test]
+fn functional_descriptor_layout() {
+    // download, upload, manifestation tolerant, will detach; 255 ms;
+    // 256 byte blocks; DFU 1.1
+    assert_eq!(functional_descriptor(), [0x0F, 0xFF, 0x00, 0x00, 0x01, 0x10, 0x01]);
+}
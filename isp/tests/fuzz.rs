@@ -0,0 +1,103 @@
+// Throws random and half-valid reports at the protocol and checks that it
+// never panics, always answers, and never touches the bootloader pages.
+// Seeded, so a failure can be replayed.
+
+use std::collections::VecDeque;
+
+use chocos_isp::protocol::{REPORT_SIZE, HEADER_SIZE};
+use chocos_isp::{crc32, Flash, Isp, Layout, MemFlash, ReportIo, Response};
+
+const L: Layout = Layout::STM32F103;
+
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u32 {
+        // xorshift64*
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 32) as u32
+    }
+
+    fn below(&mut self, n: u32) -> u32 {
+        self.next() % n
+    }
+}
+
+#[derive(Default)]
+struct Pipe {
+    from_host: VecDeque<Vec<u8>>,
+    to_host: Vec<Response>,
+}
+
+impl ReportIo for Pipe {
+    fn read_report(&mut self, buf: &mut [u8; REPORT_SIZE]) -> Option<usize> {
+        let report = self.from_host.pop_front()?;
+        buf[..report.len()].copy_from_slice(&report);
+        Some(report.len())
+    }
+
+    fn write_report(&mut self, report: &[u8; REPORT_SIZE]) -> bool {
+        self.to_host.push(Response::parse(report).unwrap());
+        true
+    }
+}
+
+// Mostly well formed requests with random fields, so that the checks
+// behind the header are reached too.
+fn random_report(rng: &mut Rng) -> Vec<u8> {
+    let mut report = vec![0u8; REPORT_SIZE];
+    for b in report.iter_mut() {
+        *b = rng.next() as u8;
+    }
+    if rng.below(4) != 0 {
+        report[0] = 1 + rng.below(6) as u8;
+        // RESET is not interesting here
+        if report[0] == 6 {
+            report[0] = 3;
+        }
+        report[2] = rng.below(60) as u8;
+        let addr = match rng.below(3) {
+            0 => rng.next(),
+            1 => L.base + rng.below(L.size),
+            _ => L.base + rng.below(L.size / L.page_size) * L.page_size,
+        };
+        report[4..8].copy_from_slice(&addr.to_le_bytes());
+        if report[0] == 5 {
+            report[HEADER_SIZE..HEADER_SIZE + 4].copy_from_slice(&rng.below(0x1_0000).to_le_bytes());
+        }
+        if rng.below(2) == 0 {
+            let len = (report[2] as usize).min(REPORT_SIZE - HEADER_SIZE);
+            let crc = crc32(&report[HEADER_SIZE..HEADER_SIZE + len]);
+            report[8..12].copy_from_slice(&crc.to_le_bytes());
+        }
+    }
+    if rng.below(8) == 0 {
+        report.truncate(rng.below(REPORT_SIZE as u32) as usize);
+    }
+    report
+}
+
+#[test]
+fn random_reports() {
+    for seed in 1..=4u64 {
+        let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        let mut isp = Isp::new(MemFlash::new(L, vec![0; L.size as usize]), (0, 1));
+        let mut pipe = Pipe::default();
+
+        let mut expected = 0;
+        for _ in 0..2000 {
+            let report = random_report(&mut rng);
+            if report.len() >= HEADER_SIZE {
+                expected += 1;
+            }
+            pipe.from_host.push_back(report);
+            isp.poll(&mut pipe);
+        }
+
+        assert_eq!(pipe.to_host.len(), expected, "seed {}", seed);
+        let boot = isp.flash().read(L.base, (L.writable_start - L.base) as usize);
+        assert!(boot.iter().all(|b| *b == 0xFF), "seed {}", seed);
+    }
+}
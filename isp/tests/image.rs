@@ -0,0 +1,77 @@
+use chocos_isp::image::{check_os, image_crc, seal_os, ImageError, OsHeader, Policy, OS_HEADER_OFFSET, OS_MAGIC, UNSEALED_CRC};
+
+const BASE: u32 = 0x0801_0000;
+const REGION: usize = 0x1_0000;
+
+// signatures have their own tests
+const SEALED: Policy = Policy { key: None, allow_unsealed: false, allow_unsigned: true };
+const DEBUGGER: Policy = Policy { key: None, allow_unsealed: true, allow_unsigned: true };
+
+// What the linker produces: vectors, header with the CRC placeholder,
+// code, and erased flash behind the image.
+fn built_image(len: usize) -> Vec<u8> {
+    let mut region = vec![0xFF; REGION];
+    for (i, b) in region[..len].iter_mut().enumerate() {
+        *b = (i * 13) as u8;
+    }
+    region[0..4].copy_from_slice(&0x2000_E500u32.to_le_bytes());
+    region[4..8].copy_from_slice(&(BASE + 0x201).to_le_bytes());
+
+    let h = OS_HEADER_OFFSET;
+    region[h..h + 4].copy_from_slice(&OS_MAGIC.to_le_bytes());
+    region[h + 4..h + 8].copy_from_slice(&(len as u32).to_le_bytes());
+    region[h + 8..h + 12].copy_from_slice(&UNSEALED_CRC.to_le_bytes());
+    region[h + 12..h + 16].copy_from_slice(&0u32.to_le_bytes());
+    region
+}
+
+#[test]
+fn sealed_image_boots() {
+    let mut region = built_image(0x3000);
+    let header = seal_os(&mut region).unwrap();
+    assert_eq!(header.length, 0x3000);
+    assert_eq!(header.crc, image_crc(&region[..0x3000]));
+    assert_eq!(check_os(&region, BASE, &SEALED), Ok(OsHeader::parse(&region).unwrap()));
+}
+
+#[test]
+fn unsealed_image() {
+    let region = built_image(0x3000);
+    assert_eq!(check_os(&region, BASE, &SEALED), Err(ImageError::Unsealed));
+    assert!(check_os(&region, BASE, &DEBUGGER).is_ok());
+}
+
+#[test]
+fn erased_or_foreign_flash() {
+    assert_eq!(check_os(&vec![0xFF; REGION], BASE, &DEBUGGER), Err(ImageError::NoHeader));
+    assert_eq!(check_os(&[0u8; 16], BASE, &DEBUGGER), Err(ImageError::NoHeader));
+}
+
+#[test]
+fn interrupted_flashing() {
+    let mut region = built_image(0x3000);
+    seal_os(&mut region).unwrap();
+    // the last pages never got written
+    for b in &mut region[0x2800..0x3000] {
+        *b = 0xFF;
+    }
+    assert!(matches!(check_os(&region, BASE, &SEALED), Err(ImageError::CrcMismatch { .. })));
+}
+
+#[test]
+fn bad_length() {
+    let mut region = built_image(0x3000);
+    let h = OS_HEADER_OFFSET + 4;
+    region[h..h + 4].copy_from_slice(&(REGION as u32 + 4).to_le_bytes());
+    assert_eq!(seal_os(&mut region), Err(ImageError::BadLength));
+    region[h..h + 4].copy_from_slice(&8u32.to_le_bytes());
+    assert_eq!(check_os(&region, BASE, &DEBUGGER), Err(ImageError::BadLength));
+}
+
+#[test]
+fn bad_vectors() {
+    let mut region = built_image(0x3000);
+    region[4..8].copy_from_slice(&(BASE + 0x4001).to_le_bytes());
+    seal_os(&mut region).unwrap();
+    assert_eq!(check_os(&region, BASE, &SEALED), Err(ImageError::BadVectors));
+}
@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+
+use chocos_isp::protocol::{cmd, MAX_DATA, REPORT_SIZE};
+use chocos_isp::{crc32, Action, DeviceInfo, Flash, Isp, Layout, MemFlash, ReportIo, Request, Response, Status};
+
+const L: Layout = Layout::STM32F103;
+const APP: u32 = 0x0802_0000;
+
+fn isp() -> Isp<MemFlash<Vec<u8>>> {
+    Isp::new(MemFlash::new(L, vec![0; L.size as usize]), (0, 1))
+}
+
+fn write_req(addr: u32, data: &[u8]) -> Request {
+    let mut req = Request::new(cmd::WRITE, addr).with_data(data);
+    req.crc = crc32(data);
+    req
+}
+
+fn status(isp: &mut Isp<MemFlash<Vec<u8>>>, req: Request) -> Status {
+    isp.handle(&req).0.status
+}
+
+#[derive(Default)]
+struct Pipe {
+    from_host: VecDeque<Vec<u8>>,
+    to_host: Vec<Response>,
+    // number of write attempts the IN endpoint rejects
+    busy: u32,
+}
+
+impl ReportIo for Pipe {
+    fn read_report(&mut self, buf: &mut [u8; REPORT_SIZE]) -> Option<usize> {
+        let report = self.from_host.pop_front()?;
+        buf[..report.len()].copy_from_slice(&report);
+        Some(report.len())
+    }
+
+    fn write_report(&mut self, report: &[u8; REPORT_SIZE]) -> bool {
+        if self.busy > 0 {
+            self.busy -= 1;
+            return false;
+        }
+        self.to_host.push(Response::parse(report).unwrap());
+        true
+    }
+}
+
+#[test]
+fn info_describes_layout() {
+    let mut isp = isp();
+    let (res, _) = isp.handle(&Request::new(cmd::INFO, 0));
+    let info = DeviceInfo::parse(res.payload()).unwrap();
+    assert_eq!(info.page_size, 2048);
+    assert_eq!(info.flash_base, 0x0800_0000);
+    assert_eq!(info.writable_start, 0x0801_0000);
+    assert_eq!(info.bootloader_version, (0, 1));
+}
+
+#[test]
+fn erase_is_page_granular() {
+    let mut isp = isp();
+    assert_eq!(status(&mut isp, write_req(APP, &[1, 2, 3, 4])), Status::Ok);
+    assert_eq!(status(&mut isp, write_req(APP + 2048, &[5, 6])), Status::Ok);
+
+    assert_eq!(status(&mut isp, Request::new(cmd::ERASE_PAGE, APP + 2)), Status::BadAlignment);
+    assert_eq!(status(&mut isp, Request::new(cmd::ERASE_PAGE, APP)), Status::Ok);
+
+    assert_eq!(isp.flash().read(APP, 4), &[0xFF; 4]);
+    assert_eq!(isp.flash().read(APP + 2048, 2), &[5, 6]);
+}
+
+#[test]
+fn write_needs_erased_flash() {
+    let mut isp = isp();
+    assert_eq!(status(&mut isp, write_req(APP, &[1, 2])), Status::Ok);
+    assert_eq!(status(&mut isp, write_req(APP, &[3, 4])), Status::FlashError);
+    isp.handle(&Request::new(cmd::ERASE_PAGE, APP));
+    assert_eq!(status(&mut isp, write_req(APP, &[3, 4])), Status::Ok);
+}
+
+#[test]
+fn write_alignment() {
+    let mut isp = isp();
+    assert_eq!(status(&mut isp, write_req(APP + 1, &[1, 2])), Status::BadAlignment);
+    assert_eq!(status(&mut isp, write_req(APP, &[1, 2, 3])), Status::BadLength);
+    assert_eq!(status(&mut isp, write_req(APP, &[])), Status::BadLength);
+
+    let mut long = write_req(APP, &[0; MAX_DATA]);
+    long.len = MAX_DATA as u8 + 2;
+    assert_eq!(status(&mut isp, long), Status::BadLength);
+}
+
+#[test]
+fn write_checks_crc() {
+    let mut isp = isp();
+    let mut req = write_req(APP, &[1, 2, 3, 4]);
+    req.data[0] = 9;
+    assert_eq!(status(&mut isp, req), Status::CrcMismatch);
+    assert_eq!(isp.flash().read(APP, 4), &[0xFF; 4]);
+}
+
+#[test]
+fn bootloader_pages_are_protected() {
+    let mut isp = isp();
+    for page in (0x0800_0000..0x0801_0000).step_by(2048) {
+        assert_eq!(status(&mut isp, Request::new(cmd::ERASE_PAGE, page)), Status::BadAddress);
+    }
+    assert_eq!(status(&mut isp, write_req(0x0800_0000, &[0, 0])), Status::BadAddress);
+    // a chunk that starts below the boundary and ends above it
+    assert_eq!(status(&mut isp, write_req(0x0801_0000 - 2, &[0, 0, 0, 0])), Status::BadAddress);
+    assert_eq!(isp.flash().erase_count, 0);
+    assert!(isp.flash().read(0x0800_0000, 0x1_0000).iter().all(|b| *b == 0xFF));
+}
+
+#[test]
+fn out_of_flash() {
+    let mut isp = isp();
+    assert_eq!(status(&mut isp, Request::new(cmd::ERASE_PAGE, L.end())), Status::BadAddress);
+    assert_eq!(status(&mut isp, write_req(L.end() - 2, &[0, 0, 0, 0])), Status::BadAddress);
+
+    let mut read = Request::new(cmd::READ, L.end() - 4);
+    read.len = 8;
+    assert_eq!(status(&mut isp, read), Status::BadAddress);
+
+    let verify = Request::new(cmd::VERIFY, 0xFFFF_FFF0).with_data(&0x100u32.to_le_bytes());
+    assert_eq!(status(&mut isp, verify), Status::BadAddress);
+}
+
+#[test]
+fn read_and_verify() {
+    let mut isp = isp();
+    let data: Vec<u8> = (0..52).collect();
+    assert_eq!(status(&mut isp, write_req(APP, &data)), Status::Ok);
+
+    let mut read = Request::new(cmd::READ, APP);
+    read.len = 52;
+    let (res, _) = isp.handle(&read);
+    assert_eq!(res.payload(), &data[..]);
+    assert_eq!(res.value, crc32(&data));
+
+    let mut verify = Request::new(cmd::VERIFY, APP).with_data(&52u32.to_le_bytes());
+    verify.crc = crc32(&data);
+    assert_eq!(status(&mut isp, verify.clone()), Status::Ok);
+    verify.crc ^= 1;
+    let (res, _) = isp.handle(&verify);
+    assert_eq!(res.status, Status::VerifyFailed);
+    assert_eq!(res.value, crc32(&data));
+}
+
+#[test]
+fn unknown_command() {
+    assert_eq!(status(&mut isp(), Request::new(0x7F, 0)), Status::UnknownCommand);
+}
+
+#[test]
+fn poll_answers_each_request_in_order() {
+    let mut isp = isp();
+    let mut pipe = Pipe::default();
+    for seq in 1..=3 {
+        let mut req = Request::new(cmd::INFO, 0);
+        req.seq = seq;
+        pipe.from_host.push_back(req.to_bytes().to_vec());
+    }
+    // the first response has to wait for the endpoint
+    pipe.busy = 2;
+
+    for _ in 0..10 {
+        assert_eq!(isp.poll(&mut pipe), Action::None);
+    }
+    let seqs: Vec<u8> = pipe.to_host.iter().map(|r| r.seq).collect();
+    assert_eq!(seqs, vec![1, 2, 3]);
+}
+
+#[test]
+fn reset_after_response_is_sent() {
+    let mut isp = isp();
+    let mut pipe = Pipe { busy: 1, ..Pipe::default() };
+    pipe.from_host.push_back(Request::new(cmd::RESET, 0).to_bytes().to_vec());
+
+    assert_eq!(isp.poll(&mut pipe), Action::None);
+    assert!(pipe.to_host.is_empty());
+    assert_eq!(isp.poll(&mut pipe), Action::Reset);
+    assert_eq!(pipe.to_host[0].status, Status::Ok);
+}
+
+#[test]
+fn short_reports() {
+    let mut isp = isp();
+    let mut pipe = Pipe::default();
+    pipe.from_host.push_back(vec![cmd::INFO, 1, 0]);
+    pipe.from_host.push_back(Request::new(cmd::INFO, 0).to_bytes()[..12].to_vec());
+    isp.poll(&mut pipe);
+    isp.poll(&mut pipe);
+    assert_eq!(pipe.to_host.len(), 1);
+}
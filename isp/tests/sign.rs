@@ -0,0 +1,79 @@
+use chocos_isp::image::{check_os, seal_os, ImageError, Policy, OS_HEADER_OFFSET, OS_MAGIC, UNSEALED_CRC};
+use chocos_isp::sign::{check_signature, public_key, sign, TRAILER_SIZE};
+
+const BASE: u32 = 0x0801_0000;
+const SECRET: [u8; 32] = [7; 32];
+const OTHER: [u8; 32] = [8; 32];
+
+// A sealed OS image in an erased slot, signed with `secret` if given
+fn slot(secret: Option<&[u8; 32]>) -> Vec<u8> {
+    let len = 0x2000;
+    let mut region = vec![0xFF; 0x1_0000];
+    for (i, b) in region[..len].iter_mut().enumerate() {
+        *b = (i * 31) as u8;
+    }
+    region[0..4].copy_from_slice(&0x2000_E500u32.to_le_bytes());
+    region[4..8].copy_from_slice(&(BASE + 0x201).to_le_bytes());
+    let h = OS_HEADER_OFFSET;
+    region[h..h + 4].copy_from_slice(&OS_MAGIC.to_le_bytes());
+    region[h + 4..h + 8].copy_from_slice(&(len as u32).to_le_bytes());
+    region[h + 8..h + 12].copy_from_slice(&UNSEALED_CRC.to_le_bytes());
+    seal_os(&mut region).unwrap();
+
+    if let Some(secret) = secret {
+        let trailer = sign(&region[..len], secret).unwrap();
+        region[len..len + TRAILER_SIZE].copy_from_slice(&trailer);
+    }
+    region
+}
+
+fn release(key: &[u8; 32]) -> Policy<'_> {
+    Policy { key: Some(key), allow_unsealed: false, allow_unsigned: false }
+}
+
+#[test]
+fn signed_image_boots() {
+    let key = public_key(&SECRET).unwrap();
+    assert!(check_os(&slot(Some(&SECRET)), BASE, &release(&key)).is_ok());
+}
+
+#[test]
+fn unsigned_image_needs_dev_mode() {
+    let key = public_key(&SECRET).unwrap();
+    assert_eq!(check_os(&slot(None), BASE, &release(&key)), Err(ImageError::Unsigned));
+
+    let dev = Policy { allow_unsigned: true, ..release(&key) };
+    assert!(check_os(&slot(None), BASE, &dev).is_ok());
+    // a signature that is there still has to match
+    assert_eq!(check_os(&slot(Some(&OTHER)), BASE, &dev), Err(ImageError::BadSignature));
+}
+
+#[test]
+fn foreign_key() {
+    let key = public_key(&SECRET).unwrap();
+    assert_eq!(check_os(&slot(Some(&OTHER)), BASE, &release(&key)), Err(ImageError::BadSignature));
+}
+
+#[test]
+fn modified_and_resealed_image() {
+    let key = public_key(&SECRET).unwrap();
+    let mut region = slot(Some(&SECRET));
+    region[0x1000] ^= 1;
+    seal_os(&mut region).unwrap();
+    assert_eq!(check_os(&region, BASE, &release(&key)), Err(ImageError::BadSignature));
+}
+
+#[test]
+fn no_key_to_check_with() {
+    let strict = Policy::default();
+    assert_eq!(check_signature(b"app", Some(&sign(b"app", &SECRET).unwrap()), &strict), Err(ImageError::Unsigned));
+    let dev = Policy { allow_unsigned: true, ..strict };
+    assert_eq!(check_signature(b"app", Some(&sign(b"app", &SECRET).unwrap()), &dev), Ok(()));
+    assert_eq!(check_signature(b"app", None, &dev), Ok(()));
+}
+
+#[test]
+fn zero_secret_is_refused() {
+    assert_eq!(public_key(&[0; 32]), None);
+    assert_eq!(sign(b"app", &[0; 32]), None);
+}
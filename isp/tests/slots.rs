@@ -0,0 +1,212 @@
+use chocos_isp::image::{seal_os, Policy, OS_HEADER_OFFSET, OS_MAGIC, UNSEALED_CRC};
+use chocos_isp::slots::{self, prepare_boot, prepare_boot_from, slot_states, SlotLayout, MAX_ATTEMPTS};
+use chocos_isp::{Flash, Layout, MemFlash};
+
+const SLOTS: SlotLayout = SlotLayout::STM32F103;
+const A: u32 = 0x0801_0000;
+const B: u32 = 0x0806_0000;
+const POLICY: Policy = Policy { key: None, allow_unsealed: false, allow_unsigned: true };
+
+fn flash() -> MemFlash<Vec<u8>> {
+    MemFlash::new(Layout::STM32F103, vec![0; Layout::STM32F103.size as usize])
+}
+
+// Flashes a sealed image linked for `base`, `fill` makes the CRC differ
+fn install(flash: &mut MemFlash<Vec<u8>>, base: u32, version: u32, fill: u8) -> u32 {
+    let len = 0x1000;
+    let mut image = vec![fill; len];
+    image[0..4].copy_from_slice(&0x2000_E500u32.to_le_bytes());
+    image[4..8].copy_from_slice(&(base + 0x201).to_le_bytes());
+    let h = OS_HEADER_OFFSET;
+    image[h..h + 4].copy_from_slice(&OS_MAGIC.to_le_bytes());
+    image[h + 4..h + 8].copy_from_slice(&(len as u32).to_le_bytes());
+    image[h + 8..h + 12].copy_from_slice(&UNSEALED_CRC.to_le_bytes());
+    image[h + 12..h + 16].copy_from_slice(&version.to_le_bytes());
+    let header = seal_os(&mut image).unwrap();
+
+    for page in (base..base + SLOTS.slot_size).step_by(2048) {
+        flash.erase_page(page).unwrap();
+    }
+    flash.write(base, &image).unwrap();
+    header.crc
+}
+
+fn confirm(flash: &mut MemFlash<Vec<u8>>, base: u32) {
+    let slot = SLOTS.slot_of(base).unwrap();
+    let crc = slot_states(flash, &SLOTS, &POLICY)[slot].image.unwrap().crc;
+    slots::confirm(flash, &SLOTS, slot, crc).unwrap();
+}
+
+#[test]
+fn empty_flash_boots_nothing() {
+    let mut flash = flash();
+    assert_eq!(prepare_boot(&mut flash, &SLOTS, &POLICY), None);
+}
+
+#[test]
+fn confirmed_image_boots_without_writing() {
+    let mut flash = flash();
+    install(&mut flash, A, 0x0001_0000, 0);
+    confirm(&mut flash, A);
+
+    let log = flash.read(SLOTS.log_page, 2048).to_vec();
+    for _ in 0..10 {
+        assert_eq!(prepare_boot(&mut flash, &SLOTS, &POLICY), Some(A));
+    }
+    assert_eq!(flash.read(SLOTS.log_page, 2048), &log[..]);
+}
+
+#[test]
+fn new_image_rolls_back_after_failed_attempts() {
+    let mut flash = flash();
+    install(&mut flash, A, 0x0001_0000, 0);
+    confirm(&mut flash, A);
+    install(&mut flash, B, 0x0001_0100, 0);
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        assert_eq!(prepare_boot(&mut flash, &SLOTS, &POLICY), Some(B));
+        assert_eq!(slot_states(&flash, &SLOTS, &POLICY)[1].attempts, attempt);
+    }
+    // never confirmed, back to the old OS for good
+    assert_eq!(prepare_boot(&mut flash, &SLOTS, &POLICY), Some(A));
+    assert_eq!(prepare_boot(&mut flash, &SLOTS, &POLICY), Some(A));
+    assert!(!slot_states(&flash, &SLOTS, &POLICY)[1].is_bootable());
+}
+
+#[test]
+fn confirmed_new_image_stays() {
+    let mut flash = flash();
+    install(&mut flash, A, 0x0001_0000, 0);
+    confirm(&mut flash, A);
+    install(&mut flash, B, 0x0001_0100, 0);
+
+    assert_eq!(prepare_boot(&mut flash, &SLOTS, &POLICY), Some(B));
+    confirm(&mut flash, B);
+    for _ in 0..2 * MAX_ATTEMPTS {
+        assert_eq!(prepare_boot(&mut flash, &SLOTS, &POLICY), Some(B));
+    }
+}
+
+#[test]
+fn reflashed_slot_starts_over() {
+    let mut flash = flash();
+    install(&mut flash, A, 0x0001_0000, 0);
+    confirm(&mut flash, A);
+    install(&mut flash, B, 0x0001_0100, 0);
+    for _ in 0..MAX_ATTEMPTS {
+        prepare_boot(&mut flash, &SLOTS, &POLICY);
+    }
+    assert_eq!(prepare_boot(&mut flash, &SLOTS, &POLICY), Some(A));
+
+    // a fixed build of the same version gets its own attempts
+    install(&mut flash, B, 0x0001_0100, 1);
+    let state = slot_states(&flash, &SLOTS, &POLICY)[1];
+    assert_eq!((state.confirmed, state.attempts), (false, 0));
+    assert_eq!(prepare_boot(&mut flash, &SLOTS, &POLICY), Some(B));
+}
+
+#[test]
+fn broken_image_falls_back() {
+    let mut flash = flash();
+    install(&mut flash, A, 0x0001_0000, 0);
+    confirm(&mut flash, A);
+    install(&mut flash, B, 0x0001_0100, 0);
+    // interrupted while flashing slot B
+    flash.erase_page(B + 0x800).unwrap();
+    assert_eq!(prepare_boot(&mut flash, &SLOTS, &POLICY), Some(A));
+}
+
+#[test]
+fn same_version_is_tried_over_the_confirmed_image() {
+    let mut flash = flash();
+    install(&mut flash, A, 0x0001_0000, 0);
+    confirm(&mut flash, A);
+    // a new build, the version not bumped
+    install(&mut flash, B, 0x0001_0000, 1);
+    assert_eq!(slot_states(&flash, &SLOTS, &POLICY)[1].version(), Some(0x0001_0000));
+
+    for _ in 0..MAX_ATTEMPTS {
+        assert_eq!(prepare_boot(&mut flash, &SLOTS, &POLICY), Some(B));
+    }
+    assert_eq!(prepare_boot(&mut flash, &SLOTS, &POLICY), Some(A));
+}
+
+#[test]
+fn same_version_confirmed_last_stays() {
+    let mut flash = flash();
+    install(&mut flash, B, 0x0001_0000, 0);
+    confirm(&mut flash, B);
+    install(&mut flash, A, 0x0001_0000, 1);
+    assert_eq!(prepare_boot(&mut flash, &SLOTS, &POLICY), Some(A));
+    confirm(&mut flash, A);
+    for _ in 0..2 * MAX_ATTEMPTS {
+        assert_eq!(prepare_boot(&mut flash, &SLOTS, &POLICY), Some(A));
+    }
+
+    // and after the log was compacted
+    for stale in 0..254 {
+        slots::confirm(&mut flash, &SLOTS, 1, stale).unwrap();
+    }
+    let states = slot_states(&flash, &SLOTS, &POLICY);
+    assert_eq!((states[0].confirmed_at, states[1].confirmed_at), (Some(1), Some(0)));
+    assert_eq!(prepare_boot(&mut flash, &SLOTS, &POLICY), Some(A));
+}
+
+#[test]
+fn requested_slot_boots_first() {
+    let mut flash = flash();
+    install(&mut flash, A, 0x0001_0000, 0);
+    confirm(&mut flash, A);
+    install(&mut flash, B, 0x0001_0100, 0);
+    confirm(&mut flash, B);
+    assert_eq!(prepare_boot_from(&mut flash, &SLOTS, &POLICY, Some(0)), Some(A));
+    assert_eq!(prepare_boot_from(&mut flash, &SLOTS, &POLICY, None), Some(B));
+
+    // but not if there is nothing to boot in it
+    flash.erase_page(A).unwrap();
+    assert_eq!(prepare_boot_from(&mut flash, &SLOTS, &POLICY, Some(0)), Some(B));
+}
+
+#[test]
+fn image_linked_for_the_other_slot() {
+    let mut flash = flash();
+    install(&mut flash, A, 0x0001_0000, 0);
+    let image = flash.read(A, 0x1000).to_vec();
+    for page in (B..B + 0x1000).step_by(2048) {
+        flash.erase_page(page).unwrap();
+    }
+    flash.write(B, &image).unwrap();
+    assert!(slot_states(&flash, &SLOTS, &POLICY)[1].image.is_err());
+}
+
+#[test]
+fn full_log_is_compacted() {
+    let mut flash = flash();
+    let crc = install(&mut flash, A, 0x0001_0000, 0);
+    install(&mut flash, B, 0x0001_0100, 0);
+    prepare_boot(&mut flash, &SLOTS, &POLICY);
+    slots::confirm(&mut flash, &SLOTS, 0, crc).unwrap();
+
+    // records about images long gone fill up the page
+    for stale in 0..254 {
+        slots::confirm(&mut flash, &SLOTS, 1, stale).unwrap();
+    }
+    let erases = flash.erase_count;
+    assert_eq!(prepare_boot(&mut flash, &SLOTS, &POLICY), Some(B));
+    assert_eq!(flash.erase_count, erases + 1);
+
+    let states = slot_states(&flash, &SLOTS, &POLICY);
+    assert_eq!((states[0].confirmed, states[0].attempts), (true, 0));
+    assert_eq!((states[1].confirmed, states[1].attempts), (false, 2));
+    assert_eq!(slots::records(flash.read(SLOTS.log_page, 2048)).count(), 3);
+}
+
+#[test]
+fn torn_record_is_skipped() {
+    let mut flash = flash();
+    install(&mut flash, A, 0x0001_0000, 0);
+    // only the first half word of a record made it
+    flash.write(SLOTS.log_page, &[0x00, 0xA0]).unwrap();
+    assert_eq!(prepare_boot(&mut flash, &SLOTS, &POLICY), Some(A));
+    assert_eq!(slot_states(&flash, &SLOTS, &POLICY)[0].attempts, 1);
+}
@@ -0,0 +1,13 @@
+use chocos_isp::usb::{serial_number, SERIAL_SIZE, UID_SIZE};
+
+#[test]
+fn serial_number_is_the_unique_id_in_hex() {
+    let uid: [u8; UID_SIZE] = [0x32, 0xFF, 0xD8, 0x05, 0x4E, 0x55, 0x39, 0x36, 0x0A, 0x72, 0x15, 0x43];
+    let mut buf = [0; SERIAL_SIZE];
+    assert_eq!(serial_number(&uid, &mut buf), "32FFD8054E5539360A721543");
+
+    let mut other = [0; SERIAL_SIZE];
+    let mut uid2 = uid;
+    uid2[UID_SIZE - 1] ^= 1;
+    assert_ne!(serial_number(&uid2, &mut other), serial_number(&uid, &mut buf));
+}
@@ -0,0 +1,200 @@
+use std::collections::VecDeque;
+
+use chocos_isp::dfu::Region;
+use chocos_isp::image::{seal_os, Policy, OS_HEADER_OFFSET, OS_MAGIC, UNSEALED_CRC};
+use chocos_isp::ymodem::{crc16, SerialIo, State, Ymodem, ACK, CAN, CRC_MODE, EOT, NAK, SOH, STX};
+use chocos_isp::{Action, Flash, Layout, MemFlash};
+
+const L: Layout = Layout::STM32F103;
+const POLICY: Policy = Policy { key: None, allow_unsealed: false, allow_unsigned: true };
+const SLOT_A: Region = Region::STM32F103[0];
+const PROGRAMS: Region = Region::STM32F103[2];
+
+#[derive(Default)]
+struct Line {
+    to_device: VecDeque<u8>,
+    from_device: Vec<u8>,
+}
+
+impl SerialIo for Line {
+    fn read_byte(&mut self) -> Option<u8> {
+        self.to_device.pop_front()
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.from_device.extend_from_slice(bytes);
+    }
+}
+
+struct Session {
+    ymodem: Ymodem<'static>,
+    flash: MemFlash<Vec<u8>>,
+    line: Line,
+}
+
+impl Session {
+    fn new() -> Self {
+        Session {
+            ymodem: Ymodem::new(&Region::STM32F103, POLICY),
+            flash: MemFlash::new(L, vec![0; L.size as usize]),
+            line: Line::default(),
+        }
+    }
+
+    // Sends bytes and returns the answer
+    fn send(&mut self, bytes: &[u8]) -> (Vec<u8>, Action) {
+        self.line.to_device.extend(bytes);
+        let action = self.ymodem.poll(&mut self.flash, &mut self.line);
+        (std::mem::take(&mut self.line.from_device), action)
+    }
+
+    // What `sb` does for one file, checking every answer on the way
+    fn send_file(&mut self, name: &str, data: &[u8]) {
+        let (answer, _) = self.send(&packet(0, &header(name, data.len())));
+        assert_eq!(answer, [ACK, CRC_MODE]);
+        for (i, chunk) in data.chunks(1024).enumerate() {
+            let (answer, _) = self.send(&packet((i + 1) as u8, chunk));
+            assert_eq!(answer, [ACK]);
+        }
+        assert_eq!(self.send(&[EOT]).0, [NAK]);
+        assert_eq!(self.send(&[EOT]).0, [ACK, CRC_MODE]);
+    }
+}
+
+fn header(name: &str, size: usize) -> Vec<u8> {
+    format!("{}\0{} 14736545714 100644", name, size).into_bytes()
+}
+
+// One block, padded like the senders do
+fn packet(block: u8, data: &[u8]) -> Vec<u8> {
+    let (start, size) = if data.len() > 128 { (STX, 1024) } else { (SOH, 128) };
+    let mut body = data.to_vec();
+    body.resize(size, if block == 0 { 0 } else { 0x1A });
+    let mut p = vec![start, block, !block];
+    p.extend_from_slice(&body);
+    p.extend_from_slice(&crc16(&body).to_be_bytes());
+    p
+}
+
+fn os_image(base: u32) -> Vec<u8> {
+    let len = 0x1234;
+    let mut image: Vec<u8> = (0..len).map(|i| (i * 7) as u8).collect();
+    image[0..4].copy_from_slice(&0x2000_E500u32.to_le_bytes());
+    image[4..8].copy_from_slice(&(base + 0x201).to_le_bytes());
+    let h = OS_HEADER_OFFSET;
+    image[h..h + 4].copy_from_slice(&OS_MAGIC.to_le_bytes());
+    image[h + 4..h + 8].copy_from_slice(&(len as u32).to_le_bytes());
+    image[h + 8..h + 12].copy_from_slice(&UNSEALED_CRC.to_le_bytes());
+    seal_os(&mut image).unwrap();
+    image
+}
+
+#[test]
+fn crc16_check_value() {
+    assert_eq!(crc16(b"123456789"), 0x31C3);
+}
+
+#[test]
+fn os_image_into_slot_a() {
+    let mut s = Session::new();
+    s.ymodem.timeout(&mut s.line);
+    assert_eq!(s.line.from_device, [CRC_MODE]);
+    s.line.from_device.clear();
+
+    let image = os_image(SLOT_A.start);
+    s.send_file("os-a.bin", &image);
+    assert_eq!(s.flash.read(SLOT_A.start, image.len()), &image[..]);
+    // only the size from the header is written, not the padding
+    assert!(s.flash.read(SLOT_A.start + image.len() as u32, 2).iter().all(|b| *b == 0xFF));
+    assert_eq!(s.flash.erase_count, 3);
+
+    // the empty header ends the batch
+    assert_eq!(s.send(&packet(0, &[])), (vec![ACK], Action::Reset));
+}
+
+#[test]
+fn batch_of_files() {
+    let mut s = Session::new();
+    let image = os_image(SLOT_A.start);
+    let programs = vec![0x5A; 1001];
+    s.send_file("OS-A.BIN", &image);
+    s.send_file("programs.bin", &programs);
+    assert_eq!(s.ymodem.files(), 2);
+    assert_eq!(s.send(&packet(0, &[])).1, Action::Reset);
+    assert_eq!(s.flash.read(PROGRAMS.start, 1002), &[&programs[..], &[0xFF]].concat()[..]);
+}
+
+#[test]
+fn damaged_and_repeated_blocks() {
+    let mut s = Session::new();
+    let data = vec![0x11; 300];
+    s.send(&packet(0, &header("programs", data.len())));
+
+    let mut bad = packet(1, &data[..128]);
+    bad[10] ^= 1;
+    assert_eq!(s.send(&bad).0, [NAK]);
+    let mut bad = packet(1, &data[..128]);
+    bad[2] = 0;
+    assert_eq!(s.send(&bad).0, [NAK]);
+
+    assert_eq!(s.send(&packet(1, &data[..128])).0, [ACK]);
+    // our ACK got lost, the sender repeats the block
+    assert_eq!(s.send(&packet(1, &data[..128])).0, [ACK]);
+    assert_eq!(s.send(&packet(2, &data[128..256])).0, [ACK]);
+    assert_eq!(s.send(&packet(3, &data[256..])).0, [ACK]);
+    s.send(&[EOT, EOT]);
+    assert_eq!(s.flash.read(PROGRAMS.start, 300), &data[..]);
+}
+
+#[test]
+fn broken_os_image_is_refused() {
+    let mut s = Session::new();
+    // linked for slot A
+    let image = os_image(SLOT_A.start);
+    s.send(&packet(0, &header("os-b.bin", image.len())));
+    for (i, chunk) in image.chunks(1024).enumerate() {
+        s.send(&packet((i + 1) as u8, chunk));
+    }
+    assert_eq!(s.send(&[EOT]).0, [NAK]);
+    assert_eq!(s.send(&[EOT]).0, [CAN, CAN]);
+    assert_eq!(s.ymodem.files(), 0);
+
+    // ending the batch without a good file doesn't restart
+    assert_eq!(s.send(&packet(0, &[])), (vec![ACK], Action::None));
+}
+
+#[test]
+fn unknown_or_oversized_files_are_cancelled() {
+    let mut s = Session::new();
+    assert_eq!(s.send(&packet(0, &header("chocos.bin", 100))).0, [CAN, CAN]);
+    let too_big = SLOT_A.size as usize + 1;
+    assert_eq!(s.send(&packet(0, &header("os-a.bin", too_big))).0, [CAN, CAN]);
+    // a data block out of nowhere
+    assert_eq!(s.send(&packet(1, &[0; 128])).0, [CAN, CAN]);
+    assert_eq!(s.ymodem.state(), State::Header);
+}
+
+#[test]
+fn sender_cancels_and_line_goes_quiet() {
+    let mut s = Session::new();
+    s.send(&packet(0, &header("programs", 2048)));
+    // half a block, then nothing
+    s.send(&packet(1, &[0; 128])[..50]);
+    s.ymodem.timeout(&mut s.line);
+    assert_eq!(s.line.from_device, [NAK]);
+    s.line.from_device.clear();
+    assert_eq!(s.send(&packet(1, &[0; 128])).0, [ACK]);
+
+    s.send(&[CAN, CAN]);
+    assert_eq!(s.ymodem.state(), State::Header);
+
+    // a sender that went away mid file is given up on
+    s.send(&packet(0, &header("programs", 2048)));
+    for _ in 0..10 {
+        s.ymodem.timeout(&mut s.line);
+    }
+    assert_eq!(s.ymodem.state(), State::Data);
+    s.ymodem.timeout(&mut s.line);
+    assert_eq!(s.ymodem.state(), State::Header);
+    assert!(s.line.from_device.ends_with(&[CAN, CAN]));
+}
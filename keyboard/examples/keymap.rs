@@ -0,0 +1,44 @@
+//! Writes an example keymap for the board's 4x4 key matrix to stdout.
+//!
+//! ```text
+//! cargo run --example keymap > keymap.bin
+//! cd ../chocflash && cargo run --release -- flash ../keyboard/keymap.bin --keymap
+//! ```
+//!
+//! Layer 0 is a number pad. Held, the bottom left key switches to layer 1
+//! with arrow keys and a macro; tapped, it types 0. The bottom right key
+//! is shift when held and enter when tapped.
+
+use std::io::Write;
+
+use chocos_keyboard::keycode::*;
+use chocos_keyboard::keymap::{self, MAX_SIZE};
+use chocos_keyboard::{Action, MacroOp, MacroStep};
+
+fn main() {
+    let k = Action::Key;
+    let t = Action::Transparent;
+    let numbers: &[Action] = &[
+        k(N1 + 6), k(N1 + 7), k(N1 + 8), k(BACKSPACE),
+        k(N1 + 3), k(N1 + 4), k(N1 + 5), k(TAB),
+        k(N1), k(N1 + 1), k(N1 + 2), Action::Toggle(1),
+        Action::LayerTap { layer: 1, tap: N0 }, k(SPACE), k(ESCAPE), Action::ModTap { hold: LEFT_SHIFT, tap: ENTER },
+    ];
+    let arrows: &[Action] = &[
+        t, k(UP), t, Action::Macro(0),
+        k(LEFT), k(DOWN), k(RIGHT), t,
+        k(F1), k(F1 + 1), k(F1 + 2), t,
+        t, t, t, t,
+    ];
+    // ctrl + a, then backspace: clears a text field
+    let clear: &[MacroStep] = &[
+        MacroStep { op: MacroOp::Press, code: LEFT_CTRL },
+        MacroStep { op: MacroOp::Tap, code: A },
+        MacroStep { op: MacroOp::Release, code: LEFT_CTRL },
+        MacroStep { op: MacroOp::Tap, code: BACKSPACE },
+    ];
+
+    let mut buf = [0; MAX_SIZE];
+    let len = keymap::write(&mut buf, 4, 4, 200, &[numbers, arrows], &[clear]).unwrap();
+    std::io::stdout().write_all(&buf[..len]).unwrap();
+}
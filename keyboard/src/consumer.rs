@@ -0,0 +1,80 @@
+// Consumer control: media keys, volume and the like
+//
+// The report holds one usage of the consumer page (0x0C), the key that is
+// down, or 0 when none is. Pressing another one replaces it.
+
+use crate::queue::Queue;
+use crate::report::{KeyError, QUEUE_LEN};
+
+pub const PLAY_PAUSE: u16 = 0xCD;
+pub const NEXT_TRACK: u16 = 0xB5;
+pub const PREVIOUS_TRACK: u16 = 0xB6;
+pub const STOP: u16 = 0xB7;
+pub const MUTE: u16 = 0xE2;
+pub const VOLUME_UP: u16 = 0xE9;
+pub const VOLUME_DOWN: u16 = 0xEA;
+/// Highest usage the report descriptor allows
+pub const MAX_USAGE: u16 = 0x3FF;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConsumerReport {
+    pub usage: u16,
+}
+
+pub struct Consumer {
+    usage: u16,
+    queue: Queue<ConsumerReport, QUEUE_LEN>,
+}
+
+impl Consumer {
+    pub fn new() -> Self {
+        Consumer { usage: 0, queue: Queue::new() }
+    }
+
+    /// The usage that is down, 0 for none
+    pub fn usage(&self) -> u16 {
+        self.usage
+    }
+
+    pub fn queue_mut(&mut self) -> &mut Queue<ConsumerReport, QUEUE_LEN> {
+        &mut self.queue
+    }
+
+    pub fn press(&mut self, usage: u16) -> Result<(), KeyError> {
+        if usage == 0 || usage > MAX_USAGE {
+            return Err(KeyError::Invalid);
+        }
+        self.set(usage, 1)
+    }
+
+    pub fn release(&mut self) -> Result<(), KeyError> {
+        self.set(0, 1)
+    }
+
+    /// Presses and releases `usage`, as two reports, both or neither
+    pub fn tap(&mut self, usage: u16) -> Result<(), KeyError> {
+        if usage == 0 || usage > MAX_USAGE {
+            return Err(KeyError::Invalid);
+        }
+        self.set(usage, 2)?;
+        self.release()
+    }
+
+    // Queues a report if the usage changes. `room` reports have to fit.
+    fn set(&mut self, usage: u16, room: usize) -> Result<(), KeyError> {
+        if self.queue.free() < room {
+            return Err(KeyError::Full);
+        }
+        if usage != self.usage {
+            self.usage = usage;
+            self.queue.push(ConsumerReport { usage });
+        }
+        Ok(())
+    }
+}
+
+impl Default for Consumer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
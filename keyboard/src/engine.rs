@@ -0,0 +1,168 @@
+// Turning key events into key presses through a keymap
+//
+// A key's action is looked up when it goes down, on the highest active
+// layer that isn't transparent there, and remembered until it goes up, so
+// a key let go after its layer changed still releases what it pressed.
+//
+// A tap-hold key is undecided at first. Let go within the tapping term it
+// taps its key; held longer, or when another key goes down meanwhile, it
+// holds its layer or modifier. Only one key is undecided at a time.
+
+use crate::keymap::{Action, Keymap, MacroOp, MAX_KEYS};
+use crate::matrix::KeyEvent;
+use crate::report::{KeyError, Keyboard};
+
+#[derive(Copy, Clone, Debug)]
+struct Pending {
+    key: usize,
+    held_ms: u32,
+}
+
+pub struct Engine {
+    // layers on, bit n for layer n; layer 0 is always on
+    momentary: u16,
+    toggled: u16,
+    // the action of every key that is down, by row * cols + col
+    down: [Action; MAX_KEYS],
+    pending: Option<Pending>,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Engine { momentary: 0, toggled: 0, down: [Action::None; MAX_KEYS], pending: None }
+    }
+
+    /// Forgets all keys and layers, e.g. for a new keymap. The keys the
+    /// keyboard holds are the caller's to release.
+    pub fn reset(&mut self) {
+        *self = Engine::new();
+    }
+
+    /// A tap-hold key is waiting for `tick` to decide it
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Bit n set for each layer that is on
+    pub fn layers(&self) -> u16 {
+        1 | self.momentary | self.toggled
+    }
+
+    pub fn event(&mut self, keymap: &Keymap, event: KeyEvent, keyboard: &mut Keyboard) -> Result<(), KeyError> {
+        let (row, col) = (event.row as usize, event.col as usize);
+        if row >= keymap.rows() || col >= keymap.cols() {
+            return Ok(());
+        }
+        let key = row * keymap.cols() + col;
+
+        if !event.pressed {
+            let action = core::mem::replace(&mut self.down[key], Action::None);
+            return match (action, self.pending) {
+                (Action::LayerTap { tap, .. } | Action::ModTap { tap, .. }, Some(pending)) if pending.key == key => {
+                    self.pending = None;
+                    keyboard.tap(tap)
+                }
+                _ => self.release(action, keyboard),
+            };
+        }
+
+        if let Some(pending) = self.pending.take() {
+            self.hold(self.down[pending.key], keyboard)?;
+        }
+        let action = self.lookup(keymap, row, col);
+        let result = match action {
+            Action::Key(code) => keyboard.press(code),
+            Action::Momentary(layer) => {
+                self.momentary |= 1 << layer;
+                Ok(())
+            }
+            Action::Toggle(layer) => {
+                self.toggled ^= 1 << layer;
+                Ok(())
+            }
+            Action::Macro(index) => play(keymap, index, keyboard),
+            Action::LayerTap { .. } | Action::ModTap { .. } if keymap.tapping_term_ms() == 0 => self.hold(action, keyboard),
+            Action::LayerTap { .. } | Action::ModTap { .. } => {
+                self.pending = Some(Pending { key, held_ms: 0 });
+                Ok(())
+            }
+            Action::None | Action::Transparent => Ok(()),
+        };
+        // a press the queue had no room for leaves the key up, so letting
+        // go of it releases nothing
+        if result.is_ok() {
+            self.down[key] = action;
+        }
+        result
+    }
+
+    /// Decides a tap-hold key that has been held long enough, call it
+    /// every `elapsed_ms`
+    pub fn tick(&mut self, keymap: &Keymap, elapsed_ms: u32, keyboard: &mut Keyboard) -> Result<(), KeyError> {
+        let pending = match self.pending.as_mut() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+        pending.held_ms = pending.held_ms.saturating_add(elapsed_ms);
+        if pending.held_ms < keymap.tapping_term_ms() as u32 {
+            return Ok(());
+        }
+        let key = pending.key;
+        self.pending = None;
+        self.hold(self.down[key], keyboard)
+    }
+
+    fn lookup(&self, keymap: &Keymap, row: usize, col: usize) -> Action {
+        let layers = self.layers();
+        (0..keymap.layers())
+            .rev()
+            .filter(|layer| layers & (1 << layer) != 0)
+            .map(|layer| keymap.action(layer, row, col))
+            .find(|action| *action != Action::Transparent)
+            .unwrap_or(Action::None)
+    }
+
+    fn hold(&mut self, action: Action, keyboard: &mut Keyboard) -> Result<(), KeyError> {
+        match action {
+            Action::LayerTap { layer, .. } => {
+                self.momentary |= 1 << layer;
+                Ok(())
+            }
+            Action::ModTap { hold, .. } => keyboard.press(hold),
+            _ => Ok(()),
+        }
+    }
+
+    fn release(&mut self, action: Action, keyboard: &mut Keyboard) -> Result<(), KeyError> {
+        match action {
+            Action::Key(code) | Action::ModTap { hold: code, .. } => keyboard.release(code),
+            Action::Momentary(layer) | Action::LayerTap { layer, .. } => {
+                self.momentary &= !(1 << layer);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Either the whole macro goes into the report queue or nothing does
+fn play(keymap: &Keymap, index: u8, keyboard: &mut Keyboard) -> Result<(), KeyError> {
+    let reports: usize = keymap.macro_steps(index).map(|step| step.reports()).sum();
+    if keyboard.queue_mut().free() < reports {
+        return Err(KeyError::Full);
+    }
+    for step in keymap.macro_steps(index) {
+        match step.op {
+            MacroOp::Press => keyboard.press(step.code)?,
+            MacroOp::Release => keyboard.release(step.code)?,
+            MacroOp::Tap => keyboard.tap(step.code)?,
+        }
+    }
+    Ok(())
+}
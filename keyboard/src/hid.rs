@@ -0,0 +1,217 @@
+// The HID interface of the keyboard
+//
+// One interface carries four reports, told apart by the report ID in
+// their first byte: the keyboard, a mouse, consumer control and the NKRO
+// keyboard. The keyboard report is the boot report behind its ID; its
+// output report is the LED byte, also behind the ID.
+//
+// The interface is a boot keyboard, so a BIOS can ask for the boot
+// protocol with SET_PROTOCOL. Then only the keyboard is reported, as plain
+// 8 byte boot reports without an ID.
+
+use crate::consumer::ConsumerReport;
+use crate::led::LEDS;
+use crate::mouse::MouseReport;
+use crate::report::KeyboardReport;
+
+pub const KEYBOARD_ID: u8 = 1;
+pub const MOUSE_ID: u8 = 2;
+pub const CONSUMER_ID: u8 = 3;
+pub const NKRO_ID: u8 = 4;
+
+/// Longest input report, ID included
+pub const MAX_REPORT: usize = 30;
+
+// Interface class, subclass and protocol of a boot keyboard
+pub const CLASS: u8 = 0x03;
+pub const SUBCLASS_BOOT: u8 = 0x01;
+pub const PROTOCOL_KEYBOARD: u8 = 0x01;
+pub const HID_DESCRIPTOR_TYPE: u8 = 0x21;
+pub const REPORT_DESCRIPTOR_TYPE: u8 = 0x22;
+const HID_VERSION: u16 = 0x0111;
+
+/// The class specific requests
+pub mod request {
+    pub const GET_REPORT: u8 = 0x01;
+    pub const GET_IDLE: u8 = 0x02;
+    pub const GET_PROTOCOL: u8 = 0x03;
+    pub const SET_REPORT: u8 = 0x09;
+    pub const SET_IDLE: u8 = 0x0A;
+    pub const SET_PROTOCOL: u8 = 0x0B;
+}
+
+/// The protocol the host has chosen, the report protocol after a reset
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    Boot = 0,
+    Report = 1,
+}
+
+impl Protocol {
+    /// From the wValue of SET_PROTOCOL
+    pub fn from_value(value: u16) -> Option<Protocol> {
+        match value {
+            0 => Some(Protocol::Boot),
+            1 => Some(Protocol::Report),
+            _ => None,
+        }
+    }
+}
+
+/// The HID descriptor after the interface descriptor, without its length
+/// and type
+pub fn hid_descriptor() -> [u8; 7] {
+    let [version_lo, version_hi] = HID_VERSION.to_le_bytes();
+    let [len_lo, len_hi] = (REPORT_DESCRIPTOR.len() as u16).to_le_bytes();
+    [version_lo, version_hi, 0, 1, REPORT_DESCRIPTOR_TYPE, len_lo, len_hi]
+}
+
+pub const REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01,       // Usage Page (Generic Desktop)
+    0x09, 0x06,       // Usage (Keyboard)
+    0xA1, 0x01,       // Collection (Application)
+    0x85, KEYBOARD_ID, //   Report ID
+    0x05, 0x07,       //   Usage Page (Keyboard/Keypad)
+    0x19, 0xE0,       //   Usage Minimum (Left Control)
+    0x29, 0xE7,       //   Usage Maximum (Right GUI)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x25, 0x01,       //   Logical Maximum (1)
+    0x75, 0x01,       //   Report Size (1)
+    0x95, 0x08,       //   Report Count (8)
+    0x81, 0x02,       //   Input (Data, Variable, Absolute): modifiers
+    0x95, 0x01,       //   Report Count (1)
+    0x75, 0x08,       //   Report Size (8)
+    0x81, 0x01,       //   Input (Constant): reserved
+    0x05, 0x08,       //   Usage Page (LEDs)
+    0x19, 0x01,       //   Usage Minimum (Num Lock)
+    0x29, 0x05,       //   Usage Maximum (Kana)
+    0x95, 0x05,       //   Report Count (5)
+    0x75, 0x01,       //   Report Size (1)
+    0x91, 0x02,       //   Output (Data, Variable, Absolute): LEDs
+    0x95, 0x01,       //   Report Count (1)
+    0x75, 0x03,       //   Report Size (3)
+    0x91, 0x01,       //   Output (Constant): padding
+    0x05, 0x07,       //   Usage Page (Keyboard/Keypad)
+    0x19, 0x00,       //   Usage Minimum (0)
+    0x2A, 0xFF, 0x00, //   Usage Maximum (255)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x75, 0x08,       //   Report Size (8)
+    0x95, 0x06,       //   Report Count (6)
+    0x81, 0x00,       //   Input (Data, Array): keys
+    0xC0,             // End Collection
+
+    0x05, 0x01,       // Usage Page (Generic Desktop)
+    0x09, 0x02,       // Usage (Mouse)
+    0xA1, 0x01,       // Collection (Application)
+    0x85, MOUSE_ID,   //   Report ID
+    0x09, 0x01,       //   Usage (Pointer)
+    0xA1, 0x00,       //   Collection (Physical)
+    0x05, 0x09,       //     Usage Page (Button)
+    0x19, 0x01,       //     Usage Minimum (1)
+    0x29, 0x05,       //     Usage Maximum (5)
+    0x15, 0x00,       //     Logical Minimum (0)
+    0x25, 0x01,       //     Logical Maximum (1)
+    0x95, 0x05,       //     Report Count (5)
+    0x75, 0x01,       //     Report Size (1)
+    0x81, 0x02,       //     Input (Data, Variable, Absolute): buttons
+    0x95, 0x01,       //     Report Count (1)
+    0x75, 0x03,       //     Report Size (3)
+    0x81, 0x01,       //     Input (Constant): padding
+    0x05, 0x01,       //     Usage Page (Generic Desktop)
+    0x09, 0x30,       //     Usage (X)
+    0x09, 0x31,       //     Usage (Y)
+    0x09, 0x38,       //     Usage (Wheel)
+    0x15, 0x81,       //     Logical Minimum (-127)
+    0x25, 0x7F,       //     Logical Maximum (127)
+    0x75, 0x08,       //     Report Size (8)
+    0x95, 0x03,       //     Report Count (3)
+    0x81, 0x06,       //     Input (Data, Variable, Relative)
+    0xC0,             //   End Collection
+    0xC0,             // End Collection
+
+    0x05, 0x0C,       // Usage Page (Consumer)
+    0x09, 0x01,       // Usage (Consumer Control)
+    0xA1, 0x01,       // Collection (Application)
+    0x85, CONSUMER_ID, //   Report ID
+    0x19, 0x00,       //   Usage Minimum (0)
+    0x2A, 0xFF, 0x03, //   Usage Maximum (0x3FF)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x26, 0xFF, 0x03, //   Logical Maximum (0x3FF)
+    0x75, 0x10,       //   Report Size (16)
+    0x95, 0x01,       //   Report Count (1)
+    0x81, 0x00,       //   Input (Data, Array)
+    0xC0,             // End Collection
+
+    0x05, 0x01,       // Usage Page (Generic Desktop)
+    0x09, 0x06,       // Usage (Keyboard)
+    0xA1, 0x01,       // Collection (Application)
+    0x85, NKRO_ID,    //   Report ID
+    0x05, 0x07,       //   Usage Page (Keyboard/Keypad)
+    0x19, 0xE0,       //   Usage Minimum (Left Control)
+    0x29, 0xE7,       //   Usage Maximum (Right GUI)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x25, 0x01,       //   Logical Maximum (1)
+    0x75, 0x01,       //   Report Size (1)
+    0x95, 0x08,       //   Report Count (8)
+    0x81, 0x02,       //   Input (Data, Variable, Absolute): modifiers
+    0x19, 0x00,       //   Usage Minimum (0)
+    0x29, 0xDF,       //   Usage Maximum (0xDF)
+    0x95, 0xE0,       //   Report Count (224)
+    0x81, 0x02,       //   Input (Data, Variable, Absolute): a bit per key
+    0xC0,             // End Collection
+];
+
+/// The LEDs of an output report from the host, or `None` if it is not
+/// the keyboard's: the LED byte behind the keyboard ID, or alone in the
+/// boot protocol.
+pub fn led_report(protocol: Protocol, report: &[u8]) -> Option<u8> {
+    match (protocol, report) {
+        (Protocol::Boot, [leds]) | (Protocol::Report, [KEYBOARD_ID, leds]) => Some(leds & LEDS),
+        _ => None,
+    }
+}
+
+/// An input report of any kind
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InputReport {
+    Keyboard(KeyboardReport),
+    Mouse(MouseReport),
+    Consumer(ConsumerReport),
+}
+
+impl InputReport {
+    /// The report on the wire, ID first. In the boot protocol a keyboard
+    /// report is the boot report without an ID and the others have no
+    /// place, `None`.
+    pub fn bytes<'a>(&self, protocol: Protocol, buf: &'a mut [u8; MAX_REPORT]) -> Option<&'a [u8]> {
+        let len = match (protocol, self) {
+            (Protocol::Boot, InputReport::Keyboard(report)) => {
+                buf[..8].copy_from_slice(&report.to_boot().to_bytes());
+                8
+            }
+            (Protocol::Boot, _) => return None,
+            (Protocol::Report, InputReport::Keyboard(KeyboardReport::Boot(report))) => {
+                buf[0] = KEYBOARD_ID;
+                buf[1..9].copy_from_slice(&report.to_bytes());
+                9
+            }
+            (Protocol::Report, InputReport::Keyboard(KeyboardReport::Nkro(report))) => {
+                let bytes = report.to_bytes();
+                buf[0] = NKRO_ID;
+                buf[1..1 + bytes.len()].copy_from_slice(&bytes);
+                1 + bytes.len()
+            }
+            (Protocol::Report, InputReport::Mouse(report)) => {
+                buf[..5].copy_from_slice(&[MOUSE_ID, report.buttons, report.x as u8, report.y as u8, report.wheel as u8]);
+                5
+            }
+            (Protocol::Report, InputReport::Consumer(report)) => {
+                let [low, high] = report.usage.to_le_bytes();
+                buf[..3].copy_from_slice(&[CONSUMER_ID, low, high]);
+                3
+            }
+        };
+        Some(&buf[..len])
+    }
+}
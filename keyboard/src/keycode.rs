@@ -0,0 +1,44 @@
+// Key codes: usages of the HID keyboard/keypad page (0x07). Only the ones
+// the kernel and the examples need by name, any usage from `A` to
+// `RIGHT_GUI` can be pressed.
+
+pub const NONE: u8 = 0x00;
+/// Sent in every key slot of a boot report when more keys are down than fit
+pub const ERROR_ROLL_OVER: u8 = 0x01;
+
+pub const A: u8 = 0x04;
+pub const Z: u8 = 0x1D;
+pub const N1: u8 = 0x1E;
+pub const N0: u8 = 0x27;
+pub const ENTER: u8 = 0x28;
+pub const ESCAPE: u8 = 0x29;
+pub const BACKSPACE: u8 = 0x2A;
+pub const TAB: u8 = 0x2B;
+pub const SPACE: u8 = 0x2C;
+pub const CAPS_LOCK: u8 = 0x39;
+pub const F1: u8 = 0x3A;
+pub const F12: u8 = 0x45;
+pub const RIGHT: u8 = 0x4F;
+pub const LEFT: u8 = 0x50;
+pub const DOWN: u8 = 0x51;
+pub const UP: u8 = 0x52;
+
+// The modifiers go into the modifier byte of a report, bit n for 0xE0 + n
+pub const LEFT_CTRL: u8 = 0xE0;
+pub const LEFT_SHIFT: u8 = 0xE1;
+pub const LEFT_ALT: u8 = 0xE2;
+pub const LEFT_GUI: u8 = 0xE3;
+pub const RIGHT_CTRL: u8 = 0xE4;
+pub const RIGHT_SHIFT: u8 = 0xE5;
+pub const RIGHT_ALT: u8 = 0xE6;
+pub const RIGHT_GUI: u8 = 0xE7;
+
+/// A key that can be pressed, not one of the error codes or reserved
+pub fn is_valid(code: u8) -> bool {
+    (A..=RIGHT_GUI).contains(&code)
+}
+
+pub fn is_modifier(code: u8) -> bool {
+    (LEFT_CTRL..=RIGHT_GUI).contains(&code)
+}
+
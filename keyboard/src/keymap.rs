@@ -0,0 +1,291 @@
+// Keymaps: what each key of the matrix does, on several layers
+//
+// A keymap is a plain byte string, so the kernel takes it from an app or
+// from flash as it is and only has to check it once. Little endian:
+//
+//   magic "KMAP" | version u8 | layers u8 | rows u8 | cols u8
+//   tapping term u16 (ms) | macro bytes u16
+//   actions: layers * rows * cols u16, layer 0 first, row by row
+//   macros: each one a step count u8 and that many steps
+//
+// An action is one u16, the high byte picks the kind:
+//
+//   0x00kk  key kk (0x0000 nothing, 0x0001 transparent)
+//   0x01nn  layer nn while held          0x02nn  toggle layer nn
+//   0x03nn  play macro nn
+//   0x4Lkk  tap: key kk, hold: layer L (0 - 15)
+//   0x5Mkk  tap: key kk, hold: modifier LEFT_CTRL + M (0 - 7)
+//
+// A macro step is two bytes, `MacroOp` and key code.
+
+use crate::keycode;
+use crate::report::QUEUE_LEN;
+
+pub const MAGIC: [u8; 4] = *b"KMAP";
+pub const VERSION: u8 = 1;
+pub const HEADER_SIZE: usize = 12;
+/// Largest keymap, one flash page
+pub const MAX_SIZE: usize = 2048;
+pub const MAX_LAYERS: usize = 16;
+/// Largest rows * cols, the engine keeps state per key
+pub const MAX_KEYS: usize = 128;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    None,
+    /// Whatever the next active layer below has
+    Transparent,
+    Key(u8),
+    /// Layer on while the key is held
+    Momentary(u8),
+    /// Layer on or off at each press
+    Toggle(u8),
+    Macro(u8),
+    /// Taps `tap`, or holds `layer` on once held for the tapping term
+    LayerTap { layer: u8, tap: u8 },
+    /// Taps `tap`, or holds the modifier key `hold` down
+    ModTap { hold: u8, tap: u8 },
+}
+
+impl Action {
+    /// `None` for a word that is no action. Layer and macro numbers are
+    /// checked against the keymap by `Keymap::parse`.
+    pub fn from_u16(word: u16) -> Option<Action> {
+        let [low, high] = word.to_le_bytes();
+        let key = |code| keycode::is_valid(code).then(|| code);
+        Some(match high {
+            0x00 if low == 0 => Action::None,
+            0x00 if low == 1 => Action::Transparent,
+            0x00 => Action::Key(key(low)?),
+            0x01 => Action::Momentary(low),
+            0x02 => Action::Toggle(low),
+            0x03 => Action::Macro(low),
+            0x40..=0x4F => Action::LayerTap { layer: high & 0x0F, tap: key(low)? },
+            0x50..=0x57 => Action::ModTap { hold: keycode::LEFT_CTRL + (high & 0x07), tap: key(low)? },
+            _ => return None,
+        })
+    }
+
+    pub fn to_u16(self) -> u16 {
+        let (high, low) = match self {
+            Action::None => (0, 0),
+            Action::Transparent => (0, 1),
+            Action::Key(code) => (0, code),
+            Action::Momentary(layer) => (0x01, layer),
+            Action::Toggle(layer) => (0x02, layer),
+            Action::Macro(index) => (0x03, index),
+            Action::LayerTap { layer, tap } => (0x40 | layer, tap),
+            Action::ModTap { hold, tap } => (0x50 | hold.wrapping_sub(keycode::LEFT_CTRL), tap),
+        };
+        u16::from_le_bytes([low, high])
+    }
+
+    fn layer(self) -> Option<u8> {
+        match self {
+            Action::Momentary(layer) | Action::Toggle(layer) | Action::LayerTap { layer, .. } => Some(layer),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MacroOp {
+    Press = 1,
+    Release = 2,
+    Tap = 3,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MacroStep {
+    pub op: MacroOp,
+    pub code: u8,
+}
+
+impl MacroStep {
+    pub fn from_bytes(bytes: [u8; 2]) -> Option<MacroStep> {
+        let op = match bytes[0] {
+            1 => MacroOp::Press,
+            2 => MacroOp::Release,
+            3 => MacroOp::Tap,
+            _ => return None,
+        };
+        keycode::is_valid(bytes[1]).then(|| MacroStep { op, code: bytes[1] })
+    }
+
+    pub fn to_bytes(self) -> [u8; 2] {
+        [self.op as u8, self.code]
+    }
+
+    /// Keyboard reports the step queues at most
+    pub fn reports(self) -> usize {
+        if self.op == MacroOp::Tap { 2 } else { 1 }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KeymapError {
+    /// Not a keymap: wrong magic or version
+    Magic,
+    /// The lengths in the header don't match the data, or it is too big
+    Size,
+    /// More layers or keys than the engine supports, or none
+    Layout,
+    /// An action that is no action, or names a missing layer or macro
+    Action,
+    /// A bad macro step, or a macro too long for the report queue
+    Macro,
+}
+
+/// A checked keymap, borrowing its bytes
+#[derive(Copy, Clone, Debug)]
+pub struct Keymap<'a> {
+    layers: usize,
+    rows: usize,
+    cols: usize,
+    tapping_term_ms: u16,
+    actions: &'a [u8],
+    macros: &'a [u8],
+}
+
+/// The length of the keymap `data` starts with, as its header says. A
+/// keymap in flash is followed by whatever else is in the page.
+pub fn size(data: &[u8]) -> Result<usize, KeymapError> {
+    if data.len() < HEADER_SIZE || data[..4] != MAGIC || data[4] != VERSION {
+        return Err(KeymapError::Magic);
+    }
+    let actions_len = data[5] as usize * data[6] as usize * data[7] as usize * 2;
+    Ok(HEADER_SIZE + actions_len + u16::from_le_bytes([data[10], data[11]]) as usize)
+}
+
+impl<'a> Keymap<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Keymap<'a>, KeymapError> {
+        let len = size(data)?;
+        let (layers, rows, cols) = (data[5] as usize, data[6] as usize, data[7] as usize);
+        if layers == 0 || layers > MAX_LAYERS || rows * cols == 0 || rows * cols > MAX_KEYS {
+            return Err(KeymapError::Layout);
+        }
+        if data.len() > MAX_SIZE || data.len() != len {
+            return Err(KeymapError::Size);
+        }
+        let actions_len = layers * rows * cols * 2;
+        let keymap = Keymap {
+            layers,
+            rows,
+            cols,
+            tapping_term_ms: u16::from_le_bytes([data[8], data[9]]),
+            actions: &data[HEADER_SIZE..HEADER_SIZE + actions_len],
+            macros: &data[HEADER_SIZE + actions_len..],
+        };
+
+        // walk the macros once, so every one can be found later
+        let mut macro_count = 0;
+        let mut rest = keymap.macros;
+        while let Some((&count, steps)) = rest.split_first() {
+            let len = count as usize * 2;
+            if steps.len() < len {
+                return Err(KeymapError::Size);
+            }
+            let mut reports = 0;
+            for step in steps[..len].chunks(2) {
+                reports += MacroStep::from_bytes([step[0], step[1]]).ok_or(KeymapError::Macro)?.reports();
+            }
+            if reports > QUEUE_LEN {
+                return Err(KeymapError::Macro);
+            }
+            rest = &steps[len..];
+            macro_count += 1;
+        }
+
+        for word in keymap.actions.chunks(2) {
+            let action = Action::from_u16(u16::from_le_bytes([word[0], word[1]])).ok_or(KeymapError::Action)?;
+            let bad_layer = action.layer().map_or(false, |layer| layer as usize >= layers);
+            let bad_macro = matches!(action, Action::Macro(index) if index as usize >= macro_count);
+            if bad_layer || bad_macro {
+                return Err(KeymapError::Action);
+            }
+        }
+        Ok(keymap)
+    }
+
+    pub fn layers(&self) -> usize {
+        self.layers
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// How long a tap-hold key has to be held to hold
+    pub fn tapping_term_ms(&self) -> u16 {
+        self.tapping_term_ms
+    }
+
+    pub fn action(&self, layer: usize, row: usize, col: usize) -> Action {
+        let i = ((layer * self.rows + row) * self.cols + col) * 2;
+        // checked by `parse`
+        Action::from_u16(u16::from_le_bytes([self.actions[i], self.actions[i + 1]])).unwrap_or(Action::None)
+    }
+
+    /// The steps of macro `index`, empty if there is no such macro
+    pub fn macro_steps(&self, index: u8) -> impl Iterator<Item = MacroStep> + 'a {
+        let mut rest = self.macros;
+        for _ in 0..index {
+            let len = rest.first().map_or(0, |&count| 1 + count as usize * 2);
+            rest = &rest[len.min(rest.len())..];
+        }
+        let len = rest.first().map_or(0, |&count| count as usize * 2);
+        rest.get(1..1 + len).unwrap_or(&[]).chunks(2).filter_map(|step| MacroStep::from_bytes([step[0], step[1]]))
+    }
+}
+
+/// Writes a keymap to `buf` and returns its length. Each layer has
+/// `rows * cols` actions, row by row.
+pub fn write(
+    buf: &mut [u8],
+    rows: usize,
+    cols: usize,
+    tapping_term_ms: u16,
+    layers: &[&[Action]],
+    macros: &[&[MacroStep]],
+) -> Result<usize, KeymapError> {
+    if rows > u8::MAX as usize || cols > u8::MAX as usize || layers.len() > MAX_LAYERS {
+        return Err(KeymapError::Layout);
+    }
+    if layers.iter().any(|layer| layer.len() != rows * cols) {
+        return Err(KeymapError::Size);
+    }
+    // e.g. a layer tap to layer 16 would come back as a mod tap
+    if layers.iter().flat_map(|layer| layer.iter()).any(|&action| Action::from_u16(action.to_u16()) != Some(action)) {
+        return Err(KeymapError::Action);
+    }
+    let macros_len: usize = macros.iter().map(|steps| 1 + steps.len() * 2).sum();
+    let len = HEADER_SIZE + layers.len() * rows * cols * 2 + macros_len;
+    if len > buf.len().min(MAX_SIZE) || macros.iter().any(|steps| steps.len() > u8::MAX as usize) {
+        return Err(KeymapError::Size);
+    }
+
+    buf[..4].copy_from_slice(&MAGIC);
+    buf[4..8].copy_from_slice(&[VERSION, layers.len() as u8, rows as u8, cols as u8]);
+    buf[8..10].copy_from_slice(&tapping_term_ms.to_le_bytes());
+    buf[10..12].copy_from_slice(&(macros_len as u16).to_le_bytes());
+    let mut at = HEADER_SIZE;
+    for action in layers.iter().flat_map(|layer| layer.iter()) {
+        buf[at..at + 2].copy_from_slice(&action.to_u16().to_le_bytes());
+        at += 2;
+    }
+    for steps in macros {
+        buf[at] = steps.len() as u8;
+        at += 1;
+        for step in steps.iter() {
+            buf[at..at + 2].copy_from_slice(&step.to_bytes());
+            at += 2;
+        }
+    }
+
+    Keymap::parse(&buf[..len])?;
+    Ok(len)
+}
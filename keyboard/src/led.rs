@@ -0,0 +1,12 @@
+// The keyboard LEDs the host lights, bits of the LED output report
+//
+// The host keeps the lock state and tells every keyboard about it, so the
+// bits follow Caps Lock pressed on any keyboard attached to it.
+
+pub const NUM_LOCK: u8 = 0x01;
+pub const CAPS_LOCK: u8 = 0x02;
+pub const SCROLL_LOCK: u8 = 0x04;
+pub const COMPOSE: u8 = 0x08;
+pub const KANA: u8 = 0x10;
+/// Every LED the report has
+pub const LEDS: u8 = 0x1F;
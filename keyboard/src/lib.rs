@@ -0,0 +1,44 @@
+//! Keyboard core of ChocOS.
+//!
+//! Everything about being a keyboard that doesn't touch the hardware, so
+//! it runs on a host as well: which keys are down, the HID reports that
+//! describe them and the queue the USB interrupt sends them from. The OS
+//! only moves reports between this and its USB endpoint.
+//!
+//! `keycode` names the HID usages, `report` keeps the key state and
+//! builds the boot or NKRO keyboard report. `matrix` scans and debounces a key
+//! matrix through the `MatrixPins` the board provides. `keymap` is the
+//! format of the keymaps that say what each key does, with layers,
+//! tap-hold keys and macros, and `engine` plays the key events through
+//! one onto the keyboard.
+//!
+//! `mouse` and `consumer` do the same as `report` for mouse buttons and
+//! moves and for media keys. `hid` has the report descriptor that puts
+//! them all behind report IDs on the one HID interface, and the boot
+//! protocol a host may ask for instead. `led` names the bits of the LED
+//! report the host sends back.
+
+#![no_std]
+// Has to build with the OS's pinned nightly, which predates
+// `bool::then_some`, `Option::is_some_and` and `div_ceil`.
+#![allow(clippy::unnecessary_lazy_evaluations, clippy::unnecessary_map_or, clippy::manual_div_ceil)]
+
+pub mod consumer;
+pub mod engine;
+pub mod hid;
+pub mod keycode;
+pub mod keymap;
+pub mod led;
+pub mod matrix;
+pub mod mouse;
+pub mod queue;
+pub mod report;
+
+pub use consumer::{Consumer, ConsumerReport};
+pub use engine::Engine;
+pub use hid::{InputReport, Protocol};
+pub use keymap::{Action, Keymap, KeymapError, MacroOp, MacroStep};
+pub use matrix::{KeyEvent, Matrix, MatrixPins};
+pub use mouse::{Mouse, MouseReport};
+pub use queue::Queue;
+pub use report::{BootReport, KeyError, Keyboard, KeyboardReport, Keys, Mode, NkroReport, ReportQueue};
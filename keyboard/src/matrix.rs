@@ -0,0 +1,108 @@
+// Key matrix scanning with per-key debounce
+//
+// The rows are selected one at a time and the columns read back, the GPIO
+// work is behind `MatrixPins`. A key's new state only counts once it has
+// read the same for the debounce time, each key on its own, so a bouncing
+// contact never hides a press of another key. Every committed change is
+// one `KeyEvent`.
+
+/// Largest matrix `Matrix` keeps state for
+pub const MAX_ROWS: usize = 16;
+pub const MAX_COLS: usize = 32;
+
+/// The GPIOs of the matrix
+pub trait MatrixPins {
+    /// Drives `row` active. Only one row is selected at a time.
+    fn select_row(&mut self, row: usize);
+
+    fn unselect_row(&mut self, row: usize);
+
+    /// The columns of the selected row, bit n set when the key in column n
+    /// is down. Waits for the lines to settle first if the board needs it.
+    fn read_cols(&mut self) -> u32;
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub row: u8,
+    pub col: u8,
+    pub pressed: bool,
+}
+
+impl KeyEvent {
+    /// One word for a system call: column in bits 0-7, row in 8-15, bit 16
+    /// set for a press
+    pub fn to_u32(self) -> u32 {
+        (self.pressed as u32) << 16 | (self.row as u32) << 8 | self.col as u32
+    }
+
+    pub fn from_u32(word: u32) -> KeyEvent {
+        KeyEvent { row: (word >> 8) as u8, col: word as u8, pressed: word & (1 << 16) != 0 }
+    }
+}
+
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    debounce_ms: u32,
+    // the debounced state, bit n for column n
+    down: [u32; MAX_ROWS],
+    // 1 + how long a key has read different from `down`, 0 while it reads
+    // the same
+    changing: [[u8; MAX_COLS]; MAX_ROWS],
+}
+
+impl Matrix {
+    /// `debounce_ms` up to 254, 0 takes every change at once
+    pub fn new(rows: usize, cols: usize, debounce_ms: u8) -> Self {
+        assert!(rows <= MAX_ROWS && cols <= MAX_COLS && debounce_ms < u8::MAX);
+        Matrix {
+            rows,
+            cols,
+            debounce_ms: debounce_ms as u32,
+            down: [0; MAX_ROWS],
+            changing: [[0; MAX_COLS]; MAX_ROWS],
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn is_down(&self, row: usize, col: usize) -> bool {
+        self.down[row] & (1 << col) != 0
+    }
+
+    /// Reads the whole matrix, `elapsed_ms` after the last scan, and calls
+    /// `event` for every key whose change has held long enough.
+    pub fn scan<P: MatrixPins>(&mut self, pins: &mut P, elapsed_ms: u32, mut event: impl FnMut(KeyEvent)) {
+        let elapsed = elapsed_ms.min(u8::MAX as u32) as u8;
+        let col_mask = if self.cols == 32 { u32::MAX } else { (1 << self.cols) - 1 };
+        for row in 0..self.rows {
+            pins.select_row(row);
+            let raw = pins.read_cols() & col_mask;
+            pins.unselect_row(row);
+
+            let differs = raw ^ self.down[row];
+            for col in 0..self.cols {
+                let changing = &mut self.changing[row][col];
+                if differs & (1 << col) == 0 {
+                    *changing = 0;
+                    continue;
+                }
+                // the change happened somewhere since the last scan, count
+                // it from now
+                *changing = if *changing == 0 { 1 } else { changing.saturating_add(elapsed) };
+                if (*changing - 1) as u32 >= self.debounce_ms {
+                    *changing = 0;
+                    self.down[row] ^= 1 << col;
+                    event(KeyEvent { row: row as u8, col: col as u8, pressed: raw & (1 << col) != 0 });
+                }
+            }
+        }
+    }
+}
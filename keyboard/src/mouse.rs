@@ -0,0 +1,108 @@
+// Mouse state and reports
+//
+// Buttons stay down until released, like keys. A move is relative and
+// sent once; one report moves at most 127 counts along each axis, so a
+// longer move goes out as several reports that add up to it.
+
+use crate::queue::Queue;
+use crate::report::{KeyError, QUEUE_LEN};
+
+pub const LEFT: u8 = 0x01;
+pub const RIGHT: u8 = 0x02;
+pub const MIDDLE: u8 = 0x04;
+pub const BACK: u8 = 0x08;
+pub const FORWARD: u8 = 0x10;
+/// Every button the report has
+pub const BUTTONS: u8 = 0x1F;
+
+/// Longest step of one report
+pub const MAX_STEP: i32 = 127;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MouseReport {
+    pub buttons: u8,
+    pub x: i8,
+    pub y: i8,
+    pub wheel: i8,
+}
+
+pub struct Mouse {
+    buttons: u8,
+    queue: Queue<MouseReport, QUEUE_LEN>,
+}
+
+impl Mouse {
+    pub fn new() -> Self {
+        Mouse { buttons: 0, queue: Queue::new() }
+    }
+
+    pub fn buttons(&self) -> u8 {
+        self.buttons
+    }
+
+    pub fn queue_mut(&mut self) -> &mut Queue<MouseReport, QUEUE_LEN> {
+        &mut self.queue
+    }
+
+    pub fn press(&mut self, buttons: u8) -> Result<(), KeyError> {
+        self.set_buttons(buttons, self.buttons | buttons)
+    }
+
+    pub fn release(&mut self, buttons: u8) -> Result<(), KeyError> {
+        self.set_buttons(buttons, self.buttons & !buttons)
+    }
+
+    pub fn release_all(&mut self) -> Result<(), KeyError> {
+        self.release(BUTTONS)
+    }
+
+    /// Moves by `x` and `y` and turns the wheel by `wheel`, in as many
+    /// reports as it takes. Either all of them are queued or none.
+    pub fn move_by(&mut self, x: i32, y: i32, wheel: i32) -> Result<(), KeyError> {
+        let steps = [x, y, wheel]
+            .iter()
+            .map(|d| (d.unsigned_abs() as usize + MAX_STEP as usize - 1) / MAX_STEP as usize)
+            .max()
+            .unwrap_or(0);
+        if steps > QUEUE_LEN {
+            return Err(KeyError::Invalid);
+        }
+        if self.queue.free() < steps {
+            return Err(KeyError::Full);
+        }
+        let (mut x, mut y, mut wheel) = (x, y, wheel);
+        for _ in 0..steps {
+            let report = MouseReport { buttons: self.buttons, x: step(&mut x), y: step(&mut y), wheel: step(&mut wheel) };
+            self.queue.push(report);
+        }
+        Ok(())
+    }
+
+    fn set_buttons(&mut self, buttons: u8, new: u8) -> Result<(), KeyError> {
+        if buttons & !BUTTONS != 0 {
+            return Err(KeyError::Invalid);
+        }
+        if new == self.buttons {
+            return Ok(());
+        }
+        if self.queue.free() == 0 {
+            return Err(KeyError::Full);
+        }
+        self.buttons = new;
+        self.queue.push(MouseReport { buttons: new, ..MouseReport::default() });
+        Ok(())
+    }
+}
+
+impl Default for Mouse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Takes the next step off what is left to move
+fn step(left: &mut i32) -> i8 {
+    let step = (*left).clamp(-MAX_STEP, MAX_STEP);
+    *left -= step;
+    step as i8
+}
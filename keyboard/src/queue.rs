@@ -0,0 +1,61 @@
+// Fixed size FIFO for what the interrupts hand to the rest of the kernel
+
+pub struct Queue<T, const N: usize> {
+    items: [T; N],
+    head: usize,
+    len: usize,
+}
+
+impl<T: Copy + Default, const N: usize> Queue<T, N> {
+    pub fn new() -> Self {
+        Queue { items: [T::default(); N], head: 0, len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn free(&self) -> usize {
+        N - self.len
+    }
+
+    /// `false` if the queue is full
+    pub fn push(&mut self, item: T) -> bool {
+        if self.len == N {
+            return false;
+        }
+        self.items[(self.head + self.len) % N] = item;
+        self.len += 1;
+        true
+    }
+
+    /// The oldest item, it stays queued until `pop`
+    pub fn peek(&self) -> Option<&T> {
+        if self.len == 0 {
+            None
+        } else {
+            Some(&self.items[self.head])
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let item = *self.peek()?;
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(item)
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl<T: Copy + Default, const N: usize> Default for Queue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
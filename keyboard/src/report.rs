@@ -0,0 +1,284 @@
+// Key state and the keyboard reports
+//
+// `Keys` remembers every key that is down, in a bitmap over all usages, so
+// no press is lost when more than six keys are held. The boot report has
+// six slots; with more keys down it reports ErrorRollOver in each slot, as
+// the HID spec asks, until enough are released. The NKRO report is the
+// bitmap itself and has room for every key.
+//
+// `Keyboard` turns presses and releases into reports of its `Mode` and
+// queues them. The
+// USB interrupt takes them out one at a time, whenever the IN endpoint is
+// free, so a tap is seen by the host as a press and then a release even
+// when both come in faster than the host polls.
+
+use crate::keycode::{self, ERROR_ROLL_OVER};
+use crate::queue::Queue;
+
+/// Key slots of the boot report
+pub const BOOT_KEYS: usize = 6;
+/// Reports the kernel buffers for the USB interrupt
+pub const QUEUE_LEN: usize = 32;
+/// Bytes of the key bitmap of the NKRO report, usages 0x00 to 0xDF
+pub const NKRO_BYTES: usize = 28;
+
+/// Modifier byte and key slots of the boot keyboard report, see
+/// `to_bytes` for the reserved byte in between.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BootReport {
+    pub modifier: u8,
+    pub keycodes: [u8; BOOT_KEYS],
+}
+
+impl BootReport {
+    /// The 8 bytes on the wire
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let k = self.keycodes;
+        [self.modifier, 0, k[0], k[1], k[2], k[3], k[4], k[5]]
+    }
+}
+
+/// Modifier byte and a bit for every other key, see `Keys`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NkroReport {
+    pub modifier: u8,
+    pub keys: [u8; NKRO_BYTES],
+}
+
+impl NkroReport {
+    /// The bytes on the wire, modifiers first
+    pub fn to_bytes(&self) -> [u8; 1 + NKRO_BYTES] {
+        let mut bytes = [0; 1 + NKRO_BYTES];
+        bytes[0] = self.modifier;
+        bytes[1..].copy_from_slice(&self.keys);
+        bytes
+    }
+
+    pub fn to_keys(&self) -> Keys {
+        let mut keys = Keys::new();
+        keys.down[..NKRO_BYTES].copy_from_slice(&self.keys);
+        keys.down[keycode::LEFT_CTRL as usize / 8] = self.modifier;
+        keys
+    }
+}
+
+impl Default for NkroReport {
+    fn default() -> Self {
+        NkroReport { modifier: 0, keys: [0; NKRO_BYTES] }
+    }
+}
+
+/// Which report the keyboard sends
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// The boot report, six keys at most; what every host understands
+    Boot,
+    /// The NKRO report, any number of keys
+    Nkro,
+}
+
+impl Mode {
+    pub fn from_u32(mode: u32) -> Option<Mode> {
+        match mode {
+            0 => Some(Mode::Boot),
+            1 => Some(Mode::Nkro),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KeyboardReport {
+    Boot(BootReport),
+    Nkro(NkroReport),
+}
+
+impl KeyboardReport {
+    /// The same keys as a boot report, for a host that has asked for the
+    /// boot protocol
+    pub fn to_boot(&self) -> BootReport {
+        match self {
+            KeyboardReport::Boot(report) => *report,
+            KeyboardReport::Nkro(report) => report.to_keys().boot_report(),
+        }
+    }
+}
+
+impl Default for KeyboardReport {
+    fn default() -> Self {
+        KeyboardReport::Boot(BootReport::default())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KeyError {
+    /// Not a key code that can be pressed
+    Invalid,
+    /// The report queue has no room, try again later
+    Full,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Keys {
+    down: [u8; 32],
+}
+
+impl Keys {
+    pub const fn new() -> Self {
+        Keys { down: [0; 32] }
+    }
+
+    pub fn is_down(&self, code: u8) -> bool {
+        self.down[code as usize / 8] & (1 << (code % 8)) != 0
+    }
+
+    /// `false` if it was down already
+    pub fn press(&mut self, code: u8) -> bool {
+        let was_down = self.is_down(code);
+        self.down[code as usize / 8] |= 1 << (code % 8);
+        !was_down
+    }
+
+    /// `false` if it wasn't down
+    pub fn release(&mut self, code: u8) -> bool {
+        let was_down = self.is_down(code);
+        self.down[code as usize / 8] &= !(1 << (code % 8));
+        was_down
+    }
+
+    pub fn release_all(&mut self) {
+        self.down = [0; 32];
+    }
+
+    /// Every key that is down, modifiers last
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..=255u8).filter(move |code| self.is_down(*code))
+    }
+
+    /// The modifier byte, bit n for `LEFT_CTRL + n`
+    pub fn modifier(&self) -> u8 {
+        self.down[keycode::LEFT_CTRL as usize / 8]
+    }
+
+    pub fn boot_report(&self) -> BootReport {
+        let mut report = BootReport { modifier: self.modifier(), keycodes: [0; BOOT_KEYS] };
+        let mut keys = self.iter().filter(|code| !keycode::is_modifier(*code));
+        for slot in report.keycodes.iter_mut() {
+            *slot = keys.next().unwrap_or(0);
+        }
+        if keys.next().is_some() {
+            report.keycodes = [ERROR_ROLL_OVER; BOOT_KEYS];
+        }
+        report
+    }
+
+    pub fn nkro_report(&self) -> NkroReport {
+        let mut report = NkroReport { modifier: self.modifier(), keys: [0; NKRO_BYTES] };
+        report.keys.copy_from_slice(&self.down[..NKRO_BYTES]);
+        report
+    }
+
+    pub fn report(&self, mode: Mode) -> KeyboardReport {
+        match mode {
+            Mode::Boot => KeyboardReport::Boot(self.boot_report()),
+            Mode::Nkro => KeyboardReport::Nkro(self.nkro_report()),
+        }
+    }
+}
+
+/// Reports waiting for the IN endpoint, oldest first
+pub type ReportQueue<const N: usize> = Queue<KeyboardReport, N>;
+
+/// The key state of the USB keyboard and the reports on their way to the host
+pub struct Keyboard {
+    keys: Keys,
+    mode: Mode,
+    queue: ReportQueue<QUEUE_LEN>,
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Keyboard { keys: Keys::new(), mode: Mode::Boot, queue: ReportQueue::new() }
+    }
+
+    pub fn keys(&self) -> &Keys {
+        &self.keys
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Sends the keys in reports of `mode` from now on. The host keeps the
+    /// keys of each report apart, so the old one is cleared first: the
+    /// keys that are down move over to the new report.
+    pub fn set_mode(&mut self, mode: Mode) -> Result<(), KeyError> {
+        if mode == self.mode {
+            return Ok(());
+        }
+        let down = self.keys.iter().next().is_some();
+        if down && self.queue.free() < 2 {
+            return Err(KeyError::Full);
+        }
+        if down {
+            self.queue.push(Keys::new().report(self.mode));
+            self.queue.push(self.keys.report(mode));
+        }
+        self.mode = mode;
+        Ok(())
+    }
+
+    pub fn queue_mut(&mut self) -> &mut ReportQueue<QUEUE_LEN> {
+        &mut self.queue
+    }
+
+    /// Holds a key or modifier down. Pressing a key that is down already
+    /// changes nothing.
+    pub fn press(&mut self, code: u8) -> Result<(), KeyError> {
+        self.change(code, 1, |keys| keys.press(code))
+    }
+
+    pub fn release(&mut self, code: u8) -> Result<(), KeyError> {
+        self.change(code, 1, |keys| keys.release(code))
+    }
+
+    /// Presses and releases a key, as two reports. Either both are queued
+    /// or neither; a key that was down ends up released.
+    pub fn tap(&mut self, code: u8) -> Result<(), KeyError> {
+        self.change(code, 2, |keys| keys.press(code))?;
+        self.release(code)
+    }
+
+    /// Lets go of everything, e.g. when the app that held the keys exits
+    pub fn release_all(&mut self) -> Result<(), KeyError> {
+        if self.keys.iter().next().is_none() {
+            return Ok(());
+        }
+        if self.queue.free() == 0 {
+            return Err(KeyError::Full);
+        }
+        self.keys.release_all();
+        self.queue.push(self.keys.report(self.mode));
+        Ok(())
+    }
+
+    // Queues a report if `f` changed the keys. `room` reports have to fit.
+    fn change(&mut self, code: u8, room: usize, f: impl FnOnce(&mut Keys) -> bool) -> Result<(), KeyError> {
+        if !keycode::is_valid(code) {
+            return Err(KeyError::Invalid);
+        }
+        if self.queue.free() < room {
+            return Err(KeyError::Full);
+        }
+        if f(&mut self.keys) {
+            self.queue.push(self.keys.report(self.mode));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,146 @@
+use chocos_keyboard::consumer::{self, MAX_USAGE, VOLUME_UP};
+use chocos_keyboard::hid::{self, InputReport, Protocol, CONSUMER_ID, KEYBOARD_ID, MAX_REPORT, MOUSE_ID, NKRO_ID};
+use chocos_keyboard::mouse::{self, MAX_STEP};
+use chocos_keyboard::report::QUEUE_LEN;
+use chocos_keyboard::keycode::A;
+use chocos_keyboard::led;
+use chocos_keyboard::{BootReport, Consumer, ConsumerReport, KeyError, KeyboardReport, Mouse, MouseReport, NkroReport};
+
+// Bits of every input and output report in a descriptor, by report ID
+fn report_bits(descriptor: &[u8]) -> Vec<(u8, u32, u32)> {
+    let mut reports: Vec<(u8, u32, u32)> = Vec::new();
+    let (mut id, mut size, mut count) = (0, 0, 0);
+    let mut i = 0;
+    while i < descriptor.len() {
+        let prefix = descriptor[i];
+        let len = [0, 1, 2, 4][(prefix & 0x03) as usize];
+        let data = descriptor[i + 1..i + 1 + len].iter().rev().fold(0u32, |value, &b| value << 8 | b as u32);
+        match prefix & 0xFC {
+            0x84 => id = data as u8,
+            0x74 => size = data,
+            0x94 => count = data,
+            tag @ (0x80 | 0x90) => {
+                let entry = match reports.iter_mut().find(|entry| entry.0 == id) {
+                    Some(entry) => entry,
+                    None => {
+                        reports.push((id, 0, 0));
+                        reports.last_mut().unwrap()
+                    }
+                };
+                if tag == 0x80 {
+                    entry.1 += size * count;
+                } else {
+                    entry.2 += size * count;
+                }
+            }
+            _ => {}
+        }
+        i += 1 + len;
+    }
+    reports
+}
+
+#[test]
+fn descriptor_matches_the_reports() {
+    let reports = [
+        InputReport::Keyboard(KeyboardReport::Boot(BootReport::default())),
+        InputReport::Mouse(MouseReport::default()),
+        InputReport::Consumer(ConsumerReport::default()),
+        InputReport::Keyboard(KeyboardReport::Nkro(NkroReport::default())),
+    ];
+    let bits = report_bits(hid::REPORT_DESCRIPTOR);
+    assert_eq!(bits, [(KEYBOARD_ID, 64, 8), (MOUSE_ID, 32, 0), (CONSUMER_ID, 16, 0), (NKRO_ID, 232, 0)]);
+    for (report, (id, input, _)) in reports.iter().zip(bits) {
+        let mut buf = [0; MAX_REPORT];
+        let bytes = report.bytes(Protocol::Report, &mut buf).unwrap();
+        assert_eq!(bytes[0], id);
+        assert_eq!((bytes.len() - 1) * 8, input as usize);
+    }
+    assert_eq!(hid::hid_descriptor()[5..], (hid::REPORT_DESCRIPTOR.len() as u16).to_le_bytes());
+
+    let mut buf = [0; MAX_REPORT];
+    let report = MouseReport { buttons: mouse::LEFT, x: -1, y: 2, wheel: -3 };
+    assert_eq!(InputReport::Mouse(report).bytes(Protocol::Report, &mut buf).unwrap(), [MOUSE_ID, 1, 0xFF, 2, 0xFD]);
+    let report = ConsumerReport { usage: VOLUME_UP };
+    assert_eq!(InputReport::Consumer(report).bytes(Protocol::Report, &mut buf).unwrap(), [CONSUMER_ID, 0xE9, 0]);
+}
+
+#[test]
+fn boot_protocol() {
+    let mut buf = [0; MAX_REPORT];
+    let mut keys = [0; 28];
+    keys[0] = 1 << A;
+    let report = InputReport::Keyboard(KeyboardReport::Nkro(NkroReport { modifier: 0x02, keys }));
+    assert_eq!(report.bytes(Protocol::Boot, &mut buf), Some(&[0x02, 0, A, 0, 0, 0, 0, 0][..]));
+    assert_eq!(InputReport::Mouse(MouseReport::default()).bytes(Protocol::Boot, &mut buf), None);
+    assert_eq!(InputReport::Consumer(ConsumerReport::default()).bytes(Protocol::Boot, &mut buf), None);
+    assert_eq!(Protocol::from_value(0), Some(Protocol::Boot));
+    assert_eq!(Protocol::from_value(2), None);
+}
+
+#[test]
+fn led_reports() {
+    let caps = led::CAPS_LOCK | led::NUM_LOCK;
+    assert_eq!(hid::led_report(Protocol::Report, &[KEYBOARD_ID, caps]), Some(caps));
+    assert_eq!(hid::led_report(Protocol::Boot, &[caps]), Some(caps));
+    // the reserved bits are not LEDs
+    assert_eq!(hid::led_report(Protocol::Boot, &[0xE0 | led::KANA]), Some(led::KANA));
+    assert_eq!(hid::led_report(Protocol::Report, &[caps]), None);
+    assert_eq!(hid::led_report(Protocol::Report, &[MOUSE_ID, caps]), None);
+    assert_eq!(hid::led_report(Protocol::Boot, &[KEYBOARD_ID, caps]), None);
+    assert_eq!(hid::led_report(Protocol::Report, b"CHOCBOOT"), None);
+}
+
+fn drain_mouse(mouse: &mut Mouse) -> Vec<MouseReport> {
+    std::iter::from_fn(|| mouse.queue_mut().pop()).collect()
+}
+
+#[test]
+fn mouse_buttons_and_moves() {
+    let mut mouse = Mouse::new();
+    mouse.press(mouse::LEFT | mouse::RIGHT).unwrap();
+    mouse.press(mouse::LEFT).unwrap();
+    mouse.move_by(300, -5, 1).unwrap();
+    mouse.release(mouse::RIGHT).unwrap();
+    mouse.move_by(0, 0, 0).unwrap();
+    let buttons = |buttons| MouseReport { buttons, ..MouseReport::default() };
+    assert_eq!(
+        drain_mouse(&mut mouse),
+        [
+            buttons(0x03),
+            MouseReport { buttons: 0x03, x: 127, y: -5, wheel: 1 },
+            MouseReport { buttons: 0x03, x: 127, y: 0, wheel: 0 },
+            MouseReport { buttons: 0x03, x: 46, y: 0, wheel: 0 },
+            buttons(0x01),
+        ]
+    );
+
+    assert_eq!(mouse.press(0x20), Err(KeyError::Invalid));
+    assert_eq!(mouse.move_by(MAX_STEP * QUEUE_LEN as i32 + 1, 0, 0), Err(KeyError::Invalid));
+    mouse.move_by(0, -MAX_STEP * (QUEUE_LEN as i32 - 1), 0).unwrap();
+    // all or nothing
+    assert_eq!(mouse.move_by(MAX_STEP + 1, 0, 0), Err(KeyError::Full));
+    mouse.release_all().unwrap();
+    assert_eq!(mouse.queue_mut().len(), QUEUE_LEN);
+    assert_eq!(mouse.buttons(), 0);
+}
+
+#[test]
+fn consumer_keys() {
+    let mut keys = Consumer::new();
+    keys.tap(VOLUME_UP).unwrap();
+    keys.press(consumer::MUTE).unwrap();
+    keys.press(consumer::PLAY_PAUSE).unwrap();
+    keys.release().unwrap();
+    keys.release().unwrap();
+    let usages: Vec<u16> = std::iter::from_fn(|| keys.queue_mut().pop()).map(|report| report.usage).collect();
+    assert_eq!(usages, [VOLUME_UP, 0, consumer::MUTE, consumer::PLAY_PAUSE, 0]);
+
+    assert_eq!(keys.press(0), Err(KeyError::Invalid));
+    assert_eq!(keys.tap(MAX_USAGE + 1), Err(KeyError::Invalid));
+    for _ in 0..QUEUE_LEN / 2 {
+        keys.tap(VOLUME_UP).unwrap();
+    }
+    assert_eq!(keys.tap(VOLUME_UP), Err(KeyError::Full));
+    assert_eq!(keys.usage(), 0);
+}
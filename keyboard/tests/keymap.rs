@@ -0,0 +1,225 @@
+use chocos_keyboard::keycode::{A, ENTER, ESCAPE, LEFT_CTRL, LEFT_SHIFT, N1, SPACE, UP};
+use chocos_keyboard::keymap::{self, HEADER_SIZE, MAX_SIZE};
+use chocos_keyboard::report::{BOOT_KEYS, QUEUE_LEN};
+use chocos_keyboard::{Action, BootReport, Engine, KeyEvent, Keyboard, KeyboardReport, Keymap, KeymapError, MacroOp, MacroStep};
+
+const TERM: u16 = 200;
+const T: Action = Action::Transparent;
+
+fn report(modifier: u8, keys: &[u8]) -> BootReport {
+    let mut keycodes = [0; BOOT_KEYS];
+    keycodes[..keys.len()].copy_from_slice(keys);
+    BootReport { modifier, keycodes }
+}
+
+fn drain(keyboard: &mut Keyboard) -> Vec<BootReport> {
+    // boot mode, the reports are boot reports already
+    std::iter::from_fn(|| keyboard.queue_mut().pop()).map(|report| report.to_boot()).collect()
+}
+
+// A 1x4 board:
+//   layer 0: A, hold layer 1 / tap SPACE, ctrl / ENTER, macro 0
+//   layer 1: UP, -, toggle layer 2, -
+//   layer 2: N1, layer 1 while held, -, -
+fn build(buf: &mut [u8; MAX_SIZE]) -> usize {
+    let layers: [&[Action]; 3] = [
+        &[
+            Action::Key(A),
+            Action::LayerTap { layer: 1, tap: SPACE },
+            Action::ModTap { hold: LEFT_CTRL, tap: ENTER },
+            Action::Macro(0),
+        ],
+        &[Action::Key(UP), T, Action::Toggle(2), T],
+        &[Action::Key(N1), Action::Momentary(1), T, T],
+    ];
+    let hello: &[MacroStep] = &[
+        MacroStep { op: MacroOp::Press, code: LEFT_SHIFT },
+        MacroStep { op: MacroOp::Tap, code: A },
+        MacroStep { op: MacroOp::Release, code: LEFT_SHIFT },
+        MacroStep { op: MacroOp::Tap, code: ESCAPE },
+    ];
+    keymap::write(buf, 1, 4, TERM, &layers, &[hello]).unwrap()
+}
+
+struct Board<'a> {
+    keymap: Keymap<'a>,
+    engine: Engine,
+    keyboard: Keyboard,
+}
+
+impl<'a> Board<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Board { keymap: Keymap::parse(data).unwrap(), engine: Engine::new(), keyboard: Keyboard::new() }
+    }
+
+    fn key(&mut self, col: u8, pressed: bool) {
+        let event = KeyEvent { row: 0, col, pressed };
+        self.engine.event(&self.keymap, event, &mut self.keyboard).unwrap();
+    }
+
+    fn tap(&mut self, col: u8) {
+        self.key(col, true);
+        self.key(col, false);
+    }
+
+    fn wait(&mut self, ms: u32) {
+        self.engine.tick(&self.keymap, ms, &mut self.keyboard).unwrap();
+    }
+
+    fn reports(&mut self) -> Vec<BootReport> {
+        drain(&mut self.keyboard)
+    }
+}
+
+#[test]
+fn round_trip() {
+    let mut buf = [0; MAX_SIZE];
+    let len = build(&mut buf);
+    assert_eq!(len, HEADER_SIZE + 3 * 4 * 2 + 1 + 4 * 2);
+    let keymap = Keymap::parse(&buf[..len]).unwrap();
+    assert_eq!((keymap.layers(), keymap.rows(), keymap.cols(), keymap.tapping_term_ms()), (3, 1, 4, TERM));
+    assert_eq!(keymap.action(0, 0, 2), Action::ModTap { hold: LEFT_CTRL, tap: ENTER });
+    assert_eq!(keymap.action(2, 0, 1), Action::Momentary(1));
+    assert_eq!(keymap.macro_steps(0).count(), 4);
+    assert_eq!(keymap.macro_steps(1).count(), 0);
+    for word in [0x0000, 0x0001, 0x0004, 0x0103, 0x4F2C, 0x5728] {
+        assert_eq!(Action::from_u16(word).unwrap().to_u16(), word);
+    }
+}
+
+#[test]
+fn bad_keymaps() {
+    let mut buf = [0; MAX_SIZE];
+    let len = build(&mut buf);
+    let broken = |at: usize, value: u8| {
+        let mut data = buf[..len].to_vec();
+        data[at] = value;
+        Keymap::parse(&data).map(|_| ())
+    };
+    assert_eq!(broken(0, b'X'), Err(KeymapError::Magic));
+    assert_eq!(broken(5, 0), Err(KeymapError::Layout));
+    assert_eq!(broken(5, 4), Err(KeymapError::Size));
+    // key A turns into a toggle of layer 4, then into macro 4
+    assert_eq!(broken(HEADER_SIZE + 1, 0x02), Err(KeymapError::Action));
+    assert_eq!(broken(HEADER_SIZE + 1, 0x03), Err(KeymapError::Action));
+    assert_eq!(broken(len - 2, 9), Err(KeymapError::Macro));
+    assert_eq!(Keymap::parse(&buf[..len - 1]).map(|_| ()), Err(KeymapError::Size));
+
+    let layer: &[Action] = &[Action::LayerTap { layer: 16, tap: A }];
+    assert_eq!(keymap::write(&mut buf, 1, 1, 0, &[layer], &[]), Err(KeymapError::Action));
+    let long = [MacroStep { op: MacroOp::Tap, code: A }; QUEUE_LEN / 2 + 1];
+    let layer: &[Action] = &[Action::Macro(0)];
+    assert_eq!(keymap::write(&mut buf, 1, 1, 0, &[layer], &[&long]), Err(KeymapError::Macro));
+}
+
+#[test]
+fn layers() {
+    let mut buf = [0; MAX_SIZE];
+    let len = build(&mut buf);
+    let mut board = Board::new(&buf[..len]);
+
+    // toggle layer 2 from layer 1, held by the tap-hold key
+    board.key(1, true);
+    board.wait(TERM as u32);
+    board.tap(2);
+    board.key(1, false);
+    assert_eq!(board.engine.layers(), 0b101);
+    board.tap(0);
+    assert_eq!(board.reports(), [report(0, &[N1]), report(0, &[])]);
+
+    // layer 2 is transparent over the macro key and the toggle
+    board.key(1, true);
+    assert_eq!(board.engine.layers(), 0b111);
+    board.tap(2);
+    board.key(1, false);
+    assert_eq!(board.engine.layers(), 0b001);
+    assert!(board.reports().is_empty());
+}
+
+#[test]
+fn release_what_was_pressed() {
+    let mut buf = [0; MAX_SIZE];
+    let len = build(&mut buf);
+    let mut board = Board::new(&buf[..len]);
+
+    board.key(1, true);
+    board.wait(TERM as u32);
+    board.key(0, true);
+    board.key(1, false);
+    board.key(0, false);
+    assert_eq!(board.reports(), [report(0, &[UP]), report(0, &[])]);
+}
+
+#[test]
+fn dropped_press_is_not_released() {
+    let mut buf = [0; MAX_SIZE];
+    let len = build(&mut buf);
+    let mut board = Board::new(&buf[..len]);
+
+    for _ in 0..QUEUE_LEN {
+        board.keyboard.queue_mut().push(KeyboardReport::default());
+    }
+    let event = KeyEvent { row: 0, col: 0, pressed: true };
+    assert!(board.engine.event(&board.keymap, event, &mut board.keyboard).is_err());
+    assert_eq!(board.reports().len(), QUEUE_LEN);
+
+    board.key(0, false);
+    assert_eq!(board.reports(), []);
+    board.tap(0);
+    assert_eq!(board.reports(), [report(0, &[A]), report(0, &[])]);
+}
+
+#[test]
+fn tap_or_hold() {
+    let mut buf = [0; MAX_SIZE];
+    let len = build(&mut buf);
+    let mut board = Board::new(&buf[..len]);
+
+    // let go within the tapping term
+    board.key(2, true);
+    board.wait(TERM as u32 - 1);
+    board.key(2, false);
+    assert_eq!(board.reports(), [report(0, &[ENTER]), report(0, &[])]);
+
+    // held past it
+    board.key(2, true);
+    board.wait(TERM as u32);
+    board.tap(0);
+    board.key(2, false);
+    assert_eq!(board.reports(), [report(0x01, &[]), report(0x01, &[A]), report(0x01, &[]), report(0, &[])]);
+
+    // another key goes down first
+    board.key(1, true);
+    board.tap(0);
+    board.key(1, false);
+    assert_eq!(board.reports(), [report(0, &[UP]), report(0, &[])]);
+    assert_eq!(board.engine.layers(), 1);
+}
+
+#[test]
+fn macros() {
+    let mut buf = [0; MAX_SIZE];
+    let len = build(&mut buf);
+    let mut board = Board::new(&buf[..len]);
+
+    board.tap(3);
+    assert_eq!(
+        board.reports(),
+        [
+            report(0x02, &[]),
+            report(0x02, &[A]),
+            report(0x02, &[]),
+            report(0, &[]),
+            report(0, &[ESCAPE]),
+            report(0, &[]),
+        ]
+    );
+
+    // all or nothing
+    for _ in 0..QUEUE_LEN - 2 {
+        board.keyboard.queue_mut().push(KeyboardReport::default());
+    }
+    let event = KeyEvent { row: 0, col: 3, pressed: true };
+    assert!(board.engine.event(&board.keymap, event, &mut board.keyboard).is_err());
+    assert_eq!(board.keyboard.queue_mut().len(), QUEUE_LEN - 2);
+}
@@ -0,0 +1,120 @@
+use chocos_keyboard::{KeyEvent, Matrix, MatrixPins};
+
+// A board where the test decides what each key reads
+struct Board {
+    keys: [u32; 4],
+    selected: Option<usize>,
+    reads: usize,
+}
+
+impl Board {
+    fn new() -> Self {
+        Board { keys: [0; 4], selected: None, reads: 0 }
+    }
+
+    fn set(&mut self, row: usize, col: usize, down: bool) {
+        if down {
+            self.keys[row] |= 1 << col;
+        } else {
+            self.keys[row] &= !(1 << col);
+        }
+    }
+}
+
+impl MatrixPins for Board {
+    fn select_row(&mut self, row: usize) {
+        assert_eq!(self.selected, None, "two rows selected");
+        self.selected = Some(row);
+    }
+
+    fn unselect_row(&mut self, row: usize) {
+        assert_eq!(self.selected, Some(row));
+        self.selected = None;
+    }
+
+    fn read_cols(&mut self) -> u32 {
+        self.reads += 1;
+        // a column outside the matrix reads as pressed
+        self.keys[self.selected.unwrap()] | 1 << 31
+    }
+}
+
+fn scan(matrix: &mut Matrix, board: &mut Board, ms: u32) -> Vec<KeyEvent> {
+    let mut events = Vec::new();
+    matrix.scan(board, ms, |e| events.push(e));
+    events
+}
+
+fn down(row: u8, col: u8) -> KeyEvent {
+    KeyEvent { row, col, pressed: true }
+}
+
+fn up(row: u8, col: u8) -> KeyEvent {
+    KeyEvent { row, col, pressed: false }
+}
+
+#[test]
+fn press_and_release_after_debounce() {
+    let (mut matrix, mut board) = (Matrix::new(4, 3, 5), Board::new());
+    assert!(scan(&mut matrix, &mut board, 1).is_empty());
+    assert_eq!(board.reads, 4);
+
+    board.set(1, 2, true);
+    for _ in 0..5 {
+        assert!(scan(&mut matrix, &mut board, 1).is_empty());
+    }
+    assert_eq!(scan(&mut matrix, &mut board, 1), [down(1, 2)]);
+    assert!(matrix.is_down(1, 2));
+    assert!(scan(&mut matrix, &mut board, 1).is_empty());
+
+    // counted from the scan that first sees it
+    board.set(1, 2, false);
+    assert!(scan(&mut matrix, &mut board, 3).is_empty());
+    assert!(scan(&mut matrix, &mut board, 3).is_empty());
+    assert_eq!(scan(&mut matrix, &mut board, 3), [up(1, 2)]);
+}
+
+#[test]
+fn bounces_are_swallowed() {
+    let (mut matrix, mut board) = (Matrix::new(4, 3, 5), Board::new());
+    // chatter while the contact closes, each one restarts the wait
+    for _ in 0..10 {
+        board.set(0, 0, true);
+        assert!(scan(&mut matrix, &mut board, 2).is_empty());
+        board.set(0, 0, false);
+        assert!(scan(&mut matrix, &mut board, 2).is_empty());
+    }
+    board.set(0, 0, true);
+    let events: Vec<_> = (0..4).flat_map(|_| scan(&mut matrix, &mut board, 2)).collect();
+    assert_eq!(events, [down(0, 0)]);
+}
+
+#[test]
+fn keys_debounce_on_their_own() {
+    let (mut matrix, mut board) = (Matrix::new(4, 3, 4), Board::new());
+    board.set(0, 0, true);
+    scan(&mut matrix, &mut board, 1);
+    scan(&mut matrix, &mut board, 2);
+    // a second key starts bouncing, the first one is not held back
+    board.set(3, 1, true);
+    assert_eq!(scan(&mut matrix, &mut board, 2), [down(0, 0)]);
+    board.set(3, 1, false);
+    assert!(scan(&mut matrix, &mut board, 2).is_empty());
+    assert!(!matrix.is_down(3, 1));
+}
+
+#[test]
+fn no_debounce() {
+    let (mut matrix, mut board) = (Matrix::new(2, 2, 0), Board::new());
+    board.set(0, 1, true);
+    board.set(1, 0, true);
+    assert_eq!(scan(&mut matrix, &mut board, 1), [down(0, 1), down(1, 0)]);
+}
+
+#[test]
+fn event_words() {
+    for e in [down(3, 17), up(15, 0), up(0, 31)] {
+        assert_eq!(KeyEvent::from_u32(e.to_u32()), e);
+    }
+    assert_eq!(down(1, 2).to_u32(), 0x1_0102);
+}
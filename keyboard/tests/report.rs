@@ -0,0 +1,134 @@
+use chocos_keyboard::keycode::{self, A, ERROR_ROLL_OVER, LEFT_SHIFT, RIGHT_GUI};
+use chocos_keyboard::report::{QUEUE_LEN, BOOT_KEYS, NKRO_BYTES};
+use chocos_keyboard::{BootReport, KeyError, Keyboard, KeyboardReport, Keys, Mode, NkroReport, ReportQueue};
+
+fn report(modifier: u8, keys: &[u8]) -> BootReport {
+    let mut keycodes = [0; BOOT_KEYS];
+    keycodes[..keys.len()].copy_from_slice(keys);
+    BootReport { modifier, keycodes }
+}
+
+fn drain(keyboard: &mut Keyboard) -> Vec<BootReport> {
+    // boot mode, the reports are boot reports already
+    std::iter::from_fn(|| keyboard.queue_mut().pop()).map(|report| report.to_boot()).collect()
+}
+
+#[test]
+fn shift_a() {
+    let mut kb = Keyboard::new();
+    kb.press(LEFT_SHIFT).unwrap();
+    kb.tap(A).unwrap();
+    kb.release(LEFT_SHIFT).unwrap();
+    assert_eq!(drain(&mut kb), [report(0x02, &[]), report(0x02, &[A]), report(0x02, &[]), report(0, &[])]);
+    assert_eq!(report(0x02, &[A]).to_bytes(), [0x02, 0, A, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn nothing_changes_nothing_is_sent() {
+    let mut kb = Keyboard::new();
+    kb.press(A).unwrap();
+    kb.press(A).unwrap();
+    kb.release(A + 1).unwrap();
+    assert_eq!(drain(&mut kb), [report(0, &[A])]);
+
+    // a tap of a held key only lets go of it
+    kb.tap(A).unwrap();
+    assert_eq!(drain(&mut kb), [report(0, &[])]);
+}
+
+#[test]
+fn only_real_keys() {
+    let mut kb = Keyboard::new();
+    for code in [keycode::NONE, ERROR_ROLL_OVER, 0x03, RIGHT_GUI + 1] {
+        assert_eq!(kb.press(code), Err(KeyError::Invalid));
+    }
+    assert!(keycode::is_valid(RIGHT_GUI));
+    assert!(kb.queue_mut().is_empty());
+}
+
+#[test]
+fn roll_over_past_six_keys() {
+    let mut keys = Keys::new();
+    for code in A..A + 7 {
+        keys.press(code);
+    }
+    keys.press(LEFT_SHIFT);
+    assert_eq!(keys.boot_report(), BootReport { modifier: 0x02, keycodes: [ERROR_ROLL_OVER; BOOT_KEYS] });
+
+    // the seventh key is still known once one goes up
+    keys.release(A);
+    assert_eq!(keys.boot_report(), report(0x02, &[A + 1, A + 2, A + 3, A + 4, A + 5, A + 6]));
+}
+
+#[test]
+fn full_queue() {
+    let mut kb = Keyboard::new();
+    for i in 0..QUEUE_LEN as u8 / 2 {
+        kb.tap(A + i).unwrap();
+    }
+    assert_eq!(kb.press(A), Err(KeyError::Full));
+    assert!(!kb.keys().is_down(A));
+
+    // a tap needs room for both reports
+    kb.queue_mut().pop();
+    assert_eq!(kb.tap(A), Err(KeyError::Full));
+    kb.press(A).unwrap();
+    assert_eq!(kb.release_all(), Err(KeyError::Full));
+
+    drain(&mut kb);
+    kb.release_all().unwrap();
+    assert_eq!(drain(&mut kb), [report(0, &[])]);
+    kb.release_all().unwrap();
+    assert!(kb.queue_mut().is_empty());
+}
+
+#[test]
+fn nkro_mode() {
+    let mut kb = Keyboard::new();
+    kb.set_mode(Mode::Nkro).unwrap();
+    assert!(kb.queue_mut().is_empty());
+    for code in A..A + 7 {
+        kb.press(code).unwrap();
+    }
+    kb.press(LEFT_SHIFT).unwrap();
+    let mut keys = [0; NKRO_BYTES];
+    keys[0] = 0xF0;
+    keys[1] = 0x07;
+    let all = NkroReport { modifier: 0x02, keys };
+    assert_eq!(kb.queue_mut().len(), 8);
+    assert_eq!(std::iter::from_fn(|| kb.queue_mut().pop()).last(), Some(KeyboardReport::Nkro(all)));
+    assert_eq!(all.to_bytes()[..3], [0x02, 0xF0, 0x07]);
+    // a host that wants the boot protocol sees the roll over
+    assert_eq!(KeyboardReport::Nkro(all).to_boot(), BootReport { modifier: 0x02, keycodes: [ERROR_ROLL_OVER; BOOT_KEYS] });
+
+    // the NKRO report is cleared and the keys go on in the boot report
+    for code in A + 1..A + 7 {
+        kb.release(code).unwrap();
+    }
+    kb.queue_mut().clear();
+    kb.set_mode(Mode::Boot).unwrap();
+    assert_eq!(kb.mode(), Mode::Boot);
+    assert_eq!(
+        std::iter::from_fn(|| kb.queue_mut().pop()).collect::<Vec<_>>(),
+        [KeyboardReport::Nkro(NkroReport::default()), KeyboardReport::Boot(report(0x02, &[A]))]
+    );
+    kb.set_mode(Mode::Boot).unwrap();
+    assert!(kb.queue_mut().is_empty());
+}
+
+#[test]
+fn queue_wraps_around() {
+    let report = |modifier, keys: &[u8]| KeyboardReport::Boot(report(modifier, keys));
+    let mut queue = ReportQueue::<3>::new();
+    for round in 0..5u8 {
+        assert!(queue.push(report(round, &[])));
+        assert!(queue.push(report(round, &[A])));
+        assert_eq!(queue.peek(), Some(&report(round, &[])));
+        assert_eq!(queue.pop(), Some(report(round, &[])));
+        assert_eq!(queue.pop(), Some(report(round, &[A])));
+    }
+    assert_eq!(queue.pop(), None);
+    assert!(queue.push(report(0, &[])) && queue.push(report(0, &[])) && queue.push(report(0, &[])));
+    assert!(!queue.push(report(0, &[])));
+    assert_eq!(queue.len(), 3);
+}
@@ -0,0 +1,27 @@
+//! Puts the app linker script `link.x` and a default `memory.x` into the
+//! output directory and adds it to the linker search path, so apps only
+//! have to pass `-Tlink.x` to the linker.
+//!
+//! An app that needs a different load address can ship its own `memory.x`
+//! in its crate root, which the linker finds before this default one.
+
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+fn main() {
+    let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    File::create(out.join("link.x"))
+        .unwrap()
+        .write_all(include_bytes!("link.x"))
+        .unwrap();
+    File::create(out.join("memory.x"))
+        .unwrap()
+        .write_all(include_bytes!("memory.x"))
+        .unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+
+    println!("cargo:rerun-if-changed=link.x");
+    println!("cargo:rerun-if-changed=memory.x");
+}
@@ -0,0 +1,113 @@
+/* Linker script for ChocOS apps built on libchoc */
+
+/* Provides information about the memory layout of the app (see `memory.x`) */
+INCLUDE memory.x
+
+/* The kernel loads a process from the header at the start of its image */
+ENTRY(_start);
+EXTERN(__CHOC_HEADER);
+
+SECTIONS
+{
+  /* ### App header, see `chocos_abi::AppHeader` */
+  .choc_header ORIGIN(FLASH) :
+  {
+    KEEP(*(.choc_header));
+  } > FLASH
+
+  /* ### .text */
+  .text :
+  {
+    __stext = .;
+    *(.text .text.*);
+
+    . = ALIGN(4);
+    __etext = .;
+  } > FLASH
+
+  /* ### .rodata */
+  .rodata : ALIGN(4)
+  {
+    . = ALIGN(4);
+    __srodata = .;
+    *(.rodata .rodata.*);
+
+    /* 4-byte align the end (VMA) of this section.
+       This is required by LLD to ensure the LMA of the following .data
+       section will have the correct alignment. */
+    . = ALIGN(4);
+    __erodata = .;
+  } > FLASH
+
+  /* ## Sections in RAM */
+  /* ### .data */
+  .data : ALIGN(4)
+  {
+    . = ALIGN(4);
+    __sdata = .;
+    *(.data .data.*);
+    . = ALIGN(4);
+  } > RAM AT>FLASH
+  . = ALIGN(4);
+  __edata = .;
+
+  /* LMA of .data */
+  __sidata = LOADADDR(.data);
+
+  /* End of the image in flash, the signature trailer goes here */
+  __choc_image_end = LOADADDR(.data) + SIZEOF(.data);
+
+  /* ### .bss */
+  .bss (NOLOAD) : ALIGN(4)
+  {
+    . = ALIGN(4);
+    __sbss = .;
+    *(.bss .bss.*);
+    *(COMMON);
+    . = ALIGN(4);
+  } > RAM
+  . = ALIGN(4);
+  __ebss = .;
+
+  /* ## .got */
+  /* Dynamic relocations are unsupported. This section is only used to detect relocatable code in
+     the input files and raise an error if relocatable code is found */
+  .got (NOLOAD) :
+  {
+    KEEP(*(.got .got.*));
+  }
+
+  /* ## Discarded sections */
+  /DISCARD/ :
+  {
+    /* Unused exception related info that only wastes space */
+    *(.ARM.exidx);
+    *(.ARM.exidx.*);
+    *(.ARM.extab.*);
+  }
+}
+
+/* # Alignment checks */
+ASSERT(ORIGIN(FLASH) % 4 == 0, "
+ERROR(libchoc): the start of the FLASH region must be 4-byte aligned");
+
+ASSERT(ORIGIN(RAM) % 4 == 0, "
+ERROR(libchoc): the start of the RAM region must be 4-byte aligned");
+
+ASSERT(__sdata % 4 == 0 && __edata % 4 == 0, "
+BUG(libchoc): .data is not 4-byte aligned");
+
+ASSERT(__sidata % 4 == 0, "
+BUG(libchoc): the LMA of .data is not 4-byte aligned");
+
+ASSERT(__sbss % 4 == 0 && __ebss % 4 == 0, "
+BUG(libchoc): .bss is not 4-byte aligned");
+
+/* # Position checks */
+ASSERT(SIZEOF(.choc_header) == 20, "
+BUG(libchoc): the app header is missing");
+
+/* # Other checks */
+ASSERT(SIZEOF(.got) == 0, "
+ERROR(libchoc): .got section detected in the input object files
+Dynamic relocations are not supported.");
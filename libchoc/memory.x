@@ -0,0 +1,7 @@
+/* Default placement of a ChocOS app. */
+/* The image starts with its header, so it is loaded with create(ORIGIN(FLASH)). */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08020000, LENGTH = 64K
+  RAM : ORIGIN = 0x20000000, LENGTH = 2K
+}
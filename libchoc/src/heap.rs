@@ -0,0 +1,140 @@
+//! Process heap.
+//!
+//! First-fit free list over memory obtained from `sbrk`. Free blocks are
+//! kept in address order and merged on free; the break only ever grows.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::mem::size_of;
+use core::ptr;
+
+use crate::syscall::{exit, sbrk};
+
+const BLOCK_ALIGN: usize = 8;
+const HEAP_GROW_MIN: usize = 256;
+
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+const MIN_BLOCK: usize = size_of::<FreeBlock>();
+
+pub struct Heap {
+    head: UnsafeCell<*mut FreeBlock>,
+}
+
+// a process is single threaded
+unsafe impl Sync for Heap {}
+
+#[global_allocator]
+static HEAP: Heap = Heap { head: UnsafeCell::new(ptr::null_mut()) };
+
+impl Heap {
+    unsafe fn take(&self, size: usize, align: usize) -> *mut u8 {
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut block = *self.head.get();
+
+        while !block.is_null() {
+            let block_start = block as usize;
+            let block_end = block_start + (*block).size;
+            let alloc_start = align_up(block_start, align);
+            let alloc_end = alloc_start + size;
+
+            if alloc_end <= block_end {
+                let mut link = (*block).next;
+                if block_end > alloc_end {
+                    let tail = alloc_end as *mut FreeBlock;
+                    tail.write(FreeBlock { size: block_end - alloc_end, next: link });
+                    link = tail;
+                }
+                if alloc_start > block_start {
+                    (*block).size = alloc_start - block_start;
+                    (*block).next = link;
+                    link = block;
+                }
+
+                if prev.is_null() {
+                    *self.head.get() = link;
+                } else {
+                    (*prev).next = link;
+                }
+                return alloc_start as *mut u8;
+            }
+
+            prev = block;
+            block = (*block).next;
+        }
+
+        ptr::null_mut()
+    }
+
+    unsafe fn give(&self, addr: *mut u8, size: usize) {
+        let start = addr as usize;
+
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut next = *self.head.get();
+        while !next.is_null() && (next as usize) < start {
+            prev = next;
+            next = (*next).next;
+        }
+
+        let block = addr as *mut FreeBlock;
+        block.write(FreeBlock { size, next });
+
+        if !next.is_null() && start + size == next as usize {
+            (*block).size += (*next).size;
+            (*block).next = (*next).next;
+        }
+
+        if prev.is_null() {
+            *self.head.get() = block;
+        } else if prev as usize + (*prev).size == start {
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        } else {
+            (*prev).next = block;
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for Heap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let size = block_size(layout);
+        let align = layout.align().max(BLOCK_ALIGN);
+
+        let ptr = self.take(size, align);
+        if !ptr.is_null() {
+            return ptr;
+        }
+
+        // ask the kernel for more, with room for the alignment padding
+        let grow = align_up((size + align).max(HEAP_GROW_MIN), BLOCK_ALIGN);
+        match sbrk(grow as i32) {
+            Ok(region) => {
+                self.give(region, grow);
+                self.take(size, align)
+            },
+            Err(_) => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.give(ptr, block_size(layout));
+    }
+}
+
+fn block_size(layout: Layout) -> usize {
+    align_up(layout.size().max(MIN_BLOCK), BLOCK_ALIGN)
+}
+
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+#[alloc_error_handler]
+fn alloc_error(layout: Layout) -> ! {
+    crate::println!("out of memory: {} bytes requested", layout.size());
+    exit(-1)
+}
+
@@ -0,0 +1,44 @@
+//! Console output through the print system call.
+
+use core::fmt;
+
+/// The kernel console. Each formatted fragment becomes one print call.
+pub struct Stdout;
+
+impl fmt::Write for Stdout {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        crate::syscall::print(s);
+        Ok(())
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    // a plain literal goes out in one call
+    match args.as_str() {
+        Some(s) => crate::syscall::print(s),
+        None => {
+            let _ = fmt::Write::write_fmt(&mut Stdout, args);
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::io::_print(format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! println {
+    () => {
+        $crate::io::_print(format_args!("\n"))
+    };
+    ($fmt:expr) => {
+        $crate::io::_print(format_args!(concat!($fmt, "\n")))
+    };
+    ($fmt:expr, $($arg:tt)*) => {
+        $crate::io::_print(format_args!(concat!($fmt, "\n"), $($arg)*))
+    };
+}
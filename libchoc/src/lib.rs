@@ -0,0 +1,40 @@
+//! Userland runtime for ChocOS apps.
+//!
+//! Provides the system call wrappers, `print!`/`println!`, a heap backed
+//! by `sbrk`, the startup code and a panic handler. An app only has to
+//! name its main function:
+//!
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! use libchoc::println;
+//!
+//! libchoc::entry!(main);
+//!
+//! fn main() -> ! {
+//!     println!("Hello from {}", "ChocOS");
+//!     libchoc::exit(0)
+//! }
+//! ```
+
+#![no_std]
+#![feature(alloc_error_handler)]
+
+pub extern crate alloc;
+
+#[macro_use]
+pub mod syscall;
+#[macro_use]
+pub mod io;
+pub mod heap;
+pub mod rt;
+
+pub use syscall::{channel_close, channel_open, channel_recv, channel_send, consumer_press, consumer_release, consumer_tap, create, exit, key_press, key_release, key_tap, keyboard_mode, keymap_load, keymap_restore, led_read, led_subscribe, led_unsubscribe, matrix_read, matrix_subscribe, matrix_unsubscribe, mouse_move, mouse_press, mouse_release, print, print_cstr, print_u32, reboot, reboot_recovery, sbrk, suspend_read, suspend_subscribe, suspend_unsubscribe, yield_now, Errno};
+/// Key codes for `key_press` and friends
+pub use chocos_keyboard::keycode;
+/// Buttons for `mouse_press` and media keys for `consumer_press`
+pub use chocos_keyboard::{consumer, led, mouse};
+pub use chocos_keyboard::{KeyEvent, Mode};
+/// Building keymaps for `keymap_load`
+pub use chocos_keyboard::{keymap, Action, MacroOp, MacroStep};
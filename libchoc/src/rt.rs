@@ -0,0 +1,83 @@
+//! Startup code and panic handler.
+
+use core::panic::PanicInfo;
+
+use chocos_abi::{ABI_VERSION, APP_MAGIC, CAP_REBOOT};
+
+use crate::syscall::exit;
+
+/// Exit code of a process that panicked.
+pub const PANIC_EXIT_CODE: i32 = 101;
+
+extern "C" {
+    static mut __sbss: u32;
+    static mut __ebss: u32;
+
+    static mut __sdata: u32;
+    static mut __edata: u32;
+    static __sidata: u32;
+
+    // end of the image in flash, see link.x
+    static __choc_image_end: u8;
+}
+
+extern "Rust" {
+    fn __choc_main() -> !;
+}
+
+// Same layout as `chocos_abi::AppHeader`, but with the entry point and the
+// end as pointers so the linker fills them in.
+#[repr(C)]
+struct AppHeader {
+    magic: u32,
+    abi_version: u32,
+    entry: unsafe extern "C" fn() -> !,
+    end: &'static u8,
+    caps: u32,
+}
+
+#[link_section = ".choc_header"]
+#[no_mangle]
+#[used]
+static __CHOC_HEADER: AppHeader = AppHeader {
+    magic: APP_MAGIC,
+    abi_version: ABI_VERSION,
+    entry: _start,
+    end: unsafe { &__choc_image_end },
+    caps: if cfg!(feature = "reboot") { CAP_REBOOT } else { 0 },
+};
+
+/// Entry point of the process. The kernel has already set up the
+/// process stack, so all that is left is initializing RAM.
+#[doc(hidden)]
+#[no_mangle]
+pub unsafe extern "C" fn _start() -> ! {
+    let count = &__ebss as *const u32 as usize - &__sbss as *const u32 as usize;
+    core::ptr::write_bytes(&mut __sbss as *mut u32 as *mut u8, 0, count);
+
+    let count = &__edata as *const u32 as usize - &__sdata as *const u32 as usize;
+    core::ptr::copy_nonoverlapping(&__sidata as *const u32 as *const u8, &mut __sdata as *mut u32 as *mut u8, count);
+
+    __choc_main()
+}
+
+/// Declare the main function of the app.
+#[macro_export]
+macro_rules! entry {
+    ($path:path) => {
+        #[export_name = "__choc_main"]
+        pub unsafe fn __choc_main() -> ! {
+            // type check the given path
+            let f: fn() -> ! = $path;
+
+            f()
+        }
+    };
+}
+
+#[inline(never)]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    crate::println!("panicked: {}", info);
+    exit(PANIC_EXIT_CODE)
+}
@@ -0,0 +1,210 @@
+//! Safe wrappers around the kernel's system calls.
+//!
+//! The raw `sys_*` functions are generated from `abi/syscalls.toml`.
+//! See `docs/os/syscall_abi.md` for the calling convention.
+
+/// Issue a raw system call by number.
+#[macro_export]
+macro_rules! syscall {
+    ($id:expr, $arg1:expr, $arg2:expr, $arg3:expr) => {
+        unsafe { $crate::syscall::raw_syscall($id as u32, $arg1 as u32, $arg2 as u32, $arg3 as u32) }
+    };
+}
+
+pub use chocos_abi::{nr, raw_syscall, Errno};
+pub use chocos_abi::user::*;
+use chocos_keyboard::{KeyEvent, Mode};
+
+/// Give up the rest of the time slice.
+pub fn yield_now() {
+    let _ = sys_yield();
+}
+
+/// Write a string to the kernel console.
+pub fn print(text: &str) {
+    let _ = unsafe { sys_print(&text as *const &str as *const u8) };
+}
+
+/// Write a NUL terminated string to the kernel console.
+pub fn print_cstr(text: &[u8]) -> Result<(), Errno> {
+    if !text.contains(&0) {
+        return Err(Errno::Inval);
+    }
+    unsafe { sys_print_cstr(text.as_ptr()) }.map(|_| ())
+}
+
+/// Write an unsigned integer in decimal to the kernel console.
+pub fn print_u32(num: u32) {
+    let _ = sys_print_u32(num);
+}
+
+/// Terminate the calling process.
+pub fn exit(code: i32) -> ! {
+    sys_exit(code)
+}
+
+/// Start a new process from the app image at `image`.
+/// Returns the pid of the new process.
+pub fn create(image: u32) -> Result<u32, Errno> {
+    sys_create(image)
+}
+
+/// Move the program break by `increment` bytes and return the old break.
+/// Fails with `Errno::NoMem` when the heap would run into the stack.
+pub fn sbrk(increment: i32) -> Result<*mut u8, Errno> {
+    sys_sbrk(increment)
+}
+
+/// Tell the bootloader the running OS image works, so it keeps booting
+/// it instead of rolling back to the other slot.
+pub fn confirm_boot() -> Result<(), Errno> {
+    sys_confirm_boot().map(|_| ())
+}
+
+/// Restart the board and boot the OS again. Only returns if the app may
+/// not reboot (`Errno::Perm`): build it with the `reboot` feature of
+/// libchoc to ask for that in its header.
+pub fn reboot() -> Result<(), Errno> {
+    sys_reboot().map(|_| ())
+}
+
+/// Restart the board into the bootloader's flash mode, ready for
+/// `chocflash`. Only returns if the app may not reboot.
+pub fn reboot_recovery() -> Result<(), Errno> {
+    sys_reboot_recovery().map(|_| ())
+}
+
+/// Hold a key of the USB keyboard down until `key_release`. `code` is a
+/// HID keyboard usage from `keycode`, the modifiers included. Fails with
+/// `Errno::Again` while the reports before it haven't gone out yet.
+pub fn key_press(code: u8) -> Result<(), Errno> {
+    sys_key_press(code as u32).map(|_| ())
+}
+
+/// Let go of a key held with `key_press`.
+pub fn key_release(code: u8) -> Result<(), Errno> {
+    sys_key_release(code as u32).map(|_| ())
+}
+
+/// Press and release a key, e.g. to type a character.
+pub fn key_tap(code: u8) -> Result<(), Errno> {
+    sys_key_tap(code as u32).map(|_| ())
+}
+
+/// Take the key events of the board's key matrix, which otherwise type
+/// on the USB keyboard. Fails with `Errno::Busy` while another app has
+/// them.
+pub fn matrix_subscribe() -> Result<(), Errno> {
+    sys_matrix_subscribe().map(|_| ())
+}
+
+/// Hand the key matrix back to the USB keyboard.
+pub fn matrix_unsubscribe() -> Result<(), Errno> {
+    sys_matrix_unsubscribe().map(|_| ())
+}
+
+/// The next key press or release of the subscribed matrix, waits for one
+/// if nothing happened since the last call.
+pub fn matrix_read() -> Result<KeyEvent, Errno> {
+    sys_matrix_read().map(KeyEvent::from_u32)
+}
+
+/// Type with `keymap` from now on, until another one is loaded or the
+/// stored one restored. Build it with `keymap::write`; fails with
+/// `Errno::Inval` if the kernel finds it broken.
+pub fn keymap_load(keymap: &[u8]) -> Result<(), Errno> {
+    unsafe { sys_keymap_load(keymap.as_ptr(), keymap.len() as u32) }.map(|_| ())
+}
+
+/// Go back to the keymap stored in flash.
+pub fn keymap_restore() -> Result<(), Errno> {
+    sys_keymap_restore().map(|_| ())
+}
+
+/// Hold down mouse buttons, see `mouse::LEFT` and friends.
+pub fn mouse_press(buttons: u8) -> Result<(), Errno> {
+    sys_mouse_press(buttons as u32).map(|_| ())
+}
+
+pub fn mouse_release(buttons: u8) -> Result<(), Errno> {
+    sys_mouse_release(buttons as u32).map(|_| ())
+}
+
+/// Move the mouse and turn its wheel, relative to where it is. Fails with
+/// `Errno::Again` while the reports don't fit in the queue.
+pub fn mouse_move(x: i32, y: i32, wheel: i32) -> Result<(), Errno> {
+    sys_mouse_move(x, y, wheel).map(|_| ())
+}
+
+/// Hold down a media key, see `consumer::VOLUME_UP` and friends.
+pub fn consumer_press(usage: u16) -> Result<(), Errno> {
+    sys_consumer_press(usage as u32).map(|_| ())
+}
+
+pub fn consumer_release() -> Result<(), Errno> {
+    sys_consumer_release().map(|_| ())
+}
+
+pub fn consumer_tap(usage: u16) -> Result<(), Errno> {
+    sys_consumer_tap(usage as u32).map(|_| ())
+}
+
+/// Send the keys in boot or NKRO reports. A host that asked for the boot
+/// protocol gets boot reports either way.
+pub fn keyboard_mode(mode: Mode) -> Result<(), Errno> {
+    sys_keyboard_mode(mode as u32).map(|_| ())
+}
+
+/// Hear of changes of the keyboard LEDs the host lights.
+pub fn led_subscribe() -> Result<(), Errno> {
+    sys_led_subscribe().map(|_| ())
+}
+
+pub fn led_unsubscribe() -> Result<(), Errno> {
+    sys_led_unsubscribe().map(|_| ())
+}
+
+/// The keyboard LEDs, see `led::CAPS_LOCK` and friends. After
+/// `led_subscribe` it blocks until they change.
+pub fn led_read() -> Result<u8, Errno> {
+    sys_led_read().map(|leds| leds as u8)
+}
+
+/// Hear of the host suspending and resuming the USB bus.
+pub fn suspend_subscribe() -> Result<(), Errno> {
+    sys_suspend_subscribe().map(|_| ())
+}
+
+pub fn suspend_unsubscribe() -> Result<(), Errno> {
+    sys_suspend_unsubscribe().map(|_| ())
+}
+
+/// Whether the bus is suspended. After `suspend_subscribe` it blocks
+/// until that changes.
+pub fn suspend_read() -> Result<bool, Errno> {
+    sys_suspend_read().map(|suspended| suspended != 0)
+}
+
+/// Take the vendor HID channel to the host. Fails with `Errno::Busy`
+/// while another app has it.
+pub fn channel_open() -> Result<(), Errno> {
+    sys_channel_open().map(|_| ())
+}
+
+/// Give the channel back.
+pub fn channel_close() -> Result<(), Errno> {
+    sys_channel_close().map(|_| ())
+}
+
+/// Wait for the next message from the host and copy it to `buf`. Returns
+/// the length of the message, which is more than `buf` holds if the
+/// rest was cut off.
+pub fn channel_recv(buf: &mut [u8]) -> Result<usize, Errno> {
+    unsafe { sys_channel_recv(buf.as_mut_ptr(), buf.len() as u32) }.map(|len| len as usize)
+}
+
+/// Send `message` to the host, up to 512 bytes, longer is `Errno::Inval`.
+/// Waits while the last one is still going out.
+pub fn channel_send(message: &[u8]) -> Result<(), Errno> {
+    unsafe { sys_channel_send(message.as_ptr(), message.len() as u32) }.map(|_| ())
+}
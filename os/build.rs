@@ -7,6 +7,11 @@
 //! Cargo re-run the build script whenever `memory.x` is changed,
 //! updating `memory.x` ensures a rebuild of the application with the
 //! new memory settings.
+//!
+//! On the way, `FLASH` is moved to the second OS slot when building with
+//! the `slot-b` feature, and the version for the OS image header is added.
+//! It also writes the public key for checking app signatures to
+//! `public_key.rs`, see `chocos_isp::build`.
 
 use std::env;
 use std::fs::File;
@@ -17,9 +22,23 @@ fn main() {
     // Put `memory.x` in our output directory and ensure it's
     // on the linker search path.
     let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let mut memory = String::from(include_str!("memory.x"));
+
+    // The same OS, linked for the second slot (see chocos_isp::slots)
+    if env::var_os("CARGO_FEATURE_SLOT_B").is_some() {
+        memory = memory.replace("FLASH : ORIGIN = 0x08010000", "FLASH : ORIGIN = 0x08060000");
+    }
+
+    // Version in the OS image header, the bootloader boots the newest image
+    let part = |name: &str| env::var(name).unwrap().parse::<u32>().unwrap().min(0xFF);
+    let version = part("CARGO_PKG_VERSION_MAJOR") << 16
+        | part("CARGO_PKG_VERSION_MINOR") << 8
+        | part("CARGO_PKG_VERSION_PATCH");
+    memory.push_str(&format!("\n_os_version = {:#010x};\n", version));
+
     File::create(out.join("memory.x"))
         .unwrap()
-        .write_all(include_bytes!("memory.x"))
+        .write_all(memory.as_bytes())
         .unwrap();
     println!("cargo:rustc-link-search={}", out.display());
 
@@ -28,4 +47,6 @@ fn main() {
     // here, we ensure the build script is only re-run when
     // `memory.x` is changed.
     println!("cargo:rerun-if-changed=memory.x");
+
+    chocos_isp::build::write_public_key(out);
 }
@@ -6,8 +6,17 @@ MEMORY
   /* FLASH : ORIGIN = 0x00000000, LENGTH = 256K */
 
   
-  FLASH : ORIGIN = 0x08010000, LENGTH = 448K
-  RAM : ORIGIN = 0x20001000, LENGTH = 60K
+  FLASH : ORIGIN = 0x08010000, LENGTH = 64K
+  /* .data/.bss of the apps, see chocos_abi::memory; nothing of the
+     kernel goes here */
+  APP_RAM : ORIGIN = 0x20000000, LENGTH = 4K
+  RAM : ORIGIN = 0x20001000, LENGTH = 8K
+  KHEAP : ORIGIN = 0x20003000, LENGTH = 8K
+  /* the RAM slots of the processes, see src/task_scheduler.rs */
+  PROCESSES : ORIGIN = 0x20005000, LENGTH = 32K
+  /* the kernel's stack (MSP), below the bootloader handoff block at
+     0x2000F500 (see chocos_handoff) */
+  STACK : ORIGIN = 0x2000D000, LENGTH = 5376
   
 
   /*
@@ -22,7 +31,29 @@ MEMORY
 /* You may want to use this variable to locate the call stack and static
    variables in different memory regions. Below is shown the default value */
 /* _stack_start = ORIGIN(RAM) + LENGTH(RAM); */
-_stack_start = 0x2000E500;
+_stack_start = ORIGIN(STACK) + LENGTH(STACK);
+
+/* Process slots, handed out from the top down by src/task_scheduler.rs */
+_processes_end = ORIGIN(PROCESSES) + LENGTH(PROCESSES);
+
+/* The regions are kept apart by hand, these catch a change to one that
+   runs into the next */
+ASSERT(ORIGIN(APP_RAM) + LENGTH(APP_RAM) <= ORIGIN(RAM), "
+ERROR(chocos): the app static RAM runs into the kernel data");
+ASSERT(ORIGIN(RAM) + LENGTH(RAM) <= ORIGIN(KHEAP), "
+ERROR(chocos): the kernel data runs into the kernel heap");
+ASSERT(ORIGIN(KHEAP) + LENGTH(KHEAP) <= ORIGIN(PROCESSES), "
+ERROR(chocos): the kernel heap runs into the process slots");
+ASSERT(LENGTH(PROCESSES) == 8 * 4K, "
+ERROR(chocos): PROCESSES has to hold MAX_PCB slots of PROCESS_RAM_SIZE (src/task_scheduler.rs)");
+ASSERT(_processes_end <= ORIGIN(STACK), "
+ERROR(chocos): the process slots run into the kernel stack");
+ASSERT(_stack_start <= 0x2000F500, "
+ERROR(chocos): the kernel stack runs into the bootloader handoff block");
+
+/* Kernel heap, handed to the global allocator in src/allocator.rs */
+_kheap_start = ORIGIN(KHEAP);
+_kheap_end = ORIGIN(KHEAP) + LENGTH(KHEAP);
 
 /* You can use this symbol to customize the location of the .text section */
 /* If omitted the .text section will be placed right after the .vector_table
@@ -32,6 +63,26 @@ _stack_start = 0x2000E500;
 /* _stext = ORIGIN(FLASH) + 0x400; */
 _stext = ORIGIN(FLASH) + 0x200;
 
+/* Start of the slot this build is linked for, see src/boot_slot.rs */
+_os_base = ORIGIN(FLASH);
+
+/* OS image header, checked by the bootloader before it boots the OS (see
+   chocos-isp `image`). It sits between the vector table and _stext. The
+   CRC is left for `chocflash seal` to fill in after the build,
+   _os_version is defined by build.rs. */
+SECTIONS {
+  .os_header ORIGIN(FLASH) + 0x1F0 :
+  {
+    LONG(0x534F4843);                                  /* magic "CHOS" */
+    LONG(LOADADDR(.data) + SIZEOF(.data) - ORIGIN(FLASH)); /* length */
+    LONG(0xFFFFFFFF);                                  /* crc32, unsealed */
+    LONG(_os_version);                                 /* version */
+  } > FLASH
+} INSERT AFTER .vector_table;
+
+ASSERT(ADDR(.vector_table) + SIZEOF(.vector_table) <= ADDR(.os_header), "
+ERROR(chocos): the vector table runs into the OS image header");
+
 /* Example of putting non-initialized variables into custom RAM locations. */
 /* This assumes you have defined a region RAM2 above, and in the Rust
    sources added the attribute `#[link_section = ".ram2bss"]` to the data
@@ -0,0 +1,273 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::mem::size_of;
+use core::ptr;
+use cortex_m::{interrupt, peripheral::SCB};
+use crate::hprintln;
+
+// Kernel heap
+//
+// A first-fit free list allocator over the KHEAP region of memory.x.
+// Free blocks are kept in address order so neighbouring blocks can be
+// merged again on free. Every block is a multiple of BLOCK_ALIGN bytes,
+// which keeps the bookkeeping exact: a split never leaves a remainder
+// smaller than a free block header.
+//
+// Running out of memory is not a panic. `alloc` logs the failure through
+// `out_of_memory` and returns null, so kernel code that uses the fallible
+// APIs (`Vec::try_reserve`, `Box::try_new`, ...) gets an error back and
+// carries on. Only an infallible allocation ends up in `alloc_error`.
+
+extern "C" {
+    static mut _kheap_start: u8;
+    static mut _kheap_end: u8;
+}
+
+const BLOCK_ALIGN: usize = 8;
+
+#[global_allocator]
+pub static KERNEL_HEAP: KernelHeap = KernelHeap::empty();
+
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+const MIN_BLOCK: usize = size_of::<FreeBlock>();
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct HeapStats {
+    pub total: usize,
+    pub used: usize,
+    pub peak_used: usize,
+    pub allocations: u32,
+    pub deallocations: u32,
+    pub failures: u32,
+    pub free_blocks: usize,
+    pub largest_free: usize,
+}
+
+impl HeapStats {
+    pub fn free(&self) -> usize {
+        self.total - self.used
+    }
+
+    /// External fragmentation in percent: how much of the free memory
+    /// can not be handed out as a single block.
+    pub fn fragmentation(&self) -> u8 {
+        let free = self.free();
+        if free == 0 {
+            return 0;
+        }
+        (100 - self.largest_free * 100 / free) as u8
+    }
+}
+
+struct HeapInner {
+    head: *mut FreeBlock,
+    stats: HeapStats,
+}
+
+pub struct KernelHeap {
+    inner: UnsafeCell<HeapInner>,
+}
+
+unsafe impl Sync for KernelHeap {}
+
+impl KernelHeap {
+    pub const fn empty() -> Self {
+        KernelHeap {
+            inner: UnsafeCell::new(HeapInner {
+                head: ptr::null_mut(),
+                stats: HeapStats {
+                    total: 0,
+                    used: 0,
+                    peak_used: 0,
+                    allocations: 0,
+                    deallocations: 0,
+                    failures: 0,
+                    free_blocks: 0,
+                    largest_free: 0,
+                },
+            }),
+        }
+    }
+
+    pub unsafe fn init(&self, start: usize, size: usize) {
+        let start_aligned = align_up(start, BLOCK_ALIGN);
+        let size = (size - (start_aligned - start)) & !(BLOCK_ALIGN - 1);
+
+        interrupt::free(|_| {
+            let inner = &mut *self.inner.get();
+            let block = start_aligned as *mut FreeBlock;
+            block.write(FreeBlock { size, next: ptr::null_mut() });
+            inner.head = block;
+            inner.stats.total = size;
+        });
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        interrupt::free(|_| unsafe {
+            let inner = &*self.inner.get();
+            let mut stats = inner.stats;
+            stats.free_blocks = 0;
+            stats.largest_free = 0;
+
+            let mut block = inner.head;
+            while !block.is_null() {
+                stats.free_blocks += 1;
+                if (*block).size > stats.largest_free {
+                    stats.largest_free = (*block).size;
+                }
+                block = (*block).next;
+            }
+            stats
+        })
+    }
+
+    unsafe fn allocate(inner: &mut HeapInner, layout: Layout) -> *mut u8 {
+        let size = block_size(layout);
+        let align = layout.align().max(BLOCK_ALIGN);
+
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut block = inner.head;
+
+        while !block.is_null() {
+            let block_start = block as usize;
+            let block_end = block_start + (*block).size;
+            let alloc_start = align_up(block_start, align);
+            let alloc_end = alloc_start + size;
+
+            if alloc_end <= block_end {
+                let next = (*block).next;
+
+                // Padding in front of an over-aligned allocation stays free.
+                // Both paddings are multiples of BLOCK_ALIGN, so they are
+                // either empty or large enough to hold a header.
+                let mut link = next;
+                if block_end > alloc_end {
+                    let tail = alloc_end as *mut FreeBlock;
+                    tail.write(FreeBlock { size: block_end - alloc_end, next });
+                    link = tail;
+                }
+                if alloc_start > block_start {
+                    (*block).size = alloc_start - block_start;
+                    (*block).next = link;
+                    link = block;
+                }
+
+                if prev.is_null() {
+                    inner.head = link;
+                } else {
+                    (*prev).next = link;
+                }
+
+                inner.stats.used += size;
+                inner.stats.allocations += 1;
+                if inner.stats.used > inner.stats.peak_used {
+                    inner.stats.peak_used = inner.stats.used;
+                }
+                return alloc_start as *mut u8;
+            }
+
+            prev = block;
+            block = (*block).next;
+        }
+
+        ptr::null_mut()
+    }
+
+    unsafe fn release(inner: &mut HeapInner, addr: *mut u8, layout: Layout) {
+        let size = block_size(layout);
+        let start = addr as usize;
+
+        // find the neighbours in the address ordered list
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut next = inner.head;
+        while !next.is_null() && (next as usize) < start {
+            prev = next;
+            next = (*next).next;
+        }
+
+        let block = addr as *mut FreeBlock;
+        block.write(FreeBlock { size, next });
+
+        if !next.is_null() && start + size == next as usize {
+            (*block).size += (*next).size;
+            (*block).next = (*next).next;
+        }
+
+        if prev.is_null() {
+            inner.head = block;
+        } else if prev as usize + (*prev).size == start {
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        } else {
+            (*prev).next = block;
+        }
+
+        inner.stats.used -= size;
+        inner.stats.deallocations += 1;
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = interrupt::free(|_| {
+            let inner = &mut *self.inner.get();
+            let ptr = KernelHeap::allocate(inner, layout);
+            if ptr.is_null() {
+                inner.stats.failures += 1;
+            }
+            ptr
+        });
+
+        if ptr.is_null() {
+            out_of_memory(layout, &self.stats());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        interrupt::free(|_| {
+            let inner = &mut *self.inner.get();
+            KernelHeap::release(inner, ptr, layout);
+        });
+    }
+}
+
+fn block_size(layout: Layout) -> usize {
+    align_up(layout.size().max(MIN_BLOCK), BLOCK_ALIGN)
+}
+
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+pub fn init() {
+    unsafe {
+        let start = &mut _kheap_start as *mut u8 as usize;
+        let end = &mut _kheap_end as *mut u8 as usize;
+        KERNEL_HEAP.init(start, end - start);
+    }
+
+    let stats = KERNEL_HEAP.stats();
+    let _ = hprintln!("[Heap] Init: {} bytes at {:#x}", stats.total, unsafe { &_kheap_start as *const u8 as u32 });
+}
+
+// Kernel OOM handler. The allocation has already failed, so this only
+// reports; the caller gets null and is expected to back off.
+fn out_of_memory(layout: Layout, stats: &HeapStats) {
+    let _ = hprintln!(
+        "[Heap] Out of memory: {} bytes (align {}) requested, {} of {} bytes free in {} blocks, largest {}, fragmentation {}%",
+        layout.size(), layout.align(), stats.free(), stats.total, stats.free_blocks, stats.largest_free, stats.fragmentation()
+    );
+}
+
+// An infallible allocation failed and the kernel can't continue from here.
+// Leave the panic path alone and restart the board instead.
+#[alloc_error_handler]
+fn alloc_error(layout: Layout) -> ! {
+    let _ = hprintln!("[Heap] Unrecoverable allocation of {} bytes, resetting", layout.size());
+    SCB::sys_reset();
+}
@@ -0,0 +1,100 @@
+use chocos_abi::Errno;
+use chocos_isp::{image::{OsHeader, OS_HEADER_OFFSET, OS_HEADER_SIZE}, slots::{self, SlotLayout}, Flash, FlashError, Layout};
+use stm32f1xx_hal::flash::{FlashSize, Parts, SectorSize};
+
+use crate::{hprintln, uptime_ms, usb_hid};
+
+extern "C" {
+    // start of the slot this build is linked for, see memory.x
+    static _os_base: u32;
+}
+
+static mut FLASH: Option<OsFlash> = None;
+
+// An image on trial is confirmed once it has shown it works: the first app
+// started, a host configured the keyboard and nothing reset the board for
+// CONFIRM_AFTER_MS since. A board without a host waits NO_HOST_CONFIRM_MS.
+// One that crashes or hangs before is reset and rolled back.
+const CONFIRM_AFTER_MS: u32 = 5_000;
+const NO_HOST_CONFIRM_MS: u32 = 60_000;
+// uptime when the first app started, until the image is confirmed
+static mut STARTED_MS: Option<u32> = None;
+
+// Only used to write the boot log, see chocos_isp::slots
+struct OsFlash {
+    parts: Parts,
+}
+
+impl Flash for OsFlash {
+    fn layout(&self) -> Layout {
+        Layout::STM32F103
+    }
+
+    fn erase_page(&mut self, addr: u32) -> Result<(), FlashError> {
+        self.parts.writer(SectorSize::Sz2K, FlashSize::Sz512K)
+            .page_erase(addr - Layout::STM32F103.base)
+            .map_err(|_| FlashError::Erase)
+    }
+
+    fn write(&mut self, addr: u32, data: &[u8]) -> Result<(), FlashError> {
+        self.parts.writer(SectorSize::Sz2K, FlashSize::Sz512K)
+            .write(addr - Layout::STM32F103.base, data)
+            .map_err(|_| FlashError::Program)
+    }
+
+    fn read(&self, addr: u32, len: usize) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(addr as *const u8, len) }
+    }
+}
+
+pub fn base() -> u32 {
+    unsafe { &_os_base as *const u32 as u32 }
+}
+
+pub fn init(parts: Parts) {
+    unsafe {
+        FLASH = Some(OsFlash { parts });
+    }
+    let _ = hprintln!("[ChocOS] Init: Running from slot at {:#x}", base());
+}
+
+// The init process started its first app, the trial of the image begins
+pub fn app_started() {
+    cortex_m::interrupt::free(|_| unsafe {
+        if STARTED_MS.is_none() {
+            STARTED_MS = Some(uptime_ms());
+        }
+    });
+}
+
+// From SysTick, so it doesn't depend on the init process getting the CPU:
+// confirms the image once the system has stayed up long enough since the
+// first app started.
+pub fn tick() {
+    let started_ms = match unsafe { STARTED_MS } {
+        Some(ms) => ms,
+        None => return,
+    };
+    let up = uptime_ms().wrapping_sub(started_ms);
+    if up < NO_HOST_CONFIRM_MS && (up < CONFIRM_AFTER_MS || !usb_hid::was_configured()) {
+        return;
+    }
+    // once, whatever comes of it
+    unsafe { STARTED_MS = None };
+    let _ = confirm();
+}
+
+// Tells the bootloader this image works, so it stops counting boot
+// attempts and won't roll back to the other slot.
+pub fn confirm() -> Result<(), Errno> {
+    let layout = SlotLayout::STM32F103;
+    let slot = layout.slot_of(base()).ok_or(Errno::Fault)?;
+    let raw = unsafe { core::slice::from_raw_parts(base() as *const u8, OS_HEADER_OFFSET + OS_HEADER_SIZE) };
+    let header = OsHeader::parse(raw).ok_or(Errno::NoExec)?;
+
+    let flash = unsafe { FLASH.as_mut().unwrap() };
+    slots::confirm(flash, &layout, slot, header.crc).map_err(|e| {
+        let _ = hprintln!("[ChocOS] Confirming the image in slot {} failed: {:?}", slot, e);
+        Errno::Io
+    })
+}
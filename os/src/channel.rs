@@ -0,0 +1,142 @@
+use chocos_abi::Errno;
+use chocos_channel::frame::{ACK, END, NAK, REPORT_SIZE};
+use chocos_channel::{ChannelError, Receiver, Sender};
+
+use crate::{hprintln, usb_hid, TASK_SCHEDULER};
+
+// The vendor HID channel, see the `chocos-channel` crate. Reports come and
+// go through the USB interrupt, messages through the system calls of the
+// process that has the channel open.
+static mut RECEIVER: Option<Receiver> = None;
+static mut SENDER: Option<Sender> = None;
+static mut OWNER: Option<usize> = None;
+
+pub fn init() {
+    unsafe {
+        RECEIVER = Some(Receiver::new());
+        SENDER = Some(Sender::new());
+    }
+}
+
+pub fn open(pid: usize) -> Result<(), Errno> {
+    cortex_m::interrupt::free(|_| unsafe {
+        match OWNER {
+            Some(other) if other != pid => Err(Errno::Busy),
+            _ => {
+                OWNER = Some(pid);
+                Ok(())
+            }
+        }
+    })
+}
+
+// Drops what is still on its way either way
+pub fn close(pid: usize) -> Result<(), Errno> {
+    cortex_m::interrupt::free(|_| unsafe {
+        if OWNER != Some(pid) {
+            return Err(Errno::Perm);
+        }
+        OWNER = None;
+        let receiver = RECEIVER.as_mut().unwrap();
+        if receiver.message().is_some() {
+            SENDER.as_mut().unwrap().status(NAK);
+        }
+        receiver.clear();
+        SENDER.as_mut().unwrap().clear();
+        Ok(())
+    })?;
+    usb_hid::kick();
+    Ok(())
+}
+
+pub fn process_exited(pid: usize) {
+    let _ = close(pid);
+}
+
+// Copies the message from the host into `buf` and lets the host send the
+// next one. Returns the length of the message, which is cut short if
+// `buf` is. Errno::Again while there is none.
+pub fn recv(pid: usize, buf: &mut [u8]) -> Result<usize, Errno> {
+    let len = cortex_m::interrupt::free(|_| unsafe {
+        if OWNER != Some(pid) {
+            return Err(Errno::Perm);
+        }
+        let receiver = RECEIVER.as_mut().unwrap();
+        let message = receiver.message().ok_or(Errno::Again)?;
+        let n = message.len().min(buf.len());
+        buf[..n].copy_from_slice(&message[..n]);
+        let len = message.len();
+        receiver.clear();
+        SENDER.as_mut().unwrap().status(ACK);
+        Ok(len)
+    })?;
+    usb_hid::kick();
+    Ok(len)
+}
+
+// Errno::Again while the last message is still going out
+pub fn send(pid: usize, message: &[u8]) -> Result<(), Errno> {
+    cortex_m::interrupt::free(|_| unsafe {
+        if OWNER != Some(pid) {
+            return Err(Errno::Perm);
+        }
+        SENDER.as_mut().unwrap().send(message).map_err(|e| match e {
+            ChannelError::Busy => Errno::Again,
+            _ => Errno::Inval,
+        })
+    })?;
+    usb_hid::kick();
+    Ok(())
+}
+
+// An output report from the host, from the USB interrupt
+pub fn received(report: &[u8]) {
+    let (receiver, sender) = match unsafe { (RECEIVER.as_mut(), SENDER.as_mut()) } {
+        (Some(receiver), Some(sender)) => (receiver, sender),
+        _ => return,
+    };
+    let owner = match unsafe { OWNER } {
+        Some(owner) => owner,
+        None => {
+            // only the last report of a message is answered
+            if report.first().map_or(true, |flags| flags & END != 0) {
+                sender.status(NAK);
+            }
+            return;
+        }
+    };
+    match receiver.push(report) {
+        Ok(true) => wake(owner),
+        Ok(false) => {}
+        Err(e) => {
+            let _ = hprintln!("[Channel] Dropped a report: {:?}", e);
+            sender.status(NAK);
+        }
+    }
+}
+
+// The input report to send next, `sent` once the endpoint took it
+pub fn next_report() -> Option<[u8; REPORT_SIZE]> {
+    unsafe { SENDER.as_ref()?.next_report() }
+}
+
+pub fn sent() {
+    let sender = match unsafe { SENDER.as_mut() } {
+        Some(sender) => sender,
+        None => return,
+    };
+    let was_busy = sender.is_busy();
+    sender.sent();
+    if was_busy && !sender.is_busy() {
+        if let Some(owner) = unsafe { OWNER } {
+            wake(owner);
+        }
+    }
+}
+
+// The owner may be blocked in channel_recv or channel_send
+fn wake(pid: usize) {
+    if let Some(task_scheduler) = unsafe { TASK_SCHEDULER.as_mut() } {
+        task_scheduler.wake(pid);
+    }
+}
@@ -0,0 +1,142 @@
+use core::fmt::Write;
+
+use chocos_console::command::{self, Command, CommandError, HELP};
+use chocos_console::{LineEditor, Ring};
+use chocos_handoff::Request;
+use stm32f1xx_hal::usb::{Peripheral, UsbBus};
+use usbd_serial::CdcAcmClass;
+
+use crate::allocator::KERNEL_HEAP;
+use crate::task_scheduler::ProcessState;
+use crate::{logger, reboot, usb_hid, TASK_SCHEDULER};
+
+// Packets of the CDC data endpoints. The USB peripheral has 512 bytes of
+// packet memory: 64 for the buffer table, 128 each for EP0 and the two HID
+// interfaces, which leaves 16 bytes each way after the 8 of the
+// notification endpoint.
+pub const PACKET_SIZE: u16 = 16;
+
+const PROMPT: &str = "choc> ";
+
+// Log and console output on its way to the host. Const, so the log from
+// before USB is up is kept until a terminal opens the port.
+static mut OUTPUT: Ring<1024> = Ring::new();
+static mut EDITOR: LineEditor = LineEditor::new();
+// the last packet was a full one, the host waits for more until a short one
+static mut LAST_FULL: bool = false;
+static mut OPEN: bool = false;
+
+// Output for the terminal, the log comes in through `logger`
+pub fn print_str(s: &str) {
+    cortex_m::interrupt::free(|_| unsafe { OUTPUT.push_text(s) });
+    usb_hid::kick();
+}
+
+pub fn print_fmt(args: core::fmt::Arguments) {
+    cortex_m::interrupt::free(|_| unsafe {
+        let _ = OUTPUT.write_fmt(args);
+    });
+    usb_hid::kick();
+}
+
+// From the USB interrupt: runs what the terminal typed and sends what is
+// buffered while a terminal has the port open
+pub fn poll(serial: &mut CdcAcmClass<'static, UsbBus<Peripheral>>) {
+    let mut buf = [0u8; PACKET_SIZE as usize];
+    while let Ok(size) = serial.read_packet(&mut buf) {
+        for &byte in &buf[..size] {
+            // only this interrupt edits the line, the echo shares OUTPUT
+            let done = cortex_m::interrupt::free(|_| unsafe { EDITOR.push(byte, &mut OUTPUT) });
+            if done {
+                run(unsafe { EDITOR.line() });
+                unsafe { EDITOR.clear() };
+                print_str(PROMPT);
+            }
+        }
+    }
+
+    // a terminal just opened the port
+    let open = serial.dtr();
+    if open && !unsafe { OPEN } {
+        print_str(PROMPT);
+    }
+    unsafe { OPEN = open };
+    if !open {
+        return;
+    }
+    cortex_m::interrupt::free(|_| unsafe {
+        while !OUTPUT.is_empty() {
+            let chunk = OUTPUT.peek();
+            let chunk = &chunk[..chunk.len().min(PACKET_SIZE as usize)];
+            match serial.write_packet(chunk) {
+                Ok(n) => {
+                    OUTPUT.consume(n);
+                    LAST_FULL = n == PACKET_SIZE as usize;
+                }
+                Err(_) => return,
+            }
+        }
+        if LAST_FULL && serial.write_packet(&[]).is_ok() {
+            LAST_FULL = false;
+        }
+    });
+}
+
+fn run(line: &str) {
+    let command = match command::parse(line) {
+        Ok(command) => command,
+        Err(CommandError::Unknown(name)) => {
+            print_fmt(format_args!("unknown command {}, try help\n", name));
+            return;
+        }
+        Err(CommandError::Usage(usage)) => {
+            print_fmt(format_args!("usage: {}\n", usage));
+            return;
+        }
+    };
+    match command {
+        Command::Nothing => {}
+        Command::Help => print_str(HELP),
+        Command::Ps => ps(),
+        Command::Heap => heap(),
+        Command::Log(Some(target)) => logger::set_target(target),
+        Command::Log(None) => {
+            let dropped = cortex_m::interrupt::free(|_| unsafe { OUTPUT.take_dropped() });
+            print_fmt(format_args!("log to {}, {} bytes dropped\n", logger::target().name(), dropped));
+        }
+        Command::Reboot => reboot::reboot(Request::None),
+        Command::Recover => reboot::reboot(Request::Recovery),
+    }
+}
+
+fn heap() {
+    let stats = KERNEL_HEAP.stats();
+    print_fmt(format_args!(
+        "{} of {} bytes used, peak {}
+{} free in {} blocks, largest {}, fragmentation {}%
+{} allocs, {} frees, {} failed
+",
+        stats.used, stats.total, stats.peak_used,
+        stats.free(), stats.free_blocks, stats.largest_free, stats.fragmentation(),
+        stats.allocations, stats.deallocations, stats.failures
+    ));
+}
+
+fn ps() {
+    let task_scheduler = match unsafe { TASK_SCHEDULER.as_ref() } {
+        Some(task_scheduler) => task_scheduler,
+        None => return,
+    };
+    print_str("pid ppid state\n");
+    for pcb in task_scheduler.pcbs.iter().filter(|pcb| pcb.is_some()) {
+        let pcb = pcb.steal();
+        let state = match pcb.state {
+            ProcessState::Initialize => "new",
+            ProcessState::Running => "running",
+            ProcessState::Ready => "ready",
+            ProcessState::Blocked => "blocked",
+            ProcessState::Terminated => "exited",
+        };
+        print_fmt(format_args!("{:>3} {:>4} {}\n", pcb.pid, pcb.ppid, state));
+    }
+}
@@ -0,0 +1,109 @@
+use chocos_abi::Errno;
+use chocos_keyboard::{Consumer, InputReport, KeyError, Keyboard, Mouse};
+
+use crate::{hprintln, suspend, usb_hid};
+
+// Keys held on the USB keyboard and the reports waiting for the host, see
+// the `chocos-keyboard` crate. Changed by system calls, emptied by the USB
+// interrupt. The mouse and the media keys share the HID interface.
+static mut KEYBOARD: Option<Keyboard> = None;
+static mut MOUSE: Option<Mouse> = None;
+static mut CONSUMER: Option<Consumer> = None;
+// processes that pressed keys or buttons, bit n for pid n
+static mut USERS: u32 = 0;
+
+pub fn init() {
+    unsafe {
+        KEYBOARD = Some(Keyboard::new());
+        MOUSE = Some(Mouse::new());
+        CONSUMER = Some(Consumer::new());
+    }
+}
+
+// Changes the keys on behalf of process `pid`
+pub fn update(pid: usize, f: impl FnOnce(&mut Keyboard) -> Result<(), KeyError>) -> Result<(), Errno> {
+    cortex_m::interrupt::free(|_| unsafe { USERS |= 1 << pid; });
+    change(f)
+}
+
+pub fn update_mouse(pid: usize, f: impl FnOnce(&mut Mouse) -> Result<(), KeyError>) -> Result<(), Errno> {
+    cortex_m::interrupt::free(|_| unsafe { USERS |= 1 << pid; });
+    apply(|| f(unsafe { MOUSE.as_mut().unwrap() }))
+}
+
+pub fn update_consumer(pid: usize, f: impl FnOnce(&mut Consumer) -> Result<(), KeyError>) -> Result<(), Errno> {
+    cortex_m::interrupt::free(|_| unsafe { USERS |= 1 << pid; });
+    apply(|| f(unsafe { CONSUMER.as_mut().unwrap() }))
+}
+
+// Lets go of the keys when a process that pressed some is gone, so no key
+// stays stuck on the host.
+pub fn process_exited(pid: usize) {
+    let was_user = cortex_m::interrupt::free(|_| unsafe {
+        let was_user = USERS & (1 << pid) != 0;
+        USERS &= !(1 << pid);
+        was_user
+    });
+    if !was_user {
+        return;
+    }
+    let released = change(|keyboard| keyboard.release_all())
+        .and(apply(|| unsafe { MOUSE.as_mut().unwrap() }.release_all()))
+        .and(apply(|| unsafe { CONSUMER.as_mut().unwrap() }.release()));
+    if released.is_err() {
+        let _ = hprintln!("[Keyboard] Could not release the keys of process {}", pid);
+    }
+}
+
+// Changes the keys for the kernel itself and has the USB interrupt send
+// the new reports
+pub fn change(f: impl FnOnce(&mut Keyboard) -> Result<(), KeyError>) -> Result<(), Errno> {
+    apply(|| f(unsafe { KEYBOARD.as_mut().unwrap() }))
+}
+
+// A report for a suspended host wakes it
+fn apply(f: impl FnOnce() -> Result<(), KeyError>) -> Result<(), Errno> {
+    let result = cortex_m::interrupt::free(|_| f());
+    if result.is_ok() {
+        suspend::activity();
+    }
+    usb_hid::kick();
+    result.map_err(|e| match e {
+        KeyError::Invalid => Errno::Inval,
+        KeyError::Full => Errno::Again,
+    })
+}
+
+// The report to send next, for the USB interrupt: keys first, then media
+// keys, then the mouse. `sent` takes it off its queue once the endpoint
+// has accepted it.
+pub fn next_report() -> Option<InputReport> {
+    unsafe {
+        let keyboard = KEYBOARD.as_mut()?.queue_mut().peek().copied().map(InputReport::Keyboard);
+        let consumer = || CONSUMER.as_mut()?.queue_mut().peek().copied().map(InputReport::Consumer);
+        let mouse = || MOUSE.as_mut()?.queue_mut().peek().copied().map(InputReport::Mouse);
+        keyboard.or_else(consumer).or_else(mouse)
+    }
+}
+
+pub fn sent(report: &InputReport) {
+    unsafe {
+        match report {
+            InputReport::Keyboard(_) => {
+                if let Some(keyboard) = KEYBOARD.as_mut() {
+                    keyboard.queue_mut().pop();
+                }
+            }
+            InputReport::Mouse(_) => {
+                if let Some(mouse) = MOUSE.as_mut() {
+                    mouse.queue_mut().pop();
+                }
+            }
+            InputReport::Consumer(_) => {
+                if let Some(consumer) = CONSUMER.as_mut() {
+                    consumer.queue_mut().pop();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use chocos_keyboard::hid::{self, request, Protocol};
+use usb_device::class_prelude::*;
+use usb_device::control::{Recipient, Request, RequestType};
+use usb_device::Result;
+
+// the high byte of wValue of SET_REPORT
+const REPORT_TYPE_OUTPUT: u8 = 2;
+
+// The HID interface of the keyboard, interface 0. A boot keyboard unlike
+// `usbd_hid::HIDClass`, so a BIOS finds it and can switch it to the boot
+// protocol; `keyboard` follows the protocol when it builds the reports.
+pub struct KeyboardClass<'a, B: UsbBus> {
+    iface: InterfaceNumber,
+    in_ep: EndpointIn<'a, B>,
+    out_ep: EndpointOut<'a, B>,
+    protocol: Protocol,
+    idle: u8,
+    // LEDs a SET_REPORT brought, for `take_leds`
+    leds: Option<u8>,
+}
+
+impl<'a, B: UsbBus> KeyboardClass<'a, B> {
+    pub fn new(alloc: &'a UsbBusAllocator<B>, poll_ms: u8) -> Self {
+        KeyboardClass {
+            iface: alloc.interface(),
+            in_ep: alloc.interrupt(64, poll_ms),
+            out_ep: alloc.interrupt(64, poll_ms),
+            protocol: Protocol::Report,
+            idle: 0,
+            leds: None,
+        }
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    pub fn push_input(&self, data: &[u8]) -> Result<usize> {
+        self.in_ep.write(data)
+    }
+
+    pub fn pull_output(&self, data: &mut [u8]) -> Result<usize> {
+        self.out_ep.read(data)
+    }
+
+    // Most hosts send the LEDs to the OUT endpoint, some with SET_REPORT
+    pub fn take_leds(&mut self) -> Option<u8> {
+        self.leds.take()
+    }
+
+    fn is_mine(&self, req: &Request) -> bool {
+        req.recipient == Recipient::Interface && req.index == u8::from(self.iface) as u16
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for KeyboardClass<'_, B> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+        writer.interface(self.iface, hid::CLASS, hid::SUBCLASS_BOOT, hid::PROTOCOL_KEYBOARD)?;
+        writer.write(hid::HID_DESCRIPTOR_TYPE, &hid::hid_descriptor())?;
+        writer.endpoint(&self.in_ep)?;
+        writer.endpoint(&self.out_ep)
+    }
+
+    // the spec has a device come back in the report protocol
+    fn reset(&mut self) {
+        self.protocol = Protocol::Report;
+        self.idle = 0;
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        if !self.is_mine(&req) || req.request_type != RequestType::Class {
+            return;
+        }
+        match req.request {
+            request::SET_PROTOCOL => match Protocol::from_value(req.value) {
+                Some(protocol) => {
+                    self.protocol = protocol;
+                    xfer.accept().ok();
+                }
+                None => {
+                    xfer.reject().ok();
+                }
+            },
+            // the reports go out when they change, whatever the rate
+            request::SET_IDLE => {
+                self.idle = (req.value >> 8) as u8;
+                xfer.accept().ok();
+            }
+            request::SET_REPORT if (req.value >> 8) as u8 == REPORT_TYPE_OUTPUT => {
+                match hid::led_report(self.protocol, xfer.data()) {
+                    Some(leds) => {
+                        self.leds = Some(leds);
+                        xfer.accept().ok();
+                    }
+                    None => {
+                        xfer.reject().ok();
+                    }
+                }
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        if !self.is_mine(&req) {
+            return;
+        }
+        match (req.request_type, req.request) {
+            (RequestType::Standard, Request::GET_DESCRIPTOR) => match (req.value >> 8) as u8 {
+                hid::REPORT_DESCRIPTOR_TYPE => {
+                    xfer.accept_with_static(hid::REPORT_DESCRIPTOR).ok();
+                }
+                hid::HID_DESCRIPTOR_TYPE => {
+                    let mut buf = [0u8; 9];
+                    buf[0] = 9;
+                    buf[1] = hid::HID_DESCRIPTOR_TYPE;
+                    buf[2..].copy_from_slice(&hid::hid_descriptor());
+                    xfer.accept_with(&buf).ok();
+                }
+                _ => {}
+            },
+            (RequestType::Class, request::GET_PROTOCOL) => {
+                xfer.accept_with(&[self.protocol as u8]).ok();
+            }
+            (RequestType::Class, request::GET_IDLE) => {
+                xfer.accept_with(&[self.idle]).ok();
+            }
+            (RequestType::Class, _) => {
+                xfer.reject().ok();
+            }
+            _ => {}
+        }
+    }
+}
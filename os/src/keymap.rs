@@ -0,0 +1,120 @@
+use alloc::vec::Vec;
+use chocos_abi::Errno;
+use chocos_keyboard::keycode::{BACKSPACE, ENTER, ESCAPE, LEFT_SHIFT, N0, N1, SPACE, TAB};
+use chocos_keyboard::keymap::{self, HEADER_SIZE, MAX_SIZE};
+use chocos_keyboard::{Action, Engine, KeyError, KeyEvent, Keyboard, Keymap, KeymapError};
+
+use crate::matrix::{COLS, ROWS};
+use crate::{hprintln, keyboard};
+
+// Flash page a keymap is kept in, see docs/memory_layout.md. Written by
+// `chocflash flash <file> --keymap`, read at boot.
+pub const KEYMAP_FLASH: u32 = 0x0807_0000;
+
+// What the keys type while the page holds no keymap
+const DEFAULT: [Action; ROWS * COLS] = [
+    Action::Key(N1 + 6), Action::Key(N1 + 7), Action::Key(N1 + 8), Action::Key(BACKSPACE),
+    Action::Key(N1 + 3), Action::Key(N1 + 4), Action::Key(N1 + 5), Action::Key(TAB),
+    Action::Key(N1), Action::Key(N1 + 1), Action::Key(N1 + 2), Action::Key(ENTER),
+    Action::Key(N0), Action::Key(SPACE), Action::Key(ESCAPE), Action::Key(LEFT_SHIFT),
+];
+static mut DEFAULT_KEYMAP: [u8; HEADER_SIZE + ROWS * COLS * 2] = [0; HEADER_SIZE + ROWS * COLS * 2];
+
+// The keymap the key matrix types with. It borrows from flash, from
+// DEFAULT_KEYMAP or from LOADED, which is only dropped after KEYMAP has
+// moved on to something else.
+static mut KEYMAP: Option<Keymap<'static>> = None;
+// a keymap an app loaded
+static mut LOADED: Option<Vec<u8>> = None;
+static mut ENGINE: Option<Engine> = None;
+
+pub fn init() {
+    unsafe {
+        let len = keymap::write(&mut DEFAULT_KEYMAP, ROWS, COLS, 0, &[&DEFAULT[..]], &[]).unwrap();
+        debug_assert_eq!(len, DEFAULT_KEYMAP.len());
+        ENGINE = Some(Engine::new());
+    }
+    restore();
+}
+
+// Goes back to the keymap in flash, or to the default one if there is
+// none there
+pub fn restore() {
+    let page = unsafe { core::slice::from_raw_parts(KEYMAP_FLASH as *const u8, MAX_SIZE) };
+    let stored = keymap::size(page).and_then(|len| Keymap::parse(&page[..len.min(MAX_SIZE)]));
+    let keymap = match stored {
+        Ok(keymap) => keymap,
+        Err(e) => {
+            // an erased page reads as 0xFF, no magic
+            if e != KeymapError::Magic {
+                let _ = hprintln!("[Keymap] Keymap at {:#x} is broken: {:?}", KEYMAP_FLASH, e);
+            }
+            Keymap::parse(unsafe { &DEFAULT_KEYMAP }).unwrap()
+        }
+    };
+    install(keymap, None);
+}
+
+// Types with a copy of the keymap `data` from now on
+pub fn load(data: &[u8]) -> Result<(), Errno> {
+    if data.len() > MAX_SIZE {
+        return Err(Errno::Inval);
+    }
+    let mut copy = Vec::new();
+    copy.try_reserve_exact(data.len()).map_err(|_| Errno::NoMem)?;
+    copy.extend_from_slice(data);
+    let keymap = Keymap::parse(&copy).map_err(|e| {
+        let _ = hprintln!("[Keymap] Rejecting keymap: {:?}", e);
+        Errno::Inval
+    })?;
+    // the bytes stay put on the heap when the Vec moves into LOADED
+    let keymap: Keymap<'static> = unsafe { core::mem::transmute(keymap) };
+    install(keymap, Some(copy));
+    Ok(())
+}
+
+fn install(keymap: Keymap<'static>, loaded: Option<Vec<u8>>) {
+    let old = cortex_m::interrupt::free(|_| unsafe {
+        KEYMAP = Some(keymap);
+        ENGINE.as_mut().unwrap().reset();
+        core::mem::replace(&mut LOADED, loaded)
+    });
+    drop(old);
+    let _ = hprintln!("[Keymap] {} layers, {}x{} keys", keymap.layers(), keymap.rows(), keymap.cols());
+    release_all();
+}
+
+// Forgets the keys and layers held, e.g. when the matrix goes to an app
+pub fn reset() {
+    cortex_m::interrupt::free(|_| unsafe { ENGINE.as_mut().unwrap().reset() });
+    release_all();
+}
+
+fn release_all() {
+    if keyboard::change(|keyboard| keyboard.release_all()).is_err() {
+        let _ = hprintln!("[Keymap] Could not release the keys");
+    }
+}
+
+// A key event of the matrix, from SysTick
+pub fn event(event: KeyEvent) {
+    if run(|engine, keymap, keyboard| engine.event(keymap, event, keyboard)).is_err() {
+        let _ = hprintln!("[Keymap] Keyboard queue full, dropped {:?}", event);
+    }
+}
+
+// From SysTick every `elapsed_ms`, for tap-hold keys
+pub fn tick(elapsed_ms: u32) {
+    let pending = unsafe { ENGINE.as_ref() }.map_or(false, |engine| engine.is_pending());
+    if pending && run(|engine, keymap, keyboard| engine.tick(keymap, elapsed_ms, keyboard)).is_err() {
+        let _ = hprintln!("[Keymap] Keyboard queue full, dropped a hold");
+    }
+}
+
+fn run(f: impl FnOnce(&mut Engine, &Keymap, &mut Keyboard) -> Result<(), KeyError>) -> Result<(), Errno> {
+    let (engine, keymap) = match unsafe { (ENGINE.as_mut(), KEYMAP.as_ref()) } {
+        (Some(engine), Some(keymap)) => (engine, keymap),
+        _ => return Ok(()),
+    };
+    keyboard::change(|keyboard| f(engine, keymap, keyboard))
+}
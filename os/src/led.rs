@@ -0,0 +1,82 @@
+use chocos_abi::Errno;
+use chocos_keyboard::led;
+use stm32f1xx_hal::gpio::{ErasedPin, Output, PushPull};
+
+use crate::subscribers::Subscribers;
+
+// The keyboard LEDs the host has lit, see `chocos_keyboard::led`. Set by
+// the USB interrupt, mirrored on the board's LEDs and read by the
+// processes that subscribed.
+static mut STATE: u8 = 0;
+static mut SUBSCRIBERS: Subscribers = Subscribers::new();
+static mut PINS: Option<[LedPin; PIN_COUNT]> = None;
+
+// The board's LEDs, set up in `main`; change the count and pins there to
+// fit the board.
+pub const PIN_COUNT: usize = 1;
+
+pub struct LedPin {
+    pub pin: ErasedPin<Output<PushPull>>,
+    // the bit of the LED report it shows
+    pub led: u8,
+    // lit when driven low, like the LED on PC13 of most boards
+    pub active_low: bool,
+}
+
+pub fn init(pins: [LedPin; PIN_COUNT]) {
+    unsafe { PINS = Some(pins) };
+    show(0);
+}
+
+// A new LED report from the host, from the USB interrupt
+pub fn set(state: u8) {
+    let changed = cortex_m::interrupt::free(|_| unsafe {
+        if state == STATE {
+            return false;
+        }
+        STATE = state;
+        SUBSCRIBERS.changed();
+        true
+    });
+    if changed {
+        show(state);
+    }
+}
+
+fn show(state: u8) {
+    let pins = match unsafe { PINS.as_mut() } {
+        Some(pins) => pins,
+        None => return,
+    };
+    for pin in pins.iter_mut() {
+        let lit = state & pin.led != 0;
+        if lit != pin.active_low {
+            pin.pin.set_high();
+        } else {
+            pin.pin.set_low();
+        }
+    }
+}
+
+// Process `pid` hears of every change from now on; its first read returns
+// at once.
+pub fn subscribe(pid: usize) {
+    cortex_m::interrupt::free(|_| unsafe { SUBSCRIBERS.subscribe(pid) });
+}
+
+pub fn unsubscribe(pid: usize) -> Result<(), Errno> {
+    cortex_m::interrupt::free(|_| unsafe { SUBSCRIBERS.unsubscribe(pid) })
+}
+
+pub fn process_exited(pid: usize) {
+    let _ = unsubscribe(pid);
+}
+
+// The LEDs. For a subscriber only once they changed since its last read,
+// Errno::Again until then.
+pub fn read(pid: usize) -> Result<u8, Errno> {
+    cortex_m::interrupt::free(|_| unsafe {
+        SUBSCRIBERS.read(pid)?;
+        Ok(STATE & led::LEDS)
+    })
+}
@@ -0,0 +1,54 @@
+use chocos_abi::{memory::USER_FLASH, AppHeader, Errno, ABI_VERSION};
+use chocos_isp::{image::Policy, sign::{check_signature, TRAILER_SIZE}};
+use crate::hprintln;
+
+// PUBLIC_KEY, from build.rs
+include!(concat!(env!("OUT_DIR"), "/public_key.rs"));
+
+// Flash region holding user programs
+pub const USER_FLASH_START: u32 = USER_FLASH.start;
+pub const USER_FLASH_END: u32 = USER_FLASH.end;
+
+// An app image that passed the checks
+pub struct App {
+    // where to start it
+    pub entry: u32,
+    // chocos_abi::CAP_* from its header
+    pub caps: u32,
+}
+
+// Check the header of the app image at `image`.
+pub unsafe fn load(image: u32) -> Result<App, Errno> {
+    if image % 4 != 0 || !(USER_FLASH_START..USER_FLASH_END).contains(&image) {
+        let _ = hprintln!("[Loader] {:#x} is not in the user program region", image);
+        return Err(Errno::Fault);
+    }
+
+    let header = core::ptr::read_volatile(image as *const AppHeader);
+    let entry = header.check().map_err(|errno| {
+        let _ = hprintln!("[Loader] Rejecting image at {:#x}: magic {:#x}, ABI version {} (kernel has {})",
+            image, header.magic, header.abi_version, ABI_VERSION);
+        errno
+    })?;
+
+    if !(USER_FLASH_START..USER_FLASH_END).contains(&entry) {
+        let _ = hprintln!("[Loader] Entry point {:#x} of image {:#x} is out of range", entry, image);
+        return Err(Errno::NoExec);
+    }
+
+    if header.end <= image || header.end > USER_FLASH_END {
+        let _ = hprintln!("[Loader] End {:#x} of image {:#x} is out of range", header.end, image);
+        return Err(Errno::NoExec);
+    }
+    let signed = core::slice::from_raw_parts(image as *const u8, (header.end - image) as usize);
+    let trailer = (header.end as usize + TRAILER_SIZE <= USER_FLASH_END as usize)
+        .then(|| core::slice::from_raw_parts(header.end as *const u8, TRAILER_SIZE));
+    let policy = Policy { key: PUBLIC_KEY.as_ref(), allow_unsealed: false, allow_unsigned: cfg!(feature = "dev-mode") };
+    check_signature(signed, trailer, &policy).map_err(|e| {
+        let _ = hprintln!("[Loader] Rejecting image at {:#x}: {}", image, e);
+        Errno::Perm
+    })?;
+
+    // the stacked PC has to be even, Thumb state comes from xPSR
+    Ok(App { entry: entry & !1, caps: header.caps })
+}
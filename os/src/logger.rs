@@ -3,9 +3,14 @@ use core::fmt::Write;
 use stm32f1::stm32f103::USART1;
 use stm32f1xx_hal::{gpio::{Input, Floating, gpioa, Alternate, PushPull}, serial::{Config, Tx, Rx}};
 use stm32f1xx_hal::time::U32Ext;
+use chocos_console::LogTarget;
+
+use crate::console;
 
 static mut SERIAL_TX: Option<Tx<USART1>> = None;
 static mut SERIAL_RX: Option<Rx<USART1>> = None;
+// where hprint! goes, see docs/os/console.md
+static mut TARGET: LogTarget = LogTarget::Both;
 
 pub fn init(
     usart1: USART1,
@@ -32,14 +37,34 @@ pub fn init(
 
 }
 
+pub fn set_target(target: LogTarget) {
+    unsafe { TARGET = target };
+}
+
+pub fn target() -> LogTarget {
+    unsafe { TARGET }
+}
+
 pub fn hstdout_str(s: &str) {
-    let mut tx = unsafe { SERIAL_TX.as_mut().unwrap() };
-    let _ = tx.write_str(s);
+    if target().usart() {
+        if let Some(tx) = unsafe { SERIAL_TX.as_mut() } {
+            let _ = tx.write_str(s);
+        }
+    }
+    if target().usb() {
+        console::print_str(s);
+    }
 }
 
 pub fn hstdout_fmt(args: core::fmt::Arguments) {
-    let mut tx = unsafe { SERIAL_TX.as_mut().unwrap() };
-    let _ = tx.write_fmt(args).ok();
+    if target().usart() {
+        if let Some(tx) = unsafe { SERIAL_TX.as_mut() } {
+            let _ = tx.write_fmt(args).ok();
+        }
+    }
+    if target().usb() {
+        console::print_fmt(args);
+    }
 }
 
 #[macro_export]
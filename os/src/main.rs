@@ -4,14 +4,30 @@
 #![feature(asm_const)]
 #![feature(asm_sym)]
 #![feature(naked_functions)]
+#![feature(alloc_error_handler)]
 
+extern crate alloc;
+
+mod allocator;
 mod structs;
 
 mod task_scheduler;
 
 #[macro_use]
 mod syscall_provider;
+mod loader;
 mod usb_hid;
+mod keyboard;
+mod keyboard_class;
+mod led;
+mod subscribers;
+mod suspend;
+mod matrix;
+mod keymap;
+mod channel;
+mod console;
+mod boot_slot;
+mod reboot;
 
 #[macro_use]
 mod logger;
@@ -19,7 +35,7 @@ mod logger;
 mod utils;
 
 // #[cfg(not(debug_assertions))]
-use panic_halt as _;
+use core::panic::PanicInfo;
 
 // #[cfg(debug_assertions)]
 // use cortex_m_semihosting::hprintln;
@@ -31,10 +47,22 @@ use cortex_m_rt::{entry, exception};
 use stm32f1::stm32f103::Interrupt;
 use stm32f1xx_hal::{device, gpio::GpioExt, rcc::RccExt, prelude::*};
 use task_scheduler::TaskScheduler;
+use chocos_handoff::{Handoff, PanicLocation};
 
 static mut MPU: Option<cortex_m::peripheral::MPU> = None;
 static mut TASK_SCHEDULER: Option<TaskScheduler> = None;
 
+// SysTick fires every TICK_MS, every SUSPEND_TICK_MS while the bus is
+// suspended (see `suspend`). UPTIME_MS counts the ms since boot either way
+// and wraps after 49 days.
+const TICK_MS: u32 = 1;
+static mut UPTIME_MS: u32 = 0;
+// with the `time-slicing` feature a process is switched out after this
+#[cfg(feature = "time-slicing")]
+const TIME_SLICE_MS: u32 = 20;
+#[cfg(feature = "time-slicing")]
+static mut SLICE_MS: u32 = 0;
+
 #[entry]
 fn main() -> ! {
 
@@ -76,13 +104,14 @@ fn main() -> ! {
     // setup timer
     let syst = &mut cp.SYST;
     syst.set_clock_source(SystClkSource::Core);
-    syst.set_reload(0x00efffff); // ?
+    syst.set_reload(main_freq / 1000 * TICK_MS - 1);
     syst.clear_current();
     syst.enable_counter();
-    // syst.enable_interrupt();
+    syst.enable_interrupt();
 
     // setup usb
     let mut gpioa = p.GPIOA.split();
+    let mut gpiob = p.GPIOB.split();
     let mut gpiod = p.GPIOD.split();
     let mut afio = p.AFIO.constrain();
 
@@ -98,15 +127,51 @@ fn main() -> ! {
     // #[cfg(debug_assertions)]
     let _ = hprintln!("[ChocOS] Init: OS init");
 
-    usb_hid::init(main_freq, p.USB, gpiod.pd6, &mut gpiod.crl, gpioa.pa11, gpioa.pa12, &mut gpioa.crh);
+    // what the bootloader left for us
+    if let Some(handoff) = unsafe { Handoff::load() } {
+        let (major, minor) = handoff.bootloader_version;
+        let _ = hprintln!("[ChocOS] Init: Boot {} after {:?} reset, bootloader {}.{}", handoff.boot_count, handoff.reset_reason, major, minor);
+        if let Some(panic) = handoff.panic {
+            let _ = hprintln!("[ChocOS] Init: Last panic at {}:{}:{}", panic.file(), panic.line, panic.column);
+        }
+    }
 
-    // let _ = usb_hid::send_msg(0);
+    allocator::init();
+
+    boot_slot::init(flash);
+
+    suspend::init(main_freq);
+    keyboard::init();
+    keymap::init();
+    channel::init();
+    // key matrix: rows PB12 - PB15, columns PB8 - PB11
+    matrix::init(
+        [
+            gpiob.pb12.into_open_drain_output(&mut gpiob.crh).erase(),
+            gpiob.pb13.into_open_drain_output(&mut gpiob.crh).erase(),
+            gpiob.pb14.into_open_drain_output(&mut gpiob.crh).erase(),
+            gpiob.pb15.into_open_drain_output(&mut gpiob.crh).erase(),
+        ],
+        [
+            gpiob.pb8.into_pull_up_input(&mut gpiob.crh).erase(),
+            gpiob.pb9.into_pull_up_input(&mut gpiob.crh).erase(),
+            gpiob.pb10.into_pull_up_input(&mut gpiob.crh).erase(),
+            gpiob.pb11.into_pull_up_input(&mut gpiob.crh).erase(),
+        ],
+    );
+    // Caps Lock on PC13
+    let mut gpioc = p.GPIOC.split();
+    led::init([led::LedPin {
+        pin: gpioc.pc13.into_push_pull_output(&mut gpioc.crh).erase(),
+        led: chocos_keyboard::led::CAPS_LOCK,
+        active_low: true,
+    }]);
+    usb_hid::init(main_freq, p.USB, gpiod.pd6, &mut gpiod.crl, gpioa.pa11, gpioa.pa12, &mut gpioa.crh);
 
     let _ = hprintln!("[ChocOS] Init: Waiting for USB to ready");
 
     cortex_m::asm::delay(main_freq);
 
-    // let _ = usb_hid::send_msg(1);
     let _ = hprintln!("[ChocOS] Init: Creating Task Scheduler instance");
     
     let task_scheduler = TaskScheduler::new();
@@ -129,13 +194,13 @@ fn sub_main() -> ! {
 
     // task_scheduler.create(0, 0x080200E0);
 
-    syscall!(6, 0x080300C2, 0, 0);
-
-    // let _ = usb_hid::send_msg(2);
+    let created = chocos_abi::decode_result(syscall!(chocos_abi::nr::CREATE, 0x08030000, 0, 0));
+    // without its app this image has not shown it works, it stays on trial
+    if let Err(errno) = created {
+        let _ = hprintln!("[ChocOS] Init: Loading demoapp failed: {:?}", errno);
+    }
 
     loop {
-        // let _ = usb_hid::send_msg(3);
-
         cortex_m::asm::wfi(); // wait for interrupt
     }
 }
@@ -144,12 +209,30 @@ fn sub_main() -> ! {
 // not needed if not boot by SRAM
 unsafe fn reset_vtor(scb: &mut cortex_m::peripheral::SCB) {
     // scb.vtor.write(0x2000_0000);
-    scb.vtor.write(0x0801_0000);
+    scb.vtor.write(boot_slot::base());
 }
 
 #[exception]
 unsafe fn SysTick() {
-    // let _ = usb_hid::send_msg(4);
+    // slower while the bus is suspended
+    let ms = suspend::tick_ms();
+    UPTIME_MS = UPTIME_MS.wrapping_add(ms);
+    matrix::tick(ms);
+    // keeps the image once it is proven, see boot_slot
+    boot_slot::tick();
+
+    #[cfg(feature = "time-slicing")]
+    {
+        SLICE_MS += ms;
+        if SLICE_MS >= TIME_SLICE_MS {
+            SLICE_MS = 0;
+            time_slice();
+        }
+    }
+}
+
+#[cfg(feature = "time-slicing")]
+unsafe fn time_slice() {
     let task_scheduler_opt = TASK_SCHEDULER.as_ref();
     if task_scheduler_opt.is_some() {
         let task_scheduler = task_scheduler_opt.unwrap();
@@ -162,3 +245,20 @@ unsafe fn SysTick() {
     }
 
 }
+
+pub fn uptime_ms() -> u32 {
+    unsafe { UPTIME_MS }
+}
+
+#[inline(never)]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    // leave the location for the next boot to report and start over. An
+    // image on trial that keeps panicking is rolled back by the bootloader
+    unsafe {
+        chocos_handoff::update(|handoff| {
+            handoff.panic = info.location().map(|l| PanicLocation::new(l.file(), l.line(), l.column()));
+        });
+    }
+    cortex_m::peripheral::SCB::sys_reset();
+}
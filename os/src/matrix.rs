@@ -0,0 +1,126 @@
+use chocos_abi::Errno;
+use chocos_keyboard::{KeyEvent, Matrix, MatrixPins, Queue};
+use cortex_m::asm;
+use stm32f1xx_hal::gpio::{ErasedPin, Input, OpenDrain, Output, PullUp};
+
+use crate::{hprintln, keymap, TASK_SCHEDULER};
+
+// The key matrix of the board: rows driven low one at a time (open drain),
+// columns pulled up, so a pressed key pulls its column low. The pins are
+// set up in `main`; change them and the size here to fit the board.
+pub const ROWS: usize = 4;
+pub const COLS: usize = 4;
+const DEBOUNCE_MS: u8 = 5;
+// for the columns to follow the selected row, about 1 us
+const SETTLE_CYCLES: u32 = 72;
+// events the subscribed process hasn't read yet
+const EVENT_QUEUE_LEN: usize = 32;
+
+pub struct GpioMatrix {
+    rows: [ErasedPin<Output<OpenDrain>>; ROWS],
+    cols: [ErasedPin<Input<PullUp>>; COLS],
+}
+
+impl MatrixPins for GpioMatrix {
+    fn select_row(&mut self, row: usize) {
+        self.rows[row].set_low();
+    }
+
+    fn unselect_row(&mut self, row: usize) {
+        self.rows[row].set_high();
+    }
+
+    fn read_cols(&mut self) -> u32 {
+        asm::delay(SETTLE_CYCLES);
+        self.cols.iter().enumerate().fold(0, |bits, (i, col)| if col.is_low() { bits | 1 << i } else { bits })
+    }
+}
+
+static mut PINS: Option<GpioMatrix> = None;
+static mut MATRIX: Option<Matrix> = None;
+static mut EVENTS: Option<Queue<KeyEvent, EVENT_QUEUE_LEN>> = None;
+// the process the key events go to instead of the USB keyboard
+static mut SUBSCRIBER: Option<usize> = None;
+
+pub fn init(
+    mut rows: [ErasedPin<Output<OpenDrain>>; ROWS],
+    cols: [ErasedPin<Input<PullUp>>; COLS],
+) {
+    for row in rows.iter_mut() {
+        row.set_high();
+    }
+    unsafe {
+        PINS = Some(GpioMatrix { rows, cols });
+        MATRIX = Some(Matrix::new(ROWS, COLS, DEBOUNCE_MS));
+        EVENTS = Some(Queue::new());
+    }
+}
+
+// Scans the matrix, from SysTick every `elapsed_ms`
+pub fn tick(elapsed_ms: u32) {
+    let (pins, matrix) = match unsafe { (PINS.as_mut(), MATRIX.as_mut()) } {
+        (Some(pins), Some(matrix)) => (pins, matrix),
+        _ => return,
+    };
+    matrix.scan(pins, elapsed_ms, deliver);
+    keymap::tick(elapsed_ms);
+}
+
+fn deliver(event: KeyEvent) {
+    if let Some(pid) = unsafe { SUBSCRIBER } {
+        let events = unsafe { EVENTS.as_mut().unwrap() };
+        if !events.push(event) {
+            let _ = hprintln!("[Matrix] Event queue full, dropped {:?}", event);
+        }
+        // it may be blocked in matrix_read
+        if let Some(task_scheduler) = unsafe { TASK_SCHEDULER.as_mut() } {
+            task_scheduler.wake(pid);
+        }
+        return;
+    }
+    keymap::event(event);
+}
+
+// The key events go to process `pid` from now on, until it unsubscribes or
+// exits. Keys and layers the keymap holds are let go.
+pub fn subscribe(pid: usize) -> Result<(), Errno> {
+    cortex_m::interrupt::free(|_| unsafe {
+        match SUBSCRIBER {
+            Some(other) if other != pid => Err(Errno::Busy),
+            _ => {
+                SUBSCRIBER = Some(pid);
+                if let Some(events) = EVENTS.as_mut() {
+                    events.clear();
+                }
+                Ok(())
+            }
+        }
+    })?;
+    keymap::reset();
+    Ok(())
+}
+
+pub fn unsubscribe(pid: usize) -> Result<(), Errno> {
+    cortex_m::interrupt::free(|_| unsafe {
+        if SUBSCRIBER != Some(pid) {
+            return Err(Errno::Perm);
+        }
+        SUBSCRIBER = None;
+        Ok(())
+    })
+}
+
+pub fn process_exited(pid: usize) {
+    let _ = unsubscribe(pid);
+}
+
+// The next key event for the subscribed process `pid`, Errno::Again if
+// there is none yet
+pub fn read(pid: usize) -> Result<KeyEvent, Errno> {
+    cortex_m::interrupt::free(|_| unsafe {
+        if SUBSCRIBER != Some(pid) {
+            return Err(Errno::Perm);
+        }
+        EVENTS.as_mut().unwrap().pop().ok_or(Errno::Again)
+    })
+}
@@ -0,0 +1,14 @@
+use chocos_handoff::Request;
+
+use crate::hprintln;
+
+// Restarts the board through the bootloader, which takes `request` from
+// the handoff block on its way up. The reset goes through SCB AIRCR, like
+// the bootloader's panic handler.
+pub fn reboot(request: Request) -> ! {
+    let _ = hprintln!("[ChocOS] Rebooting, request {:?}", request);
+    unsafe {
+        chocos_handoff::update(|handoff| handoff.request = request);
+    }
+    cortex_m::peripheral::SCB::sys_reset();
+}
@@ -0,0 +1,54 @@
+use chocos_abi::Errno;
+
+use crate::TASK_SCHEDULER;
+
+// Processes that want to hear when some state changes, bit n for pid n.
+// Each subscriber reads the new state once, blocking until it changes.
+pub struct Subscribers {
+    all: u32,
+    // subscribers that haven't read the latest state yet
+    unseen: u32,
+}
+
+impl Subscribers {
+    pub const fn new() -> Self {
+        Subscribers { all: 0, unseen: 0 }
+    }
+
+    // The first read of a new subscriber returns at once
+    pub fn subscribe(&mut self, pid: usize) {
+        self.all |= 1 << pid;
+        self.unseen |= 1 << pid;
+    }
+
+    pub fn unsubscribe(&mut self, pid: usize) -> Result<(), Errno> {
+        if self.all & (1 << pid) == 0 {
+            return Err(Errno::Perm);
+        }
+        self.all &= !(1 << pid);
+        self.unseen &= !(1 << pid);
+        Ok(())
+    }
+
+    // Everyone has the new state to read, the blocked readers are woken
+    pub fn changed(&mut self) {
+        self.unseen = self.all;
+        if let Some(task_scheduler) = unsafe { TASK_SCHEDULER.as_mut() } {
+            for pid in (0..32).filter(|pid| self.all & (1 << pid) != 0) {
+                task_scheduler.wake(pid);
+            }
+        }
+    }
+
+    // Whether `pid` may read now: anyone who didn't subscribe may, a
+    // subscriber only once per change, Errno::Again until then
+    pub fn read(&mut self, pid: usize) -> Result<(), Errno> {
+        if self.all & (1 << pid) != 0 {
+            if self.unseen & (1 << pid) == 0 {
+                return Err(Errno::Again);
+            }
+            self.unseen &= !(1 << pid);
+        }
+        Ok(())
+    }
+}
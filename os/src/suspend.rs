@@ -0,0 +1,89 @@
+use chocos_abi::Errno;
+use usb_device::device::UsbDeviceState;
+
+use crate::subscribers::Subscribers;
+use crate::{hprintln, usb_hid, TASK_SCHEDULER, TICK_MS};
+
+// The host suspends the bus when it sleeps and the board has to get by on
+// little power until it resumes. The apps are parked, so only the init
+// process runs and it sleeps in `wfi`; SysTick slows down to
+// SUSPEND_TICK_MS to wake the core less often for the scans of the
+// matrix. A key pressed then wakes the host if it allowed that. Processes
+// that subscribed hear of both once they run again.
+const SUSPEND_TICK_MS: u32 = 10;
+
+static mut SUSPENDED: bool = false;
+static mut SUBSCRIBERS: Subscribers = Subscribers::new();
+// SysTick reload for one ms
+static mut CYCLES_PER_MS: u32 = 0;
+
+pub fn init(sysclk: u32) {
+    unsafe { CYCLES_PER_MS = sysclk / 1000 };
+}
+
+// The state after a poll of the USB device, from the USB interrupt.
+// usb-device leaves suspend for the Default state, though the host has
+// kept the configuration, so anything but Suspend is awake.
+pub fn update(state: UsbDeviceState) {
+    let suspended = state == UsbDeviceState::Suspend;
+    let changed = cortex_m::interrupt::free(|_| unsafe {
+        if suspended == SUSPENDED {
+            return false;
+        }
+        SUSPENDED = suspended;
+        SUBSCRIBERS.changed();
+        true
+    });
+    if !changed {
+        return;
+    }
+    set_tick(tick_ms());
+    if let Some(task_scheduler) = unsafe { TASK_SCHEDULER.as_mut() } {
+        task_scheduler.park_apps(suspended);
+    }
+    let _ = hprintln!("[USB] {}", if suspended { "Suspended" } else { "Resumed" });
+}
+
+pub fn is_suspended() -> bool {
+    unsafe { SUSPENDED }
+}
+
+// How long SysTick takes now
+pub fn tick_ms() -> u32 {
+    if is_suspended() { SUSPEND_TICK_MS } else { TICK_MS }
+}
+
+fn set_tick(ms: u32) {
+    let mut syst = unsafe { cortex_m::Peripherals::steal() }.SYST;
+    syst.set_reload(unsafe { CYCLES_PER_MS } * ms - 1);
+    syst.clear_current();
+}
+
+// Something for the host came up, e.g. a key was pressed. Wakes it if the
+// bus is suspended and the host allows it.
+pub fn activity() {
+    if is_suspended() {
+        usb_hid::remote_wakeup();
+    }
+}
+
+pub fn subscribe(pid: usize) {
+    cortex_m::interrupt::free(|_| unsafe { SUBSCRIBERS.subscribe(pid) });
+}
+
+pub fn unsubscribe(pid: usize) -> Result<(), Errno> {
+    cortex_m::interrupt::free(|_| unsafe { SUBSCRIBERS.unsubscribe(pid) })
+}
+
+pub fn process_exited(pid: usize) {
+    let _ = unsubscribe(pid);
+}
+
+// Whether the bus is suspended. For a subscriber only once that changed
+// since its last read, Errno::Again until then.
+pub fn read(pid: usize) -> Result<bool, Errno> {
+    cortex_m::interrupt::free(|_| unsafe {
+        SUBSCRIBERS.read(pid)?;
+        Ok(SUSPENDED)
+    })
+}
@@ -6,7 +6,11 @@ use cortex_m_rt::{exception};
 // use cortex_m_semihosting::{hprintln, hprint};
 use crate::{hprintln, hprint};
 
-use crate::{task_scheduler::{SavedState, self, ProcessState}, TASK_SCHEDULER, usb_hid};
+use chocos_abi::{dispatch, encode_result, memory::UserMemory, require_cap, Errno, SyscallHandler, CAP_REBOOT};
+
+use crate::{task_scheduler::{SavedState, self, ProcessState}, TASK_SCHEDULER, channel, keyboard, keymap, led, matrix, loader, boot_slot, reboot, suspend};
+use chocos_handoff::Request;
+use chocos_keyboard::Mode;
 
 #[allow(unused_macros)]
 
@@ -34,9 +38,9 @@ macro_rules! syscall {
 }
 
 
-// ARM EABI Syscall ABI: 
-//     r7: syscall #
-//     r0 - r6: arguments
+// ChocOS Syscall ABI (see abi/syscalls.toml):
+//     r0: syscall #
+//     r1 - r3: arguments
 //     r0: return value
 #[naked]
 #[no_mangle]
@@ -69,58 +73,315 @@ pub unsafe extern "C" fn svc_handler(caller_stack_addr: * const u32, exc_stack_a
     let pc = *caller_stack_addr.offset(6);
 
     let _ = hprintln!("[Exception] SVCall: System Call {} ({:#x}, {:#x}, {:#x})", syscall_id, arg1, arg2, arg3);
-    // let _ = usb_hid::send_msg(5);
-
-    match syscall_id {
-        0 => {
-            // Reserved
-            
-        },
-        1 => {
-            // Yield
-            SCB::set_pendsv();
-            dsb();
-        },
-        3 => {
-            // print
-            let text = *(arg1 as * const &str) as &str;
-            let _ = hprint!("{}", text);
-        },
-        4 => {
-            // C compatible print
-            let text = cstr_core::CStr::from_ptr(arg1 as * const u8);
-            let _ = hprint!("{}", text.to_str().unwrap());
-        },
-        5 => {
-            // _exit
-            let current_pid = TASK_SCHEDULER.as_ref().unwrap().current_process;
-            let return_code = arg1;
-            TASK_SCHEDULER.as_mut().unwrap().exit(current_pid as u16);
-            let _ = hprintln!("process {} exited, return code {}");
-            SCB::set_pendsv();
-            dsb();
-        }, 6 => {
-            // create
-            let address = arg1;
-            let task_scheduler = TASK_SCHEDULER.as_mut().unwrap();
-            let current_pid = task_scheduler.current_process;
-            let pid = task_scheduler.create(current_pid, address).unwrap().pid;
-            task_scheduler.set_pending_process(pid);
-            // jump to 
-            SCB::set_pendsv();
-            dsb();
-        }
-        7 => {
-            // print an integer
-            let num = arg1 as u32;
-            let _ = hprint!("{}", num);
-        },
-        _ => {
-            panic!("unknown syscall: {}", syscall_id);
+
+    let mut context = SvcContext { caller_stack_addr, restart: false };
+    let result = dispatch(&mut context, syscall_id, [arg1, arg2, arg3]);
+
+    if result == Err(Errno::NoSys) {
+        let _ = hprintln!("[Exception] SVCall: Unknown system call {} at {:#x}", syscall_id, pc);
+    }
+
+    // back to the SVC instruction with the arguments untouched, it runs
+    // again once the caller is woken
+    if context.restart {
+        core::ptr::write_volatile(caller_stack_addr.offset(6) as *mut u32, pc - 2);
+        return 0;
+    }
+
+    // hand the result back through the stacked R0 of the caller
+    let return_value = encode_result(result);
+    core::ptr::write_volatile(caller_stack_addr as *mut u32, return_value);
+
+    return_value
+}
+
+// State of the system call being served
+struct SvcContext {
+    caller_stack_addr: * const u32,
+    // block the caller and serve the call again on wake
+    restart: bool,
+}
+
+impl SvcContext {
+    // Blocks the caller while `f` would (Errno::Again). The init process
+    // can't block, it gets the error.
+    fn wait<T>(&mut self, f: impl FnOnce() -> Result<T, Errno>) -> Result<T, Errno> {
+        cortex_m::interrupt::free(|_| {
+            let result = f();
+            let pid = current_pid();
+            if matches!(result, Err(Errno::Again)) && pid != 0 {
+                unsafe { TASK_SCHEDULER.as_mut().unwrap() }.block(pid);
+                self.restart = true;
+            }
+            result
+        })
+    }
+}
+
+impl SyscallHandler for SvcContext {
+    fn sys_yield(&mut self) -> Result<u32, Errno> {
+        SCB::set_pendsv();
+        dsb();
+        Ok(0)
+    }
+
+    fn sys_print(&mut self, text: u32) -> Result<u32, Errno> {
+        // a &str: pointer and length, then the bytes they point to
+        let raw = user_bytes(text, 8)?;
+        let ptr = u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]);
+        let len = u32::from_le_bytes([raw[4], raw[5], raw[6], raw[7]]);
+        let text = core::str::from_utf8(user_bytes(ptr, len)?).map_err(|_| Errno::Inval)?;
+        let _ = hprint!("{}", text);
+        Ok(0)
+    }
+
+    fn sys_print_cstr(&mut self, text: u32) -> Result<u32, Errno> {
+        // C compatible print, the NUL has to come before the end of the
+        // memory the string starts in
+        let end = caller_memory().readable_end(text).ok_or_else(|| fault(text, 1))?;
+        let bytes = user_bytes(text, end - text)?;
+        let len = bytes.iter().position(|&b| b == 0).ok_or_else(|| fault(text, end - text))?;
+        let text = core::str::from_utf8(&bytes[..len]).map_err(|_| Errno::Inval)?;
+        let _ = hprint!("{}", text);
+        Ok(0)
+    }
+
+    fn sys_exit(&mut self, code: i32) -> Result<u32, Errno> {
+        let task_scheduler = unsafe { TASK_SCHEDULER.as_mut().unwrap() };
+        let current_pid = task_scheduler.current_process;
+        task_scheduler.exit(current_pid as u16);
+        keyboard::process_exited(current_pid);
+        matrix::process_exited(current_pid);
+        channel::process_exited(current_pid);
+        led::process_exited(current_pid);
+        suspend::process_exited(current_pid);
+        let _ = hprintln!("process {} exited, return code {}", current_pid, code);
+        SCB::set_pendsv();
+        dsb();
+        Ok(0)
+    }
+
+    fn sys_create(&mut self, image: u32) -> Result<u32, Errno> {
+        let app = unsafe { loader::load(image)? };
+        let task_scheduler = unsafe { TASK_SCHEDULER.as_mut().unwrap() };
+        let current_pid = task_scheduler.current_process;
+        // an app can't hand out more than it has itself
+        let caps = app.caps & task_scheduler.caps(current_pid);
+        let pid = task_scheduler.create(current_pid, app.entry, caps).ok_or(Errno::Again)?.pid;
+        if current_pid == 0 {
+            boot_slot::app_started();
         }
+        task_scheduler.set_pending_process(pid);
+        // jump to
+        SCB::set_pendsv();
+        dsb();
+        Ok(pid as u32)
     }
 
-    0
+    fn sys_print_u32(&mut self, num: u32) -> Result<u32, Errno> {
+        let _ = hprint!("{}", num);
+        Ok(0)
+    }
+
+    fn sys_sbrk(&mut self, increment: i32) -> Result<u32, Errno> {
+        let task_scheduler = unsafe { TASK_SCHEDULER.as_mut().unwrap() };
+        let current_pid = task_scheduler.current_process;
+        // the exception frame sits on top of the caller's stack
+        let sp = self.caller_stack_addr as u32;
+        task_scheduler.sbrk(current_pid, increment, sp).ok_or(Errno::NoMem)
+    }
+
+    fn sys_confirm_boot(&mut self) -> Result<u32, Errno> {
+        boot_slot::confirm()?;
+        Ok(0)
+    }
+
+    fn sys_reboot(&mut self) -> Result<u32, Errno> {
+        may_reboot()?;
+        reboot::reboot(Request::None)
+    }
+
+    fn sys_reboot_recovery(&mut self) -> Result<u32, Errno> {
+        may_reboot()?;
+        reboot::reboot(Request::Recovery)
+    }
+
+    fn sys_key_press(&mut self, code: u32) -> Result<u32, Errno> {
+        let code = key_code(code)?;
+        keyboard::update(current_pid(), |keyboard| keyboard.press(code))?;
+        Ok(0)
+    }
+
+    fn sys_key_release(&mut self, code: u32) -> Result<u32, Errno> {
+        let code = key_code(code)?;
+        keyboard::update(current_pid(), |keyboard| keyboard.release(code))?;
+        Ok(0)
+    }
+
+    fn sys_key_tap(&mut self, code: u32) -> Result<u32, Errno> {
+        let code = key_code(code)?;
+        keyboard::update(current_pid(), |keyboard| keyboard.tap(code))?;
+        Ok(0)
+    }
+
+    fn sys_matrix_subscribe(&mut self) -> Result<u32, Errno> {
+        matrix::subscribe(current_pid())?;
+        Ok(0)
+    }
+
+    fn sys_matrix_unsubscribe(&mut self) -> Result<u32, Errno> {
+        matrix::unsubscribe(current_pid())?;
+        Ok(0)
+    }
+
+    fn sys_matrix_read(&mut self) -> Result<u32, Errno> {
+        let pid = current_pid();
+        self.wait(|| matrix::read(pid)).map(|event| event.to_u32())
+    }
+
+    fn sys_keymap_load(&mut self, data: u32, len: u32) -> Result<u32, Errno> {
+        keymap::load(user_bytes(data, len)?)?;
+        Ok(0)
+    }
+
+    fn sys_keymap_restore(&mut self) -> Result<u32, Errno> {
+        keymap::restore();
+        Ok(0)
+    }
+
+    fn sys_channel_open(&mut self) -> Result<u32, Errno> {
+        channel::open(current_pid())?;
+        Ok(0)
+    }
+
+    fn sys_channel_close(&mut self) -> Result<u32, Errno> {
+        channel::close(current_pid())?;
+        Ok(0)
+    }
+
+    fn sys_channel_recv(&mut self, buf: u32, len: u32) -> Result<u32, Errno> {
+        let buf = user_bytes_mut(buf, len)?;
+        let pid = current_pid();
+        self.wait(|| channel::recv(pid, buf)).map(|len| len as u32)
+    }
+
+    fn sys_channel_send(&mut self, data: u32, len: u32) -> Result<u32, Errno> {
+        let data = user_bytes(data, len)?;
+        let pid = current_pid();
+        self.wait(|| channel::send(pid, data))?;
+        Ok(0)
+    }
+
+    fn sys_mouse_press(&mut self, buttons: u32) -> Result<u32, Errno> {
+        let buttons = u8::try_from(buttons).map_err(|_| Errno::Inval)?;
+        keyboard::update_mouse(current_pid(), |mouse| mouse.press(buttons))?;
+        Ok(0)
+    }
+
+    fn sys_mouse_release(&mut self, buttons: u32) -> Result<u32, Errno> {
+        let buttons = u8::try_from(buttons).map_err(|_| Errno::Inval)?;
+        keyboard::update_mouse(current_pid(), |mouse| mouse.release(buttons))?;
+        Ok(0)
+    }
+
+    fn sys_mouse_move(&mut self, x: i32, y: i32, wheel: i32) -> Result<u32, Errno> {
+        keyboard::update_mouse(current_pid(), |mouse| mouse.move_by(x, y, wheel))?;
+        Ok(0)
+    }
+
+    fn sys_consumer_press(&mut self, usage: u32) -> Result<u32, Errno> {
+        let usage = u16::try_from(usage).map_err(|_| Errno::Inval)?;
+        keyboard::update_consumer(current_pid(), |consumer| consumer.press(usage))?;
+        Ok(0)
+    }
+
+    fn sys_consumer_release(&mut self) -> Result<u32, Errno> {
+        keyboard::update_consumer(current_pid(), |consumer| consumer.release())?;
+        Ok(0)
+    }
+
+    fn sys_consumer_tap(&mut self, usage: u32) -> Result<u32, Errno> {
+        let usage = u16::try_from(usage).map_err(|_| Errno::Inval)?;
+        keyboard::update_consumer(current_pid(), |consumer| consumer.tap(usage))?;
+        Ok(0)
+    }
+
+    fn sys_keyboard_mode(&mut self, mode: u32) -> Result<u32, Errno> {
+        let mode = Mode::from_u32(mode).ok_or(Errno::Inval)?;
+        keyboard::update(current_pid(), |keyboard| keyboard.set_mode(mode))?;
+        Ok(0)
+    }
+
+    fn sys_led_subscribe(&mut self) -> Result<u32, Errno> {
+        led::subscribe(current_pid());
+        Ok(0)
+    }
+
+    fn sys_led_unsubscribe(&mut self) -> Result<u32, Errno> {
+        led::unsubscribe(current_pid())?;
+        Ok(0)
+    }
+
+    fn sys_led_read(&mut self) -> Result<u32, Errno> {
+        let pid = current_pid();
+        self.wait(|| led::read(pid)).map(|leds| leds as u32)
+    }
+
+    fn sys_suspend_subscribe(&mut self) -> Result<u32, Errno> {
+        suspend::subscribe(current_pid());
+        Ok(0)
+    }
+
+    fn sys_suspend_unsubscribe(&mut self) -> Result<u32, Errno> {
+        suspend::unsubscribe(current_pid())?;
+        Ok(0)
+    }
+
+    fn sys_suspend_read(&mut self) -> Result<u32, Errno> {
+        let pid = current_pid();
+        self.wait(|| suspend::read(pid)).map(|suspended| suspended as u32)
+    }
+}
+
+fn current_pid() -> usize {
+    unsafe { TASK_SCHEDULER.as_ref().unwrap() }.current_process
+}
+
+// Memory the calling process may hand the kernel, see chocos_abi::memory
+fn caller_memory() -> UserMemory {
+    UserMemory { slot: unsafe { TASK_SCHEDULER.as_ref().unwrap() }.ram(current_pid()) }
+}
+
+fn fault(addr: u32, len: u32) -> Errno {
+    let _ = hprintln!("[Exception] SVCall: Process {} passed {:#x} ({} bytes), not its memory", current_pid(), addr, len);
+    Errno::Fault
+}
+
+// `len` bytes at `addr` the caller may let the kernel read
+fn user_bytes(addr: u32, len: u32) -> Result<&'static [u8], Errno> {
+    caller_memory().check(addr, len).map_err(|_| fault(addr, len))?;
+    Ok(unsafe { core::slice::from_raw_parts(addr as *const u8, len as usize) })
+}
+
+// `len` bytes at `addr` the caller may let the kernel write
+fn user_bytes_mut(addr: u32, len: u32) -> Result<&'static mut [u8], Errno> {
+    caller_memory().check_mut(addr, len).map_err(|_| fault(addr, len))?;
+    Ok(unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, len as usize) })
+}
+
+fn key_code(code: u32) -> Result<u8, Errno> {
+    u8::try_from(code).map_err(|_| Errno::Inval)
+}
+
+// Only apps whose header asks for CAP_REBOOT may restart the board, and
+// the kernel's init process
+fn may_reboot() -> Result<(), Errno> {
+    let pid = current_pid();
+    let caps = unsafe { TASK_SCHEDULER.as_ref().unwrap() }.caps(pid);
+    require_cap(caps, CAP_REBOOT).map_err(|errno| {
+        let _ = hprintln!("[Exception] SVCall: Process {} may not reboot", pid);
+        errno
+    })
 }
 
 
@@ -153,8 +414,6 @@ pub unsafe extern "C" fn pendsv_handler(caller_stack_addr: * const u32, exc_stac
         loop {}
     }
 
-    // let _ = usb_hid::send_msg(7);
-
     let saved_state = SavedState {
         rsp: exc_stack_addr as u32,
         psp: caller_stack_addr as u32,
@@ -25,20 +25,39 @@ pub struct ProcessControlBlock {
     pub pid: usize,
     pub ppid: usize,
     pub stack_base: u32,
+    pub heap_start: u32,
+    pub brk: u32,
     pub entry_point: u32,
+    // chocos_abi::CAP_*
+    pub caps: u32,
     pub priority: u8,
     pub state: ProcessState,
     pub running_state: SavedState,
 }
 
+extern "C" {
+    // end of the PROCESSES region, see memory.x
+    static _processes_end: u32;
+}
+
 // Max processes. This is mainly limited by the memory available.
+// memory.x checks that PROCESSES holds MAX_PCB slots.
 pub const MAX_PCB: usize = 8;
 
+// RAM handed to each process: heap at the bottom, stack at the top.
+// Keep this a power of two so a slot maps onto a single MPU region.
+pub const PROCESS_RAM_SIZE: u32 = 0x1000;
+
+// Minimum distance sbrk keeps between the program break and the stack.
+pub const STACK_GUARD: u32 = 0x100;
+
 #[repr(C)]
 pub struct TaskScheduler {
     pub is_activated: bool,
     pub current_process: usize,
     pub pending_process: usize,
+    // only the init process runs, e.g. while the USB bus is suspended
+    pub apps_parked: bool,
     pub pcbs: [OptionalStruct<ProcessControlBlock>; MAX_PCB],
 }
 
@@ -67,13 +86,17 @@ impl TaskScheduler {
             is_activated: false,
             current_process: 0,
             pending_process: 0,
+            apps_parked: false,
             pcbs: [OptionalStruct {
                 is_some: false,
                 value: ProcessControlBlock {
                     pid: 0,
                     ppid: 0,
                     stack_base: 0,
+                    heap_start: 0,
+                    brk: 0,
                     entry_point: 0,
+                    caps: 0,
                     priority: 0,
                     state: ProcessState::Initialize,
                     running_state: SavedState {
@@ -95,8 +118,12 @@ impl TaskScheduler {
         self.pcbs[0].value.ppid = 0;
         self.pcbs[0].value.state = ProcessState::Initialize;
         self.pcbs[0].value.priority = 0;
+        // the kernel's own, it may do anything
+        self.pcbs[0].value.caps = u32::MAX;
 
         self.pcbs[0].value.stack_base = get_base_stack_pointer_from_pid(0);
+        self.pcbs[0].value.heap_start = get_heap_start_from_pid(0);
+        self.pcbs[0].value.brk = self.pcbs[0].value.heap_start;
 
         // get address of sub_main
         self.pcbs[0].value.entry_point = crate::sub_main as *const u32 as u32;
@@ -126,7 +153,7 @@ impl TaskScheduler {
         let _ = hprintln!("[Task Scheduler] Init: Set PendSV");
         // Trigger context switch
         // SCB::set_pendsv();
-        syscall!(1, 0, 0, 0);
+        syscall!(chocos_abi::nr::YIELD, 0, 0, 0);
 
         loop {}
 
@@ -154,9 +181,9 @@ impl TaskScheduler {
         // MPU::arm();
     }
 
-    pub fn create(&mut self, ppid: usize, entry_point: u32) -> Option<&ProcessControlBlock> {
+    pub fn create(&mut self, ppid: usize, entry_point: u32, caps: u32) -> Option<&ProcessControlBlock> {
         let mut i = 1;
-        while i < 12 {
+        while i < MAX_PCB {
             if self.pcbs[i].is_some() {
                 i += 1;
             } else {
@@ -165,7 +192,10 @@ impl TaskScheduler {
                 self.pcbs[i].value.pid = i;
                 self.pcbs[i].value.state = ProcessState::Initialize;
                 self.pcbs[i].value.stack_base = get_base_stack_pointer_from_pid(i);
+                self.pcbs[i].value.heap_start = get_heap_start_from_pid(i);
+                self.pcbs[i].value.brk = self.pcbs[i].value.heap_start;
                 self.pcbs[i].value.entry_point = entry_point;
+                self.pcbs[i].value.caps = caps;
 
                 let _ = hprintln!("[Task Scheduler] Process {} created, ppid {}", i, ppid);
 
@@ -176,27 +206,18 @@ impl TaskScheduler {
         None
     }
 
+    // Round robin over the apps after the current one, the current one
+    // last. The init process (pid 0) only runs when no app is ready or the
+    // apps are parked.
     pub fn next_ready(&mut self) -> &mut ProcessControlBlock {
-        let mut i = self.current_process;
-        while i < 12 {
-            if self.pcbs[i].is_some() && self.pcbs[i].value.state == ProcessState::Ready {
-                self.current_process = i;
-                break;
-            }
-            
-            i += 1;
-
-            if i > 11 {
-                i = 1;
-            }
-
-            if i == self.current_process {
-                self.current_process = 0;
-                return &mut self.pcbs[0].value;
-            }
-        }
-
-        &mut self.pcbs[i].value
+        let apps = if self.apps_parked { 0 } else { MAX_PCB - 1 };
+        let current = self.current_process;
+        let next = (1..=apps)
+            .map(|n| (current + n - 1) % apps + 1)
+            .find(|&pid| self.pcbs[pid].is_some() && self.pcbs[pid].value.state == ProcessState::Ready)
+            .unwrap_or(0);
+        self.current_process = next;
+        &mut self.pcbs[next].value
     }
 
     pub fn this_process_status(&self) -> ProcessState{
@@ -209,7 +230,9 @@ impl TaskScheduler {
 
         let this_process = &mut self.pcbs[self.current_process].value;
 
-        this_process.state = ProcessState::Ready;
+        if this_process.state != ProcessState::Blocked {
+            this_process.state = ProcessState::Ready;
+        }
         this_process.running_state = old_saved_state;
 
         let next_process = match self.pending_process {
@@ -221,6 +244,7 @@ impl TaskScheduler {
             _ => {
                 let pending_process = self.pending_process;
                 self.pending_process = 0;
+                self.current_process = pending_process;
                 &mut self.pcbs[pending_process].value
             },
         };
@@ -234,12 +258,69 @@ impl TaskScheduler {
         next_process
     }
 
+    // Stops running the apps, they keep their state and go on once
+    // unparked; the init process idles meanwhile. Takes effect with the
+    // next switch, which this asks for.
+    pub fn park_apps(&mut self, parked: bool) {
+        self.apps_parked = parked;
+        if self.is_activated {
+            SCB::set_pendsv();
+        }
+    }
+
     pub fn set_pending_process(&mut self, pid: usize) {
         if pid > 0 && pid < MAX_PCB {
             self.pending_process = pid;
         }
     }
 
+    // Move the program break of a process by `increment` bytes and return
+    // the old break. The heap may not leave the process' RAM slot and has
+    // to stay STACK_GUARD bytes below the current stack pointer `sp`.
+    pub fn sbrk(&mut self, pid: usize, increment: i32, sp: u32) -> Option<u32> {
+        let pcb = &mut self.pcbs[pid].value;
+        let old_brk = pcb.brk;
+        let new_brk = old_brk as i64 + increment as i64;
+
+        if new_brk < pcb.heap_start as i64 || new_brk > (sp - STACK_GUARD) as i64 {
+            let _ = hprintln!("[Task Scheduler] Process {} sbrk({}) refused, break {:#x}", pid, increment, old_brk);
+            return None;
+        }
+
+        pcb.brk = new_brk as u32;
+        Some(old_brk)
+    }
+
+    // Takes process `pid` off the CPU until `wake`
+    pub fn block(&mut self, pid: usize) {
+        self.pcbs[pid].value.state = ProcessState::Blocked;
+        SCB::set_pendsv();
+    }
+
+    // Makes a blocked process ready again. The init process only waits for
+    // interrupts, so it is switched out at once.
+    pub fn wake(&mut self, pid: usize) {
+        if !self.pcbs[pid].is_some() || self.pcbs[pid].value.state != ProcessState::Blocked {
+            return;
+        }
+        self.pcbs[pid].value.state = ProcessState::Ready;
+        if self.current_process == 0 && self.is_activated {
+            SCB::set_pendsv();
+        }
+    }
+
+    // What process `pid` may do besides the ordinary calls, the caps from
+    // its app header; none once it is gone
+    pub fn caps(&self, pid: usize) -> u32 {
+        if self.pcbs[pid].is_some() { self.pcbs[pid].value.caps } else { 0 }
+    }
+
+    // The RAM slot of process `pid`, heap at the bottom and stack at the
+    // top; what it may hand to system calls besides the app static data
+    pub fn ram(&self, pid: usize) -> core::ops::Range<u32> {
+        let pcb = &self.pcbs[pid].value;
+        pcb.heap_start..pcb.stack_base
+    }
 
     pub fn exit(&mut self, pid: u16) {
         self.pcbs[pid as usize].value.state = ProcessState::Terminated;
@@ -248,11 +329,13 @@ impl TaskScheduler {
 }
 
 // https://crates.io/crates/thumb2-stack-size
-// hardcoded base sp
-// the division line between stack and heap lays in 0x20008000
-// 0x2000F500 - 0x2000FFFF is for bootloader flags (reserved)
-// OS occupies 0x2000E000 - 0x2000F500
-// Each process occupy 0xC00 (3072 bytes) of stack
+// Slots are handed out from the top of the PROCESSES region in memory.x
+// downwards, pid 0 highest. Each process occupies a PROCESS_RAM_SIZE slot
+// shared by its heap and stack.
 fn get_base_stack_pointer_from_pid(pid: usize) -> u32 {
-    0x2000D000 - (pid as u32) * 0x1000
+    unsafe { &_processes_end as *const u32 as u32 } - (pid as u32) * PROCESS_RAM_SIZE
+}
+
+fn get_heap_start_from_pid(pid: usize) -> u32 {
+    get_base_stack_pointer_from_pid(pid) - PROCESS_RAM_SIZE
 }
@@ -2,13 +2,36 @@
 use cortex_m::{asm, peripheral::NVIC};
 // use cortex_m_semihosting::hprintln;
 use stm32f1xx_hal::{pac::interrupt, gpio::{Input, Cr, CRL, CRH, Floating, gpioa, gpiod}, usb::{Peripheral, UsbBus}, rcc::Clocks};
-use usb_device::{class_prelude::{UsbBusAllocator}, device::{UsbDeviceBuilder, UsbVidPid, UsbDevice}, UsbError};
-use usbd_hid::descriptor::SerializedDescriptor;
-use usbd_hid::{hid_class::HIDClass, descriptor::KeyboardReport};
+use usb_device::{class_prelude::{UsbBusAllocator}, device::{UsbDeviceBuilder, UsbDeviceState, UsbVidPid, UsbDevice}, UsbError};
+use usbd_hid::hid_class::HIDClass;
+use chocos_handoff::Request;
+use chocos_keyboard::hid::{self, MAX_REPORT};
+use chocos_isp::protocol::REPORT_DESCRIPTOR;
+use chocos_isp::usb::{self as identity, SERIAL_SIZE};
+use usbd_serial::CdcAcmClass;
+
+use crate::keyboard_class::KeyboardClass;
+use crate::{channel, console, keyboard, led, reboot, suspend};
 
 static mut USB_BUS: Option<UsbBusAllocator<UsbBus<Peripheral>>> = None;
-static mut USB_HID: Option<HIDClass<'static, UsbBus<Peripheral>>> = None;
+static mut USB_HID: Option<KeyboardClass<'static, UsbBus<Peripheral>>> = None;
+// The vendor channel, interface 1, see channel.rs
+static mut USB_CHANNEL: Option<HIDClass<'static, UsbBus<Peripheral>>> = None;
+// The serial console, interfaces 2 and 3, see console.rs
+static mut USB_SERIAL: Option<CdcAcmClass<'static, UsbBus<Peripheral>>> = None;
 static mut USB_DEVICE: Option<UsbDevice<'static, UsbBus<Peripheral>>> = None;
+// the serial number string, from the unique ID
+static mut SERIAL: [u8; SERIAL_SIZE] = [0; SERIAL_SIZE];
+// cycles of the resume signal a remote wakeup drives, 1 to 15 ms
+static mut RESUME_CYCLES: u32 = 0;
+// the host has configured the device since boot
+static mut CONFIGURED: bool = false;
+
+// Sent by `chocflash recover`. The keyboard's own output report is the
+// report ID and the LED byte, so a full 8 byte report can't be a real one.
+const RECOVERY_REQUEST: [u8; 8] = *b"CHOCBOOT";
+// Sent by `chocflash reboot`, a plain restart
+const REBOOT_REQUEST: [u8; 8] = *b"CHOCRSET";
 
 pub fn init(
     sysclk: u32,
@@ -39,22 +62,31 @@ pub fn init(
         }
     };
 
+    unsafe { RESUME_CYCLES = sysclk / 200; }
     let usb_bus = UsbBus::new(usb);
     unsafe { USB_BUS = Some(usb_bus); }
     let bus_ref = unsafe { USB_BUS.as_ref().unwrap() };
 
-    let usb_hid = HIDClass::new(bus_ref, KeyboardReport::desc(), 10);
-    let usb_device = UsbDeviceBuilder::new(bus_ref, UsbVidPid(0x16c0, 0x27dd))
-        .manufacturer("Test Company")
-        .product("ChocOS Keyboard")
-        .serial_number("0001")
-        .device_class(0xEF)
+    // keyboard, mouse and media keys behind report IDs
+    let usb_hid = KeyboardClass::new(bus_ref, 10);
+    let usb_channel = HIDClass::new(bus_ref, REPORT_DESCRIPTOR, 1);
+    let usb_serial = CdcAcmClass::new(bus_ref, console::PACKET_SIZE);
+    let serial = identity::serial_number(&unsafe { identity::read_uid() }, unsafe { &mut SERIAL });
+    let usb_device = UsbDeviceBuilder::new(bus_ref, UsbVidPid(identity::VID, identity::PID))
+        .manufacturer(identity::MANUFACTURER)
+        .product(identity::PRODUCT)
+        .serial_number(serial)
+        // the CDC function spans two interfaces, tied together by an IAD
+        .composite_with_iads()
+        .supports_remote_wakeup(true)
         .max_packet_size_0(64)
         .build();
 
 
     unsafe {
         USB_HID = Some(usb_hid);
+        USB_CHANNEL = Some(usb_channel);
+        USB_SERIAL = Some(usb_serial);
         USB_DEVICE = Some(usb_device);
     }
 
@@ -68,10 +100,67 @@ pub fn init(
     // asm::wfi();
 }
 
-pub fn send_msg(msgid: u8) -> Result<usize, UsbError> {
-    let usb_hid = unsafe { USB_HID.as_ref().unwrap() };
+// Whether a host has enumerated and configured the board since boot
+pub fn was_configured() -> bool {
+    unsafe { CONFIGURED }
+}
+
+// Runs the USB interrupt soon, e.g. to send reports queued outside of it
+pub fn kick() {
+    NVIC::pend(interrupt::USB_LP_CAN_RX0);
+}
+
+// Signals resume on the suspended bus to wake the host, if it enabled
+// remote wakeup. usb-device has no call for it, so this goes to the
+// peripheral: leave suspend and drive resume for 5 ms.
+pub fn remote_wakeup() {
+    let enabled = unsafe { USB_DEVICE.as_ref() }.map_or(false, |usb_dev| usb_dev.remote_wakeup_enabled());
+    if !enabled {
+        return;
+    }
+    let usb = unsafe { &*stm32f1xx_hal::pac::USB::ptr() };
+    cortex_m::interrupt::free(|_| {
+        usb.cntr.modify(|_, w| w.fsusp().clear_bit().lpmode().clear_bit().resume().set_bit());
+    });
+    asm::delay(unsafe { RESUME_CYCLES });
+    cortex_m::interrupt::free(|_| {
+        usb.cntr.modify(|_, w| w.resume().clear_bit());
+    });
+}
 
-    usb_hid.push_raw_input(&msgid.to_ne_bytes())
+// One queued keyboard, mouse or media key report per free IN endpoint;
+// the interrupt for its completion sends the next one. A host in the boot
+// protocol only gets the keys, the other reports are dropped.
+fn send_reports(usb_hid: &KeyboardClass<'static, UsbBus<Peripheral>>) {
+    while let Some(report) = keyboard::next_report() {
+        let mut buf = [0u8; MAX_REPORT];
+        match report.bytes(usb_hid.protocol(), &mut buf) {
+            Some(bytes) => {
+                if usb_hid.push_input(bytes).is_ok() {
+                    keyboard::sent(&report);
+                }
+                return;
+            }
+            None => keyboard::sent(&report),
+        }
+    }
+}
+
+fn send_channel(usb_channel: &HIDClass<'static, UsbBus<Peripheral>>) {
+    if let Some(report) = channel::next_report() {
+        if usb_channel.push_raw_input(&report).is_ok() {
+            channel::sent();
+        }
+    }
+}
+
+// Read every time, an OUT endpoint left full would keep the interrupt
+// coming
+fn receive_channel(usb_channel: &HIDClass<'static, UsbBus<Peripheral>>) {
+    let mut buf = [0u8; 64];
+    if let Ok(size) = usb_channel.pull_raw_output(&mut buf) {
+        channel::received(&buf[..size]);
+    }
 }
 
 #[interrupt]
@@ -88,7 +177,20 @@ fn usb_interrupt() {
     // let _ = hprintln!("USB_INTERRUPT");
     let usb_dev = unsafe { USB_DEVICE.as_mut().unwrap() };
     let usb_hid = unsafe { USB_HID.as_mut().unwrap() };
-    let poll_result = usb_dev.poll(&mut [usb_hid]);
+    let usb_channel = unsafe { USB_CHANNEL.as_mut().unwrap() };
+    let usb_serial = unsafe { USB_SERIAL.as_mut().unwrap() };
+    let poll_result = usb_dev.poll(&mut [usb_hid, usb_channel, usb_serial]);
+    suspend::update(usb_dev.state());
+    if usb_dev.state() == UsbDeviceState::Configured {
+        unsafe { CONFIGURED = true };
+    }
+    send_reports(usb_hid);
+    receive_channel(usb_channel);
+    send_channel(usb_channel);
+    console::poll(usb_serial);
+    if let Some(leds) = usb_hid.take_leds() {
+        led::set(leds);
+    }
     // let _ = hprintln!("USB_POLL: {}", poll_result);
     // let _ = hprintln!("USB_STATE: {:?}", usb_dev.state());
     if !poll_result {
@@ -96,11 +198,19 @@ fn usb_interrupt() {
     }
 
     let mut buf = [0u8; 64];
-    let data = usb_hid.pull_raw_output(&mut buf);
+    let data = usb_hid.pull_output(&mut buf);
 
     match data {
         Ok(size) => {
-            // TODO
+            if buf[..size] == RECOVERY_REQUEST {
+                reboot::reboot(Request::Recovery);
+            }
+            if buf[..size] == REBOOT_REQUEST {
+                reboot::reboot(Request::None);
+            }
+            if let Some(leds) = hid::led_report(usb_hid.protocol(), &buf[..size]) {
+                led::set(leds);
+            }
         },
         Err(UsbError::InvalidEndpoint) => {
 
@@ -111,3 +221,4 @@ fn usb_interrupt() {
         n => unreachable!()
     }
 }
+